@@ -0,0 +1,176 @@
+//! Automatic brightness from the ambient light sensor, on devices that have one. Each reading is
+//! mapped to a percentage through a curve from the settings and sent to the controller thread,
+//! which applies it while auto brightness is on.
+
+use std::sync::mpsc::Sender;
+
+use windows::{
+    Devices::Sensors::{LightSensor, LightSensorReadingChangedEventArgs},
+    Foundation::{EventRegistrationToken, TypedEventHandler},
+};
+
+use crate::{automation, group::PERCENT_MAX, BrightnessEvent};
+
+/// Least change of the percentage that is passed on, so that flickering light, e.g. from a screen
+/// or a passing shadow, does not keep changing the brightness.
+const MIN_CHANGE: u32 = 3;
+
+/// How often the sensor reports at most, in milliseconds.
+const REPORT_INTERVAL: u32 = 1000;
+
+/// Brightness in percent for the illuminance in lux, interpolated linearly between points.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AmbientCurve {
+    /// `(lux, percent)` sorted by strictly increasing lux.
+    points: Vec<(u32, u32)>,
+}
+
+impl AmbientCurve {
+    /// The curve through `points` of `(lux, percent)`, in any order. `None` without any points or
+    /// if two of them have the same lux. Percentages above 100 are taken as 100.
+    pub fn new(mut points: Vec<(u32, u32)>) -> Option<AmbientCurve> {
+        points.sort_unstable();
+        let increasing = points.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        for (_, percent) in &mut points {
+            *percent = (*percent).min(PERCENT_MAX);
+        }
+        (!points.is_empty() && increasing).then_some(AmbientCurve { points })
+    }
+
+    /// The percentage for `lux`. Below the first point and above the last one it is the percentage
+    /// of that point.
+    pub fn percent_at(&self, lux: f32) -> u32 {
+        let (first_lux, first_percent) = self.points[0];
+        if lux <= first_lux as f32 {
+            return first_percent;
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if lux <= x1 as f32 {
+                let fraction = (lux - x0 as f32) / (x1 - x0) as f32;
+                return (y0 as f32 + fraction * (y1 as f32 - y0 as f32)).round() as u32;
+            }
+        }
+        self.points[self.points.len() - 1].1
+    }
+}
+
+impl Default for AmbientCurve {
+    /// Dim in the dark, full brightness in daylight by a window.
+    fn default() -> AmbientCurve {
+        AmbientCurve {
+            points: vec![(0, 20), (50, 40), (300, 70), (1000, 100)],
+        }
+    }
+}
+
+/// Whether the percentage `target` differs enough from the last one passed on to be passed on too.
+fn should_follow(last: Option<u32>, target: u32) -> bool {
+    last.is_none_or(|last| last.abs_diff(target) >= MIN_CHANGE)
+}
+
+/// The ambient light sensor, sending `BrightnessEvent::Ambient` for its readings until dropped.
+pub struct AmbientLight {
+    sensor: LightSensor,
+    token: EventRegistrationToken,
+}
+
+impl Drop for AmbientLight {
+    fn drop(&mut self) {
+        let _ = self.sensor.RemoveReadingChanged(self.token);
+    }
+}
+
+impl AmbientLight {
+    /// Start reading the default light sensor, if there is one. The percentages from `curve` are
+    /// sent through `sender`, starting with that of the current reading.
+    pub fn new(sender: Sender<BrightnessEvent>, curve: AmbientCurve) -> Option<AmbientLight> {
+        let sensor = LightSensor::GetDefault().ok()?;
+        // Not every sensor allows the interval; its own is used then
+        let interval = sensor
+            .MinimumReportInterval()
+            .map_or(REPORT_INTERVAL, |minimum| minimum.max(REPORT_INTERVAL));
+        let _ = sensor.SetReportInterval(interval);
+
+        let mut last = None;
+        let mut follow = move |lux: f32| {
+            if automation::is_paused() {
+                // The first reading after resuming is passed on however small the change
+                last = None;
+                return;
+            }
+            let target = curve.percent_at(lux);
+            if should_follow(last, target) {
+                last = Some(target);
+                let _ = sender.send(BrightnessEvent::Ambient(target));
+            }
+        };
+        if let Ok(reading) = sensor.GetCurrentReading() {
+            if let Ok(lux) = reading.IlluminanceInLux() {
+                follow(lux);
+            }
+        }
+        let token = sensor
+            .ReadingChanged(TypedEventHandler::<
+                LightSensor,
+                LightSensorReadingChangedEventArgs,
+            >::new(move |_, args| {
+                if let Some(args) = args {
+                    follow(args.Reading()?.IlluminanceInLux()?);
+                }
+                Ok(())
+            }))
+            .ok()?;
+        Some(AmbientLight { sensor, token })
+    }
+}
+
+/// The percentage from `curve` for the current reading of the default light sensor, if there is
+/// one.
+pub fn current_percent(curve: &AmbientCurve) -> Option<u32> {
+    let lux = (LightSensor::GetDefault().ok()?)
+        .GetCurrentReading()
+        .and_then(|reading| reading.IlluminanceInLux())
+        .ok()?;
+    Some(curve.percent_at(lux))
+}
+
+/// Whether the device has an ambient light sensor, for enabling the menu item.
+pub fn has_sensor() -> bool {
+    LightSensor::GetDefault().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_the_curve() {
+        let curve = AmbientCurve::new(vec![(1000, 100), (0, 20), (50, 40)]).unwrap();
+        assert_eq!(curve.percent_at(0.0), 20);
+        assert_eq!(curve.percent_at(25.0), 30);
+        assert_eq!(curve.percent_at(50.0), 40);
+        assert_eq!(curve.percent_at(525.0), 70);
+        assert_eq!(curve.percent_at(1000.0), 100);
+        // Beyond the points
+        assert_eq!(curve.percent_at(-1.0), 20);
+        assert_eq!(curve.percent_at(50_000.0), 100);
+    }
+
+    #[test]
+    fn curve_needs_distinct_lux() {
+        assert_eq!(AmbientCurve::new(vec![]), None);
+        assert_eq!(AmbientCurve::new(vec![(10, 20), (10, 30)]), None);
+        let curve = AmbientCurve::new(vec![(100, 150)]).unwrap();
+        assert_eq!(curve.percent_at(0.0), PERCENT_MAX);
+    }
+
+    #[test]
+    fn small_changes_are_not_followed() {
+        assert!(should_follow(None, 50));
+        assert!(!should_follow(Some(50), 50));
+        assert!(!should_follow(Some(50), 52));
+        assert!(should_follow(Some(50), 53));
+        assert!(should_follow(Some(50), 47));
+    }
+}
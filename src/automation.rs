@@ -0,0 +1,36 @@
+//! The switch of **Pause automation**, which stops every automatic source of brightness changes at
+//! once, e.g. for a movie: idle dimming, auto brightness, and following the built-in display. The
+//! flyout, the hotkeys, and the named pipe keep working. Each source checks `is_paused` before
+//! sending; restoring the brightness after dimming is still sent so that nothing stays dimmed.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
+};
+
+use crate::{ambient, builtin, settings, BrightnessEvent};
+
+/// Whether the automatic sources are paused. Starts out running at every start of the app.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the automatic sources are paused.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Pause the automatic sources, or resume them. Resuming catches up right away with what they ask
+/// for now, since what they asked for meanwhile was dropped.
+pub fn set_paused(paused: bool, sender: &Sender<BrightnessEvent>) {
+    PAUSED.store(paused, Ordering::SeqCst);
+    if paused {
+        return;
+    }
+    if let Some(percent) = ambient::current_percent(&settings::ambient_curve()) {
+        let _ = sender.send(BrightnessEvent::Ambient(percent));
+    }
+    if settings::follow_builtin_display() {
+        if let Some(percent) = builtin::brightness() {
+            let _ = sender.send(BrightnessEvent::BuiltinBrightness(percent));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Retrying of DDC/CI commands, which often fail when the monitor is busy.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+/// Schedule of waits between attempts of a failing command. Waits start at `initial_delay` and
+/// double after each failure up to `max_delay`. Each wait is randomly shortened or lengthened by
+/// up to `jitter` of itself so that retries for several monitors do not line up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    /// Wait after the first failed attempt.
+    pub initial_delay: Duration,
+    /// Upper limit of a single wait, before jitter.
+    pub max_delay: Duration,
+    /// Fraction between 0 and 1 of each wait that is randomized.
+    pub jitter: f64,
+    /// Total time that can be spent waiting. The wait that uses it up is followed by one last
+    /// attempt.
+    pub budget: Duration,
+}
+
+impl Default for Backoff {
+    /// 10ms, 20ms, 40ms, etc. for at most a second in total.
+    fn default() -> Self {
+        Backoff {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(320),
+            jitter: 0.2,
+            budget: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Backoff {
+    /// The same schedule with a different total `budget`.
+    pub fn with_budget(self, budget: Duration) -> Backoff {
+        Backoff { budget, ..self }
+    }
+
+    /// Call `f` until it succeeds or the budget runs out. Returns the result of the last attempt.
+    pub fn retry<T, E>(&self, f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        self.retry_with_sleep(f, thread::sleep)
+    }
+
+    fn retry_with_sleep<T, E>(
+        &self,
+        mut f: impl FnMut() -> Result<T, E>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Result<T, E> {
+        let mut remaining = self.budget;
+        let mut delay = self.initial_delay;
+        loop {
+            let result = f();
+            if result.is_ok() || remaining.is_zero() {
+                return result;
+            }
+            let wait = self.jittered(delay).min(remaining);
+            sleep(wait);
+            remaining -= wait;
+            delay = (delay * 2).min(self.max_delay);
+        }
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        // Between `1 - jitter` and `1 + jitter`
+        let factor = 1.0 + jitter * (2.0 * random_unit() - 1.0);
+        delay.mul_f64(factor)
+    }
+}
+
+/// A random number in `[0, 1)`. Every `RandomState` is seeded differently which is plenty for
+/// spreading out retries.
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_stops_at_budget() {
+        let backoff = Backoff::default().with_budget(Duration::from_millis(100));
+        let mut attempts = 0;
+        let mut waited = Duration::ZERO;
+        let result: Result<(), ()> = backoff.retry_with_sleep(
+            || {
+                attempts += 1;
+                Err(())
+            },
+            |wait| waited += wait,
+        );
+        assert!(result.is_err());
+        assert_eq!(waited, backoff.budget);
+        // 10ms, 20ms, 40ms, give or take the jitter, and the rest of the budget
+        assert!((4..=6).contains(&attempts), "{} attempts", attempts);
+    }
+
+    #[test]
+    fn retry_stops_on_success() {
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..Backoff::default()
+        };
+        let mut attempts = 0;
+        let mut waits = Vec::new();
+        let result: Result<u32, ()> = backoff.retry_with_sleep(
+            || {
+                attempts += 1;
+                if attempts == 3 {
+                    Ok(attempts)
+                } else {
+                    Err(())
+                }
+            },
+            |wait| waits.push(wait),
+        );
+        assert_eq!(result, Ok(3));
+        assert_eq!(
+            waits,
+            [Duration::from_millis(10), Duration::from_millis(20)]
+        );
+    }
+
+    #[test]
+    fn zero_budget_tries_once() {
+        let backoff = Backoff::default().with_budget(Duration::ZERO);
+        let mut attempts = 0;
+        let result: Result<(), ()> = backoff.retry_with_sleep(
+            || {
+                attempts += 1;
+                Err(())
+            },
+            |_| panic!("should not wait"),
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,95 @@
+//! Temporary brightness, e.g. for a presentation, that is undone after a while. The brightness from
+//! before is kept for each monitor so that it comes back exactly, whatever was scheduled meanwhile.
+
+use std::time::Instant;
+
+/// The monitors that are boosted and their brightness from before.
+#[derive(Debug)]
+pub struct Boost {
+    until: Instant,
+    /// The brightness to restore of each monitor, `None` if it is not boosted.
+    previous: Vec<Option<u32>>,
+}
+
+impl Boost {
+    /// A boost of none of `num_monitors` monitors that ends at `until`.
+    pub fn new(num_monitors: usize, until: Instant) -> Boost {
+        Boost {
+            until,
+            previous: vec![None; num_monitors],
+        }
+    }
+
+    /// When the brightness is to be restored.
+    pub fn until(&self) -> Instant {
+        self.until
+    }
+
+    /// Move the end of the boost to `until`, e.g. when boosting again.
+    pub fn extend(&mut self, until: Instant) {
+        self.until = self.until.max(until);
+    }
+
+    /// Boost the monitor at the index, which is at `brightness`. A monitor that is already boosted
+    /// keeps its brightness from before the first boost.
+    pub fn add(&mut self, monitor: usize, brightness: u32) {
+        self.previous[monitor].get_or_insert(brightness);
+    }
+
+    /// Whether the monitor at the index is boosted.
+    pub fn holds(&self, monitor: usize) -> bool {
+        self.previous[monitor].is_some()
+    }
+
+    /// Stop boosting the monitor at the index without restoring it, e.g. since the user set it.
+    pub fn release(&mut self, monitor: usize) {
+        self.previous[monitor] = None;
+    }
+
+    /// Put the brightness from before back into `brightness_vals`, where boosted.
+    pub fn restore(self, brightness_vals: &mut [u32]) {
+        for (brightness, previous) in brightness_vals.iter_mut().zip(self.previous) {
+            if let Some(previous) = previous {
+                *brightness = previous;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn restores_the_first_brightness() {
+        let start = Instant::now();
+        let mut boost = Boost::new(3, start + Duration::from_secs(60));
+        boost.add(0, 30);
+        boost.add(2, 50);
+        // Boosting again keeps the brightness from before the first boost
+        boost.add(0, 100);
+        boost.extend(start + Duration::from_secs(120));
+        boost.extend(start + Duration::from_secs(90));
+        assert_eq!(boost.until(), start + Duration::from_secs(120));
+        assert!(boost.holds(0) && !boost.holds(1));
+
+        let mut brightness_vals = [100, 70, 100];
+        boost.restore(&mut brightness_vals);
+        assert_eq!(brightness_vals, [30, 70, 50]);
+    }
+
+    #[test]
+    fn released_monitors_stay() {
+        let mut boost = Boost::new(2, Instant::now());
+        boost.add(0, 30);
+        boost.add(1, 40);
+        boost.release(1);
+        assert!(!boost.holds(1));
+
+        let mut brightness_vals = [100, 60];
+        boost.restore(&mut brightness_vals);
+        assert_eq!(brightness_vals, [30, 60]);
+    }
+}
@@ -0,0 +1,156 @@
+//! The brightness of the display that Windows controls itself, e.g. a laptop's internal panel,
+//! through the `WmiMonitorBrightness` classes of WMI. DDC/CI does not reach such displays, so they
+//! are not among the monitors, but the monitors can be set to match them.
+
+use std::{
+    sync::{mpsc::Sender, OnceLock},
+    thread,
+};
+
+use windows::{
+    core::{Result, PCWSTR},
+    Win32::{
+        Foundation::BSTR,
+        System::{
+            Com::{
+                CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+                VARIANT,
+            },
+            Ole::{VariantClear, VT_BOOL, VT_UI1},
+            Wmi::{
+                IEnumWbemClassObject, IWbemClassObject, IWbemLocator, IWbemServices, WbemLocator,
+                WBEM_FLAG_FORWARD_ONLY, WBEM_FLAG_RETURN_IMMEDIATELY, WBEM_INFINITE,
+            },
+        },
+    },
+};
+
+use crate::{automation, BrightnessEvent};
+
+/// WMI namespace of the monitor classes.
+const NAMESPACE: &str = "root\\WMI";
+/// Whether `detect` found a built-in display, once it has finished.
+static HAS_DISPLAY: OnceLock<bool> = OnceLock::new();
+
+/// Current brightness of each built-in display, in percent.
+const BRIGHTNESS_QUERY: &str = "SELECT Active, CurrentBrightness FROM WmiMonitorBrightness";
+/// Raised whenever the brightness of a built-in display changes, in percent.
+const EVENT_QUERY: &str = "SELECT Brightness FROM WmiMonitorBrightnessEvent";
+
+/// The brightness of the first active built-in display in percent, or `None` if there is none or
+/// WMI cannot be reached. Blocks for the query; one takes tens of milliseconds.
+pub fn brightness() -> Option<u32> {
+    let objects = query(BRIGHTNESS_QUERY, false).ok()?;
+    std::iter::from_fn(|| next(&objects))
+        .filter(|display| read_u32(display, "Active") != Some(0))
+        .find_map(|display| read_u32(&display, "CurrentBrightness"))
+}
+
+/// Look for a built-in display whose brightness can be read, for `has_display`. Done once on a
+/// thread of its own, since the query blocks and the UI thread cannot join the multithreaded
+/// apartment that `query` needs.
+pub fn detect() {
+    thread::spawn(|| {
+        let _ = HAS_DISPLAY.set(brightness().is_some());
+    });
+}
+
+/// Whether `detect` found a built-in display, for enabling the menu item. `false` until it has
+/// finished.
+pub fn has_display() -> bool {
+    HAS_DISPLAY.get().copied().unwrap_or(false)
+}
+
+/// Send `BrightnessEvent::BuiltinBrightness` with the percentage whenever the brightness of a
+/// built-in display changes, e.g. through the keys of a laptop or the quick settings. The events
+/// are waited for on a thread of its own, which ends at the first event after the receiver is gone.
+/// Without a built-in display there are none.
+pub fn watch(sender: Sender<BrightnessEvent>) {
+    thread::spawn(move || {
+        let events = match query(EVENT_QUERY, true) {
+            Ok(events) => events,
+            Err(e) => {
+                crate::debug_log(&format!("Not following the built-in display: {}", e));
+                return;
+            }
+        };
+        while let Some(event) = next(&events) {
+            if automation::is_paused() {
+                continue;
+            }
+            if let Some(percent) = read_u32(&event, "Brightness") {
+                if sender
+                    .send(BrightnessEvent::BuiltinBrightness(percent))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Run the WQL query `wql` in `NAMESPACE`, or subscribe to the events it selects if `notification`.
+/// The calling thread joins the multithreaded apartment unless it already initialized COM, which
+/// also works.
+fn query(wql: &str, notification: bool) -> Result<IEnumWbemClassObject> {
+    unsafe {
+        let _ = CoInitializeEx(std::ptr::null(), COINIT_MULTITHREADED);
+        let locator: IWbemLocator = CoCreateInstance(&WbemLocator, None, CLSCTX_INPROC_SERVER)?;
+        let services: IWbemServices = locator.ConnectServer(
+            BSTR::from(NAMESPACE),
+            BSTR::new(),
+            BSTR::new(),
+            BSTR::new(),
+            0,
+            BSTR::new(),
+            None,
+        )?;
+        let flags = WBEM_FLAG_FORWARD_ONLY.0 | WBEM_FLAG_RETURN_IMMEDIATELY.0;
+        if notification {
+            services.ExecNotificationQuery(BSTR::from("WQL"), BSTR::from(wql), flags, None)
+        } else {
+            services.ExecQuery(BSTR::from("WQL"), BSTR::from(wql), flags, None)
+        }
+    }
+}
+
+/// The next object of `objects`, waiting for it if need be, or `None` once there are no more.
+fn next(objects: &IEnumWbemClassObject) -> Option<IWbemClassObject> {
+    let mut object = [None];
+    let mut returned = 0;
+    unsafe {
+        objects
+            .Next(WBEM_INFINITE.0, &mut object, &mut returned)
+            .ok()
+            .ok()?;
+    }
+    // `WBEM_S_FALSE`, which is a success, with nothing returned at the end
+    let [object] = object;
+    object.filter(|_| returned == 1)
+}
+
+/// The property `name` of `object` as a number, if it is an unsigned byte or a boolean.
+fn read_u32(object: &IWbemClassObject, name: &str) -> Option<u32> {
+    let name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut value = VARIANT::default();
+    unsafe {
+        object
+            .Get(
+                PCWSTR(name.as_ptr()),
+                0,
+                &mut value,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+            .ok()?;
+        let inner = &value.Anonymous.Anonymous;
+        let number = match inner.vt {
+            vt if vt == VT_UI1.0 as u16 => Some(inner.Anonymous.bVal as u32),
+            vt if vt == VT_BOOL.0 as u16 => Some((inner.Anonymous.boolVal != 0) as u32),
+            _ => None,
+        };
+        let _ = VariantClear(&mut value);
+        number
+    }
+}
@@ -0,0 +1,186 @@
+//! Brightness calibration of monitors whose response is far from linear. A table maps percentages
+//! of the brightness range to the values that are written to the monitor, and the brightness
+//! between two points of the table is interpolated linearly.
+
+/// A calibration table: points of `(percent, value)`, where `value` is written to the monitor for
+/// `percent` of its brightness range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// Sorted by strictly increasing percentage, with values that never decrease.
+    points: Vec<(u32, u32)>,
+}
+
+impl Calibration {
+    /// The table with `points`, in any order. `None` unless it has at least two points and is
+    /// monotonic: the percentages, from 0 to 100, are all different and a higher one never has a
+    /// lower value.
+    pub fn new(mut points: Vec<(u32, u32)>) -> Option<Calibration> {
+        points.sort_unstable();
+        let monotonic = points
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 <= pair[1].1);
+        let in_range = points.last().is_some_and(|&(percent, _)| percent <= 100);
+        (points.len() >= 2 && monotonic && in_range).then_some(Calibration { points })
+    }
+
+    /// The table of the linear mapping that is used without one: `min` at 0 percent to `max` at
+    /// 100.
+    pub fn linear(min: u32, max: u32) -> Calibration {
+        Calibration {
+            points: vec![(0, min), (100, max.max(min))],
+        }
+    }
+
+    /// The points of the table, sorted by their percentage.
+    pub fn points(&self) -> &[(u32, u32)] {
+        &self.points
+    }
+
+    /// The table with the point at `percent` set to `value`, e.g. the brightness that the monitor
+    /// was set to with its own buttons. `None` if the table would not be monotonic anymore.
+    pub fn with_point(&self, percent: u32, value: u32) -> Option<Calibration> {
+        let mut points = self.points.clone();
+        points.retain(|&(other, _)| other != percent);
+        points.push((percent, value));
+        Calibration::new(points)
+    }
+
+    /// The value written to the monitor for `brightness` within `min..=max`. Brightness below the
+    /// first point or above the last one gets the value of that point.
+    pub(crate) fn apply(&self, brightness: u32, (min, max): (u32, u32)) -> u32 {
+        // The position is `offset * 100 / range` percent, kept as a fraction to stay exact
+        let range = (max.saturating_sub(min) as u64).max(1);
+        let position = brightness.clamp(min, max.max(min)).saturating_sub(min) as u64 * 100;
+        let (first_percent, first_value) = self.points[0];
+        if position <= first_percent as u64 * range {
+            return first_value;
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if position <= x1 as u64 * range {
+                let span = (x1 - x0) as u64 * range;
+                let rise = (y1 - y0) as u64 * (position - x0 as u64 * range);
+                return y0 + ((rise + span / 2) / span) as u32;
+            }
+        }
+        self.points[self.points.len() - 1].1
+    }
+
+    /// Inverse of `apply`: the brightness within `min..=max` for a `value` read from the monitor.
+    /// Where several percentages share a value, the lowest one is taken.
+    pub(crate) fn invert(&self, value: u32, (min, max): (u32, u32)) -> u32 {
+        let range = max.saturating_sub(min) as u64;
+        // The percentage as the fraction `numerator / denominator`
+        let (numerator, denominator) = self.percent_of(value);
+        let offset = (numerator * range + denominator * 50) / (denominator * 100);
+        min + offset.min(range) as u32
+    }
+
+    /// The percentage at which `value` is written, as a fraction.
+    fn percent_of(&self, value: u32) -> (u64, u64) {
+        let (first_percent, first_value) = self.points[0];
+        if value <= first_value {
+            return (first_percent as u64, 1);
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if value <= y1 {
+                // `y1 > y0` since a flat segment would have ended the search earlier
+                let rise = (y1 - y0) as u64;
+                return (
+                    x0 as u64 * rise + (value - y0) as u64 * (x1 - x0) as u64,
+                    rise,
+                );
+            }
+        }
+        (self.points[self.points.len() - 1].0 as u64, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Calibration {
+        Calibration::new(vec![(0, 5), (50, 20), (100, 80)]).unwrap()
+    }
+
+    #[test]
+    fn only_monotonic_tables() {
+        assert!(Calibration::new(vec![(100, 80), (0, 5), (50, 20)]).is_some());
+        assert!(Calibration::new(vec![(0, 5), (50, 5), (100, 80)]).is_some());
+        // Higher percentage, lower value
+        assert_eq!(Calibration::new(vec![(0, 5), (50, 40), (100, 30)]), None);
+        // The same percentage twice
+        assert_eq!(Calibration::new(vec![(0, 5), (50, 20), (50, 30)]), None);
+        assert_eq!(Calibration::new(vec![(0, 5), (101, 80)]), None);
+        assert_eq!(Calibration::new(vec![(50, 20)]), None);
+        assert_eq!(Calibration::new(vec![]), None);
+    }
+
+    #[test]
+    fn interpolates_at_points() {
+        let table = table();
+        assert_eq!(table.apply(0, (0, 100)), 5);
+        assert_eq!(table.apply(50, (0, 100)), 20);
+        assert_eq!(table.apply(100, (0, 100)), 80);
+        // Percentages of a range that is not 0 to 100
+        assert_eq!(table.apply(10, (10, 210)), 5);
+        assert_eq!(table.apply(110, (10, 210)), 20);
+        assert_eq!(table.apply(210, (10, 210)), 80);
+
+        assert_eq!(table.invert(5, (0, 100)), 0);
+        assert_eq!(table.invert(20, (0, 100)), 50);
+        assert_eq!(table.invert(80, (0, 100)), 100);
+        assert_eq!(table.invert(20, (10, 210)), 110);
+    }
+
+    #[test]
+    fn interpolates_between_points() {
+        let table = table();
+        assert_eq!(table.apply(25, (0, 100)), 13);
+        assert_eq!(table.apply(75, (0, 100)), 50);
+        assert_eq!(table.apply(60, (10, 210)), 13);
+        assert_eq!(table.apply(1, (0, 100)), 5);
+
+        assert_eq!(table.invert(50, (0, 100)), 75);
+        assert_eq!(table.invert(65, (0, 100)), 88);
+        assert_eq!(table.invert(50, (10, 210)), 160);
+    }
+
+    #[test]
+    fn beyond_the_table() {
+        let table = Calibration::new(vec![(10, 20), (90, 60)]).unwrap();
+        assert_eq!(table.apply(0, (0, 100)), 20);
+        assert_eq!(table.apply(100, (0, 100)), 60);
+        assert_eq!(table.invert(0, (0, 100)), 10);
+        assert_eq!(table.invert(100, (0, 100)), 90);
+        // A flat part reads back as its lowest percentage
+        let table = Calibration::new(vec![(0, 0), (20, 30), (60, 30), (100, 100)]).unwrap();
+        assert_eq!(table.apply(40, (0, 100)), 30);
+        assert_eq!(table.invert(30, (0, 100)), 20);
+        // A bogus range where `min` is above `max`
+        assert_eq!(table.apply(50, (80, 20)), 0);
+    }
+
+    #[test]
+    fn linear_table_changes_nothing() {
+        for (min, max) in [(0, 100), (20, 120), (0, 1000)] {
+            let table = Calibration::linear(min, max);
+            for brightness in min..=max {
+                assert_eq!(table.apply(brightness, (min, max)), brightness);
+                assert_eq!(table.invert(brightness, (min, max)), brightness);
+            }
+        }
+    }
+
+    #[test]
+    fn edit_points() {
+        let table = table();
+        let edited = table.with_point(50, 30).unwrap();
+        assert_eq!(edited.points(), [(0, 5), (50, 30), (100, 80)]);
+        let edited = table.with_point(75, 40).unwrap();
+        assert_eq!(edited.points(), [(0, 5), (50, 20), (75, 40), (100, 80)]);
+        assert_eq!(table.with_point(50, 90), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! Parsing of the MCCS capabilities string that DDC/CI monitors report. A typical string looks
+//! like `(prot(monitor)type(lcd)model(XYZ)cmds(01 02 03 0C F3)vcp(02 04 10 12 14(05 08 0B) 60(0F
+//! 11))mccs_ver(2.2))`.
+
+use std::collections::BTreeMap;
+
+/// VCP codes (and their allowed values, if listed) supported by a monitor, and the type of its
+/// display.
+pub struct Capabilities {
+    vcp_codes: BTreeMap<u8, Vec<u8>>,
+    display_type: Option<String>,
+}
+
+impl Capabilities {
+    /// Parse a raw capabilities string. Malformed parts are skipped instead of failing the whole
+    /// parse since a lot of monitors do not strictly follow the format.
+    pub fn parse(raw: &str) -> Capabilities {
+        let vcp_codes = find_tag(raw, "vcp").map(parse_vcp_list).unwrap_or_default();
+        let display_type = find_tag(raw, "type").map(|tag| tag.trim().to_owned());
+        Capabilities {
+            vcp_codes,
+            display_type,
+        }
+    }
+
+    /// Whether the monitor advertises support for the VCP `code`.
+    pub fn supports(&self, code: u8) -> bool {
+        self.vcp_codes.contains_key(&code)
+    }
+
+    /// The values listed for the VCP `code`, e.g. the inputs of `60(0F 11)`. Empty if the code is
+    /// not supported or has no list.
+    pub fn values(&self, code: u8) -> &[u8] {
+        self.vcp_codes.get(&code).map_or(&[], Vec::as_slice)
+    }
+
+    /// The VCP codes the monitor advertises, in ascending order.
+    pub fn vcp_codes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.vcp_codes.keys().copied()
+    }
+
+    /// The contents of `type(...)`, e.g. `lcd`, if the string has it.
+    pub fn display_type(&self) -> Option<&str> {
+        self.display_type.as_deref()
+    }
+}
+
+/// Find the contents of the parenthesized `tag(...)`, handling nested parentheses.
+fn find_tag<'a>(raw: &'a str, tag: &str) -> Option<&'a str> {
+    let bytes = raw.as_bytes();
+    let mut search_from = 0;
+    while let Some(offset) = raw[search_from..].find(tag) {
+        let start = search_from + offset;
+        let open = start + tag.len();
+        search_from = open;
+
+        // Must be a whole word followed by a '(', e.g. "vcp(" but not "vcpname("
+        let preceded_by_ident = start > 0 && bytes[start - 1].is_ascii_alphanumeric();
+        if preceded_by_ident || bytes.get(open) != Some(&b'(') {
+            continue;
+        }
+
+        let mut depth = 0;
+        for (i, &c) in bytes.iter().enumerate().skip(open) {
+            match c {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&raw[open + 1..i]);
+                    }
+                }
+                _ => (),
+            }
+        }
+        // Unbalanced; assume the tag extends to the end of the string
+        return Some(&raw[open + 1..]);
+    }
+    None
+}
+
+/// Parse a list of the form `02 04 14(05 08 0B) 60(0F 11)`.
+fn parse_vcp_list(list: &str) -> BTreeMap<u8, Vec<u8>> {
+    let mut codes = BTreeMap::new();
+    let mut rest = list;
+    loop {
+        rest = rest.trim_start();
+        let code_len = rest
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(rest.len());
+        if code_len == 0 {
+            // Skip a stray character that is neither a code nor the start of a value list
+            match rest.chars().next() {
+                Some(c) => rest = &rest[c.len_utf8()..],
+                None => break,
+            }
+            continue;
+        }
+        let code = u8::from_str_radix(&rest[..code_len], 16).ok();
+        rest = rest[code_len..].trim_start();
+
+        let mut values = Vec::new();
+        if let Some(inner) = rest.strip_prefix('(') {
+            let end = inner.find(')').unwrap_or(inner.len());
+            values = inner[..end]
+                .split_whitespace()
+                .filter_map(|v| u8::from_str_radix(v, 16).ok())
+                .collect();
+            rest = inner.get(end + 1..).unwrap_or("");
+        }
+        if let Some(code) = code {
+            codes.insert(code, values);
+        }
+    }
+    codes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPABILITIES: &str = "(prot(monitor)type(lcd)model(XYZ)cmds(01 02 03 0C F3)\
+        vcp(02 04 10 12 14(05 08 0B) 60(0F 11) D6(01 04))mccs_ver(2.2))";
+
+    #[test]
+    fn parse_vcp_codes() {
+        let capabilities = Capabilities::parse(CAPABILITIES);
+        for code in [0x02, 0x04, 0x10, 0x12, 0x14, 0x60, 0xd6] {
+            assert!(capabilities.supports(code), "missing {:#04x}", code);
+        }
+        // Values inside the parentheses are not codes
+        assert!(!capabilities.supports(0x0b));
+        assert!(!capabilities.supports(0x01));
+        assert_eq!(capabilities.vcp_codes[&0x14], vec![0x05, 0x08, 0x0b]);
+        assert_eq!(capabilities.values(0x60), [0x0f, 0x11]);
+        assert!(capabilities.values(0x10).is_empty());
+        assert!(capabilities.values(0xa5).is_empty());
+        assert_eq!(
+            capabilities.vcp_codes().collect::<Vec<_>>(),
+            vec![0x02, 0x04, 0x10, 0x12, 0x14, 0x60, 0xd6]
+        );
+        assert_eq!(capabilities.display_type(), Some("lcd"));
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let capabilities = Capabilities::parse("garbage");
+        assert!(!capabilities.supports(0x10));
+        assert_eq!(capabilities.display_type(), None);
+
+        let capabilities = Capabilities::parse("(vcp(10 12 zz 60(0F");
+        assert!(capabilities.supports(0x10));
+        assert!(capabilities.supports(0x12));
+    }
+}
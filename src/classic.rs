@@ -0,0 +1,361 @@
+//! Fallback flyout made of classic Win32 controls for systems where XAML islands are not
+//! available, e.g. some Server SKUs. It only has a monitor selector and a trackbar.
+
+use std::{
+    cell::Cell,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use windows::{
+    core::{Result, PCSTR, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, RECT, WPARAM},
+        System::LibraryLoader::GetModuleHandleA,
+        UI::{
+            Controls::{
+                InitCommonControlsEx, ICC_BAR_CLASSES, INITCOMMONCONTROLSEX, TBM_SETPOS,
+                TBM_SETRANGEMAX, TBM_SETRANGEMIN, TBS_BOTH, TBS_HORZ, TBS_NOTICKS,
+            },
+            Input::KeyboardAndMouse::{EnableWindow, VK_ESCAPE},
+            WindowsAndMessaging::{
+                CreateWindowExW, IsDialogMessageA, SendMessageA, SendMessageW, CBS_DROPDOWNLIST,
+                CB_ADDSTRING, CB_GETCURSEL, CB_GETDROPPEDSTATE, CB_SETCURSEL, HMENU, MSG,
+                WINDOW_EX_STYLE, WINDOW_STYLE, WM_KEYDOWN, WM_USER, WS_CHILD, WS_TABSTOP,
+                WS_VISIBLE, WS_VSCROLL,
+            },
+        },
+    },
+};
+
+use crate::{
+    group::{self, MonitorGroup},
+    menu::{FlyoutMenu, MenuHandler},
+    priority::ChangeSource,
+    settings,
+    theme::Theme,
+    window::{is_locked, scaled, system_text_width, unscaled, Window, WindowEvent},
+    BrightnessEvent, Monitor, NotificationIcon, SharedMonitors,
+};
+
+const COMBO_BOX_CLASS: &str = "COMBOBOX";
+const TRACKBAR_CLASS: &str = "msctls_trackbar32";
+/// Not defined by `windows` 0.37.
+const TBM_GETPOS: u32 = WM_USER;
+
+pub struct ClassicControls<'a> {
+    parent: &'a Window<'a>,
+    selector: HWND,
+}
+
+impl<'a> Drop for ClassicControls<'a> {
+    fn drop(&mut self) {
+        // The controls themselves are destroyed together with the parent window
+        self.parent.remove_event_handler();
+    }
+}
+
+impl<'a> ClassicControls<'a> {
+    const MARGIN: i32 = 12;
+    const SELECTOR_HEIGHT: i32 = 24;
+    /// Height of the selector including its drop-down list.
+    const SELECTOR_LIST_HEIGHT: i32 = 200;
+    const TRACKBAR_HEIGHT: i32 = 40;
+
+    /// Build the flyout in `parent`, which must have been created without hosting XAML.
+    pub fn new(
+        parent: &'a Window<'a>,
+        monitors: SharedMonitors,
+        groups: Arc<Vec<MonitorGroup>>,
+        tx: Sender<BrightnessEvent>,
+        notification_icon: Arc<Mutex<NotificationIcon>>,
+    ) -> Result<Self> {
+        let init = INITCOMMONCONTROLSEX {
+            dwSize: std::mem::size_of::<INITCOMMONCONTROLSEX>() as u32,
+            dwICC: ICC_BAR_CLASSES,
+        };
+        unsafe {
+            if !InitCommonControlsEx(&init).as_bool() {
+                return Err(windows::core::Error::from_win32());
+            }
+        }
+
+        // The flyout is widened for the longest monitor name before anything is laid out. The
+        // selector shows it in the system font, which does not grow with the flyout size, next to
+        // its drop-down button, which is about as wide as the selector is high.
+        let name_width = {
+            let monitors = monitors.lock().unwrap();
+            (groups.iter())
+                .map(|group| system_text_width(&group.display_name(&monitors)))
+                .max()
+                .unwrap_or(0)
+        };
+        let padding = 2 * ClassicControls::MARGIN + ClassicControls::SELECTOR_HEIGHT;
+        Window::fit_width(unscaled(name_width + padding));
+
+        let selector = create_child(
+            parent,
+            COMBO_BOX_CLASS,
+            WS_VSCROLL | WINDOW_STYLE(CBS_DROPDOWNLIST as u32),
+            ClassicControls::MARGIN,
+            ClassicControls::SELECTOR_LIST_HEIGHT,
+        )?;
+        let trackbar = create_child(
+            parent,
+            TRACKBAR_CLASS,
+            WINDOW_STYLE(TBS_HORZ | TBS_BOTH | TBS_NOTICKS),
+            2 * ClassicControls::MARGIN + ClassicControls::SELECTOR_HEIGHT,
+            ClassicControls::TRACKBAR_HEIGHT,
+        )?;
+
+        {
+            let monitors = monitors.lock().unwrap();
+            for group in groups.iter() {
+                let name: Vec<u16> = (group.display_name(&monitors).encode_utf16())
+                    .chain(std::iter::once(0))
+                    .collect();
+                unsafe {
+                    SendMessageW(
+                        selector,
+                        CB_ADDSTRING,
+                        WPARAM(0),
+                        LPARAM(name.as_ptr() as isize),
+                    );
+                }
+            }
+            let initial = group::initial_index(&groups, &monitors);
+            if let Some(group) = groups.get(initial) {
+                unsafe {
+                    SendMessageA(selector, CB_SETCURSEL, WPARAM(initial), LPARAM(0));
+                }
+                show_group(trackbar, group, &monitors);
+            }
+        }
+
+        set_window_event_handler(
+            parent,
+            selector,
+            trackbar,
+            monitors,
+            groups,
+            tx,
+            notification_icon,
+        );
+        Ok(ClassicControls { parent, selector })
+    }
+
+    /// Intercept Windows message events. Used in a `GetMessage` loop. Lets the keyboard move
+    /// between the controls like in a dialog. <kbd>Esc</kbd> closes the flyout, or the list of the
+    /// selector first if it is open.
+    pub fn filter_message(&self, message: *const MSG) -> bool {
+        unsafe {
+            let msg = &*message;
+            if msg.message == WM_KEYDOWN
+                && msg.wParam.0 == VK_ESCAPE.0 as usize
+                && SendMessageA(self.selector, CB_GETDROPPEDSTATE, WPARAM(0), LPARAM(0)).0 == 0
+            {
+                self.parent.hide_flyout();
+                return true;
+            }
+            IsDialogMessageA(self.parent.as_handle(), message).as_bool()
+        }
+    }
+}
+
+/// Create a control spanning the width of `parent`, `y` pixels from its top. It is a Unicode
+/// window so that it shows monitor names in any language.
+fn create_child(
+    parent: &Window,
+    class: &str,
+    style: WINDOW_STYLE,
+    y: i32,
+    height: i32,
+) -> Result<HWND> {
+    let width = scaled(Window::width()) - 2 * ClassicControls::MARGIN;
+    let class: Vec<u16> = class.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            PCWSTR(class.as_ptr()),
+            None,
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | style,
+            ClassicControls::MARGIN,
+            y,
+            width,
+            height,
+            parent.as_handle(),
+            HMENU::default(),
+            GetModuleHandleA(PCSTR::default())?,
+            std::ptr::null(),
+        );
+        if hwnd.0 != 0 {
+            Ok(hwnd)
+        } else {
+            Err(windows::core::Error::from_win32())
+        }
+    }
+}
+
+/// Index of the entry chosen in the selector, if any.
+fn selected_index(selector: HWND) -> Option<usize> {
+    let index = unsafe { SendMessageA(selector, CB_GETCURSEL, WPARAM(0), LPARAM(0)) };
+    // `CB_ERR` (-1) if nothing is selected
+    usize::try_from(index.0).ok()
+}
+
+/// Move the trackbar without sending a `WM_HSCROLL`, so no `BrightnessEvent` is sent either.
+fn set_position(trackbar: HWND, brightness: u32) {
+    unsafe {
+        SendMessageA(trackbar, TBM_SETPOS, WPARAM(1), LPARAM(brightness as isize));
+    }
+}
+
+/// Switch the trackbar to the range and brightness of `group`.
+fn show_group(trackbar: HWND, group: &MonitorGroup, monitors: &[Monitor]) {
+    let (min, max) = group.brightness_range(monitors);
+    unsafe {
+        SendMessageA(trackbar, TBM_SETRANGEMIN, WPARAM(0), LPARAM(min as isize));
+        SendMessageA(trackbar, TBM_SETRANGEMAX, WPARAM(1), LPARAM(max as isize));
+    }
+    set_position(trackbar, group.brightness(monitors));
+}
+
+/// What `MenuHandler` needs to know about the classic flyout.
+struct ClassicMenu {
+    selector: HWND,
+    trackbar: HWND,
+    monitors: SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    /// Whether the monitors of the selected entry answer, as last raised by `UsableChanged`.
+    usable: Cell<bool>,
+}
+
+impl FlyoutMenu for ClassicMenu {
+    fn selected(&self) -> Option<usize> {
+        selected_index(self.selector).filter(|&i| i < self.groups.len())
+    }
+
+    fn name(&self, index: usize) -> Option<String> {
+        let group = self.groups.get(index)?;
+        Some(group.name(&self.monitors.lock().unwrap()))
+    }
+
+    fn can_reset(&self, index: usize) -> bool {
+        self.groups[index].supports_factory_reset(&self.monitors.lock().unwrap())
+    }
+
+    fn follows_primary(&self, index: usize) -> Option<bool> {
+        let monitors = self.monitors.lock().unwrap();
+        let group = &self.groups[index];
+        let follows =
+            || (group.members().iter()).all(|&i| settings::follows_primary(monitors[i].get_name()));
+        (!group.is_primary(&monitors)).then(follows)
+    }
+
+    fn bounds(&self, index: usize) -> Vec<RECT> {
+        let monitors = self.monitors.lock().unwrap();
+        (self.groups[index].members().iter())
+            .filter_map(|&i| monitors[i].bounds())
+            .collect()
+    }
+
+    fn lock_controls(&self, locked: bool) {
+        unsafe {
+            EnableWindow(self.trackbar, self.usable.get() && !locked);
+        }
+    }
+}
+
+/// Handles the events from the native window, like the XAML flyout does. Exporting and importing
+/// the settings is left to the XAML flyout.
+fn set_window_event_handler(
+    parent: &Window,
+    selector: HWND,
+    trackbar: HWND,
+    monitors: SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    tx: Sender<BrightnessEvent>,
+    notification_icon: Arc<Mutex<NotificationIcon>>,
+) {
+    let hwnd = parent.as_handle();
+    let can_standby = monitors
+        .lock()
+        .unwrap()
+        .iter()
+        .any(Monitor::supports_standby);
+    let handler = MenuHandler::new(
+        hwnd,
+        can_standby,
+        tx.clone(),
+        notification_icon.clone(),
+        monitors.clone(),
+    );
+    let flyout = ClassicMenu {
+        selector,
+        trackbar,
+        monitors,
+        groups,
+        usable: Cell::new(true),
+    };
+
+    // Errors are ignored since there is nowhere to propagate them to; at worst the UI is stale
+    parent.set_event_handler(move |event| {
+        if handler.handle_event(&event, &flyout) {
+            return;
+        }
+        let selected = flyout.selected();
+
+        match event {
+            WindowEvent::TrackbarMoved => {
+                if let Some(index) = selected.filter(|_| !is_locked(hwnd)) {
+                    let position =
+                        unsafe { SendMessageA(trackbar, TBM_GETPOS, WPARAM(0), LPARAM(0)) };
+                    let brightness = position.0 as u32;
+                    let _ = tx.send(BrightnessEvent::Change(
+                        index,
+                        brightness,
+                        ChangeSource::Manual,
+                    ));
+                }
+            }
+            WindowEvent::SelectionChanged => {
+                if let Some(index) = selected {
+                    show_group(
+                        trackbar,
+                        &flyout.groups[index],
+                        &flyout.monitors.lock().unwrap(),
+                    );
+                    let _ = tx.send(BrightnessEvent::Select(index));
+                }
+            }
+            WindowEvent::BrightnessChanged(index, brightness, _) if selected == Some(index) => {
+                set_position(trackbar, brightness);
+                // Like the XAML slider, brings the other members of the group to the same value
+                let _ = tx.send(BrightnessEvent::Change(
+                    index,
+                    brightness,
+                    ChangeSource::Manual,
+                ));
+            }
+            WindowEvent::UsableChanged(index, is_usable) if selected == Some(index) => {
+                flyout.usable.set(is_usable);
+                unsafe {
+                    EnableWindow(trackbar, is_usable && !is_locked(hwnd));
+                }
+            }
+            WindowEvent::PollBrightness => {
+                if let Some(index) = selected {
+                    let _ = tx.send(BrightnessEvent::Refresh(index));
+                }
+            }
+            // Classic controls always use the system colors, unlike the level icon
+            WindowEvent::ThemeChanged => {
+                if let Ok(mut notification_icon) = notification_icon.lock() {
+                    let _ = notification_icon.set_theme(Theme::taskbar());
+                }
+            }
+            // Handled by `MenuHandler`, about another entry than the selected one, or only raised
+            // for the XAML flyout
+            _ => (),
+        }
+    });
+}
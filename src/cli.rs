@@ -0,0 +1,329 @@
+//! Command line options. The app is not a console program, so the output goes to the console of
+//! the process that started it, if there is one.
+
+use std::fmt;
+
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+use crate::guid::{BUILD, ICON_GUID};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Start the app as usual.
+    Run,
+    /// Start the app without the notification icon and the flyout, controlled only through the
+    /// named pipe.
+    Daemon,
+    /// Print the version and build information, then exit.
+    Version,
+    /// Change the brightness of the monitor at the index, or of every monitor, by `delta` percent
+    /// through the named pipe of the running instance, then exit.
+    Adjust { monitor: Option<usize>, delta: i32 },
+    /// Set the brightness of the monitor at the index, or of every monitor, to `percent` for
+    /// `seconds`, then restore it. Done by the running instance if there is one, otherwise by this
+    /// process, which exits once restored.
+    Temporary {
+        monitor: Option<usize>,
+        percent: u32,
+        seconds: u64,
+    },
+    /// Set the point at `percent` of the calibration table of the monitor at the index, or of
+    /// every monitor, to the brightness that the monitor is at, then exit.
+    Calibrate {
+        monitor: Option<usize>,
+        percent: u32,
+    },
+    /// Print every monitor, as JSON if `json`, then exit.
+    List { json: bool },
+    /// Print the brightness of the monitor at the index in percent, or its first physical monitor
+    /// as JSON if `json`, then exit.
+    Get { monitor: usize, json: bool },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UsageError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+    /// The first option is only used together with the second one.
+    Requires(&'static str, &'static str),
+    /// The two options cannot be used together.
+    Conflicts(&'static str, &'static str),
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UsageError::UnknownOption(arg) => write!(f, "unknown option '{}'", arg),
+            UsageError::MissingValue(option) => write!(f, "missing value for '{}'", option),
+            UsageError::InvalidValue(option, value) => {
+                write!(f, "invalid value '{}' for '{}'", value, option)
+            }
+            UsageError::Requires(option, other) => {
+                write!(f, "'{}' requires '{}'", option, other)
+            }
+            UsageError::Conflicts(option, other) => {
+                write!(f, "'{}' cannot be used with '{}'", option, other)
+            }
+        }
+    }
+}
+
+/// Parse the arguments, without the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, UsageError> {
+    let mut args = args.into_iter();
+    let mut command = Command::Run;
+    let (mut delta, mut monitor) = (None, None);
+    let (mut percent, mut seconds) = (None, None);
+    let mut calibrate = None;
+    let (mut list, mut get, mut json) = (false, false, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--version" | "-V" => command = Command::Version,
+            "--daemon" => command = Command::Daemon,
+            "--adjust" => delta = Some(value(&mut args, "--adjust")?),
+            "--monitor" => monitor = Some(value(&mut args, "--monitor")?),
+            "--temporary" => {
+                let value: u32 = value(&mut args, "--temporary")?;
+                if value > 100 {
+                    return Err(UsageError::InvalidValue("--temporary", value.to_string()));
+                }
+                percent = Some(value);
+            }
+            "--for" => seconds = Some(value(&mut args, "--for")?),
+            "--calibrate" => {
+                let value: u32 = value(&mut args, "--calibrate")?;
+                if value > 100 {
+                    return Err(UsageError::InvalidValue("--calibrate", value.to_string()));
+                }
+                calibrate = Some(value);
+            }
+            "--list" => list = true,
+            "--get" => get = true,
+            "--json" => json = true,
+            _ => return Err(UsageError::UnknownOption(arg)),
+        }
+    }
+    match (list, get, monitor) {
+        (true, true, _) => return Err(UsageError::Conflicts("--list", "--get")),
+        (true, false, Some(_)) => return Err(UsageError::Conflicts("--list", "--monitor")),
+        (true, false, None) => return Ok(Command::List { json }),
+        (false, true, Some(monitor)) => return Ok(Command::Get { monitor, json }),
+        (false, true, None) => return Err(UsageError::Requires("--get", "--monitor")),
+        (false, false, _) if json => return Err(UsageError::Requires("--json", "--list")),
+        (false, false, _) => (),
+    }
+    match (command, delta, percent, seconds, calibrate) {
+        (Command::Run, Some(_), _, _, Some(_)) => {
+            Err(UsageError::Conflicts("--adjust", "--calibrate"))
+        }
+        (Command::Run, None, Some(_), _, Some(_)) => {
+            Err(UsageError::Conflicts("--temporary", "--calibrate"))
+        }
+        (Command::Run, None, None, None, Some(percent)) => {
+            Ok(Command::Calibrate { monitor, percent })
+        }
+        (Command::Run, Some(_), Some(_), ..) => {
+            Err(UsageError::Conflicts("--adjust", "--temporary"))
+        }
+        (Command::Run, Some(_), None, Some(_), _) => {
+            Err(UsageError::Requires("--for", "--temporary"))
+        }
+        (Command::Run, Some(delta), None, None, _) => Ok(Command::Adjust { monitor, delta }),
+        (Command::Run, None, Some(percent), Some(seconds), _) => Ok(Command::Temporary {
+            monitor,
+            percent,
+            seconds,
+        }),
+        (Command::Run, None, Some(_), None, _) => Err(UsageError::Requires("--temporary", "--for")),
+        (Command::Run, None, None, Some(_), _) => Err(UsageError::Requires("--for", "--temporary")),
+        (Command::Run, None, None, None, None) if monitor.is_some() => {
+            Err(UsageError::Requires("--monitor", "--adjust"))
+        }
+        (command, ..) => Ok(command),
+    }
+}
+
+/// Parse the argument after `option`.
+fn value<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    option: &'static str,
+) -> Result<T, UsageError> {
+    let value = args.next().ok_or(UsageError::MissingValue(option))?;
+    value
+        .parse()
+        .map_err(|_| UsageError::InvalidValue(option, value))
+}
+
+/// Text printed for `--version`. The icon GUID is included since the debug and release builds
+/// register different notification icons.
+pub fn version() -> String {
+    format!(
+        "{} {}\nbuild: {}\nicon GUID: {:?}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        BUILD,
+        ICON_GUID
+    )
+}
+
+/// Attach to the console of the parent process so that `println!` and `eprintln!` show up there.
+/// Output that is redirected, e.g. to a file, works without it.
+pub fn attach_console() {
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, UsageError> {
+        parse_args(args.iter().map(|&arg| arg.to_owned()))
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(parse(&[]), Ok(Command::Run));
+        assert_eq!(parse(&["--version"]), Ok(Command::Version));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+        assert_eq!(parse(&["--daemon"]), Ok(Command::Daemon));
+        assert_eq!(
+            parse(&["--verbose"]),
+            Err(UsageError::UnknownOption("--verbose".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parse_adjust() {
+        assert_eq!(
+            parse(&["--adjust", "+10"]),
+            Ok(Command::Adjust {
+                monitor: None,
+                delta: 10
+            })
+        );
+        assert_eq!(
+            parse(&["--monitor", "1", "--adjust", "-5"]),
+            Ok(Command::Adjust {
+                monitor: Some(1),
+                delta: -5
+            })
+        );
+        assert_eq!(
+            parse(&["--adjust"]),
+            Err(UsageError::MissingValue("--adjust"))
+        );
+        assert_eq!(
+            parse(&["--adjust", "up"]),
+            Err(UsageError::InvalidValue("--adjust", "up".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--monitor", "0"]),
+            Err(UsageError::Requires("--monitor", "--adjust"))
+        );
+    }
+
+    #[test]
+    fn parse_temporary() {
+        assert_eq!(
+            parse(&["--temporary", "100", "--for", "600"]),
+            Ok(Command::Temporary {
+                monitor: None,
+                percent: 100,
+                seconds: 600
+            })
+        );
+        assert_eq!(
+            parse(&["--for", "60", "--temporary", "80", "--monitor", "1"]),
+            Ok(Command::Temporary {
+                monitor: Some(1),
+                percent: 80,
+                seconds: 60
+            })
+        );
+        assert_eq!(
+            parse(&["--temporary", "101", "--for", "60"]),
+            Err(UsageError::InvalidValue("--temporary", "101".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--temporary", "100"]),
+            Err(UsageError::Requires("--temporary", "--for"))
+        );
+        assert_eq!(
+            parse(&["--for", "600"]),
+            Err(UsageError::Requires("--for", "--temporary"))
+        );
+        assert_eq!(
+            parse(&["--adjust", "10", "--temporary", "100", "--for", "600"]),
+            Err(UsageError::Conflicts("--adjust", "--temporary"))
+        );
+    }
+
+    #[test]
+    fn parse_calibrate() {
+        assert_eq!(
+            parse(&["--calibrate", "50"]),
+            Ok(Command::Calibrate {
+                monitor: None,
+                percent: 50
+            })
+        );
+        assert_eq!(
+            parse(&["--monitor", "1", "--calibrate", "100"]),
+            Ok(Command::Calibrate {
+                monitor: Some(1),
+                percent: 100
+            })
+        );
+        assert_eq!(
+            parse(&["--calibrate", "101"]),
+            Err(UsageError::InvalidValue("--calibrate", "101".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--calibrate", "50", "--adjust", "10"]),
+            Err(UsageError::Conflicts("--adjust", "--calibrate"))
+        );
+        assert_eq!(
+            parse(&["--calibrate", "50", "--temporary", "100", "--for", "60"]),
+            Err(UsageError::Conflicts("--temporary", "--calibrate"))
+        );
+        assert_eq!(
+            parse(&["--calibrate", "50", "--for", "60"]),
+            Err(UsageError::Requires("--for", "--temporary"))
+        );
+    }
+
+    #[test]
+    fn parse_list_and_get() {
+        assert_eq!(parse(&["--list"]), Ok(Command::List { json: false }));
+        assert_eq!(
+            parse(&["--json", "--list"]),
+            Ok(Command::List { json: true })
+        );
+        assert_eq!(
+            parse(&["--get", "--monitor", "1", "--json"]),
+            Ok(Command::Get {
+                monitor: 1,
+                json: true
+            })
+        );
+        assert_eq!(
+            parse(&["--get"]),
+            Err(UsageError::Requires("--get", "--monitor"))
+        );
+        assert_eq!(
+            parse(&["--list", "--monitor", "0"]),
+            Err(UsageError::Conflicts("--list", "--monitor"))
+        );
+        assert_eq!(
+            parse(&["--list", "--get", "--monitor", "0"]),
+            Err(UsageError::Conflicts("--list", "--get"))
+        );
+        assert_eq!(
+            parse(&["--json"]),
+            Err(UsageError::Requires("--json", "--list"))
+        );
+    }
+}
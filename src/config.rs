@@ -0,0 +1,130 @@
+//! Settings that can be exported to a JSON file and imported on another machine. Monitors are
+//! identified by name since handles and indices differ between machines.
+
+use std::{fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Format version written to exported files. Bump it when the format changes and add a step to
+/// `migrate` that upgrades the previous version.
+const VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Config {
+    version: u64,
+    monitors: Vec<MonitorConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MonitorConfig {
+    name: String,
+    brightness: u32,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The file was written by a newer version of the app, or is not an exported file at all.
+    UnsupportedVersion(Option<u64>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => e.fmt(f),
+            ConfigError::Json(e) => e.fmt(f),
+            ConfigError::UnsupportedVersion(Some(version)) => {
+                write!(f, "unsupported version {}", version)
+            }
+            ConfigError::UnsupportedVersion(None) => write!(f, "missing version"),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+impl Config {
+    /// Settings of the monitors given as `(name, brightness)`.
+    pub fn new(monitors: impl IntoIterator<Item = (String, u32)>) -> Config {
+        let monitors = monitors
+            .into_iter()
+            .map(|(name, brightness)| MonitorConfig { name, brightness })
+            .collect();
+        Config {
+            version: VERSION,
+            monitors,
+        }
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), ConfigError> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn import(path: &Path) -> Result<Config, ConfigError> {
+        Config::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn from_json(json: &str) -> Result<Config, ConfigError> {
+        let value = migrate(serde_json::from_str(json)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// The brightness saved for the monitor called `name`, if any. The first entry wins if the
+    /// name is shared by several monitors.
+    pub fn brightness(&self, name: &str) -> Option<u32> {
+        self.monitors
+            .iter()
+            .find(|monitor| monitor.name == name)
+            .map(|monitor| monitor.brightness)
+    }
+}
+
+/// Upgrade a parsed file of any supported version to `VERSION`.
+fn migrate(value: Value) -> Result<Value, ConfigError> {
+    match value.get("version").and_then(Value::as_u64) {
+        Some(VERSION) => Ok(value),
+        version => Err(ConfigError::UnsupportedVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let config = Config::new([("DELL U2720Q".to_owned(), 40), ("LG".to_owned(), 75)]);
+        let json = serde_json::to_string(&config).unwrap();
+        let imported = Config::from_json(&json).unwrap();
+        assert_eq!(imported, config);
+        assert_eq!(imported.brightness("LG"), Some(75));
+        assert_eq!(imported.brightness("Unknown"), None);
+    }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let newer = r#"{"version": 999, "monitors": []}"#;
+        assert!(matches!(
+            Config::from_json(newer),
+            Err(ConfigError::UnsupportedVersion(Some(999)))
+        ));
+        assert!(matches!(
+            Config::from_json("{}"),
+            Err(ConfigError::UnsupportedVersion(None))
+        ));
+    }
+}
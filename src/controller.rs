@@ -0,0 +1,1363 @@
+//! The controller thread, the only one that talks to the monitors through DDC/CI. It applies the
+//! `BrightnessEvent`s from the flyout, the pipe, the sensors, and the power notifications, writes
+//! the result to the monitors, and announces it to the listeners.
+
+use std::{
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+
+use crate::{
+    boost::Boost,
+    builtin, diagnostics,
+    group::{self, MonitorGroup},
+    health::{self, MonitorHealth},
+    priority::{ChangePriority, ChangeSource},
+    settings::{self, BrightnessKeys, ExitBrightness, WriteFailures},
+    target::BrightnessTarget,
+    throttle::WriteThrottle,
+    window::Window,
+    BrightnessChange, BrightnessEvent, BrightnessListener, WindowPoster, BRIGHTNESS_POLL_INTERVAL,
+    DEBOUNCE_INTERVAL, FAILURES_BEFORE_DISABLING,
+};
+
+/// The settings that the controller thread reads and writes, so that `brightness_controller_loop`
+/// can be driven by fakes in tests instead of the registry. The methods mirror the functions of
+/// `settings`, which `RegistrySettings` calls.
+pub trait ControllerSettings {
+    fn write_interval(&self, device_name: &str) -> Duration;
+    fn follows_primary(&self, device_name: &str) -> bool;
+    fn set_follows_primary(&mut self, device_name: &str, follow: bool);
+    fn auto_brightness(&self) -> bool;
+    fn brightness_keys(&self) -> Option<BrightnessKeys>;
+    fn selected_monitor(&self) -> Option<String>;
+    fn set_selected_monitor(&mut self, device_name: &str);
+    fn default_brightness(&self) -> u32;
+    fn exit_brightness(&self) -> ExitBrightness;
+    fn write_failures(&self) -> WriteFailures;
+}
+
+/// The settings in the registry, shared with the rest of the app.
+pub struct RegistrySettings;
+
+impl ControllerSettings for RegistrySettings {
+    fn write_interval(&self, device_name: &str) -> Duration {
+        settings::write_interval(device_name)
+    }
+
+    fn follows_primary(&self, device_name: &str) -> bool {
+        settings::follows_primary(device_name)
+    }
+
+    fn set_follows_primary(&mut self, device_name: &str, follow: bool) {
+        settings::set_follows_primary(device_name, follow)
+    }
+
+    fn auto_brightness(&self) -> bool {
+        settings::auto_brightness()
+    }
+
+    fn brightness_keys(&self) -> Option<BrightnessKeys> {
+        settings::brightness_keys()
+    }
+
+    fn selected_monitor(&self) -> Option<String> {
+        settings::selected_monitor()
+    }
+
+    fn set_selected_monitor(&mut self, device_name: &str) {
+        settings::set_selected_monitor(device_name)
+    }
+
+    fn default_brightness(&self) -> u32 {
+        settings::default_brightness()
+    }
+
+    fn exit_brightness(&self) -> ExitBrightness {
+        settings::exit_brightness()
+    }
+
+    fn write_failures(&self) -> WriteFailures {
+        settings::write_failures()
+    }
+}
+
+/// Lock `monitors` for a single operation on the monitor at `index`. DDC/CI calls can take tens of
+/// milliseconds, up to the `Backoff` budget when retried, so the lock is never held across several
+/// monitors and the UI thread waits for at most one operation.
+fn with_monitor<M, T>(
+    monitors: &Arc<Mutex<Vec<M>>>,
+    index: usize,
+    f: impl FnOnce(&mut M) -> T,
+) -> T {
+    f(&mut monitors.lock().unwrap()[index])
+}
+
+/// What the controller keeps track of for each monitor.
+struct MonitorState {
+    /// The brightness to write, and the one announced.
+    brightness: u32,
+    /// When the user last changed it, to keep automatic changes from undoing it.
+    priority: ChangePriority,
+    /// Monitors whose writes keep failing are skipped for a while.
+    health: MonitorHealth,
+    /// Keeps the writes apart by the interval from the settings.
+    throttle: WriteThrottle,
+    /// Whether it takes on the brightness of the primary display until changed on its own.
+    following: bool,
+    /// Whether it is off since `BrightnessEvent::DisplaysOff`, until the displays are back on.
+    powered_off: bool,
+    /// Whether the brightness must be written even if it seems unchanged, until a write succeeds.
+    reapply: bool,
+    /// Whether it is left alone as `WriteFailures::Disable` asks.
+    disabled: bool,
+    /// Whether it was named in a notification as `WriteFailures::NotifyOnce` asks.
+    notified: bool,
+    /// Whether `Window::DDC_CI_DISABLED` was posted for it.
+    hinted: bool,
+    /// Whether it refused the brightness asked for in the current round.
+    rejected: bool,
+    /// Speaker volume waiting to be written.
+    volume: Option<u32>,
+    /// Brightness of the pictures besides the main one waiting to be written, by window number.
+    pictures: Vec<(u8, u32)>,
+}
+
+impl MonitorState {
+    fn new(monitor: &impl BrightnessTarget, settings: &impl ControllerSettings) -> MonitorState {
+        let name = monitor.get_name();
+        MonitorState {
+            brightness: monitor.get_brightness(),
+            priority: ChangePriority::default(),
+            health: MonitorHealth::default(),
+            throttle: WriteThrottle::new(settings.write_interval(name)),
+            following: !monitor.is_primary() && settings.follows_primary(name),
+            powered_off: false,
+            reapply: false,
+            disabled: false,
+            notified: false,
+            hinted: false,
+            rejected: false,
+            volume: None,
+            pictures: Vec::new(),
+        }
+    }
+}
+
+/// What the controller keeps track of for each selector entry.
+struct EntryState {
+    /// The brightness last passed to the listeners.
+    announced: u32,
+    /// Whether any of its monitors is healthy, as last posted.
+    usable: bool,
+    /// What changed it in the current round, if something other than the flyout did.
+    source: Option<ChangeSource>,
+}
+
+/// The state of `brightness_controller_loop`, with a method per event.
+struct Controller<M, S> {
+    monitors: Arc<Mutex<Vec<M>>>,
+    groups: Arc<Vec<MonitorGroup>>,
+    settings: S,
+    post: WindowPoster,
+    listeners: Vec<BrightnessListener>,
+    /// In the order of `monitors`.
+    states: Vec<MonitorState>,
+    /// In the order of `groups`.
+    entries: Vec<EntryState>,
+    /// The brightness at startup, restored on exit if the settings ask for it.
+    initial: Vec<u32>,
+    /// The selector entry of the primary display, if any.
+    primary: Option<usize>,
+    /// The selector entry chosen in the flyout, initially the one from the settings.
+    selected: usize,
+    last_change: Option<Instant>,
+    /// Whether the monitors were put into standby through `BrightnessEvent::Standby`.
+    standby: bool,
+    /// The brightness from before `BrightnessEvent::IdleDim`, while dimmed.
+    undimmed: Option<Vec<u32>>,
+    /// The monitors changed by `BrightnessEvent::Boost` and their brightness from before.
+    boost: Option<Boost>,
+    /// Whether `BrightnessEvent::Ambient` is applied, and the percentage it last asked for.
+    auto_brightness: bool,
+    ambient: Option<u32>,
+    brightness_keys: Option<BrightnessKeys>,
+    write_failures: WriteFailures,
+}
+
+impl<M: BrightnessTarget, S: ControllerSettings> Controller<M, S> {
+    fn new(
+        monitors: Arc<Mutex<Vec<M>>>,
+        groups: Arc<Vec<MonitorGroup>>,
+        settings: S,
+        post: WindowPoster,
+        listeners: Vec<BrightnessListener>,
+    ) -> Controller<M, S> {
+        let locked = monitors.lock().unwrap();
+        let states = (locked.iter())
+            .map(|monitor| MonitorState::new(monitor, &settings))
+            .collect::<Vec<_>>();
+        let entries = (groups.iter())
+            .map(|group| EntryState {
+                announced: states[group.members()[0]].brightness,
+                usable: true,
+                source: None,
+            })
+            .collect();
+        let primary = groups.iter().position(|group| group.is_primary(&locked));
+        let selected_monitor = settings.selected_monitor();
+        let selected = group::selected_index(&groups, &locked, selected_monitor.as_deref());
+        drop(locked);
+        Controller {
+            initial: states.iter().map(|state| state.brightness).collect(),
+            states,
+            entries,
+            primary,
+            selected,
+            last_change: None,
+            standby: false,
+            undimmed: None,
+            boost: None,
+            auto_brightness: settings.auto_brightness(),
+            ambient: None,
+            brightness_keys: settings.brightness_keys(),
+            write_failures: settings.write_failures(),
+            monitors,
+            groups,
+            settings,
+            post,
+            listeners,
+        }
+    }
+
+    /// The brightness of every monitor, e.g. to restore it later with `restore_brightness`.
+    fn brightness_vals(&self) -> Vec<u32> {
+        self.states.iter().map(|state| state.brightness).collect()
+    }
+
+    fn restore_brightness(&mut self, brightness_vals: &[u32]) {
+        for (state, &brightness) in self.states.iter_mut().zip(brightness_vals) {
+            state.brightness = brightness;
+        }
+    }
+
+    /// Mark every selector entry as changed by `source`.
+    fn changed_all(&mut self, source: ChangeSource) {
+        for entry in &mut self.entries {
+            entry.source = Some(source);
+        }
+    }
+
+    /// Wait for the next event, or produce `BrightnessEvent::BoostEnded` or
+    /// `BrightnessEvent::Probe` once it is their time. `None` once every sender is gone.
+    fn next_event(&self, rx: &Receiver<BrightnessEvent>) -> Option<BrightnessEvent> {
+        let now = Instant::now();
+        let boost_until = self.boost.as_ref().map(Boost::until);
+        // Failing monitors are probed even if nothing else happens, since their sliders stay
+        // disabled until they answer. Those that are off are probed once they are back on.
+        let probe_at = (self.states.iter())
+            .filter(|state| !state.disabled && !state.powered_off && !self.standby)
+            .filter_map(|state| state.health.probe_at())
+            .min();
+        let received = match boost_until.into_iter().chain(probe_at).min() {
+            // Ends the boost even if other events keep coming
+            Some(until) if until <= now => Err(RecvTimeoutError::Timeout),
+            Some(until) => rx.recv_timeout(until - now),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(msg) => Some(msg),
+            Err(RecvTimeoutError::Timeout)
+                if boost_until.is_some_and(|until| until <= Instant::now()) =>
+            {
+                Some(BrightnessEvent::BoostEnded)
+            }
+            Err(RecvTimeoutError::Timeout) => Some(BrightnessEvent::Probe),
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Apply `msg` and the changes received after it until `DEBOUNCE_INTERVAL` has passed. This is
+    /// done so that it will not try to set the brightness, or volume, one by one for each value
+    /// sent by the callback; only the latest value of each monitor is kept. Returns the event that
+    /// ended the burst, or its last change, and `None` once every sender is gone.
+    fn collect_changes(
+        &mut self,
+        rx: &Receiver<BrightnessEvent>,
+        mut msg: BrightnessEvent,
+    ) -> Option<BrightnessEvent> {
+        let deadline = Instant::now() + DEBOUNCE_INTERVAL;
+        for entry in &mut self.entries {
+            entry.source = None;
+        }
+        loop {
+            // Relative to the latest value, including changes still waiting to be written
+            if let BrightnessEvent::Adjust(i, delta) = msg {
+                let brightness = self.adjusted(i, delta);
+                msg = BrightnessEvent::Change(i, brightness, ChangeSource::Automatic);
+            }
+            match msg {
+                BrightnessEvent::Change(i, brightness, source) => {
+                    self.change(i, brightness, source)
+                }
+                BrightnessEvent::VolumeChange(i, volume) => {
+                    for &member in self.groups[i].members() {
+                        self.states[member].volume = Some(volume);
+                    }
+                }
+                BrightnessEvent::PictureBrightnessChange(i, window, brightness) => {
+                    for &member in self.groups[i].members() {
+                        let pictures = &mut self.states[member].pictures;
+                        pictures.retain(|&(pending, _)| pending != window);
+                        pictures.push((window, brightness));
+                    }
+                }
+                _ => return Some(msg),
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            msg = match rx.recv_timeout(timeout) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => return Some(msg),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Apply `msg`, which ended a burst of changes, and write the result to the monitors. Returns
+    /// whether to keep going, i.e. `false` for `BrightnessEvent::Exit`.
+    fn handle(&mut self, msg: BrightnessEvent) -> bool {
+        match msg {
+            BrightnessEvent::FollowPrimary(i, follow) => self.follow_primary(i, follow),
+            BrightnessEvent::Ambient(percent) => {
+                self.ambient = Some(percent);
+                self.follow_ambient();
+            }
+            BrightnessEvent::AutoBrightness(enabled) => {
+                self.auto_brightness = enabled;
+                // Catches up with the last reading right away
+                if enabled {
+                    self.follow_ambient();
+                }
+            }
+            BrightnessEvent::BuiltinBrightness(percent) => self.follow_builtin(percent),
+            BrightnessEvent::MatchBuiltin => self.match_builtin(),
+            BrightnessEvent::BrightnessKey(up) => self.brightness_key(up),
+            BrightnessEvent::ResetAll => self.reset_all(),
+            BrightnessEvent::Boost(target, percent, duration) => {
+                self.start_boost(target, percent, duration)
+            }
+            BrightnessEvent::BoostEnded => self.end_boost(),
+            BrightnessEvent::IdleDim(dim) => self.idle_dim(dim),
+            BrightnessEvent::Exit => self.exit(),
+            BrightnessEvent::DisplaysOff => {
+                for state in &mut self.states {
+                    state.powered_off = true;
+                }
+            }
+            BrightnessEvent::Standby(enter) => self.set_standby(enter),
+            BrightnessEvent::Offset(offset) => self.set_offset(offset),
+            BrightnessEvent::Reset => self.reset(),
+            _ => (),
+        }
+        self.write_brightness();
+        // Only the writes matter when exiting
+        if let BrightnessEvent::Exit = msg {
+            return false;
+        }
+        self.revert_rejected();
+        self.post_usable(&msg);
+        self.write_pending();
+        match msg {
+            BrightnessEvent::FactoryReset(i) => self.factory_reset(i),
+            BrightnessEvent::Select(i) => self.select(i),
+            BrightnessEvent::Diagnose => self.diagnose(),
+            BrightnessEvent::Refresh(i) => self.refresh(i),
+            _ => (),
+        }
+        self.announce(&msg);
+        true
+    }
+
+    /// The brightness of the selector entry at `i` changed by `delta` percent of its maximum,
+    /// within its range.
+    fn adjusted(&self, i: usize, delta: i32) -> u32 {
+        let range = self.groups[i].brightness_range(&self.monitors.lock().unwrap());
+        let step = group::from_percent(delta.unsigned_abs(), range.1) as i32;
+        let brightness = self.states[self.groups[i].members()[0]].brightness;
+        group::adjusted(brightness, delta.signum() * step, range)
+    }
+
+    fn change(&mut self, i: usize, brightness: u32, source: ChangeSource) {
+        let now = Instant::now();
+        let mut changed = false;
+        for &member in self.groups[i].members() {
+            // Boosted monitors keep their brightness until the boost ends
+            let boosted = self.boost.as_ref().is_some_and(|boost| boost.holds(member));
+            if boosted && !source.by_user() {
+                continue;
+            }
+            // The flyout sends back the changes it is moved to; those are not the user's and must
+            // not start a grace period
+            let state = &mut self.states[member];
+            if state.brightness != brightness && state.priority.accept(source, now) {
+                state.brightness = brightness;
+                changed = true;
+                if source != ChangeSource::Manual {
+                    self.entries[i].source = Some(source);
+                }
+                if let Some(boost) = &mut self.boost {
+                    boost.release(member);
+                }
+                // Setting a follower on its own detaches it
+                if source == ChangeSource::Manual && state.following {
+                    state.following = false;
+                    let name = self.monitors.lock().unwrap()[member].get_name().to_owned();
+                    self.settings.set_follows_primary(&name, false);
+                }
+            }
+        }
+        if changed && self.primary == Some(i) {
+            self.mirror_primary();
+        }
+        self.last_change = Some(now);
+    }
+
+    /// Set the monitors that are following the primary display to the same percentage of their
+    /// range as the selector entry of the primary display has, and mark their entries as changed
+    /// by it.
+    fn mirror_primary(&mut self) {
+        if let Some(primary) = self.primary {
+            let monitors = self.monitors.lock().unwrap();
+            let (_, primary_max) = self.groups[primary].brightness_range(&monitors);
+            let brightness = self.states[self.groups[primary].members()[0]].brightness;
+            let percent = group::to_percent(brightness, primary_max);
+            for (i, group) in self.groups.iter().enumerate() {
+                let (_, max_brightness) = group.brightness_range(&monitors);
+                for &member in group.members() {
+                    if self.states[member].following {
+                        self.states[member].brightness =
+                            group::from_percent(percent, max_brightness);
+                        self.entries[i].source = Some(ChangeSource::FollowPrimary);
+                    }
+                }
+            }
+        }
+    }
+
+    fn follow_primary(&mut self, i: usize, follow: bool) {
+        if let (Some(group), Some(primary)) = (self.groups.get(i), self.primary) {
+            for &member in group.members() {
+                let following = follow && i != primary;
+                self.states[member].following = following;
+                let name = self.monitors.lock().unwrap()[member].get_name().to_owned();
+                self.settings.set_follows_primary(&name, following);
+            }
+            // Catches up right away
+            self.mirror_primary();
+        }
+    }
+
+    /// Set every monitor to the last percentage from the ambient light sensor, while auto
+    /// brightness is on. Dimming and standby win; the brightness catches up with the next reading
+    /// after them.
+    fn follow_ambient(&mut self) {
+        let percent = match self.ambient {
+            Some(percent) if self.auto_brightness && self.undimmed.is_none() && !self.standby => {
+                percent
+            }
+            _ => return,
+        };
+        let now = Instant::now();
+        let monitors = self.monitors.lock().unwrap();
+        for (i, group) in self.groups.iter().enumerate() {
+            let (_, max_brightness) = group.brightness_range(&monitors);
+            let brightness = group::from_percent(percent, max_brightness);
+            for &member in group.members() {
+                let boosted = self.boost.as_ref().is_some_and(|boost| boost.holds(member));
+                let state = &mut self.states[member];
+                // The user overrides it for a while like any other automatic change
+                if !boosted
+                    && state.brightness != brightness
+                    && state.priority.accept(ChangeSource::Ambient, now)
+                {
+                    state.brightness = brightness;
+                    self.entries[i].source = Some(ChangeSource::Ambient);
+                }
+            }
+        }
+    }
+
+    /// Like the ambient light sensor; the built-in display keeps changing while dimmed, and
+    /// monitors in standby would be woken up.
+    fn follow_builtin(&mut self, percent: u32) {
+        if self.undimmed.is_some() || self.standby {
+            return;
+        }
+        let now = Instant::now();
+        let monitors = self.monitors.lock().unwrap();
+        for (i, group) in self.groups.iter().enumerate() {
+            let (_, max_brightness) = group.brightness_range(&monitors);
+            let brightness = group::from_percent(percent, max_brightness);
+            for &member in group.members() {
+                let boosted = self.boost.as_ref().is_some_and(|boost| boost.holds(member));
+                let state = &mut self.states[member];
+                if !boosted
+                    && !monitors[member].controls_sdr_white_level()
+                    && state.brightness != brightness
+                    && state.priority.accept(ChangeSource::Builtin, now)
+                {
+                    state.brightness = brightness;
+                    self.entries[i].source = Some(ChangeSource::Builtin);
+                }
+            }
+        }
+    }
+
+    fn match_builtin(&mut self) {
+        if let Some(percent) = builtin::brightness() {
+            // The user chose the brightness of every monitor, which is not undone
+            self.boost = None;
+            let now = Instant::now();
+            let monitors = self.monitors.lock().unwrap();
+            for (i, group) in self.groups.iter().enumerate() {
+                let (_, max_brightness) = group.brightness_range(&monitors);
+                for &member in group.members() {
+                    // Windows already set the displays it controls itself
+                    if monitors[member].controls_sdr_white_level() {
+                        continue;
+                    }
+                    let state = &mut self.states[member];
+                    state.brightness = group::from_percent(percent, max_brightness);
+                    state.priority.accept(ChangeSource::Hotkey, now);
+                }
+                self.entries[i].source = Some(ChangeSource::Hotkey);
+            }
+            self.last_change = Some(now);
+        }
+    }
+
+    fn brightness_key(&mut self, up: bool) {
+        let keys = match self.brightness_keys {
+            Some(keys) => keys,
+            None => return,
+        };
+        let now = Instant::now();
+        let indices = match (keys.primary_only, self.primary) {
+            (false, _) => 0..self.groups.len(),
+            (true, Some(primary)) => primary..primary + 1,
+            (true, None) => 0..0,
+        };
+        for i in indices {
+            let monitors = self.monitors.lock().unwrap();
+            let range = self.groups[i].brightness_range(&monitors);
+            let step = group::from_percent(keys.step, range.1) as i32;
+            let delta = if up { step } else { -step };
+            for &member in self.groups[i].members() {
+                // Windows changes the displays it controls itself, e.g. a laptop's panel, which
+                // would change twice in HDR mode
+                if monitors[member].controls_sdr_white_level() {
+                    continue;
+                }
+                let state = &mut self.states[member];
+                state.brightness = group::adjusted(state.brightness, delta, range);
+                state.priority.accept(ChangeSource::Hotkey, now);
+                if let Some(boost) = &mut self.boost {
+                    boost.release(member);
+                }
+            }
+            self.entries[i].source = Some(ChangeSource::Hotkey);
+        }
+        self.mirror_primary();
+        self.last_change = Some(now);
+    }
+
+    fn reset_all(&mut self) {
+        // The user chose the brightness of every monitor, which is not undone
+        self.boost = None;
+        let percent = self.settings.default_brightness();
+        let now = Instant::now();
+        let monitors = self.monitors.lock().unwrap();
+        for (i, group) in self.groups.iter().enumerate() {
+            let (_, max_brightness) = group.brightness_range(&monitors);
+            for &member in group.members() {
+                let state = &mut self.states[member];
+                state.brightness = group::from_percent(percent, max_brightness);
+                state.priority.accept(ChangeSource::Hotkey, now);
+            }
+            self.entries[i].source = Some(ChangeSource::Hotkey);
+        }
+        // Pauses polling like a change through the slider
+        self.last_change = Some(now);
+    }
+
+    fn start_boost(&mut self, target: Option<usize>, percent: u32, duration: Duration) {
+        // The brightness from before dimming is the one to restore
+        if let Some(values) = self.undimmed.take() {
+            self.restore_brightness(&values);
+            self.changed_all(ChangeSource::Idle);
+        }
+        let until = Instant::now() + duration;
+        let num_monitors = self.states.len();
+        let active = (self.boost).get_or_insert_with(|| Boost::new(num_monitors, until));
+        active.extend(until);
+        let indices = match target {
+            Some(i) => i..(i + 1).min(self.groups.len()),
+            None => 0..self.groups.len(),
+        };
+        let monitors = self.monitors.lock().unwrap();
+        for i in indices {
+            let (_, max_brightness) = self.groups[i].brightness_range(&monitors);
+            for &member in self.groups[i].members() {
+                let state = &mut self.states[member];
+                active.add(member, state.brightness);
+                state.brightness = group::from_percent(percent, max_brightness);
+            }
+            self.entries[i].source = Some(ChangeSource::Boost);
+        }
+        self.last_change = Some(Instant::now());
+    }
+
+    fn end_boost(&mut self) {
+        if let Some(ended) = self.boost.take() {
+            let mut brightness_vals = self.brightness_vals();
+            ended.restore(&mut brightness_vals);
+            self.restore_brightness(&brightness_vals);
+            self.mirror_primary();
+            self.changed_all(ChangeSource::Boost);
+            self.last_change = Some(Instant::now());
+        }
+    }
+
+    fn idle_dim(&mut self, dim: Option<u32>) {
+        match dim {
+            // Monitors in standby would be woken up by the writes, and boosted ones are meant to
+            // stay bright, e.g. during a presentation
+            Some(percent) if self.undimmed.is_none() && !self.standby && self.boost.is_none() => {
+                self.undimmed = Some(self.brightness_vals());
+                let monitors = self.monitors.lock().unwrap();
+                for group in self.groups.iter() {
+                    let (_, max_brightness) = group.brightness_range(&monitors);
+                    let dimmed = group::from_percent(percent, max_brightness);
+                    for &member in group.members() {
+                        let state = &mut self.states[member];
+                        state.brightness = state.brightness.min(dimmed);
+                    }
+                }
+            }
+            None => {
+                if let Some(values) = self.undimmed.take() {
+                    self.restore_brightness(&values);
+                }
+            }
+            Some(_) => (),
+        }
+        self.changed_all(ChangeSource::Idle);
+        // Pauses polling like a change through the slider
+        self.last_change = Some(Instant::now());
+    }
+
+    fn exit(&mut self) {
+        // The exit brightness is based on the brightness from before dimming or boosting
+        if let Some(values) = self.undimmed.take() {
+            self.restore_brightness(&values);
+        }
+        if let Some(ended) = self.boost.take() {
+            let mut brightness_vals = self.brightness_vals();
+            ended.restore(&mut brightness_vals);
+            self.restore_brightness(&brightness_vals);
+        }
+        match self.settings.exit_brightness() {
+            ExitBrightness::Leave => (),
+            ExitBrightness::Set(percent) => {
+                let monitors = self.monitors.lock().unwrap();
+                for group in self.groups.iter() {
+                    let (_, max_brightness) = group.brightness_range(&monitors);
+                    for &member in group.members() {
+                        self.states[member].brightness =
+                            group::from_percent(percent, max_brightness);
+                    }
+                }
+            }
+            ExitBrightness::Restore => {
+                for (state, &brightness) in self.states.iter_mut().zip(&self.initial) {
+                    state.brightness = brightness;
+                }
+            }
+        }
+    }
+
+    fn set_standby(&mut self, enter: bool) {
+        let mut failed = false;
+        for i in 0..self.states.len() {
+            failed |= with_monitor(&self.monitors, i, |monitor| {
+                monitor.supports_standby() && monitor.set_standby(enter).is_err()
+            });
+        }
+        self.standby = enter;
+        for state in &mut self.states {
+            state.reapply = false;
+        }
+        if failed {
+            (self.post)(Window::STANDBY_FAILED, WPARAM(enter as usize), LPARAM(0));
+        }
+        // Waking the monitors back up re-applies their brightness
+        if !enter {
+            self.reset();
+        }
+    }
+
+    fn set_offset(&mut self, offset: i32) {
+        for i in 0..self.states.len() {
+            with_monitor(&self.monitors, i, |monitor| {
+                monitor.set_brightness_offset(offset)
+            });
+        }
+        // The values are unchanged, but what is written for them is not
+        for state in &mut self.states {
+            state.reapply = true;
+        }
+    }
+
+    /// Re-apply the brightness of every monitor, e.g. after they were turned back on. Windows may
+    /// turn the display off and on while the monitors are in standby; their `Reset` must not write
+    /// to them since that wakes some monitors up.
+    fn reset(&mut self) {
+        for state in &mut self.states {
+            // Each monitor gets its own last value, so one that was dimmed on purpose stays dimmed.
+            // Those that wake up slower than the others stay pending until they answer.
+            state.reapply |= !self.standby;
+            state.powered_off = false;
+            // Probed again, e.g. after they were plugged back in or turned on
+            state.disabled = false;
+        }
+    }
+
+    /// Write the brightness of every monitor that is not at its value yet. Monitors already at
+    /// their value are skipped unless the brightness needs to be re-applied, e.g. after the monitor
+    /// was turned back on. Monitors that keep failing are left alone until their cooldown has
+    /// passed. They are then probed with a single attempt so the others are not held up by their
+    /// retries, even if their brightness seems unchanged.
+    fn write_brightness(&mut self) {
+        let now = Instant::now();
+        for (i, state) in self.states.iter_mut().enumerate() {
+            state.rejected = false;
+            if !state.health.is_available(now) || state.disabled {
+                continue;
+            }
+            let brightness = state.brightness;
+            let (asleep, differs) = with_monitor(&self.monitors, i, |monitor| {
+                (
+                    state.powered_off || self.standby && monitor.supports_standby(),
+                    monitor.get_brightness() != brightness,
+                )
+            });
+            // Writes to monitors that are off fail, or wake them up. The brightness is queued to
+            // be written once they are back on.
+            if asleep {
+                state.reapply |= differs;
+                continue;
+            }
+            let probe = !state.health.is_healthy();
+            if !state.reapply && !differs && !probe {
+                continue;
+            }
+            let pending = state.reapply;
+            // Waited for without holding the lock, so the flyout can read the monitors meanwhile
+            thread::sleep(state.throttle.delay(Instant::now()));
+            let result = with_monitor(&self.monitors, i, |monitor| {
+                if state.health.is_healthy() {
+                    monitor.set_brightness(brightness)
+                } else {
+                    monitor.try_set_brightness(brightness)
+                }
+            });
+            state.throttle.record(Instant::now());
+            state.health.record(result.is_ok(), now);
+            state.disabled = self.write_failures == WriteFailures::Disable
+                && state.health.failures() >= FAILURES_BEFORE_DISABLING;
+            state.reapply &= result.is_err();
+            if let Err(e) = result {
+                // Values that are re-applied stay pending instead, for monitors that are slow to
+                // wake up. A failed probe of the value the monitor already has refuses nothing.
+                state.rejected = !pending && differs;
+                if state.health.never_answered() && !health::monitor_gone(e.code()) && !state.hinted
+                {
+                    state.hinted = true;
+                    (self.post)(Window::DDC_CI_DISABLED, WPARAM(i), LPARAM(0));
+                }
+            }
+        }
+    }
+
+    /// Drop the values that were refused so that the flyout moves back to the brightness the
+    /// monitor stayed at, instead of showing one that was never set.
+    fn revert_rejected(&mut self) {
+        for (i, group) in self.groups.iter().enumerate() {
+            for &member in group.members() {
+                if self.states[member].rejected {
+                    self.states[member].brightness =
+                        with_monitor(&self.monitors, member, |monitor| monitor.get_brightness());
+                    self.entries[i].source = Some(ChangeSource::Monitor);
+                }
+            }
+        }
+    }
+
+    /// Post `Window::USABLE_CHANGED` for the selector entries whose monitors became usable or
+    /// unusable, and for the one that `msg` selects.
+    fn post_usable(&mut self, msg: &BrightnessEvent) {
+        for (i, group) in self.groups.iter().enumerate() {
+            let is_usable =
+                (group.members().iter()).any(|&member| self.states[member].health.is_healthy());
+            let selected_now = matches!(msg, BrightnessEvent::Select(j) if *j == i);
+            if is_usable != self.entries[i].usable || selected_now {
+                self.entries[i].usable = is_usable;
+                (self.post)(
+                    Window::USABLE_CHANGED,
+                    WPARAM(i),
+                    LPARAM(is_usable as isize),
+                );
+            }
+        }
+    }
+
+    /// Write the speaker volumes and picture brightness waiting to be written.
+    fn write_pending(&mut self) {
+        for (i, state) in self.states.iter_mut().enumerate() {
+            if let Some(volume) = state.volume.take() {
+                with_monitor(&self.monitors, i, |monitor| {
+                    if monitor.supports_volume() {
+                        let _ = monitor.set_volume(volume);
+                    }
+                });
+            }
+            for (window, brightness) in state.pictures.drain(..) {
+                with_monitor(&self.monitors, i, |monitor| {
+                    // Members that do not show the picture refuse it
+                    let _ = monitor.set_picture_brightness(window, brightness);
+                });
+            }
+        }
+    }
+
+    fn factory_reset(&mut self, i: usize) {
+        if let Some(group) = self.groups.get(i) {
+            let mut result = Ok(());
+            for &member in group.members() {
+                let state = &mut self.states[member];
+                result = result.and(with_monitor(&self.monitors, member, |monitor| {
+                    let result = monitor.reset_to_factory();
+                    state.brightness = monitor.get_brightness();
+                    result
+                }));
+            }
+            if result.is_ok() {
+                self.entries[i].source = Some(ChangeSource::Monitor);
+            } else {
+                (self.post)(Window::FACTORY_RESET_FAILED, WPARAM(i), LPARAM(0));
+            }
+        }
+    }
+
+    fn select(&mut self, i: usize) {
+        if let (Some(group), true) = (self.groups.get(i), i != self.selected) {
+            let name = self.monitors.lock().unwrap()[group.members()[0]]
+                .get_name()
+                .to_owned();
+            self.settings.set_selected_monitor(&name);
+        }
+        self.selected = i;
+    }
+
+    fn diagnose(&mut self) {
+        let diagnoses: Vec<_> = (0..self.states.len())
+            .map(|i| with_monitor(&self.monitors, i, M::diagnose))
+            .collect();
+        let report = Box::into_raw(Box::new(diagnostics::report(&diagnoses)));
+        if !(self.post)(Window::DIAGNOSTICS, WPARAM(0), LPARAM(report as isize)) {
+            unsafe {
+                drop(Box::from_raw(report));
+            }
+        }
+    }
+
+    fn refresh(&mut self, i: usize) {
+        let dragging = (self.last_change)
+            .zip(BRIGHTNESS_POLL_INTERVAL)
+            .is_some_and(|(time, interval)| time.elapsed() < interval);
+        // Monitors in standby do not answer, or wake up when asked
+        if let (Some(group), false) = (self.groups.get(i), dragging || self.standby) {
+            for &member in group.members() {
+                // A monitor that is still to be re-applied may read as its own default after
+                // waking up, which is not a change by the user
+                let state = &mut self.states[member];
+                if state.reapply || state.powered_off {
+                    continue;
+                }
+                with_monitor(&self.monitors, member, |monitor| {
+                    if monitor.refresh_brightness().is_ok() {
+                        state.brightness = monitor.get_brightness();
+                    }
+                });
+            }
+            self.entries[i].source = Some(ChangeSource::Monitor);
+        }
+    }
+
+    /// Pass the brightness of the selector entries that changed to the listeners. Only the latest
+    /// value of a burst of changes is announced, once it was written. A newly selected entry is
+    /// announced even if unchanged so the tray shows its brightness.
+    fn announce(&mut self, msg: &BrightnessEvent) {
+        // Each monitor is named in a notification about refusing a brightness at most once
+        let notify = (self.states.iter_mut())
+            .map(|state| {
+                let notify = self.write_failures == WriteFailures::NotifyOnce
+                    && state.rejected
+                    && !state.health.never_answered()
+                    && !state.notified;
+                state.notified |= notify;
+                notify
+            })
+            .collect::<Vec<_>>();
+
+        for (i, group) in self.groups.iter().enumerate() {
+            let brightness = self.states[group.members()[0]].brightness;
+            let reselected = matches!(msg, BrightnessEvent::Select(j) if *j == i);
+            // Refused values are announced even if the brightness is back where it was announced,
+            // since the flyout moved on to them
+            let reverted = (group.members().iter()).any(|&member| self.states[member].rejected);
+            if self.entries[i].announced == brightness && !reselected && !reverted {
+                continue;
+            }
+            self.entries[i].announced = brightness;
+            let (_, max_brightness) = group.brightness_range(&self.monitors.lock().unwrap());
+            let change = BrightnessChange {
+                index: i,
+                brightness,
+                percent: group::to_percent(brightness, max_brightness),
+                selected: i == self.selected,
+                source: self.entries[i].source.unwrap_or(ChangeSource::Manual),
+                rejected: group.members().iter().any(|&member| notify[member]),
+            };
+            for listener in &mut self.listeners {
+                listener(&change);
+            }
+        }
+    }
+}
+
+/// Event loop that handles directly setting the brightness of the monitors. Should be used in a
+/// separate thread since setting the brightness can stall the GUI. The indices in the events refer
+/// to `groups` and are applied to each of the group's members. Failures are posted with `post`.
+/// Every change of a group's brightness, whatever its source, is passed to `listeners` on this
+/// thread. That also keeps the round trips to the shell for the tooltip and icon off the UI thread,
+/// where they would make dragging the slider stutter. The settings are read from and written to
+/// `settings` only.
+#[inline]
+pub fn brightness_controller_loop<M: BrightnessTarget>(
+    monitors: Arc<Mutex<Vec<M>>>,
+    groups: Arc<Vec<MonitorGroup>>,
+    rx: Receiver<BrightnessEvent>,
+    settings: impl ControllerSettings,
+    post: WindowPoster,
+    listeners: Vec<BrightnessListener>,
+) {
+    let mut controller = Controller::new(monitors, groups, settings, post, listeners);
+    while let Some(msg) = controller.next_event(&rx) {
+        let msg = match controller.collect_changes(&rx, msg) {
+            Some(msg) => msg,
+            None => break,
+        };
+        if !controller.handle(msg) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Sender},
+        thread::JoinHandle,
+    };
+
+    use windows::Win32::Foundation::E_FAIL;
+
+    use super::*;
+    use crate::diagnostics::MonitorDiagnosis;
+
+    /// A monitor that remembers what was written to it.
+    struct FakeTarget {
+        name: String,
+        brightness: u32,
+        writes: Vec<u32>,
+        /// Whether every write fails, like with DDC/CI turned off.
+        rejects: bool,
+        /// How many of the next writes fail, like while the monitor is busy.
+        failures: u32,
+        /// Writes tried, whether they failed or not.
+        attempts: u32,
+    }
+
+    impl FakeTarget {
+        fn new(name: &str, brightness: u32) -> FakeTarget {
+            FakeTarget {
+                name: name.to_owned(),
+                brightness,
+                writes: Vec::new(),
+                rejects: false,
+                failures: 0,
+                attempts: 0,
+            }
+        }
+    }
+
+    impl BrightnessTarget for FakeTarget {
+        fn get_name(&self) -> &str {
+            &self.name
+        }
+
+        fn is_primary(&self) -> bool {
+            false
+        }
+
+        fn get_brightness(&self) -> u32 {
+            self.brightness
+        }
+
+        fn get_min_brightness(&self) -> u32 {
+            0
+        }
+
+        fn get_max_brightness(&self) -> u32 {
+            100
+        }
+
+        fn set_brightness(&mut self, brightness: u32) -> windows::core::Result<()> {
+            self.attempts += 1;
+            if self.rejects || self.failures > 0 {
+                self.failures = self.failures.saturating_sub(1);
+                return Err(E_FAIL.into());
+            }
+            self.brightness = brightness;
+            self.writes.push(brightness);
+            Ok(())
+        }
+
+        fn try_set_brightness(&mut self, brightness: u32) -> windows::core::Result<()> {
+            self.set_brightness(brightness)
+        }
+
+        fn refresh_brightness(&mut self) -> windows::core::Result<()> {
+            Ok(())
+        }
+
+        fn diagnose(&mut self) -> MonitorDiagnosis {
+            unreachable!("not sent by the tests")
+        }
+    }
+
+    /// Settings at their defaults, without the registry.
+    struct FakeSettings {
+        write_failures: WriteFailures,
+        exit_brightness: ExitBrightness,
+    }
+
+    impl FakeSettings {
+        fn new() -> FakeSettings {
+            FakeSettings {
+                write_failures: WriteFailures::NotifyOnce,
+                exit_brightness: ExitBrightness::Leave,
+            }
+        }
+    }
+
+    impl ControllerSettings for FakeSettings {
+        fn write_interval(&self, _device_name: &str) -> Duration {
+            Duration::ZERO
+        }
+
+        fn follows_primary(&self, _device_name: &str) -> bool {
+            false
+        }
+
+        fn set_follows_primary(&mut self, _device_name: &str, _follow: bool) {}
+
+        fn auto_brightness(&self) -> bool {
+            false
+        }
+
+        fn brightness_keys(&self) -> Option<BrightnessKeys> {
+            None
+        }
+
+        fn selected_monitor(&self) -> Option<String> {
+            None
+        }
+
+        fn set_selected_monitor(&mut self, _device_name: &str) {}
+
+        fn default_brightness(&self) -> u32 {
+            80
+        }
+
+        fn exit_brightness(&self) -> ExitBrightness {
+            self.exit_brightness
+        }
+
+        fn write_failures(&self) -> WriteFailures {
+            self.write_failures
+        }
+    }
+
+    /// A controller thread for `targets`, each in a selector entry of its own, that has `events`
+    /// queued up so that they arrive within one debounce interval.
+    struct FakeController {
+        sender: Sender<BrightnessEvent>,
+        announced: Receiver<(usize, u32)>,
+        /// The selector entries and whether they are usable, from `Window::USABLE_CHANGED`.
+        usable: Receiver<(usize, bool)>,
+        targets: Arc<Mutex<Vec<FakeTarget>>>,
+        thread: JoinHandle<()>,
+    }
+
+    impl FakeController {
+        fn start(targets: Vec<FakeTarget>, events: Vec<BrightnessEvent>) -> FakeController {
+            FakeController::start_with(targets, events, FakeSettings::new())
+        }
+
+        /// Like `start`, with `settings` in place of the defaults.
+        fn start_with(
+            targets: Vec<FakeTarget>,
+            events: Vec<BrightnessEvent>,
+            settings: FakeSettings,
+        ) -> FakeController {
+            let groups = (0..targets.len()).map(|i| MonitorGroup::new(vec![i]));
+            let groups = Arc::new(groups.collect::<Vec<_>>());
+            let targets = Arc::new(Mutex::new(targets));
+            let (sender, rx) = mpsc::channel();
+            for event in events {
+                sender.send(event).unwrap();
+            }
+            let (announce, announced) = mpsc::channel();
+            let listener: BrightnessListener = Box::new(move |change| {
+                let _ = announce.send((change.index, change.brightness));
+            });
+            let (post_usable, usable) = mpsc::channel();
+            let post: WindowPoster = Box::new(move |message, wparam, lparam| {
+                if message == Window::USABLE_CHANGED {
+                    let _ = post_usable.send((wparam.0, lparam.0 != 0));
+                }
+                true
+            });
+            let thread = thread::spawn({
+                let targets = targets.clone();
+                move || {
+                    let listeners = vec![listener];
+                    brightness_controller_loop(targets, groups, rx, settings, post, listeners)
+                }
+            });
+            FakeController {
+                sender,
+                announced,
+                usable,
+                targets,
+                thread,
+            }
+        }
+
+        /// Wait for the next announced change, which comes after the writes for it.
+        fn next_announced(&self) -> (usize, u32) {
+            self.announced
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no change announced")
+        }
+
+        /// Wait for the next change of whether a selector entry is usable.
+        fn next_usable(&self) -> (usize, bool) {
+            self.usable
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no change of usability posted")
+        }
+
+        /// Wait until `count` writes were made to the target at `index`, e.g. by a probe that is
+        /// not announced.
+        fn wait_for_writes(&self, index: usize, count: usize) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.targets.lock().unwrap()[index].writes.len() < count {
+                assert!(Instant::now() < deadline, "no write made");
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        /// Wait until `count` writes were tried on the target at `index`.
+        fn wait_for_attempts(&self, index: usize, count: u32) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.targets.lock().unwrap()[index].attempts < count {
+                assert!(Instant::now() < deadline, "no write tried");
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        /// Stop the thread and return what was written to each target.
+        fn stop(self) -> Vec<Vec<u32>> {
+            drop(self.sender);
+            self.thread.join().unwrap();
+            let targets = self.targets.lock().unwrap();
+            targets.iter().map(|target| target.writes.clone()).collect()
+        }
+    }
+
+    #[test]
+    fn changes_are_coalesced() {
+        let controller = FakeController::start(
+            vec![FakeTarget::new("A", 50)],
+            vec![
+                BrightnessEvent::Change(0, 10, ChangeSource::Manual),
+                BrightnessEvent::Change(0, 20, ChangeSource::Manual),
+                BrightnessEvent::Change(0, 30, ChangeSource::Manual),
+            ],
+        );
+        assert_eq!(controller.next_announced(), (0, 30));
+        assert_eq!(controller.stop(), vec![vec![30]]);
+    }
+
+    #[test]
+    fn unchanged_monitors_are_not_written() {
+        let controller = FakeController::start(
+            vec![FakeTarget::new("A", 50), FakeTarget::new("B", 60)],
+            vec![
+                BrightnessEvent::Change(0, 50, ChangeSource::Manual),
+                BrightnessEvent::Change(1, 70, ChangeSource::Manual),
+            ],
+        );
+        assert_eq!(controller.next_announced(), (1, 70));
+        assert_eq!(controller.stop(), vec![vec![], vec![70]]);
+    }
+
+    #[test]
+    fn reset_writes_every_monitor_again() {
+        let controller = FakeController::start(
+            vec![FakeTarget::new("A", 40), FakeTarget::new("B", 60)],
+            // Nothing is announced for the reset itself, since no brightness changed
+            vec![
+                BrightnessEvent::Reset,
+                BrightnessEvent::Change(0, 45, ChangeSource::Manual),
+            ],
+        );
+        assert_eq!(controller.next_announced(), (0, 45));
+        assert_eq!(controller.stop(), vec![vec![40, 45], vec![60]]);
+    }
+
+    #[test]
+    fn rejected_changes_are_undone() {
+        let target = FakeTarget {
+            rejects: true,
+            ..FakeTarget::new("A", 50)
+        };
+        let change = BrightnessEvent::Change(0, 30, ChangeSource::Manual);
+        let controller = FakeController::start(vec![target], vec![change]);
+        // Moves the slider back
+        assert_eq!(controller.next_announced(), (0, 50));
+        assert_eq!(controller.stop(), vec![Vec::<u32>::new()]);
+    }
+
+    #[test]
+    fn failing_monitors_are_probed() {
+        let target = FakeTarget {
+            failures: 1,
+            ..FakeTarget::new("A", 50)
+        };
+        let change = BrightnessEvent::Change(0, 30, ChangeSource::Manual);
+        let controller = FakeController::start(vec![target], vec![change]);
+        assert_eq!(controller.next_announced(), (0, 50));
+        // Written again once the cooldown is over, without any other event
+        controller.wait_for_writes(0, 1);
+        assert_eq!(controller.stop(), vec![vec![50]]);
+    }
+
+    #[test]
+    fn refused_monitors_become_usable_again() {
+        let target = FakeTarget {
+            failures: 1,
+            ..FakeTarget::new("A", 50)
+        };
+        let change = BrightnessEvent::Change(0, 30, ChangeSource::Manual);
+        let controller = FakeController::start(vec![target], vec![change]);
+        assert_eq!(controller.next_usable(), (0, false));
+        assert_eq!(controller.next_announced(), (0, 50));
+        // The probe after the cooldown succeeds, which enables the slider again
+        assert_eq!(controller.next_usable(), (0, true));
+        assert_eq!(controller.stop(), vec![vec![50]]);
+    }
+
+    #[test]
+    fn disabled_monitors_are_skipped_until_reset() {
+        let target = FakeTarget {
+            rejects: true,
+            ..FakeTarget::new("A", 50)
+        };
+        let change = BrightnessEvent::Change(0, 30, ChangeSource::Manual);
+        let settings = FakeSettings {
+            write_failures: WriteFailures::Disable,
+            ..FakeSettings::new()
+        };
+        let controller = FakeController::start_with(vec![target], vec![change], settings);
+        assert_eq!(controller.next_announced(), (0, 50));
+        // The write and the probes after the following cooldowns
+        controller.wait_for_attempts(0, FAILURES_BEFORE_DISABLING);
+
+        // Neither probed nor written to anymore
+        thread::sleep(MonitorHealth::COOLDOWN * 3);
+        let change = BrightnessEvent::Change(0, 40, ChangeSource::Manual);
+        controller.sender.send(change).unwrap();
+        assert_eq!(controller.next_announced(), (0, 40));
+        let attempts = controller.targets.lock().unwrap()[0].attempts;
+        assert_eq!(attempts, FAILURES_BEFORE_DISABLING);
+
+        // Tried again once the displays are turned back on or changed
+        controller.sender.send(BrightnessEvent::Reset).unwrap();
+        controller.wait_for_attempts(0, FAILURES_BEFORE_DISABLING + 1);
+        assert_eq!(controller.stop(), vec![Vec::<u32>::new()]);
+    }
+
+    #[test]
+    fn changes_wait_for_displays_to_turn_on() {
+        let controller = FakeController::start(
+            vec![FakeTarget::new("A", 40), FakeTarget::new("B", 60)],
+            vec![
+                BrightnessEvent::DisplaysOff,
+                BrightnessEvent::Change(0, 30, ChangeSource::Manual),
+            ],
+        );
+        // Announced right away, but only written once the displays are back on
+        assert_eq!(controller.next_announced(), (0, 30));
+        assert!(controller.targets.lock().unwrap()[0].writes.is_empty());
+
+        controller.sender.send(BrightnessEvent::Reset).unwrap();
+        let change = BrightnessEvent::Change(1, 70, ChangeSource::Manual);
+        controller.sender.send(change).unwrap();
+        assert_eq!(controller.next_announced(), (1, 70));
+        assert_eq!(controller.stop(), vec![vec![30], vec![60, 70]]);
+    }
+
+    #[test]
+    fn exit_brightness_is_written_on_exit() {
+        let settings = FakeSettings {
+            exit_brightness: ExitBrightness::Restore,
+            ..FakeSettings::new()
+        };
+        let change = BrightnessEvent::Change(0, 70, ChangeSource::Manual);
+        let controller =
+            FakeController::start_with(vec![FakeTarget::new("A", 40)], vec![change], settings);
+        assert_eq!(controller.next_announced(), (0, 70));
+        controller.sender.send(BrightnessEvent::Exit).unwrap();
+        assert_eq!(controller.stop(), vec![vec![70, 40]]);
+    }
+}
@@ -0,0 +1,190 @@
+//! A plain text report of how each monitor answers over DDC/CI, copied to the clipboard from the
+//! context menu so that it can be attached to bug reports.
+
+use std::fmt::Write;
+
+use windows::{
+    core::Result,
+    Win32::{
+        Foundation::{HANDLE, HWND},
+        System::{
+            DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData},
+            Memory::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+            SystemServices::CF_UNICODETEXT,
+        },
+    },
+};
+
+use monitor_brightness_controller::{DisplayTechnology, Monitor};
+
+use crate::cli;
+
+/// Outcome of testing a single monitor. Errors are kept as their messages.
+pub struct MonitorDiagnosis {
+    name: String,
+    primary: bool,
+    sdr_white_level: bool,
+    technology: DisplayTechnology,
+    /// `GetMonitorBrightness`: the minimum, current, and maximum brightness.
+    read: std::result::Result<(u32, u32, u32), String>,
+    /// `SetMonitorBrightness` with the brightness that was read.
+    write: std::result::Result<(), String>,
+    capabilities: std::result::Result<String, String>,
+}
+
+impl MonitorDiagnosis {
+    /// Read the brightness of `monitor`, write the same value back, and read its capabilities
+    /// string. Talks to the monitor through DDC/CI, so it belongs on the controller thread.
+    pub fn run(monitor: &mut Monitor) -> MonitorDiagnosis {
+        let read = monitor.refresh_brightness().map(|_| {
+            (
+                monitor.get_min_brightness(),
+                monitor.get_brightness(),
+                monitor.get_max_brightness(),
+            )
+        });
+        let write = monitor.try_set_brightness(monitor.get_brightness());
+        MonitorDiagnosis {
+            name: monitor.get_name().to_owned(),
+            primary: monitor.is_primary(),
+            sdr_white_level: monitor.controls_sdr_white_level(),
+            technology: monitor.technology(),
+            read: read.map_err(|e| e.message().to_string()),
+            write: write.map_err(|e| e.message().to_string()),
+            capabilities: monitor
+                .read_capabilities_string()
+                .map_err(|e| e.message().to_string()),
+        }
+    }
+}
+
+/// The report of `diagnoses`, one per monitor, after the version of the app.
+pub fn report(diagnoses: &[MonitorDiagnosis]) -> String {
+    let mut report = cli::version();
+    report.push('\n');
+    for (i, diagnosis) in diagnoses.iter().enumerate() {
+        let _ = write_diagnosis(&mut report, i, diagnosis);
+    }
+    if diagnoses.is_empty() {
+        report.push_str("\nNo monitors found\n");
+    }
+    report
+}
+
+fn write_diagnosis(
+    report: &mut String,
+    index: usize,
+    diagnosis: &MonitorDiagnosis,
+) -> std::fmt::Result {
+    write!(report, "\nMonitor {}: {}", index, diagnosis.name)?;
+    if diagnosis.primary {
+        report.push_str(" (primary)");
+    }
+    report.push('\n');
+    if diagnosis.sdr_white_level {
+        writeln!(report, "  brightness: SDR white level in HDR mode")?;
+    }
+    if diagnosis.technology != DisplayTechnology::Unknown {
+        writeln!(report, "  technology: {}", diagnosis.technology)?;
+    }
+    match &diagnosis.read {
+        Ok((min, current, max)) => writeln!(
+            report,
+            "  read brightness: ok (min {}, current {}, max {})",
+            min, current, max
+        )?,
+        Err(e) => writeln!(report, "  read brightness: failed: {}", e)?,
+    }
+    match &diagnosis.write {
+        Ok(()) => writeln!(report, "  write brightness: ok")?,
+        Err(e) => writeln!(report, "  write brightness: failed: {}", e)?,
+    }
+    match &diagnosis.capabilities {
+        Ok(capabilities) => writeln!(report, "  capabilities: {}", capabilities),
+        Err(e) => writeln!(report, "  capabilities: failed: {}", e),
+    }
+}
+
+/// Replace the contents of the clipboard with `text`. `window` becomes the owner of the clipboard.
+pub fn copy_to_clipboard(window: HWND, text: &str) -> Result<()> {
+    let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let size = text.len() * std::mem::size_of::<u16>();
+    unsafe {
+        if !OpenClipboard(window).as_bool() {
+            return Err(windows::core::Error::from_win32());
+        }
+        let result = (|| {
+            if !EmptyClipboard().as_bool() {
+                return Err(windows::core::Error::from_win32());
+            }
+            let memory = GlobalAlloc(GMEM_MOVEABLE, size);
+            if memory == 0 {
+                return Err(windows::core::Error::from_win32());
+            }
+            let data = GlobalLock(memory) as *mut u16;
+            if data.is_null() {
+                GlobalFree(memory);
+                return Err(windows::core::Error::from_win32());
+            }
+            std::ptr::copy_nonoverlapping(text.as_ptr(), data, text.len());
+            GlobalUnlock(memory);
+            // The clipboard owns the memory once this succeeds
+            if let Err(e) = SetClipboardData(CF_UNICODETEXT.0, HANDLE(memory)) {
+                GlobalFree(memory);
+                return Err(e);
+            }
+            Ok(())
+        })();
+        CloseClipboard();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_every_monitor() {
+        let diagnoses = [
+            MonitorDiagnosis {
+                name: "Generic PnP Monitor".to_owned(),
+                primary: true,
+                sdr_white_level: false,
+                technology: DisplayTechnology::Unknown,
+                read: Ok((0, 40, 100)),
+                write: Ok(()),
+                capabilities: Ok("(prot(monitor)vcp(10))".to_owned()),
+            },
+            MonitorDiagnosis {
+                name: "DELL U2720Q".to_owned(),
+                primary: false,
+                sdr_white_level: false,
+                technology: DisplayTechnology::Oled,
+                read: Err("I2C error".to_owned()),
+                write: Err("timeout".to_owned()),
+                capabilities: Err("timeout".to_owned()),
+            },
+        ];
+        let report = report(&diagnoses);
+        assert!(report.starts_with(&cli::version()));
+        let expected = "
+Monitor 0: Generic PnP Monitor (primary)
+  read brightness: ok (min 0, current 40, max 100)
+  write brightness: ok
+  capabilities: (prot(monitor)vcp(10))
+
+Monitor 1: DELL U2720Q
+  technology: OLED
+  read brightness: failed: I2C error
+  write brightness: failed: timeout
+  capabilities: failed: timeout
+";
+        assert!(report.ends_with(expected), "{}", report);
+    }
+
+    #[test]
+    fn report_without_monitors() {
+        assert!(report(&[]).ends_with("\nNo monitors found\n"));
+    }
+}
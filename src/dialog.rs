@@ -0,0 +1,70 @@
+//! Common dialogs for choosing the file to import settings from or export them to.
+
+use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf};
+
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{BOOL, HWND, MAX_PATH},
+        UI::Controls::Dialogs::{
+            GetOpenFileNameW, GetSaveFileNameW, OFN_FILEMUSTEXIST, OFN_NOCHANGEDIR,
+            OFN_OVERWRITEPROMPT, OFN_PATHMUSTEXIST, OPENFILENAMEW, OPEN_FILENAME_FLAGS,
+        },
+    },
+};
+
+/// Pairs of description and pattern, each null-terminated, with an extra null at the end.
+const JSON_FILTER: &str = "JSON (*.json)\0*.json\0\0";
+const JSON_EXTENSION: &str = "json\0";
+
+/// Ask for an existing JSON file. `None` if the dialog was cancelled.
+pub fn open_json_file(owner: HWND) -> Option<PathBuf> {
+    let flags = OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR;
+    show(owner, "", flags, |ofn| unsafe { GetOpenFileNameW(ofn) })
+}
+
+/// Ask where to save a JSON file, suggesting `file_name`. `None` if the dialog was cancelled.
+pub fn save_json_file(owner: HWND, file_name: &str) -> Option<PathBuf> {
+    let flags = OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR;
+    show(owner, file_name, flags, |ofn| unsafe {
+        GetSaveFileNameW(ofn)
+    })
+}
+
+fn show(
+    owner: HWND,
+    file_name: &str,
+    flags: OPEN_FILENAME_FLAGS,
+    dialog: impl FnOnce(*mut OPENFILENAMEW) -> BOOL,
+) -> Option<PathBuf> {
+    let filter = to_wide(JSON_FILTER);
+    let extension = to_wide(JSON_EXTENSION);
+
+    // Receives the chosen path; initially holds the suggested file name
+    let mut path = [0u16; MAX_PATH as usize];
+    let max_len = path.len() - 1; // Room for the null terminator
+    for (dst, src) in path[..max_len].iter_mut().zip(file_name.encode_utf16()) {
+        *dst = src;
+    }
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: owner,
+        lpstrFilter: PCWSTR(filter.as_ptr()),
+        lpstrFile: PWSTR(path.as_mut_ptr()),
+        nMaxFile: path.len() as u32,
+        Flags: flags,
+        lpstrDefExt: PCWSTR(extension.as_ptr()),
+        ..Default::default()
+    };
+    if !dialog(&mut ofn).as_bool() {
+        return None;
+    }
+
+    let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+    Some(OsString::from_wide(&path[..len]).into())
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
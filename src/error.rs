@@ -0,0 +1,53 @@
+//! Errors of the app that are not a failed Win32 call, kept apart so that they are not reported
+//! with whatever `GetLastError` happens to return.
+
+use std::fmt;
+
+/// Why the app, or one of its parts, failed.
+#[derive(Debug)]
+pub enum AppError {
+    /// No monitor can be controlled through DDC/CI, e.g. on a laptop without external monitors.
+    NoMonitors,
+    /// There is no selector entry with the index, e.g. from the command line.
+    NoSuchMonitor(usize),
+    /// Setting the point of the calibration table at the percentage to the value would make the
+    /// table decrease somewhere.
+    NotMonotonic(u32, u32),
+    /// The notification area rejected a change of the icon. `Shell_NotifyIconW` does not say why.
+    NotificationIcon,
+    /// The running instance replied to a command of the named pipe with the error, e.g. one it is
+    /// too old to know. Without the `error: ` prefix.
+    Reply(String),
+    /// Building the flyout failed partway. What was built of it is torn down again.
+    Flyout(windows::core::Error),
+    /// A Win32 or WinRT call failed.
+    Windows(windows::core::Error),
+}
+
+pub type Result<T> = std::result::Result<T, AppError>;
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::NoMonitors => write!(f, "no monitors that support DDC/CI were found"),
+            AppError::NoSuchMonitor(i) => write!(f, "no monitor with index {}", i),
+            AppError::NotMonotonic(percent, value) => write!(
+                f,
+                "{} at {}% would make the calibration table decrease",
+                value, percent
+            ),
+            AppError::NotificationIcon => write!(f, "the notification area rejected the icon"),
+            AppError::Reply(reason) => write!(f, "the app replied: {}", reason),
+            AppError::Flyout(e) => write!(f, "the flyout could not be built: {}", e),
+            AppError::Windows(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<windows::core::Error> for AppError {
+    fn from(e: windows::core::Error) -> Self {
+        AppError::Windows(e)
+    }
+}
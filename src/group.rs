@@ -0,0 +1,302 @@
+use monitor_brightness_controller::Monitor;
+
+use crate::{settings, strings::strings, target::BrightnessTarget};
+
+/// Physical monitors that are controlled together through a single selector entry.
+pub struct MonitorGroup {
+    members: Vec<usize>,
+}
+
+impl MonitorGroup {
+    /// A group of the monitors at `members`, which must not be empty.
+    #[cfg(test)]
+    pub fn new(members: Vec<usize>) -> MonitorGroup {
+        MonitorGroup { members }
+    }
+
+    /// Indices of the monitors in the group. Never empty.
+    pub fn members(&self) -> &[usize] {
+        &self.members
+    }
+
+    /// Name shown in the selector.
+    pub fn name(&self, monitors: &[Monitor]) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for &i in &self.members {
+            let name = monitors[i].get_name();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.join(" + ")
+    }
+
+    /// `name` marked as the primary display if any member shows it, and with which brightness is
+    /// controlled if that is the one of SDR content, for the selector.
+    pub fn display_name(&self, monitors: &[Monitor]) -> String {
+        let mut name = self.name(monitors);
+        if self.is_primary(monitors) {
+            name.push_str(strings().primary_suffix);
+        }
+        if (self.members.iter()).any(|&i| monitors[i].controls_sdr_white_level()) {
+            name.push_str(strings().sdr_suffix);
+        }
+        name
+    }
+
+    /// Whether any of the members shows the primary display.
+    pub fn is_primary(&self, monitors: &[impl BrightnessTarget]) -> bool {
+        self.members.iter().any(|&i| monitors[i].is_primary())
+    }
+
+    /// Brightness of the group, taken from its first member.
+    pub fn brightness(&self, monitors: &[Monitor]) -> u32 {
+        monitors[self.members[0]].get_brightness()
+    }
+
+    /// `brightness` as a percentage of the maximum, see `to_percent`.
+    pub fn percent(&self, monitors: &[Monitor]) -> u32 {
+        to_percent(self.brightness(monitors), self.brightness_range(monitors).1)
+    }
+
+    /// Brightness range that every member can show, as `(min, max)`.
+    pub fn brightness_range<M: BrightnessTarget>(&self, monitors: &[M]) -> (u32, u32) {
+        let members = self.members.iter().map(|&i| &monitors[i]);
+        // Never empty, so both exist
+        let min = members.clone().map(M::get_min_brightness).max().unwrap();
+        let max = members.map(M::get_max_brightness).min().unwrap();
+        // Members with disjoint ranges all end up at the highest minimum
+        (min, max.max(min))
+    }
+
+    /// Speaker volume of the first member with controllable speakers and its maximum, if any.
+    pub fn volume(&self, monitors: &[Monitor]) -> Option<(u32, u32)> {
+        (self.members.iter()).find_map(|&member| monitors[member].get_volume())
+    }
+
+    /// Pictures besides the main one of the first member that shows several, see
+    /// `Monitor::get_pictures`. Empty if none does.
+    pub fn pictures<'a>(&self, monitors: &'a [Monitor]) -> &'a [(u8, u32, u32)] {
+        (self.members.iter())
+            .map(|&member| monitors[member].get_pictures())
+            .find(|pictures| !pictures.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Luminance range of the first member in nits, if its display reports one.
+    pub fn luminance_range(&self, monitors: &[Monitor]) -> Option<(f32, f32)> {
+        monitors[self.members[0]].get_luminance_range()
+    }
+
+    /// Whether all of the members can be reset to their factory defaults.
+    pub fn supports_factory_reset(&self, monitors: &[Monitor]) -> bool {
+        self.members
+            .iter()
+            .all(|&i| monitors[i].supports_factory_reset())
+    }
+}
+
+/// Maximum brightness up to which monitors are assumed to report a percentage. Monitors with a
+/// larger maximum, e.g. 1000, allow finer steps than whole percents.
+pub const PERCENT_MAX: u32 = 100;
+
+/// `brightness` as a percentage of `max_brightness`, rounded to the nearest percent. Monitors with
+/// a maximum of up to `PERCENT_MAX` already report percentages which are returned as is.
+pub fn to_percent(brightness: u32, max_brightness: u32) -> u32 {
+    if max_brightness <= PERCENT_MAX {
+        brightness
+    } else {
+        let percent = (brightness as u64 * 100 + max_brightness as u64 / 2) / max_brightness as u64;
+        percent as u32
+    }
+}
+
+/// The brightness closest to `percent` of `max_brightness`; the inverse of `to_percent`. Never more
+/// than `max_brightness`, even for more than 100 percent.
+pub fn from_percent(percent: u32, max_brightness: u32) -> u32 {
+    if max_brightness <= PERCENT_MAX {
+        percent.min(max_brightness)
+    } else {
+        let percent = percent.min(PERCENT_MAX);
+        let brightness = (percent as u64 * max_brightness as u64 + 50) / 100;
+        brightness as u32
+    }
+}
+
+/// `brightness` changed by `delta` and clamped to `range`, as `(min, max)`.
+pub fn adjusted(brightness: u32, delta: i32, (min, max): (u32, u32)) -> u32 {
+    (brightness as i64 + delta as i64).clamp(min as i64, max as i64) as u32
+}
+
+/// Index of the entry selected at startup: the one with the monitor that was selected last, then
+/// the one with the primary display, or the first if neither is there.
+pub fn initial_index(groups: &[MonitorGroup], monitors: &[impl BrightnessTarget]) -> usize {
+    selected_index(groups, monitors, settings::selected_monitor().as_deref())
+}
+
+/// Like `initial_index`, with `selected` as the name of the monitor that was selected last.
+pub fn selected_index(
+    groups: &[MonitorGroup],
+    monitors: &[impl BrightnessTarget],
+    selected: Option<&str>,
+) -> usize {
+    preferred_index(
+        groups,
+        |i| selected == Some(monitors[i].get_name()),
+        |i| monitors[i].is_primary(),
+    )
+}
+
+fn preferred_index(
+    groups: &[MonitorGroup],
+    was_selected: impl Fn(usize) -> bool,
+    is_primary: impl Fn(usize) -> bool,
+) -> usize {
+    let position = |matches: &dyn Fn(usize) -> bool| {
+        (groups.iter()).position(|group| group.members.iter().any(|&i| matches(i)))
+    };
+    position(&was_selected)
+        .or_else(|| position(&is_primary))
+        .unwrap_or(0)
+}
+
+/// Build the selector entries. If `group_duplicated` is set, monitors that show the same display
+/// (e.g. when duplicating displays) share an entry; otherwise each monitor has its own. The entries
+/// are in the order from the settings, then from left to right. Their indices are used everywhere
+/// else, so the controller and the pipe follow the same order.
+pub fn group_monitors(monitors: &[Monitor], group_duplicated: bool) -> Vec<MonitorGroup> {
+    let mut groups: Vec<MonitorGroup> = Vec::new();
+    for (i, monitor) in monitors.iter().enumerate() {
+        let existing = groups.iter_mut().find(|group| {
+            group_duplicated
+                && monitors[group.members[0]].display_index() == monitor.display_index()
+        });
+        match existing {
+            Some(group) => group.members.push(i),
+            None => groups.push(MonitorGroup { members: vec![i] }),
+        }
+    }
+    sort_groups(&mut groups, |i| {
+        let position = settings::monitor_position(monitors[i].get_name());
+        let bounds = monitors[i].bounds().map(|bounds| (bounds.left, bounds.top));
+        (position, bounds)
+    });
+    groups
+}
+
+/// Sort `groups` by the position of their members from the settings, then by the top left corner of
+/// the display of their members, both from `key`. Groups without either keep the enumeration order
+/// after the others.
+fn sort_groups(
+    groups: &mut [MonitorGroup],
+    key: impl Fn(usize) -> (Option<u32>, Option<(i32, i32)>),
+) {
+    // `None` sorts first, so both are sorted as `(is_none, value)`
+    groups.sort_by_cached_key(|group| {
+        let keys = group.members.iter().map(|&i| key(i));
+        let position = keys.clone().filter_map(|(position, _)| position).min();
+        let corner = keys.filter_map(|(_, corner)| corner).min();
+        ((position.is_none(), position), (corner.is_none(), corner))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_ranges_are_returned_as_is() {
+        for max in [50, PERCENT_MAX] {
+            assert_eq!(to_percent(0, max), 0);
+            assert_eq!(to_percent(37, max), 37);
+            assert_eq!(from_percent(37, max), 37);
+        }
+    }
+
+    #[test]
+    fn finer_ranges_are_mapped_to_percent() {
+        assert_eq!(to_percent(0, 1000), 0);
+        assert_eq!(to_percent(1000, 1000), 100);
+        assert_eq!(to_percent(374, 1000), 37);
+        assert_eq!(to_percent(375, 1000), 38);
+        assert_eq!(to_percent(128, 255), 50);
+
+        assert_eq!(from_percent(0, 1000), 0);
+        assert_eq!(from_percent(100, 1000), 1000);
+        assert_eq!(from_percent(50, 255), 128);
+        assert_eq!(from_percent(37, 1000), 370);
+    }
+
+    #[test]
+    fn percentages_are_clamped_to_the_maximum() {
+        assert_eq!(from_percent(100, 50), 50);
+        assert_eq!(from_percent(150, PERCENT_MAX), PERCENT_MAX);
+        assert_eq!(from_percent(150, 1000), 1000);
+    }
+
+    #[test]
+    fn adjusted_within_range() {
+        assert_eq!(adjusted(50, 10, (0, 100)), 60);
+        assert_eq!(adjusted(50, -5, (0, 100)), 45);
+        assert_eq!(adjusted(95, 10, (0, 100)), 100);
+        assert_eq!(adjusted(3, -5, (0, 100)), 0);
+        assert_eq!(adjusted(30, -20, (20, 80)), 20);
+    }
+
+    #[test]
+    fn primary_entry() {
+        let groups = [
+            MonitorGroup { members: vec![0] },
+            MonitorGroup {
+                members: vec![1, 2],
+            },
+        ];
+        assert_eq!(preferred_index(&groups, |_| false, |i| i == 2), 1);
+        assert_eq!(preferred_index(&groups, |_| false, |i| i == 0), 0);
+        assert_eq!(preferred_index(&groups, |_| false, |_| false), 0);
+        assert_eq!(preferred_index(&[], |_| true, |_| true), 0);
+    }
+
+    #[test]
+    fn last_selected_entry_first() {
+        let groups = [
+            MonitorGroup { members: vec![0] },
+            MonitorGroup {
+                members: vec![1, 2],
+            },
+        ];
+        assert_eq!(preferred_index(&groups, |i| i == 1, |i| i == 0), 1);
+        // Gone since, e.g. unplugged
+        assert_eq!(preferred_index(&groups, |i| i == 3, |i| i == 2), 1);
+        assert_eq!(preferred_index(&groups, |i| i == 3, |_| false), 0);
+    }
+
+    #[test]
+    fn sorted_by_settings_then_layout() {
+        let mut groups: Vec<_> = (0..4).map(|i| MonitorGroup { members: vec![i] }).collect();
+        groups.push(MonitorGroup {
+            members: vec![4, 5],
+        });
+        let corners = [
+            None,
+            Some((1920, 0)),
+            Some((-1920, 0)),
+            Some((0, 0)),
+            None,
+            None,
+        ];
+        let positions = [None, None, None, None, None, Some(0)];
+        sort_groups(&mut groups, |i| (positions[i], corners[i]));
+        let order: Vec<_> = groups.iter().map(|group| group.members[0]).collect();
+        assert_eq!(order, [4, 2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn percent_round_trip() {
+        for max in [101, 255, 1000, 65535] {
+            for percent in 0..=100 {
+                assert_eq!(to_percent(from_percent(percent, max), max), percent);
+            }
+        }
+    }
+}
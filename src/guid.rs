@@ -8,3 +8,10 @@ pub const ICON_GUID: GUID = GUID::from_u128(0x128b0e05_5bce_4f87_8ea4_2f79b27970
 
 #[cfg(not(debug_assertions))]
 pub const ICON_GUID: GUID = GUID::from_u128(0x098588ea_adc5_4b17_a2c0_a5a2bb92fa4f);
+
+/// The kind of build, which decides the `ICON_GUID`.
+#[cfg(debug_assertions)]
+pub const BUILD: &str = "debug";
+
+#[cfg(not(debug_assertions))]
+pub const BUILD: &str = "release";
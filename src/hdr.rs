@@ -0,0 +1,194 @@
+//! SDR content brightness of displays in HDR mode. With HDR on, Windows draws SDR content at a
+//! white level of its own, which is what the "SDR content brightness" slider of the display
+//! settings changes. The monitor's brightness through DDC/CI often has little visible effect then.
+
+use windows::{
+    core::{Error, Result},
+    Win32::{
+        Devices::Display::{
+            DisplayConfigGetDeviceInfo, DisplayConfigSetDeviceInfo, GetDisplayConfigBufferSizes,
+            QueryDisplayConfig, DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+            DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+            DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_HEADER,
+            DISPLAYCONFIG_DEVICE_INFO_TYPE, DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO,
+            DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_SDR_WHITE_LEVEL,
+            DISPLAYCONFIG_SOURCE_DEVICE_NAME,
+        },
+        Foundation::{LUID, WIN32_ERROR},
+        Graphics::Gdi::{
+            GetMonitorInfoW, HMONITOR, MONITORINFO, MONITORINFOEXW, QDC_ONLY_ACTIVE_PATHS,
+        },
+    },
+};
+
+/// SDR white levels in nits that the slider of the display settings spans, from 0 to 100.
+pub const SDR_WHITE_LEVEL_RANGE: (f32, f32) = (80.0, 480.0);
+
+/// Request type of `DisplayConfigSetDeviceInfo` for setting the SDR white level. Not in the SDK
+/// headers but what the display settings use.
+const DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL: DISPLAYCONFIG_DEVICE_INFO_TYPE =
+    DISPLAYCONFIG_DEVICE_INFO_TYPE(-18);
+
+/// Packet of `DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL`.
+#[repr(C)]
+struct SetSdrWhiteLevel {
+    header: DISPLAYCONFIG_DEVICE_INFO_HEADER,
+    /// In the units of `DISPLAYCONFIG_SDR_WHITE_LEVEL`.
+    sdr_white_level: u32,
+    /// Whether this is the last value of a series, e.g. when releasing a slider.
+    final_value: u8,
+}
+
+/// The target of a display path, i.e. a monitor, as known to the display configuration API.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DisplayTarget {
+    adapter_id: LUID,
+    id: u32,
+}
+
+impl DisplayTarget {
+    /// The target showing the display `monitor_handle`, matched through its GDI device name, e.g.
+    /// `\\.\DISPLAY1`. `None` if it is not among the active paths.
+    pub fn find(monitor_handle: HMONITOR) -> Option<DisplayTarget> {
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        let info_ptr = &mut info as *mut MONITORINFOEXW as *mut MONITORINFO;
+        if !unsafe { GetMonitorInfoW(monitor_handle, info_ptr) }.as_bool() {
+            return None;
+        }
+
+        active_paths().ok()?.into_iter().find_map(|path| {
+            let mut source_name = DISPLAYCONFIG_SOURCE_DEVICE_NAME {
+                header: header::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>(
+                    DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+                    path.sourceInfo.adapterId,
+                    path.sourceInfo.id,
+                ),
+                ..Default::default()
+            };
+            get_device_info(&mut source_name.header).ok()?;
+            (source_name.viewGdiDeviceName == info.szDevice).then_some(DisplayTarget {
+                adapter_id: path.targetInfo.adapterId,
+                id: path.targetInfo.id,
+            })
+        })
+    }
+
+    /// Whether the display is in HDR mode, which Windows calls advanced color.
+    pub fn is_hdr_enabled(self) -> bool {
+        const ADVANCED_COLOR_ENABLED: u32 = 1 << 1;
+
+        let mut color_info = DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO {
+            header: self.header::<DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO>(
+                DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+            ),
+            ..Default::default()
+        };
+        get_device_info(&mut color_info.header).is_ok()
+            && unsafe { color_info.Anonymous.value } & ADVANCED_COLOR_ENABLED != 0
+    }
+
+    /// The white level of SDR content in nits.
+    pub fn sdr_white_level(self) -> Result<f32> {
+        let mut white_level = DISPLAYCONFIG_SDR_WHITE_LEVEL {
+            header: self.header::<DISPLAYCONFIG_SDR_WHITE_LEVEL>(
+                DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+            ),
+            ..Default::default()
+        };
+        get_device_info(&mut white_level.header)?;
+        Ok(nits_from_white_level(white_level.SDRWhiteLevel))
+    }
+
+    /// Set the white level of SDR content to `nits`, clamped to `SDR_WHITE_LEVEL_RANGE`.
+    pub fn set_sdr_white_level(self, nits: f32) -> Result<()> {
+        let (min_nits, max_nits) = SDR_WHITE_LEVEL_RANGE;
+        let packet = SetSdrWhiteLevel {
+            header: self.header::<SetSdrWhiteLevel>(DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL),
+            sdr_white_level: white_level_from_nits(nits.clamp(min_nits, max_nits)),
+            final_value: 1,
+        };
+        check(unsafe { DisplayConfigSetDeviceInfo(&packet.header) })
+    }
+
+    fn header<T>(
+        self,
+        request: DISPLAYCONFIG_DEVICE_INFO_TYPE,
+    ) -> DISPLAYCONFIG_DEVICE_INFO_HEADER {
+        header::<T>(request, self.adapter_id, self.id)
+    }
+}
+
+/// Header of a packet of type `T` for `request` about `id` on the adapter `adapter_id`.
+fn header<T>(
+    request: DISPLAYCONFIG_DEVICE_INFO_TYPE,
+    adapter_id: LUID,
+    id: u32,
+) -> DISPLAYCONFIG_DEVICE_INFO_HEADER {
+    DISPLAYCONFIG_DEVICE_INFO_HEADER {
+        r#type: request,
+        size: std::mem::size_of::<T>() as u32,
+        adapterId: adapter_id,
+        id,
+    }
+}
+
+/// The paths from the sources of the desktop to the monitors that are currently in use.
+fn active_paths() -> Result<Vec<DISPLAYCONFIG_PATH_INFO>> {
+    let (mut num_paths, mut num_modes) = (0, 0);
+    check(unsafe {
+        GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut num_paths, &mut num_modes)
+    })?;
+    let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); num_paths as usize];
+    let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); num_modes as usize];
+    check(unsafe {
+        QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut num_paths,
+            paths.as_mut_ptr(),
+            &mut num_modes,
+            modes.as_mut_ptr(),
+            std::ptr::null_mut(),
+        )
+    })?;
+    // The counts are updated to the number of entries filled in
+    paths.truncate(num_paths as usize);
+    Ok(paths)
+}
+
+fn get_device_info(header: &mut DISPLAYCONFIG_DEVICE_INFO_HEADER) -> Result<()> {
+    check(unsafe { DisplayConfigGetDeviceInfo(header) })
+}
+
+/// Turn the Win32 error code returned by the display configuration functions into a `Result`.
+fn check(code: i32) -> Result<()> {
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(Error::from(WIN32_ERROR(code as u32)))
+    }
+}
+
+/// `DISPLAYCONFIG_SDR_WHITE_LEVEL` is in thousandths of 80 nits, the white level of sRGB.
+fn nits_from_white_level(white_level: u32) -> f32 {
+    white_level as f32 * 80.0 / 1000.0
+}
+
+/// The inverse of `nits_from_white_level`.
+fn white_level_from_nits(nits: f32) -> u32 {
+    (nits * 1000.0 / 80.0).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_level_units() {
+        assert_eq!(nits_from_white_level(1000), 80.0);
+        assert_eq!(nits_from_white_level(6000), 480.0);
+        assert_eq!(white_level_from_nits(80.0), 1000);
+        assert_eq!(white_level_from_nits(240.0), 3000);
+        assert_eq!(white_level_from_nits(nits_from_white_level(2550)), 2550);
+    }
+}
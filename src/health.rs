@@ -0,0 +1,123 @@
+//! Tracking of monitors whose DDC/CI writes keep failing. The controller skips them for a while so
+//! that the retries of one broken monitor do not hold up every other one.
+
+use std::time::{Duration, Instant};
+
+use windows::{
+    core::HRESULT,
+    Win32::Foundation::{
+        ERROR_GRAPHICS_INVALID_PHYSICAL_MONITOR_HANDLE, ERROR_GRAPHICS_MONITOR_NO_LONGER_EXISTS,
+    },
+};
+
+/// Whether a monitor is answering. A failed write puts it in a cooldown after which the controller
+/// probes it again, whether or not there is a new brightness for it; a successful write makes it
+/// healthy.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MonitorHealth {
+    /// When the monitor may be probed again, or `None` if it is healthy.
+    retry_at: Option<Instant>,
+    /// Whether any write succeeded so far.
+    answered: bool,
+    /// Writes that failed since the last one that succeeded.
+    failures: u32,
+}
+
+impl MonitorHealth {
+    /// How long a failing monitor is skipped before probing it again.
+    #[cfg(not(test))]
+    pub const COOLDOWN: Duration = Duration::from_secs(30);
+    /// Short enough for the tests of the controller thread to wait for a probe.
+    #[cfg(test)]
+    pub const COOLDOWN: Duration = Duration::from_millis(100);
+
+    pub fn is_healthy(&self) -> bool {
+        self.retry_at.is_none()
+    }
+
+    /// Whether the monitor should be written to at `now`: either it is healthy or it is due for a
+    /// probe.
+    pub fn is_available(&self, now: Instant) -> bool {
+        match self.retry_at {
+            Some(retry_at) => now >= retry_at,
+            None => true,
+        }
+    }
+
+    /// When the cooldown of a failing monitor is over and it is due for a probe. `None` if it is
+    /// healthy.
+    pub fn probe_at(&self) -> Option<Instant> {
+        self.retry_at
+    }
+
+    /// Whether every write so far failed. A monitor that is there but never answers usually has
+    /// DDC/CI turned off in its on-screen menu.
+    pub fn never_answered(&self) -> bool {
+        !self.answered
+    }
+
+    /// How many writes in a row failed, the probes included. 0 while healthy.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record the outcome of a write made at `now`.
+    pub fn record(&mut self, success: bool, now: Instant) {
+        self.retry_at = (!success).then(|| now + MonitorHealth::COOLDOWN);
+        self.answered |= success;
+        self.failures = if success { 0 } else { self.failures + 1 };
+    }
+}
+
+/// Whether a write failed with `code` because the monitor is not there anymore, e.g. it was
+/// unplugged, rather than because it did not answer.
+pub fn monitor_gone(code: HRESULT) -> bool {
+    code == ERROR_GRAPHICS_INVALID_PHYSICAL_MONITOR_HANDLE
+        || code == ERROR_GRAPHICS_MONITOR_NO_LONGER_EXISTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cooldown_and_recovery() {
+        let start = Instant::now();
+        let mut health = MonitorHealth::default();
+        assert!(health.is_healthy());
+        assert!(health.is_available(start));
+
+        health.record(false, start);
+        assert!(health.never_answered());
+        assert!(!health.is_healthy());
+        assert!(!health.is_available(start));
+        assert!(!health.is_available(start + MonitorHealth::COOLDOWN / 2));
+        assert_eq!(health.probe_at(), Some(start + MonitorHealth::COOLDOWN));
+
+        // A failed probe starts another cooldown
+        let probe = start + MonitorHealth::COOLDOWN;
+        assert!(health.is_available(probe));
+        health.record(false, probe);
+        assert!(!health.is_available(probe + MonitorHealth::COOLDOWN / 2));
+        assert_eq!(health.failures(), 2);
+
+        let probe = probe + MonitorHealth::COOLDOWN;
+        health.record(true, probe);
+        assert!(health.is_healthy());
+        assert_eq!(health.probe_at(), None);
+        assert!(health.is_available(probe));
+        assert!(!health.never_answered());
+        assert_eq!(health.failures(), 0);
+
+        // Still answered before, even if failing again
+        health.record(false, probe);
+        assert!(!health.never_answered());
+    }
+
+    #[test]
+    fn gone_monitors() {
+        assert!(monitor_gone(ERROR_GRAPHICS_MONITOR_NO_LONGER_EXISTS));
+        assert!(monitor_gone(ERROR_GRAPHICS_INVALID_PHYSICAL_MONITOR_HANDLE));
+        assert!(!monitor_gone(HRESULT(0x80070005u32 as i32)));
+    }
+}
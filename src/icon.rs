@@ -1,31 +1,60 @@
-use std::io::Write;
+use std::time::{Duration, Instant};
+
 use windows::{
-    core::{Result, PCSTR},
+    core::PCSTR,
     Win32::{
-        Foundation::HWND,
+        Foundation::{BOOL, HWND},
+        Graphics::Gdi::{CreateBitmap, DeleteObject, HBITMAP},
         System::LibraryLoader::GetModuleHandleA,
         UI::{
             Shell::{
-                Shell_NotifyIconA, NIF_GUID, NIF_ICON, NIF_MESSAGE, NIF_SHOWTIP, NIF_TIP, NIM_ADD,
-                NIM_DELETE, NIM_MODIFY, NIM_SETVERSION, NOTIFYICONDATAA, NOTIFYICONDATAA_0,
-                NOTIFYICON_VERSION_4,
+                Shell_NotifyIconW, NIF_GUID, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_SHOWTIP, NIF_TIP,
+                NIIF_INFO, NIIF_NOSOUND, NIM_ADD, NIM_DELETE, NIM_MODIFY, NIM_SETVERSION,
+                NOTIFYICONDATAW, NOTIFYICONDATAW_0, NOTIFYICON_VERSION_4,
+            },
+            WindowsAndMessaging::{
+                CopyIcon, CreateIconIndirect, DestroyIcon, LoadIconW, LoadImageA, HICON, ICONINFO,
+                IDI_APPLICATION, IMAGE_ICON, LR_DEFAULTSIZE, WM_APP,
             },
-            WindowsAndMessaging::{LoadImageA, HICON, IMAGE_ICON, LR_DEFAULTSIZE, WM_APP},
         },
     },
 };
 
+use crate::{
+    error::{AppError, Result},
+    priority::ChangeSource,
+    strings::{strings, Strings},
+    theme::Theme,
+    xaml::image::{ICON, IMAGE_HEIGHT, IMAGE_WIDTH},
+};
+
 const ICON_RESOURCE: PCSTR = PCSTR(201 as *mut u8);
 
-pub struct NotificationIcon(NOTIFYICONDATAA);
+pub struct NotificationIcon {
+    data: NOTIFYICONDATAW,
+    /// When `notify_brightness` last showed a balloon.
+    last_notification: Option<Instant>,
+    /// Whether the icon was already taken off the notification area with `remove`.
+    removed: bool,
+    /// The brightness and its source last passed to `modify_tooltip`.
+    percent: Option<(u32, ChangeSource)>,
+    /// Whether the tooltip says that the brightness is locked.
+    locked: bool,
+    /// Whether the tooltip says that the automatic changes are paused.
+    paused: bool,
+    /// The percentage last passed to `show_level`, while the icon shows it.
+    level: Option<u32>,
+    /// The taskbar theme the level icon is drawn for.
+    theme: Theme,
+}
 
 impl Drop for NotificationIcon {
     fn drop(&mut self) {
-        self.0.uFlags = NIF_GUID;
+        if let Err(error) = self.remove() {
+            crate::report_drop_error("remove the notification icon", &error);
+        }
         unsafe {
-            if !Shell_NotifyIconA(NIM_DELETE, &self.0).as_bool() {
-                panic!("{:?}", windows::core::Error::from_win32());
-            }
+            DestroyIcon(self.data.hIcon);
         }
     }
 }
@@ -33,29 +62,29 @@ impl Drop for NotificationIcon {
 impl NotificationIcon {
     /// Message ID for the notification icon callback.
     pub const MESSAGE: u32 = WM_APP + 1;
+    const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(2);
 
     /// Create a new `NotificationIcon` for the given `window`.
     pub fn new(window: HWND) -> Result<Self> {
-        let icon = unsafe {
-            LoadImageA(
-                GetModuleHandleA(PCSTR::default())?,
-                ICON_RESOURCE,
-                IMAGE_ICON,
-                0,
-                0,
-                LR_DEFAULTSIZE,
-            )
-        }?;
-        // icon.0 is already checked for nulls by the `LoadImageA` call
-        let icon = HICON(icon.0);
-
-        let mut nid = NOTIFYICONDATAA {
-            cbSize: std::mem::size_of::<NOTIFYICONDATAA>() as u32,
+        let icon = match load_icon_resource() {
+            Ok(icon) => icon,
+            Err(e) => {
+                // E.g. a build without the resources; a generic icon still gives access to the app
+                crate::debug_log(&format!(
+                    "Using the generic icon, loading our own failed: {}",
+                    e
+                ));
+                unsafe { CopyIcon(LoadIconW(None, IDI_APPLICATION)?)? }
+            }
+        };
+
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
             hWnd: window,
             uFlags: NIF_ICON | NIF_MESSAGE | NIF_GUID,
             uCallbackMessage: NotificationIcon::MESSAGE,
             hIcon: icon,
-            Anonymous: NOTIFYICONDATAA_0 {
+            Anonymous: NOTIFYICONDATAW_0 {
                 uVersion: NOTIFYICON_VERSION_4,
             },
             guidItem: crate::ICON_GUID,
@@ -63,33 +92,353 @@ impl NotificationIcon {
         };
 
         unsafe {
-            if Shell_NotifyIconA(NIM_ADD, &nid).as_bool()
-                && Shell_NotifyIconA(NIM_SETVERSION, &nid).as_bool()
+            if Shell_NotifyIconW(NIM_ADD, &nid).as_bool()
+                && Shell_NotifyIconW(NIM_SETVERSION, &nid).as_bool()
             {
                 // Set flags for `modify_tooltip`
                 nid.uFlags = NIF_TIP | NIF_SHOWTIP | NIF_GUID;
-                return Ok(NotificationIcon(nid));
+                return Ok(NotificationIcon {
+                    data: nid,
+                    last_notification: None,
+                    removed: false,
+                    percent: None,
+                    locked: false,
+                    paused: false,
+                    level: None,
+                    theme: Theme::taskbar(),
+                });
             }
         }
-        Err(windows::core::Error::from_win32())
+        Err(AppError::NotificationIcon)
     }
 
-    /// Modifies the notification icon's tooltip that is shown when highlighted by the cursor.
-    pub fn modify_tooltip(&mut self, brightness: u32) -> Result<()> {
-        let data: &mut [u8; 128] = unsafe {
-            // SAFETY: CHAR is just a #[repr(transparent)] u8
-            std::mem::transmute(&mut self.0.szTip)
-        };
-        if write!(data.as_mut_slice(), "Brightness: {}\0", brightness).is_err() {
-            return Err(windows::core::Error::from_win32());
+    /// Modifies the notification icon's tooltip that is shown when highlighted by the cursor. The
+    /// brightness is given in percent, along with what changed it.
+    pub fn modify_tooltip(&mut self, percent: u32, source: ChangeSource) -> Result<()> {
+        self.percent = Some((percent, source));
+        let mut text = brightness_text(percent, source);
+        if self.locked {
+            text.push_str(strings().locked_suffix);
         }
+        if self.paused {
+            text.push_str(strings().paused_suffix);
+        }
+        self.set_tooltip(&text)
+    }
+
+    /// Says in the tooltip that the monitors are still being read, until the first
+    /// `modify_tooltip`.
+    pub fn show_loading(&mut self) -> Result<()> {
+        self.set_tooltip(strings().loading_monitors)
+    }
+
+    /// Marks the brightness in the tooltip as locked, or not, from now on.
+    pub fn set_locked(&mut self, locked: bool) -> Result<()> {
+        self.locked = locked;
+        match self.percent {
+            Some((percent, source)) => self.modify_tooltip(percent, source),
+            None => Ok(()),
+        }
+    }
+
+    /// Marks the automatic changes in the tooltip as paused, or not, from now on.
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.paused = paused;
+        match self.percent {
+            Some((percent, source)) => self.modify_tooltip(percent, source),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the icon with the brightness icon of the flyout, with a bar along the bottom that
+    /// is filled up to `percent`. It is drawn in black on a light taskbar. The previous icon is
+    /// destroyed.
+    pub fn show_level(&mut self, percent: u32) -> Result<()> {
+        let icon = create_level_icon(percent, self.theme)?;
+        self.level = Some(percent);
+        let previous = std::mem::replace(&mut self.data.hIcon, icon);
 
+        // Only for this call, like `show_balloon`
+        let flags = self.data.uFlags;
+        self.data.uFlags |= NIF_ICON;
+        let result = self.modify();
+        self.data.uFlags = flags;
         unsafe {
-            if Shell_NotifyIconA(NIM_MODIFY, &self.0).as_bool() {
+            DestroyIcon(previous);
+        }
+        result
+    }
+
+    /// Redraws the level icon, if shown, for a change of the taskbar theme.
+    pub fn set_theme(&mut self, theme: Theme) -> Result<()> {
+        if theme == self.theme {
+            return Ok(());
+        }
+        self.theme = theme;
+        match self.level {
+            Some(percent) => self.show_level(percent),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the icon off the notification area ahead of the drop, e.g. when the session ends and
+    /// the process may be terminated without dropping it. Does nothing if already removed; the
+    /// other methods fail afterwards.
+    pub fn remove(&mut self) -> Result<()> {
+        if self.removed {
+            return Ok(());
+        }
+        let mut data = self.data;
+        data.uFlags = NIF_GUID;
+        unsafe {
+            if !Shell_NotifyIconW(NIM_DELETE, &data).as_bool() {
+                return Err(AppError::NotificationIcon);
+            }
+        }
+        self.removed = true;
+        Ok(())
+    }
+
+    /// Replaces the tooltip with `message` until the next `modify_tooltip` call.
+    pub fn show_message(&mut self, message: &str) -> Result<()> {
+        self.set_tooltip(message)
+    }
+
+    /// Show a balloon with the new brightness `percent` of the monitor called `name`, changed by
+    /// `source`. Rate limited to one balloon per `NotificationIcon::NOTIFICATION_INTERVAL` so rapid
+    /// changes do not pile up; skipped changes are still shown in the tooltip.
+    pub fn notify_brightness(
+        &mut self,
+        name: &str,
+        percent: u32,
+        source: ChangeSource,
+    ) -> Result<()> {
+        let now = Instant::now();
+        let too_soon = self
+            .last_notification
+            .is_some_and(|last| now - last < NotificationIcon::NOTIFICATION_INTERVAL);
+        if too_soon {
+            return Ok(());
+        }
+        self.last_notification = Some(now);
+        self.show_balloon(name, &brightness_text(percent, source))
+    }
+
+    /// Show a balloon notification next to the icon. The tooltip is left as is.
+    pub fn show_balloon(&mut self, title: &str, text: &str) -> Result<()> {
+        copy_to_wide(&mut self.data.szInfoTitle, title);
+        copy_to_wide(&mut self.data.szInfo, text);
+        self.data.dwInfoFlags = NIIF_INFO | NIIF_NOSOUND;
+
+        // Only for this call; the following tooltip updates would otherwise show it again
+        let flags = self.data.uFlags;
+        self.data.uFlags |= NIF_INFO;
+        let result = self.modify();
+        self.data.uFlags = flags;
+        result
+    }
+
+    fn set_tooltip(&mut self, text: &str) -> Result<()> {
+        copy_to_wide(&mut self.data.szTip, text);
+        self.modify()
+    }
+
+    fn modify(&self) -> Result<()> {
+        unsafe {
+            if Shell_NotifyIconW(NIM_MODIFY, &self.data).as_bool() {
                 Ok(())
             } else {
-                Err(windows::core::Error::from_win32())
+                Err(AppError::NotificationIcon)
             }
         }
     }
 }
+
+/// The brightness `percent` as shown in the tooltip and balloons, naming `source` if
+/// `SHOW_CHANGE_SOURCE`.
+fn brightness_text(percent: u32, source: ChangeSource) -> String {
+    let mut text = Strings::format(strings().brightness_tooltip, percent);
+    if crate::SHOW_CHANGE_SOURCE {
+        text.push_str(source.suffix());
+    }
+    text
+}
+
+/// The icon embedded as a resource of the executable.
+fn load_icon_resource() -> windows::core::Result<HICON> {
+    let icon = unsafe {
+        LoadImageA(
+            GetModuleHandleA(PCSTR::default())?,
+            ICON_RESOURCE,
+            IMAGE_ICON,
+            0,
+            0,
+            LR_DEFAULTSIZE,
+        )
+    }?;
+    // icon.0 is already checked for nulls by the `LoadImageA` call
+    Ok(HICON(icon.0))
+}
+
+/// Copy `text` as a null-terminated wide string so that text in any language shows up as is. Text
+/// that does not fit, e.g. with a long monitor name, is cut off after a whole character and ends
+/// with an ellipsis instead.
+fn copy_to_wide(buffer: &mut [u16], text: &str) {
+    const ELLIPSIS: char = '\u{2026}';
+
+    let max_len = buffer.len() - 1; // Room for the null terminator
+    let fits = text.encode_utf16().count() <= max_len;
+    let mut len = 0;
+    for c in text.chars() {
+        let mut units = [0; 2];
+        let units = c.encode_utf16(&mut units);
+        // Keeps a surrogate pair together, and room for the ellipsis if cut off
+        let reserved = if fits { 0 } else { ELLIPSIS.len_utf16() };
+        if len + units.len() + reserved > max_len {
+            break;
+        }
+        buffer[len..len + units.len()].copy_from_slice(units);
+        len += units.len();
+    }
+    if !fits {
+        ELLIPSIS.encode_utf16(&mut buffer[len..]);
+        len += ELLIPSIS.len_utf16();
+    }
+    buffer[len] = 0;
+}
+
+/// Pixels of `ICON` with a level bar filled up to `percent`, unpremultiplied as icons expect. Both
+/// are white, or black for the light `theme`.
+fn level_icon_pixels(percent: u32, theme: Theme) -> Vec<u8> {
+    /// Height of the bar along the bottom.
+    const BAR_HEIGHT: usize = 2;
+    /// Opacity of the part of the bar that is not filled.
+    const TRACK_ALPHA: u8 = 0x40;
+
+    let (width, height) = (IMAGE_WIDTH as usize, IMAGE_HEIGHT as usize);
+    let color = match theme {
+        Theme::Light => 0,
+        Theme::Dark => 0xff,
+    };
+    let filled = (width * percent.min(100) as usize + 50) / 100;
+    let mut pixels = ICON.to_vec();
+    for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        if y >= height - BAR_HEIGHT {
+            let alpha = if x < filled { 0xff } else { TRACK_ALPHA };
+            pixel.copy_from_slice(&[color, color, color, alpha]);
+        } else if pixel[3] != 0 {
+            // White, premultiplied by the alpha, unless black
+            pixel[..3].fill(color);
+        }
+    }
+    pixels
+}
+
+fn create_level_icon(percent: u32, theme: Theme) -> windows::core::Result<HICON> {
+    let pixels = level_icon_pixels(percent, theme);
+    // Only the alpha of the color bitmap is used; the mask just needs to exist
+    let mask = vec![0u8; (IMAGE_WIDTH * IMAGE_HEIGHT / 8) as usize];
+    unsafe {
+        let color = CreateBitmap(IMAGE_WIDTH, IMAGE_HEIGHT, 1, 32, pixels.as_ptr().cast());
+        let mask = CreateBitmap(IMAGE_WIDTH, IMAGE_HEIGHT, 1, 1, mask.as_ptr().cast());
+        let icon = if color.is_invalid() || mask.is_invalid() {
+            Err(windows::core::Error::from_win32())
+        } else {
+            let info = ICONINFO {
+                fIcon: BOOL(1),
+                hbmMask: mask,
+                hbmColor: color,
+                ..Default::default()
+            };
+            CreateIconIndirect(&info)
+        };
+        // The icon has its own copies of the bitmaps
+        delete_bitmap(color);
+        delete_bitmap(mask);
+        icon
+    }
+}
+
+unsafe fn delete_bitmap(bitmap: HBITMAP) {
+    if !bitmap.is_invalid() {
+        DeleteObject(bitmap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_text_is_cut_off() {
+        let mut buffer = [0xffffu16; 8];
+        copy_to_wide(&mut buffer, "Brightness");
+        let expected: Vec<u16> = "Bright\u{2026}\0".encode_utf16().collect();
+        assert_eq!(buffer[..], expected[..]);
+
+        // Exactly fits, with the terminator in the last element
+        copy_to_wide(&mut buffer, "Monitor");
+        let expected: Vec<u16> = "Monitor\0".encode_utf16().collect();
+        assert_eq!(buffer[..], expected[..]);
+
+        // A character outside the BMP is not split
+        copy_to_wide(&mut buffer, "ab\u{1F506}cdefg");
+        let expected: Vec<u16> = "ab\u{1F506}cd\u{2026}\0".encode_utf16().collect();
+        assert_eq!(buffer[..], expected[..]);
+        copy_to_wide(&mut buffer, "abcde\u{1F506}fg");
+        let expected: Vec<u16> = "abcde\u{2026}\0".encode_utf16().collect();
+        assert_eq!(buffer[..7], expected[..]);
+    }
+
+    #[test]
+    fn tooltip_with_long_name() {
+        let mut data = NOTIFYICONDATAW::default();
+        let text = "Generic PnP Monitor ".repeat(10);
+        copy_to_wide(&mut data.szTip, &text);
+        let len = data.szTip.iter().position(|&c| c == 0).unwrap();
+        assert_eq!(len, data.szTip.len() - 1);
+        assert_eq!(data.szTip[len - 1], '\u{2026}' as u16);
+    }
+
+    #[test]
+    fn level_bar() {
+        let alpha_at = |pixels: &[u8], x: i32| {
+            let bottom_row = (IMAGE_HEIGHT - 1) * IMAGE_WIDTH;
+            pixels[((bottom_row + x) * 4 + 3) as usize]
+        };
+        let half = level_icon_pixels(50, Theme::Dark);
+        assert_eq!(alpha_at(&half, 0), 0xff);
+        assert_eq!(alpha_at(&half, IMAGE_WIDTH / 2 - 1), 0xff);
+        assert_eq!(alpha_at(&half, IMAGE_WIDTH / 2), 0x40);
+
+        let empty = level_icon_pixels(0, Theme::Dark);
+        assert_eq!(alpha_at(&empty, 0), 0x40);
+        let full = level_icon_pixels(150, Theme::Dark);
+        assert_eq!(alpha_at(&full, IMAGE_WIDTH - 1), 0xff);
+    }
+
+    #[test]
+    fn level_icon_themes() {
+        let visible = |pixels: &[u8]| {
+            (pixels.chunks_exact(4))
+                .filter(|pixel| pixel[3] != 0)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect::<Vec<_>>()
+        };
+        let dark = level_icon_pixels(50, Theme::Dark);
+        assert!(visible(&dark).iter().all(|&rgb| rgb == [0xff; 3]));
+        // Black on a light taskbar, with the same shape
+        let light = level_icon_pixels(50, Theme::Light);
+        assert!(visible(&light).iter().all(|&rgb| rgb == [0; 3]));
+        let alpha = |pixels: &[u8]| {
+            pixels
+                .iter()
+                .skip(3)
+                .step_by(4)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(alpha(&dark), alpha(&light));
+    }
+}
@@ -0,0 +1,61 @@
+//! Keeps a single instance of the app running per session. Launching it again shows the flyout of
+//! the running instance instead of adding a second notification icon with the same GUID.
+
+use windows::{
+    core::{Result, PCSTR},
+    Win32::{
+        Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, LPARAM, WPARAM},
+        System::Threading::CreateMutexA,
+        UI::WindowsAndMessaging::{
+            AllowSetForegroundWindow, FindWindowA, GetWindowThreadProcessId, PostMessageA,
+        },
+    },
+};
+
+use crate::window::Window;
+
+/// Local to the session so that each logged on user gets their own instance.
+const MUTEX_NAME: &[u8] = b"Local\\MonitorBrightnessController\0";
+
+/// Marks this process as the running instance until dropped.
+pub struct InstanceLock(HANDLE);
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+impl InstanceLock {
+    /// Become the running instance. Returns `None` if another process already is.
+    pub fn acquire() -> Result<Option<InstanceLock>> {
+        unsafe {
+            let handle = CreateMutexA(std::ptr::null(), false, PCSTR(MUTEX_NAME.as_ptr()))?;
+            if GetLastError() == ERROR_ALREADY_EXISTS {
+                CloseHandle(handle);
+                Ok(None)
+            } else {
+                Ok(Some(InstanceLock(handle)))
+            }
+        }
+    }
+}
+
+/// Ask the running instance to show its flyout. Nothing happens if it has not created its window
+/// yet, e.g. while it is still enumerating the monitors.
+pub fn show_running_instance() {
+    unsafe {
+        let window = FindWindowA(Window::CLASS_NAME, PCSTR::default());
+        if window.0 == 0 {
+            return;
+        }
+        // Only the foreground process, which this one is when launched by the user, can let
+        // another process take the focus
+        let mut process_id = 0;
+        GetWindowThreadProcessId(window, &mut process_id);
+        AllowSetForegroundWindow(process_id);
+        PostMessageA(window, Window::SHOW_FLYOUT, WPARAM(0), LPARAM(0));
+    }
+}
@@ -0,0 +1,138 @@
+//! The brightness keys of keyboards, read through raw input. Windows only applies them to displays
+//! it controls itself, e.g. a laptop's panel, so external monitors are left to this app.
+//!
+//! The keys are not virtual keys and raise no `WM_APPCOMMAND`; keyboards report them as the
+//! Display Brightness Increment and Decrement usages of the HID consumer page.
+
+use windows::{
+    core::Result,
+    Win32::{
+        Foundation::{HWND, LPARAM},
+        UI::Input::{
+            GetRawInputData, RegisterRawInputDevices, HRAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER,
+            RIDEV_INPUTSINK, RID_INPUT, RIM_TYPEHID,
+        },
+    },
+};
+
+/// HID usage page of consumer controls, e.g. media and brightness keys.
+const USAGE_PAGE_CONSUMER: u16 = 0x0C;
+/// HID usage of the collection with the consumer controls.
+const USAGE_CONSUMER_CONTROL: u16 = 0x01;
+/// HID usage of the brightness up key.
+const USAGE_BRIGHTNESS_UP: u16 = 0x6F;
+/// HID usage of the brightness down key.
+const USAGE_BRIGHTNESS_DOWN: u16 = 0x70;
+
+/// Receive the reports of every consumer control in `window` as `WM_INPUT`, also while another app
+/// has the focus.
+pub fn register(window: HWND) -> Result<()> {
+    let device = RAWINPUTDEVICE {
+        usUsagePage: USAGE_PAGE_CONSUMER,
+        usUsage: USAGE_CONSUMER_CONTROL,
+        dwFlags: RIDEV_INPUTSINK,
+        hwndTarget: window,
+    };
+    unsafe {
+        if RegisterRawInputDevices(&[device], std::mem::size_of::<RAWINPUTDEVICE>() as u32)
+            .as_bool()
+        {
+            Ok(())
+        } else {
+            Err(windows::core::Error::from_win32())
+        }
+    }
+}
+
+/// The brightness key pressed in the input of a `WM_INPUT` message, `true` for up. `None` for
+/// other keys and for releasing a key.
+///
+/// # Safety
+///
+/// `lparam` must be that of a `WM_INPUT` message that is being handled.
+pub unsafe fn pressed(lparam: LPARAM) -> Option<bool> {
+    let input = HRAWINPUT(lparam.0);
+    let header_size = std::mem::size_of::<RAWINPUTHEADER>() as u32;
+    let mut size = 0;
+    GetRawInputData(
+        input,
+        RID_INPUT,
+        std::ptr::null_mut(),
+        &mut size,
+        header_size,
+    );
+    // `u64`s for the alignment of `RAWINPUT`
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    let read = GetRawInputData(
+        input,
+        RID_INPUT,
+        buffer.as_mut_ptr() as *mut _,
+        &mut size,
+        header_size,
+    );
+    if read == u32::MAX || read < header_size + 8 {
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(buffer.as_ptr() as *const u8, read as usize);
+    let header = &*(buffer.as_ptr() as *const RAWINPUTHEADER);
+    if header.dwType != RIM_TYPEHID.0 {
+        return None;
+    }
+    // `RAWHID` is the size of each report and their count, followed by the reports
+    let hid = &bytes[header_size as usize..];
+    let report_size = u32::from_ne_bytes(hid[0..4].try_into().unwrap()) as usize;
+    let count = u32::from_ne_bytes(hid[4..8].try_into().unwrap()) as usize;
+    let reports = hid.get(8..8 + report_size * count)?;
+    if report_size == 0 {
+        return None;
+    }
+    reports.chunks(report_size).find_map(key_in_report)
+}
+
+/// The brightness key in a report of a consumer control. The layout of the reports is up to the
+/// device, but keyboards report the usages of the pressed keys as 16-bit numbers after the report
+/// ID, and nothing once they are released. Windows puts the report ID first, 0 for devices without
+/// them. Other bytes are not read as usages, e.g. a report ID of `USAGE_BRIGHTNESS_DOWN`.
+fn key_in_report(report: &[u8]) -> Option<bool> {
+    let usages = report.get(1..)?;
+    usages
+        .chunks_exact(2)
+        .find_map(|pair| match u16::from_le_bytes([pair[0], pair[1]]) {
+            USAGE_BRIGHTNESS_UP => Some(true),
+            USAGE_BRIGHTNESS_DOWN => Some(false),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_brightness_keys() {
+        // Report ID followed by the usage
+        assert_eq!(key_in_report(&[0x02, 0x6F, 0x00]), Some(true));
+        assert_eq!(key_in_report(&[0x02, 0x70, 0x00]), Some(false));
+        // From a device without report IDs, and next to another key
+        assert_eq!(key_in_report(&[0x00, 0x70, 0x00, 0xE9, 0x00]), Some(false));
+        assert_eq!(key_in_report(&[0x01, 0xE9, 0x00, 0x6F, 0x00]), Some(true));
+    }
+
+    #[test]
+    fn ignores_unaligned_bytes() {
+        // Report ID 0x70 with all keys released
+        assert_eq!(key_in_report(&[0x70, 0x00, 0x00]), None);
+        // Bytes that only make up a brightness key across two usages
+        assert_eq!(key_in_report(&[0x01, 0x00, 0x6F, 0x00, 0x00]), None);
+        assert_eq!(key_in_report(&[0x01, 0xE9, 0x70, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn ignores_other_keys_and_releases() {
+        // Volume up
+        assert_eq!(key_in_report(&[0x02, 0xE9, 0x00]), None);
+        // All keys released
+        assert_eq!(key_in_report(&[0x02, 0x00, 0x00]), None);
+        assert_eq!(key_in_report(&[]), None);
+    }
+}
@@ -0,0 +1,30 @@
+//! Control the brightness and other VCP features of external monitors through DDC/CI.
+//!
+//! ```no_run
+//! let mut monitors = monitor_brightness_controller::get_monitors()?;
+//! for monitor in &mut monitors {
+//!     println!("{}: {}", monitor.get_name(), monitor.get_brightness());
+//!     monitor.set_brightness(50)?;
+//! }
+//! # Ok::<(), windows::core::Error>(())
+//! ```
+
+#![warn(missing_docs)]
+
+mod backoff;
+mod calibration;
+mod capabilities;
+mod hdr;
+mod monitor;
+mod snapshot;
+mod technology;
+
+pub use backoff::Backoff;
+pub use calibration::Calibration;
+pub use capabilities::Capabilities;
+pub use monitor::{
+    brightness_to_nits, get_monitors, nits_to_brightness, Monitor, FALLBACK_BRIGHTNESS,
+    VCP_POWER_MODE, VCP_RESTORE_FACTORY_DEFAULTS, VCP_WINDOW_SELECT,
+};
+pub use snapshot::MonitorSnapshot;
+pub use technology::{DisplayTechnology, VCP_DISPLAY_TECHNOLOGY};
@@ -0,0 +1,149 @@
+//! Flashing a colored overlay with the number of a selector entry over its monitors, to show which
+//! physical display an entry is when their names do not tell, e.g. two monitors of the same model.
+
+use std::time::Duration;
+
+use windows::{
+    core::{Result, PCSTR, PCWSTR},
+    Win32::{
+        Foundation::{
+            GetLastError, ERROR_CLASS_ALREADY_EXISTS, HWND, LPARAM, LRESULT, RECT, WPARAM,
+        },
+        Graphics::Gdi::{
+            BeginPaint, CreateFontW, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect,
+            SelectObject, SetBkMode, SetTextColor, CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS,
+            DEFAULT_CHARSET, DT_CENTER, DT_SINGLELINE, DT_VCENTER, FF_DONTCARE, FW_BOLD,
+            OUT_DEFAULT_PRECIS, PAINTSTRUCT, TRANSPARENT,
+        },
+        System::LibraryLoader::GetModuleHandleA,
+        UI::WindowsAndMessaging::{
+            CreateWindowExA, DefWindowProcA, DestroyWindow, GetClientRect, GetWindowLongPtrA,
+            RegisterClassExA, SetLayeredWindowAttributes, SetTimer, SetWindowLongPtrA, ShowWindow,
+            GWLP_USERDATA, LWA_ALPHA, SW_SHOWNOACTIVATE, WM_PAINT, WM_TIMER, WNDCLASSEXA,
+            WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT,
+            WS_POPUP,
+        },
+    },
+};
+
+/// How long the overlay stays.
+const DURATION: Duration = Duration::from_secs(1);
+
+/// Color of the overlay as `0x00BBGGRR`, the blue of the Windows accent by default.
+const COLOR: u32 = 0x00D7_7800;
+/// Color of the number.
+const TEXT_COLOR: u32 = 0x00FF_FFFF;
+/// Opacity of the overlay, from 0 to 255.
+const OPACITY: u8 = 160;
+
+const CLASS_NAME: PCSTR = PCSTR(b"MonitorBrightnessControllerOverlay\0".as_ptr() as *mut u8);
+
+/// Cover each of `bounds`, in virtual screen coordinates, with an overlay showing `number` for
+/// `DURATION`. The overlays do not take the focus or clicks, so the flyout stays usable, and
+/// destroy themselves.
+pub fn flash(bounds: &[RECT], number: usize) -> Result<()> {
+    unsafe extern "system" fn overlay_procedure(
+        hwnd: HWND,
+        umsg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match umsg {
+            WM_PAINT => {
+                let mut paint = PAINTSTRUCT::default();
+                let hdc = BeginPaint(hwnd, &mut paint);
+                let mut rect = RECT::default();
+                GetClientRect(hwnd, &mut rect);
+                let brush = CreateSolidBrush(COLOR);
+                FillRect(hdc, &rect, brush);
+                DeleteObject(brush);
+
+                // The number takes up half of the height of the display
+                let font = CreateFontW(
+                    (rect.bottom - rect.top) / 2,
+                    0,
+                    0,
+                    0,
+                    FW_BOLD as i32,
+                    0,
+                    0,
+                    0,
+                    DEFAULT_CHARSET,
+                    OUT_DEFAULT_PRECIS,
+                    CLIP_DEFAULT_PRECIS,
+                    CLEARTYPE_QUALITY,
+                    FF_DONTCARE,
+                    PCWSTR::default(),
+                );
+                let previous = SelectObject(hdc, font);
+                SetBkMode(hdc, TRANSPARENT);
+                SetTextColor(hdc, TEXT_COLOR);
+                let number = GetWindowLongPtrA(hwnd, GWLP_USERDATA);
+                let text: Vec<u16> = number.to_string().encode_utf16().collect();
+                DrawTextW(
+                    hdc,
+                    &text,
+                    &mut rect,
+                    DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+                );
+                SelectObject(hdc, previous);
+                DeleteObject(font);
+                EndPaint(hwnd, &paint);
+                LRESULT(0)
+            }
+            WM_TIMER => {
+                DestroyWindow(hwnd);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, umsg, wparam, lparam),
+        }
+    }
+
+    let instance = unsafe { GetModuleHandleA(PCSTR::default())? };
+    let wcex = WNDCLASSEXA {
+        cbSize: std::mem::size_of::<WNDCLASSEXA>() as u32,
+        lpfnWndProc: Some(overlay_procedure),
+        hInstance: instance,
+        lpszClassName: CLASS_NAME,
+        ..Default::default()
+    };
+    unsafe {
+        // Registered by the first flash
+        if RegisterClassExA(&wcex) == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+            return Err(windows::core::Error::from_win32());
+        }
+    }
+
+    for rect in bounds {
+        let hwnd = unsafe {
+            CreateWindowExA(
+                WS_EX_TOPMOST
+                    | WS_EX_TOOLWINDOW
+                    | WS_EX_LAYERED
+                    | WS_EX_NOACTIVATE
+                    | WS_EX_TRANSPARENT,
+                CLASS_NAME,
+                None,
+                WS_POPUP,
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                None,
+                None,
+                instance,
+                std::ptr::null(),
+            )
+        };
+        if hwnd.0 == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        unsafe {
+            SetWindowLongPtrA(hwnd, GWLP_USERDATA, number as isize);
+            SetLayeredWindowAttributes(hwnd, 0, OPACITY, LWA_ALPHA);
+            SetTimer(hwnd, 1, DURATION.as_millis() as u32, None);
+            ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+        }
+    }
+    Ok(())
+}
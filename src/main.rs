@@ -1,66 +1,704 @@
 #![windows_subsystem = "windows"]
 
+mod ambient;
+mod automation;
+mod boost;
+mod builtin;
+mod classic;
+mod cli;
+mod config;
+mod controller;
+mod diagnostics;
+mod dialog;
+mod error;
+mod group;
 mod guid;
+mod health;
 mod icon;
-mod monitor;
+mod instance;
+mod keys;
+mod locate;
+mod menu;
+mod pipe;
 mod power;
+mod priority;
+mod settings;
+mod strings;
+mod target;
+mod theme;
+mod throttle;
 mod window;
 mod xaml;
 
 use std::{
-    sync::mpsc::{self, Receiver, TryRecvError},
-    thread,
+    cell::RefCell,
+    ops::Range,
+    process,
+    rc::Rc,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use windows::{
-    core::Result,
+    core::PCSTR,
     Win32::{
-        Foundation::HWND,
-        System::WinRT::{RoInitialize, RO_INIT_SINGLETHREADED},
-        UI::WindowsAndMessaging::{DispatchMessageA, GetMessageA, TranslateMessage, MSG},
+        Foundation::{HWND, LPARAM, WPARAM},
+        System::{
+            Diagnostics::Debug::OutputDebugStringA,
+            WinRT::{RoInitialize, RO_INIT_SINGLETHREADED},
+        },
+        UI::{
+            Input::KeyboardAndMouse::{
+                HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, VIRTUAL_KEY, VK_B, VK_HOME,
+            },
+            WindowsAndMessaging::{
+                DispatchMessageA, GetMessageA, PostMessageA, TranslateMessage, MSG,
+            },
+        },
     },
+    UI::Xaml::Hosting::WindowsXamlManager,
 };
 
+use monitor_brightness_controller::{Calibration, DisplayTechnology, Monitor, MonitorSnapshot};
+
+use ambient::AmbientLight;
+use boost::Boost;
+use classic::ClassicControls;
+use controller::{brightness_controller_loop, RegistrySettings};
+use error::AppError;
+use group::MonitorGroup;
 use guid::ICON_GUID;
 use icon::NotificationIcon;
-use monitor::Monitor;
+use instance::InstanceLock;
+use pipe::PipeServer;
 use power::PowerNotifyHandle;
-use window::Window;
+use priority::ChangeSource;
+use strings::{strings, Strings};
+use window::{Window, WindowKind};
+use xaml::XamlControls;
 
 pub enum BrightnessEvent {
-    Change(usize, u32),
+    /// Set the brightness of the selector entry at the index. Automatic changes are ignored for a
+    /// while after a manual one, see `ChangePriority`.
+    Change(usize, u32, ChangeSource),
+    /// Change the brightness of the selector entry at the index by the percentage of its maximum,
+    /// within its range. Treated as a `Change` to the result from `ChangeSource::Automatic`.
+    Adjust(usize, i32),
+    /// Write the brightness of every monitor again, even where it seems unchanged, e.g. after the
+    /// displays were turned back on. Monitors that do not answer yet are retried on later events.
     Reset,
+    /// Restore the factory defaults of the monitor at the index.
+    FactoryReset(usize),
+    /// Re-read the brightness of the monitor at the index in case it was changed through the
+    /// monitor's own buttons.
+    Refresh(usize),
+    /// Put every monitor that supports it into standby, or turn them back on if `false`.
+    Standby(bool),
+    /// The displays were turned off, e.g. by the power plan. Brightness changes are not written
+    /// until the `Reset` that follows turning them back on, since writes to monitors that are off
+    /// fail only after every retry.
+    DisplaysOff,
+    /// The selector entry at the index was chosen. Its brightness is shown in the tooltip.
+    Select(usize),
+    /// Set every monitor to the default brightness from the settings.
+    ResetAll,
+    /// Set the speaker volume of the monitors of the selector entry at the index that have
+    /// controllable speakers.
+    VolumeChange(usize, u32),
+    /// Set the brightness of the picture with the window number, of `Monitor::get_pictures`, on the
+    /// monitors of the selector entry at the index that show it.
+    PictureBrightnessChange(usize, u8, u32),
+    /// Dim every monitor to the percentage, or restore the brightness from before dimming if
+    /// `None`. Sent while the user is idle and on their next input.
+    IdleDim(Option<u32>),
+    /// Set the selector entry at the index, or every entry if `None`, to the percentage for the
+    /// duration, then restore the brightness from before. Automatic changes and idle dimming leave
+    /// boosted monitors alone; setting one by hand ends its boost.
+    Boost(Option<usize>, u32, Duration),
+    /// The time of a `Boost` is up. Produced by the controller thread itself.
+    BoostEnded,
+    /// The cooldown of a monitor whose writes failed is over, see `MonitorHealth`. Its brightness
+    /// is written again to find out whether it answers now. Produced by the controller thread
+    /// itself.
+    Probe,
+    /// Make the monitors of the selector entry at the index take on the brightness of the primary
+    /// display from now on, or stop if `false`. Remembered in the settings.
+    FollowPrimary(usize, bool),
+    /// The ambient light sensor asks for the percentage. Applied to every monitor as an automatic
+    /// change while auto brightness is on, except to boosted ones and while dimmed.
+    Ambient(u32),
+    /// Turn auto brightness on, which catches up with the last `Ambient` right away, or off. The
+    /// settings are changed by the sender.
+    AutoBrightness(bool),
+    /// Apply the offset from `settings::brightness_offset` to every monitor, in percent of its
+    /// range, on top of its own brightness. The brightness seen everywhere else stays the same.
+    Offset(i32),
+    /// Set every monitor to the brightness of the built-in display, as read by
+    /// `builtin::brightness`, like the user would with the slider.
+    MatchBuiltin,
+    /// The built-in display changed to the percentage. Applied to every monitor as an automatic
+    /// change, except to boosted ones and while dimmed. Sent by `builtin::watch`.
+    BuiltinBrightness(u32),
+    /// A brightness key of a keyboard was pressed, up if `true`. Changes the monitors as set in
+    /// `settings::brightness_keys` like the user would with the slider.
+    BrightnessKey(bool),
+    /// Test every monitor and post the report to the window as `Window::DIAGNOSTICS`.
+    Diagnose,
+    /// Write the pending brightness and the brightness chosen for exiting, then stop the controller
+    /// thread.
+    Exit,
+}
+
+/// A change of the brightness of a selector entry, as passed to a `BrightnessListener`.
+pub struct BrightnessChange {
+    /// Index of the selector entry.
+    pub index: usize,
+    pub brightness: u32,
+    /// The brightness as shown to the user.
+    pub percent: u32,
+    /// Whether the entry is the one chosen in the flyout.
+    pub selected: bool,
+    /// What made the change. Anything other than `ChangeSource::Manual` is external, e.g. the
+    /// monitor's own buttons or a factory reset; the flyout already shows its own changes.
+    pub source: ChangeSource,
+    /// Whether to tell the user that a monitor refused the brightness that was asked for, as
+    /// `settings::write_failures` asks. The change is then `ChangeSource::Monitor` back to the
+    /// brightness it stayed at. Monitors that never accepted any write get
+    /// `Window::DDC_CI_DISABLED` instead.
+    pub rejected: bool,
+}
+
+impl BrightnessChange {
+    /// Whether the change came from somewhere other than the flyout.
+    pub fn external(&self) -> bool {
+        self.source != ChangeSource::Manual
+    }
+}
+
+/// Notified by the controller thread of every brightness change.
+pub type BrightnessListener = Box<dyn FnMut(&BrightnessChange) + Send>;
+
+/// Posts a message from the controller thread to the window, see `window_poster`. Returns whether
+/// it was posted.
+pub type WindowPoster = Box<dyn FnMut(u32, WPARAM, LPARAM) -> bool + Send>;
+
+/// Monitors shared between the UI thread and the controller thread, which is the only one that
+/// talks to them through DDC/CI. Everyone else only reads the cached values, e.g. with
+/// `Monitor::get_brightness`, and locks for as short as possible. Other locks must not be taken
+/// while holding this one.
+pub type SharedMonitors = Arc<Mutex<Vec<Monitor>>>;
+
+/// The state of every monitor, in the order of `SharedMonitors`. With `refresh`, the brightness is
+/// read from the monitors first, which takes a DDC/CI round trip each; otherwise the last known
+/// state is returned right away.
+pub fn monitor_snapshots(monitors: &SharedMonitors, refresh: bool) -> Vec<MonitorSnapshot> {
+    let mut monitors = monitors.lock().unwrap();
+    (monitors.iter_mut())
+        .map(|monitor| {
+            if refresh {
+                let _ = monitor.refresh_brightness();
+            }
+            monitor.snapshot()
+        })
+        .collect()
+}
+
+/// Title of balloon notifications.
+const APP_NAME: &str = "Monitor Brightness Controller";
+
+/// Whether monitors that duplicate the same display are controlled as one selector entry.
+const GROUP_DUPLICATED_DISPLAYS: bool = true;
+
+/// Whether to show a balloon notification when the brightness is changed other than with the
+/// slider, e.g. through the named pipe, while the flyout is hidden.
+const NOTIFY_BRIGHTNESS_CHANGES: bool = true;
+
+/// Whether the notification icon shows the brightness of the selected monitor as a bar.
+const SHOW_LEVEL_IN_ICON: bool = true;
+
+/// Whether the tooltip and the balloons name what changed the brightness when it was not the
+/// flyout, e.g. "(idle)" for idle dimming.
+const SHOW_CHANGE_SOURCE: bool = true;
+
+/// Hotkey that sets every monitor to the default brightness, if any.
+const RESET_ALL_HOTKEY: Option<(HOT_KEY_MODIFIERS, VIRTUAL_KEY)> = Some((
+    HOT_KEY_MODIFIERS(MOD_CONTROL.0 | MOD_ALT.0 | MOD_NOREPEAT.0),
+    VK_HOME,
+));
+
+/// Hotkey that shows or hides the flyout, if any. See `settings::flyout_at_cursor` for where.
+const SHOW_FLYOUT_HOTKEY: Option<(HOT_KEY_MODIFIERS, VIRTUAL_KEY)> = Some((
+    HOT_KEY_MODIFIERS(MOD_CONTROL.0 | MOD_ALT.0 | MOD_NOREPEAT.0),
+    VK_B,
+));
+
+/// Whether other processes can query and set the brightness through a named pipe.
+const ENABLE_PIPE_SERVER: bool = true;
+
+/// Whether the slider of monitors whose display is in HDR mode at startup sets the brightness of
+/// SDR content, like the display settings, instead of the monitor's brightness through DDC/CI.
+const SDR_BRIGHTNESS_ON_HDR: bool = true;
+
+/// Whether to notify once per OLED monitor that its slider starts above the lowest brightness, see
+/// `settings::brightness_floor`.
+const OLED_FLOOR_HINT: bool = true;
+
+/// Whether launching the app while it is already running shows the running instance's flyout
+/// instead of starting another one.
+const SINGLE_INSTANCE: bool = true;
+
+/// How often the selected monitor's brightness is re-read, if at all. Polling is paused for this
+/// long after each brightness change so it does not fight with the slider while dragging.
+const BRIGHTNESS_POLL_INTERVAL: Option<Duration> = Some(Duration::from_secs(3));
+
+/// How long to keep collecting brightness changes before writing them to the monitors. Slider
+/// positions that were passed within this interval are never written.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(40);
+
+/// How many writes in a row, the probes after each cooldown included, a monitor may fail before
+/// `WriteFailures::Disable` leaves it alone.
+const FAILURES_BEFORE_DISABLING: u32 = 3;
+
+/// Brightness percentage and duration of the boost item of the context menu. Its label names the
+/// duration.
+const MENU_BOOST: (u32, Duration) = (100, Duration::from_secs(10 * 60));
+
+/// The controls shown in the flyout.
+enum Flyout<'a> {
+    Xaml(XamlControls<'a>),
+    /// Used where XAML islands are not available.
+    Classic(ClassicControls<'a>),
+}
+
+impl<'a> Flyout<'a> {
+    /// Intercept Windows message events. Used in a `GetMessage` loop.
+    fn filter_message(&self, message: *const MSG) -> bool {
+        match self {
+            Flyout::Xaml(controls) => controls.filter_message(message),
+            Flyout::Classic(controls) => controls.filter_message(message),
+        }
+    }
+}
+
+/// Write `message` as a line to the debugger output, e.g. for DebugView. The app has no log file.
+fn debug_log(message: &str) {
+    let message = format!("{}\n\0", message);
+    unsafe {
+        OutputDebugStringA(PCSTR(message.as_ptr()));
+    }
 }
 
-/// Event loop that handles directly setting the brightness of the monitors. Should be used in a
-/// separate thread since setting the brightness can stall the GUI.
-#[inline]
-fn brightness_controller_loop(mut monitors: Vec<Monitor>, rx: Receiver<BrightnessEvent>) {
-    let mut brightness_vals = monitors
-        .iter()
-        .map(|m| m.get_brightness())
-        .collect::<Vec<_>>();
+/// Report a failure to release a resource from a `Drop` implementation. Panicking there aborts the
+/// process if it is already unwinding, so the error is only written to the debugger output. Debug
+/// builds still panic when it is safe to do so.
+fn report_drop_error(action: &str, error: &dyn std::fmt::Display) {
+    debug_log(&format!("Failed to {}: {}", action, error));
+    if cfg!(debug_assertions) && !thread::panicking() {
+        panic!("Failed to {}: {}", action, error);
+    }
+}
+
+/// The hint to turn on DDC/CI for the monitor at `index`, unless it was already shown for a monitor
+/// with the same name. It is then remembered as shown.
+fn ddc_ci_hint(monitors: &SharedMonitors, index: usize) -> Option<String> {
+    let name = monitors.lock().unwrap().get(index)?.get_name().to_owned();
+    if settings::ddc_ci_hint_shown(&name) {
+        return None;
+    }
+    settings::set_ddc_ci_hint_shown(&name);
+    Some(Strings::format(strings().ddc_ci_disabled, name))
+}
 
-    'outer: while let Ok(mut msg) = rx.recv() {
-        // Once a message is received, repeatedly `try_recv` until there is no more.
-        // This is done so that it will not try to set the brightness one by one for each
-        // value sent by the callback.
-        while let BrightnessEvent::Change(i, brightness) = msg {
-            brightness_vals[i] = brightness;
-            msg = match rx.try_recv() {
-                Ok(msg) => msg,
-                Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => break 'outer,
+/// Has the controller thread write the pending and exit brightness, then waits for it to stop.
+/// Does nothing if it was already stopped.
+fn stop_controller(sender: &Sender<BrightnessEvent>, controller: &RefCell<Option<JoinHandle<()>>>) {
+    if let Some(controller) = controller.borrow_mut().take() {
+        if sender.send(BrightnessEvent::Exit).is_ok() {
+            let _ = controller.join();
+        }
+    }
+}
+
+/// Moves the flyout's controls to brightness changes that did not come from them, including back
+/// from a brightness that the monitors refused. Those also bring the other members of the group to
+/// the same value.
+fn window_listener(window: HWND) -> BrightnessListener {
+    Box::new(move |change| {
+        if change.external() {
+            unsafe {
+                PostMessageA(
+                    window,
+                    Window::BRIGHTNESS_CHANGED,
+                    WPARAM(change.index),
+                    LPARAM((change.brightness | (change.source.id() as u32) << 16) as isize),
+                );
             }
         }
+        if change.rejected {
+            unsafe {
+                PostMessageA(
+                    window,
+                    Window::BRIGHTNESS_REJECTED,
+                    WPARAM(change.index),
+                    LPARAM(0),
+                );
+            }
+        }
+    })
+}
+
+/// Posts the messages of the controller thread to `window` with `PostMessageA`.
+fn window_poster(window: HWND) -> WindowPoster {
+    Box::new(move |message, wparam, lparam| unsafe {
+        PostMessageA(window, message, wparam, lparam).as_bool()
+    })
+}
+
+/// Shows the brightness of the selected entry in the tooltip and, if `SHOW_LEVEL_IN_ICON`, the
+/// icon of `notification_icon`.
+fn tray_listener(notification_icon: Arc<Mutex<NotificationIcon>>) -> BrightnessListener {
+    let mut shown = None;
+    Box::new(move |change| {
+        if !change.selected || shown == Some((change.percent, change.source)) {
+            return;
+        }
+        if let Ok(mut notification_icon) = notification_icon.lock() {
+            if SHOW_LEVEL_IN_ICON {
+                let _ = notification_icon.show_level(change.percent);
+            }
+            shown = notification_icon
+                .modify_tooltip(change.percent, change.source)
+                .ok()
+                .map(|_| (change.percent, change.source));
+        }
+    })
+}
+
+/// The monitors to control, set up as the settings ask. Hidden monitors are dropped before anything
+/// is indexed, so the indices of the selector, the named pipe, and the controller thread all refer
+/// to the remaining ones.
+fn controlled_monitors() -> windows::core::Result<Vec<Monitor>> {
+    let mut monitors = monitor_brightness_controller::get_monitors()?;
+    monitors.retain(|monitor| !settings::is_monitor_hidden(monitor.get_name()));
+    for monitor in &mut monitors {
+        if SDR_BRIGHTNESS_ON_HDR && monitor.is_hdr_enabled() {
+            // Keeps using DDC/CI if the SDR white level cannot be read
+            let _ = monitor.use_sdr_white_level();
+        }
+        let cap = settings::brightness_cap(monitor.get_name());
+        monitor.set_brightness_cap(cap);
+        monitor.set_calibration(settings::calibration(monitor.get_name()));
+        if let Some(floor) = brightness_floor(monitor) {
+            let (_, max) = monitor.get_calibration().points()[1];
+            let min = monitor.get_min_brightness();
+            let floor_value = min + (max - min) * floor / 100;
+            monitor.set_calibration(Some(Calibration::linear(floor_value, max)));
+        }
+        // Left dimmed by the offset at the last exit, which is not the monitor's own brightness
+        monitor.set_brightness_offset(settings::brightness_offset());
+    }
+    Ok(monitors)
+}
+
+/// The floor of `monitor` if it applies: without a calibration table, which sets its own lowest
+/// value, and above 0.
+fn brightness_floor(monitor: &Monitor) -> Option<u32> {
+    let oled = monitor.technology() == DisplayTechnology::Oled;
+    let floor = settings::brightness_floor(monitor.get_name(), oled);
+    (floor > 0 && settings::calibration(monitor.get_name()).is_none()).then_some(floor)
+}
+
+/// The hint about the brightness floor for the first OLED monitor that has one and was not hinted
+/// about yet. It is then remembered as shown.
+fn oled_floor_hint(monitors: &SharedMonitors) -> Option<String> {
+    let monitors = monitors.lock().unwrap();
+    let monitor = monitors.iter().find(|monitor| {
+        monitor.technology() == DisplayTechnology::Oled
+            && brightness_floor(monitor).is_some()
+            && !settings::oled_hint_shown(monitor.get_name())
+    })?;
+    settings::set_oled_hint_shown(monitor.get_name());
+    Some(Strings::format(strings().oled_floor, monitor.get_name()))
+}
+
+/// `BrightnessEvent::Boost` for the command line while the app is not running. Blocks for
+/// `duration` unless boosting failed; the brightness is restored either way.
+fn boost_without_ui(monitor: Option<usize>, percent: u32, duration: Duration) -> error::Result<()> {
+    let mut monitors = controlled_monitors()?;
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+    let indices = selector_indices(monitor, groups.len())?;
+
+    let mut brightness_vals: Vec<u32> = monitors.iter().map(Monitor::get_brightness).collect();
+    let mut boost = Boost::new(monitors.len(), Instant::now() + duration);
+    for i in indices {
+        let (_, max_brightness) = groups[i].brightness_range(&monitors);
+        for &member in groups[i].members() {
+            boost.add(member, brightness_vals[member]);
+            brightness_vals[member] = group::from_percent(percent, max_brightness);
+        }
+    }
+    let boosted = write_brightness(&mut monitors, &brightness_vals);
+    if boosted.is_ok() {
+        thread::sleep(duration);
+    }
+    boost.restore(&mut brightness_vals);
+    write_brightness(&mut monitors, &brightness_vals)?;
+    Ok(boosted?)
+}
+
+/// `cli::Command::Calibrate`: store the brightness that each member of the selector entry is at,
+/// e.g. after setting it with the monitor's own buttons, as the point at `percent` of its
+/// calibration table. The running instance, if any, picks up the tables when it is restarted.
+fn capture_calibration(monitor: Option<usize>, percent: u32) -> error::Result<()> {
+    let monitors = controlled_monitors()?;
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+    for i in selector_indices(monitor, groups.len())? {
+        for &member in groups[i].members() {
+            let monitor = &monitors[member];
+            let value = monitor.read_raw_brightness()?;
+            let calibration = (monitor.get_calibration())
+                .with_point(percent, value)
+                .ok_or(AppError::NotMonotonic(percent, value))?;
+            settings::set_calibration(monitor.get_name(), &calibration);
+            println!("{}: {}% is {}", monitor.get_name(), percent, value);
+        }
+    }
+    Ok(())
+}
+
+/// `cli::Command::List`: `<index> <percent> <name>` for each selector entry like the `list`
+/// command of the named pipe, or with `json` the `MonitorSnapshot`s of every physical monitor on a
+/// single line like `snapshot`. Asks the running instance if there is one, otherwise reads the
+/// monitors.
+fn list_monitors(json: bool) -> error::Result<Vec<String>> {
+    let running = if json {
+        pipe::send_command("snapshot").map(|reply| vec![reply])
+    } else {
+        pipe::send_list_command()
+    };
+    if let Ok(lines) = running {
+        // Only `snapshot` can fail, e.g. on an instance from before it existed
+        if let Some(reason) = lines.first().and_then(|line| line.strip_prefix("error: ")) {
+            return Err(AppError::Reply(reason.to_owned()));
+        }
+        return Ok(lines);
+    }
+    let monitors = controlled_monitors()?;
+    if json {
+        let snapshots: Vec<_> = monitors.iter().map(Monitor::snapshot).collect();
+        // Plain data, which always serializes
+        return Ok(vec![serde_json::to_string(&snapshots).unwrap()]);
+    }
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+    let lines = (groups.iter().enumerate())
+        .map(|(i, group)| {
+            let (percent, name) = (group.percent(&monitors), group.name(&monitors));
+            format!("{} {} {}", i, percent, name)
+        })
+        .collect();
+    Ok(lines)
+}
+
+/// `cli::Command::Get` while the app is not running: the brightness of the selector entry in
+/// percent, read from its first member like the `get` command of the named pipe, or with `json`
+/// the `MonitorSnapshot` of that member like `snapshot <index>`.
+fn read_monitor(monitor: usize, json: bool) -> error::Result<String> {
+    let monitors = controlled_monitors()?;
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+    let group = groups
+        .get(monitor)
+        .ok_or(AppError::NoSuchMonitor(monitor))?;
+    if json {
+        let snapshot = monitors[group.members()[0]].snapshot();
+        // Plain data, which always serializes
+        return Ok(serde_json::to_string(&snapshot).unwrap());
+    }
+    Ok(group.percent(&monitors).to_string())
+}
+
+/// The indices of the selector entry `monitor` of the command line, or of all `num_groups`.
+fn selector_indices(monitor: Option<usize>, num_groups: usize) -> error::Result<Range<usize>> {
+    match monitor {
+        Some(i) if i >= num_groups => Err(AppError::NoSuchMonitor(i)),
+        Some(i) => Ok(i..i + 1),
+        None => Ok(0..num_groups),
+    }
+}
+
+/// `cli::Command::Daemon`: the controller thread, idle dimming, auto brightness, and the named
+/// pipe, without the notification icon or a flyout. A message-only window receives the power
+/// broadcasts in place of the flyout's window. The pipe server is started regardless of
+/// `ENABLE_PIPE_SERVER` since it is the only way to control the app, and its `exit` command is the
+/// only way to stop it.
+fn run_daemon() -> error::Result<()> {
+    // For the ambient light sensor
+    unsafe {
+        RoInitialize(RO_INIT_SINGLETHREADED)?;
+    }
+
+    let (tx, rx) = mpsc::channel();
+
+    let monitors = controlled_monitors()?;
+    if monitors.is_empty() {
+        return Err(AppError::NoMonitors);
+    }
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+
+    let window = Window::new(&tx, WindowKind::MessageOnly)?;
+    let _power_notify_handle = PowerNotifyHandle::new(window.as_handle());
+    if let Some(idle_dim) = settings::idle_dim() {
+        window.start_idle_dimming(idle_dim)?;
+    }
+
+    let hwnd = window.as_handle();
+    let monitors: SharedMonitors = Arc::new(Mutex::new(monitors));
+    let groups = Arc::new(groups);
+    let _pipe_server = PipeServer::new(tx.clone(), monitors.clone(), groups.clone(), hwnd);
+    let _ambient_light = AmbientLight::new(tx.clone(), settings::ambient_curve());
+    if settings::follow_builtin_display() {
+        builtin::watch(tx.clone());
+    }
+    if settings::brightness_keys().is_some() {
+        // Without raw input the keys only change the displays that Windows controls
+        let _ = window.register_brightness_keys();
+    }
+
+    let controller = RefCell::new(Some(thread::spawn(move || {
+        let post = window_poster(hwnd);
+        brightness_controller_loop(monitors, groups, rx, RegistrySettings, post, Vec::new());
+    })));
+
+    let mut msg = MSG::default();
+    unsafe {
+        while GetMessageA(&mut msg, HWND::default(), 0, 0).as_bool() {
+            TranslateMessage(&msg);
+            DispatchMessageA(&msg);
+        }
+    }
 
-        for (monitor, brightness) in monitors.iter_mut().zip(brightness_vals.iter()) {
-            let _ = monitor.set_brightness(*brightness);
+    stop_controller(&tx, &controller);
+
+    Ok(())
+}
+
+/// Set each of `monitors` whose brightness differs to its value in `brightness_vals`. Returns the
+/// first error, after trying every monitor.
+fn write_brightness(
+    monitors: &mut [Monitor],
+    brightness_vals: &[u32],
+) -> windows::core::Result<()> {
+    let mut result = Ok(());
+    for (monitor, &brightness) in monitors.iter_mut().zip(brightness_vals) {
+        if monitor.get_brightness() != brightness {
+            result = result.and(monitor.set_brightness(brightness));
         }
     }
+    result
 }
 
-fn main() -> Result<()> {
+fn main() -> error::Result<()> {
+    let daemon = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::Command::Run) => false,
+        Ok(cli::Command::Daemon) => true,
+        Ok(cli::Command::Version) => {
+            cli::attach_console();
+            println!("{}", cli::version());
+            return Ok(());
+        }
+        Ok(cli::Command::Adjust { monitor, delta }) => {
+            cli::attach_console();
+            let target = monitor.map_or("all".to_owned(), |i| i.to_string());
+            match pipe::send_command(&format!("adjust {} {}", target, delta)) {
+                Ok(reply) if reply == "ok" => return Ok(()),
+                Ok(reply) => eprintln!("{}", reply),
+                Err(e) => eprintln!("error: the app is not running: {}", e),
+            }
+            process::exit(1);
+        }
+        Ok(cli::Command::Temporary {
+            monitor,
+            percent,
+            seconds,
+        }) => {
+            cli::attach_console();
+            let target = monitor.map_or("all".to_owned(), |i| i.to_string());
+            match pipe::send_command(&format!("boost {} {} {}", target, percent, seconds)) {
+                Ok(reply) if reply == "ok" => return Ok(()),
+                Ok(reply) => eprintln!("{}", reply),
+                // Without a running instance, the boost is done by this process alone
+                Err(_) => match boost_without_ui(monitor, percent, Duration::from_secs(seconds)) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => eprintln!("error: {}", e),
+                },
+            }
+            process::exit(1);
+        }
+        Ok(cli::Command::Calibrate { monitor, percent }) => {
+            cli::attach_console();
+            match capture_calibration(monitor, percent) {
+                Ok(()) => return Ok(()),
+                Err(e) => eprintln!("error: {}", e),
+            }
+            process::exit(1);
+        }
+        Ok(cli::Command::List { json }) => {
+            cli::attach_console();
+            match list_monitors(json) {
+                Ok(lines) => {
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                    return Ok(());
+                }
+                Err(e) => eprintln!("error: {}", e),
+            }
+            process::exit(1);
+        }
+        Ok(cli::Command::Get { monitor, json }) => {
+            cli::attach_console();
+            let command = if json { "snapshot" } else { "get" };
+            match pipe::send_command(&format!("{} {}", command, monitor)) {
+                Ok(reply) if !reply.starts_with("error: ") => {
+                    println!("{}", reply);
+                    return Ok(());
+                }
+                Ok(reply) => eprintln!("{}", reply),
+                Err(_) => match read_monitor(monitor, json) {
+                    Ok(line) => {
+                        println!("{}", line);
+                        return Ok(());
+                    }
+                    Err(e) => eprintln!("error: {}", e),
+                },
+            }
+            process::exit(1);
+        }
+        Err(e) => {
+            cli::attach_console();
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let _instance_lock = if SINGLE_INSTANCE {
+        match InstanceLock::acquire()? {
+            Some(lock) => Some(lock),
+            None => {
+                instance::show_running_instance();
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    if daemon {
+        return run_daemon();
+    }
+
     // Initialize WinRT
     unsafe {
         RoInitialize(RO_INIT_SINGLETHREADED)?;
@@ -70,32 +708,155 @@ fn main() -> Result<()> {
     let tx1 = tx.clone();
     let tx2 = tx;
 
-    let monitors = monitor::Monitor::get_monitors()?;
+    // Fails on some locked-down systems and Server SKUs without XAML islands. The window has to
+    // know before it is created since it cannot show classic controls while hosting XAML.
+    let xaml_manager = WindowsXamlManager::InitializeForCurrentThread();
 
-    let window = Window::new(&tx1)?;
+    let kind = if xaml_manager.is_ok() {
+        WindowKind::Xaml
+    } else {
+        WindowKind::Classic
+    };
+    let window = Window::new(&tx1, kind)?;
     let mut notification_icon = NotificationIcon::new(window.as_handle())?;
-    let _power_notify_handle = PowerNotifyHandle::new(window.as_handle())?;
+    let _power_notify_handle = PowerNotifyHandle::new(window.as_handle());
+
+    // Reading every monitor over DDC/CI can take seconds, so the notification icon is there first
+    let _ = notification_icon.show_loading();
+    let monitors = window.while_loading(controlled_monitors)?;
+    if monitors.is_empty() {
+        return Err(AppError::NoMonitors);
+    }
+    let groups = group::group_monitors(&monitors, GROUP_DUPLICATED_DISPLAYS);
+
+    if let Some(group) = groups.get(group::initial_index(&groups, &monitors)) {
+        let (_, max_brightness) = group.brightness_range(&monitors);
+        let percent = group::to_percent(group.brightness(&monitors), max_brightness);
+        notification_icon.modify_tooltip(percent, ChangeSource::Manual)?;
+        if SHOW_LEVEL_IN_ICON {
+            notification_icon.show_level(percent)?;
+        }
+    }
+
+    let monitors: SharedMonitors = Arc::new(Mutex::new(monitors));
+    let groups = Arc::new(groups);
+    let notification_icon = Arc::new(Mutex::new(notification_icon));
 
-    if let Some(monitor) = monitors.first() {
-        let brightness = monitor.get_brightness();
-        notification_icon.modify_tooltip(brightness)?;
+    let flyout = match xaml_manager {
+        Ok(manager) => XamlControls::new(
+            &window,
+            manager,
+            monitors.clone(),
+            groups.clone(),
+            tx2,
+            notification_icon.clone(),
+        )
+        .map(Flyout::Xaml),
+        Err(_) => {
+            if let Ok(mut notification_icon) = notification_icon.lock() {
+                let _ = notification_icon.show_balloon(APP_NAME, strings().xaml_unavailable);
+            }
+            ClassicControls::new(
+                &window,
+                monitors.clone(),
+                groups.clone(),
+                tx2,
+                notification_icon.clone(),
+            )
+            .map(Flyout::Classic)
+        }
+    };
+    let flyout = match flyout {
+        Ok(flyout) => flyout,
+        Err(e) => {
+            // Handlers of controls that XAML has not let go of yet may still hold on to the icon,
+            // which would then stay in the notification area until hovered over
+            if let Ok(mut notification_icon) = notification_icon.lock() {
+                let _ = notification_icon.remove();
+            }
+            let error = AppError::Flyout(e);
+            debug_log(&error.to_string());
+            return Err(error);
+        }
+    };
+    window.finish_loading();
+    if !settings::welcome_shown() {
+        if let Ok(mut notification_icon) = notification_icon.lock() {
+            // Tried again at the next start if the notification area is not ready yet
+            if notification_icon
+                .show_balloon(APP_NAME, strings().welcome)
+                .is_ok()
+            {
+                settings::set_welcome_shown();
+            }
+        }
+    } else if OLED_FLOOR_HINT {
+        // After the welcome notification, which would replace it, was shown at an earlier start
+        if let Some(message) = oled_floor_hint(&monitors) {
+            if let Ok(mut notification_icon) = notification_icon.lock() {
+                let _ = notification_icon.show_balloon(APP_NAME, &message);
+            }
+        }
+    }
+    if let Some((modifiers, key)) = RESET_ALL_HOTKEY {
+        // Fails if another app already uses the hotkey; the context menu still works
+        let _ = window.register_reset_all_hotkey(modifiers, key);
+    }
+    if let Some((modifiers, key)) = SHOW_FLYOUT_HOTKEY {
+        let _ = window.register_show_flyout_hotkey(modifiers, key);
+    }
+    if settings::brightness_keys().is_some() {
+        // Without raw input the keys only change the displays that Windows controls
+        let _ = window.register_brightness_keys();
+    }
+    if let Some(interval) = BRIGHTNESS_POLL_INTERVAL {
+        window.start_brightness_polling(interval)?;
+    }
+    if let Some(idle_dim) = settings::idle_dim() {
+        window.start_idle_dimming(idle_dim)?;
     }
 
-    let xaml_controls = xaml::XamlControls::new(&window, &monitors, tx2, notification_icon)?;
+    let hwnd = window.as_handle();
+    let _pipe_server = ENABLE_PIPE_SERVER
+        .then(|| PipeServer::new(tx1.clone(), monitors.clone(), groups.clone(), hwnd));
+    // Nothing to follow without a sensor; the menu item stays disabled then
+    let _ambient_light = AmbientLight::new(tx1.clone(), settings::ambient_curve());
+    // For the menu item that matches it
+    builtin::detect();
+    if settings::follow_builtin_display() {
+        builtin::watch(tx1.clone());
+    }
 
-    thread::spawn(move || {
-        brightness_controller_loop(monitors, rx);
-    });
+    let tray = tray_listener(notification_icon.clone());
+    let controller = Rc::new(RefCell::new(Some(thread::spawn(move || {
+        let listeners = vec![window_listener(hwnd), tray];
+        let post = window_poster(hwnd);
+        brightness_controller_loop(monitors, groups, rx, RegistrySettings, post, listeners);
+    }))));
+    {
+        let controller = controller.clone();
+        let sender = tx1.clone();
+        window.set_end_session_handler(move || {
+            stop_controller(&sender, &controller);
+            if let Ok(mut notification_icon) = notification_icon.lock() {
+                let _ = notification_icon.remove();
+            }
+        });
+    }
 
     let mut msg = MSG::default();
     unsafe {
         while GetMessageA(&mut msg, HWND::default(), 0, 0).as_bool() {
-            if !xaml_controls.filter_message(&msg) {
+            if !flyout.filter_message(&msg) {
                 TranslateMessage(&msg);
                 DispatchMessageA(&msg);
             }
         }
     }
 
+    // The window was destroyed. The monitors are released once the controller thread and the
+    // flyout let go of them, which must come after the last write.
+    stop_controller(&tx1, &controller);
+
     Ok(())
 }
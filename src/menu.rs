@@ -0,0 +1,464 @@
+use std::sync::{mpsc::Sender, Arc, Mutex};
+
+use windows::{
+    core::{Result, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, POINT, RECT, WPARAM},
+        UI::WindowsAndMessaging::{
+            AppendMenuW, CreatePopupMenu, DestroyMenu, GetCursorPos, PostMessageA, SendMessageA,
+            SetForegroundWindow, TrackPopupMenu, HMENU, MENU_ITEM_FLAGS, MF_CHECKED, MF_GRAYED,
+            MF_SEPARATOR, MF_STRING, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_CLOSE, WM_NULL,
+        },
+    },
+};
+
+use crate::{
+    ambient, automation, builtin, locate, settings,
+    strings::{strings, Strings},
+    window::{is_locked, set_locked, WindowEvent},
+    BrightnessEvent, NotificationIcon, SharedMonitors, APP_NAME,
+};
+
+/// Actions that can be selected from the context menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuCommand {
+    ResetMonitor,
+    /// Set every monitor to the default brightness.
+    ResetAll,
+    /// Toggle ignoring brightness changes from the flyout, the hotkey, and the brightness keys.
+    Lock,
+    /// Toggle pausing the automatic brightness changes, see `automation`.
+    PauseAutomation,
+    /// Toggle whether the selected monitor takes on the brightness of the primary display.
+    FollowPrimary,
+    /// Toggle whether the brightness follows the ambient light sensor.
+    AutoBrightness,
+    /// Set every monitor to the brightness of the built-in display, see `builtin::brightness`.
+    MatchBuiltin,
+    /// Flash the index of the selected monitor over it, see `locate::flash`.
+    Locate,
+    /// Brighten every monitor for a while, see `MENU_BOOST`.
+    Boost,
+    /// Toggle dimming every monitor by the offset of `settings::brightness_offset`.
+    DimAll,
+    Standby,
+    Wake,
+    ExportSettings,
+    ImportSettings,
+    /// Copy a report of how each monitor answers to the clipboard.
+    CopyDiagnostics,
+    Exit,
+}
+
+impl MenuCommand {
+    const ALL: [MenuCommand; 16] = [
+        MenuCommand::ResetMonitor,
+        MenuCommand::ResetAll,
+        MenuCommand::Lock,
+        MenuCommand::PauseAutomation,
+        MenuCommand::FollowPrimary,
+        MenuCommand::AutoBrightness,
+        MenuCommand::MatchBuiltin,
+        MenuCommand::Locate,
+        MenuCommand::Boost,
+        MenuCommand::DimAll,
+        MenuCommand::Standby,
+        MenuCommand::Wake,
+        MenuCommand::ExportSettings,
+        MenuCommand::ImportSettings,
+        MenuCommand::CopyDiagnostics,
+        MenuCommand::Exit,
+    ];
+
+    /// Item ID used with `AppendMenuW`. Zero is reserved for "nothing selected".
+    fn id(self) -> usize {
+        self as usize + 1
+    }
+
+    fn from_id(id: usize) -> Option<MenuCommand> {
+        MenuCommand::ALL
+            .into_iter()
+            .find(|command| command.id() == id)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MenuCommand::ResetMonitor => strings().menu_reset_monitor,
+            MenuCommand::ResetAll => strings().menu_reset_all,
+            MenuCommand::Lock => strings().menu_lock,
+            MenuCommand::PauseAutomation => strings().menu_pause_automation,
+            MenuCommand::FollowPrimary => strings().menu_follow_primary,
+            MenuCommand::AutoBrightness => strings().menu_auto_brightness,
+            MenuCommand::MatchBuiltin => strings().menu_match_builtin,
+            MenuCommand::Locate => strings().menu_locate,
+            MenuCommand::Boost => strings().menu_boost,
+            MenuCommand::DimAll => strings().menu_dim_all,
+            MenuCommand::Standby => strings().menu_standby,
+            MenuCommand::Wake => strings().menu_wake,
+            MenuCommand::ExportSettings => strings().menu_export_settings,
+            MenuCommand::ImportSettings => strings().menu_import_settings,
+            MenuCommand::CopyDiagnostics => strings().menu_copy_diagnostics,
+            MenuCommand::Exit => strings().menu_exit,
+        }
+    }
+}
+
+/// Which items of the context menu are enabled and checked, see `show_context_menu`.
+#[derive(Clone, Copy, Debug)]
+pub struct MenuState {
+    /// Enables the item for restoring the selected monitor's factory defaults.
+    pub can_reset: bool,
+    /// Enables the items for switching the power of all monitors.
+    pub can_standby: bool,
+    /// Enables the items for exporting and importing the settings.
+    pub can_transfer: bool,
+    /// Checks the lock item and disables the items that change the brightness of every monitor.
+    pub locked: bool,
+    /// Checks the item for pausing the automatic changes.
+    pub paused: bool,
+    /// Whether the selected monitor follows the primary display, `None` if it cannot since it is
+    /// the primary one or none is selected.
+    pub follows_primary: Option<bool>,
+    /// Whether the brightness follows the ambient light sensor, `None` without a sensor.
+    pub auto_brightness: Option<bool>,
+    /// Enables the item for matching the built-in display.
+    pub can_match_builtin: bool,
+    /// Enables the item for flashing the selected monitor's index over it.
+    pub can_locate: bool,
+    /// Whether every monitor is dimmed by the offset from the settings.
+    pub dim_all: bool,
+}
+
+/// Show the context menu of the notification icon with its items as in `state` and return the
+/// selected command.
+pub fn show_context_menu(window: HWND, state: &MenuState) -> Result<Option<MenuCommand>> {
+    let MenuState {
+        can_reset,
+        can_standby,
+        can_transfer,
+        locked,
+        paused,
+        follows_primary,
+        auto_brightness,
+        can_match_builtin,
+        can_locate,
+        dim_all,
+    } = *state;
+    let menu = ContextMenu::new()?;
+    menu.append(MenuCommand::ResetMonitor, can_reset)?;
+    menu.append(MenuCommand::ResetAll, !locked)?;
+    menu.append_checked(MenuCommand::Lock, locked)?;
+    menu.append_checked(MenuCommand::PauseAutomation, paused)?;
+    match follows_primary {
+        Some(follows) => menu.append_checked(MenuCommand::FollowPrimary, follows)?,
+        None => menu.append(MenuCommand::FollowPrimary, false)?,
+    }
+    match auto_brightness {
+        Some(enabled) => menu.append_checked(MenuCommand::AutoBrightness, enabled)?,
+        None => menu.append(MenuCommand::AutoBrightness, false)?,
+    }
+    menu.append(MenuCommand::MatchBuiltin, can_match_builtin && !locked)?;
+    menu.append(MenuCommand::Locate, can_locate)?;
+    menu.append(MenuCommand::Boost, !locked)?;
+    if locked {
+        menu.append(MenuCommand::DimAll, false)?;
+    } else {
+        menu.append_checked(MenuCommand::DimAll, dim_all)?;
+    }
+    menu.append_separator()?;
+    // Both are always shown since the monitors can also be turned on with their power buttons
+    menu.append(MenuCommand::Standby, can_standby)?;
+    menu.append(MenuCommand::Wake, can_standby)?;
+    menu.append_separator()?;
+    menu.append(MenuCommand::ExportSettings, can_transfer)?;
+    menu.append(MenuCommand::ImportSettings, can_transfer && !locked)?;
+    menu.append(MenuCommand::CopyDiagnostics, true)?;
+    menu.append_separator()?;
+    menu.append(MenuCommand::Exit, true)?;
+    Ok(menu.show(window))
+}
+
+/// The parts of the context menu and of the window events that differ between the XAML and the
+/// classic flyout.
+pub trait FlyoutMenu {
+    /// Index of the selector entry chosen in the flyout, if any.
+    fn selected(&self) -> Option<usize>;
+
+    /// Name of the selector entry at `index` for the messages about it.
+    fn name(&self, index: usize) -> Option<String>;
+
+    /// Whether the factory defaults of the monitors of the entry at `index` can be restored.
+    fn can_reset(&self, index: usize) -> bool;
+
+    /// Whether the monitors of the entry at `index` follow the primary display, `None` for the
+    /// primary one itself.
+    fn follows_primary(&self, index: usize) -> Option<bool>;
+
+    /// Bounds of the monitors of the entry at `index` that are known, for `locate::flash`.
+    fn bounds(&self, index: usize) -> Vec<RECT>;
+
+    /// Disable the brightness controls while `locked`, or enable the usable ones again.
+    fn lock_controls(&self, locked: bool);
+
+    /// Whether the flyout can export and import the settings. The classic flyout leaves it to the
+    /// XAML one.
+    fn can_transfer(&self) -> bool {
+        false
+    }
+
+    /// Ask for a file and save the settings to it. Only called if `can_transfer`.
+    fn export_settings(&self) {}
+
+    /// Ask for a file and apply the settings in it. Only called if `can_transfer`.
+    fn import_settings(&self) {}
+}
+
+/// Handles the context menu and the window events that are the same for both flyouts.
+pub struct MenuHandler {
+    window: HWND,
+    can_standby: bool,
+    tx: Sender<BrightnessEvent>,
+    notification_icon: Arc<Mutex<NotificationIcon>>,
+    monitors: SharedMonitors,
+}
+
+impl MenuHandler {
+    /// `can_standby` enables the items for switching the power of all monitors.
+    pub fn new(
+        window: HWND,
+        can_standby: bool,
+        tx: Sender<BrightnessEvent>,
+        notification_icon: Arc<Mutex<NotificationIcon>>,
+        monitors: SharedMonitors,
+    ) -> Self {
+        MenuHandler {
+            window,
+            can_standby,
+            tx,
+            notification_icon,
+            monitors,
+        }
+    }
+
+    /// Handle `event` if it does not depend on `flyout`. Returns `false` if it is left to the
+    /// flyout.
+    pub fn handle_event(&self, event: &WindowEvent, flyout: &impl FlyoutMenu) -> bool {
+        match *event {
+            WindowEvent::ContextMenu => self.show_context_menu(flyout),
+            WindowEvent::FactoryResetFailed(index) => {
+                if let Some(name) = flyout.name(index) {
+                    self.show_message(&Strings::format(strings().reset_failed, name));
+                }
+            }
+            WindowEvent::BrightnessRejected(index) => {
+                if let Some(name) = flyout.name(index) {
+                    self.show_message(&Strings::format(strings().brightness_rejected, name));
+                }
+            }
+            WindowEvent::StandbyFailed(standby) => {
+                self.show_message(if standby {
+                    strings().standby_failed
+                } else {
+                    strings().wake_failed
+                });
+            }
+            WindowEvent::DiagnosticsCopied(copied) => {
+                let message = if copied {
+                    strings().diagnostics_copied
+                } else {
+                    strings().diagnostics_failed
+                };
+                if let Ok(mut notification_icon) = self.notification_icon.lock() {
+                    let _ = notification_icon.show_balloon(APP_NAME, message);
+                }
+            }
+            WindowEvent::DdcCiDisabled(index) => {
+                if let Some(message) = crate::ddc_ci_hint(&self.monitors, index) {
+                    self.show_message(&message);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Show the context menu for the entry chosen in `flyout` and carry out the selected command.
+    /// Errors are ignored since there is nowhere to propagate them to.
+    fn show_context_menu(&self, flyout: &impl FlyoutMenu) {
+        let selected = flyout.selected();
+        let can_reset = selected.is_some_and(|i| flyout.can_reset(i));
+        let follows_primary = selected.and_then(|i| flyout.follows_primary(i));
+        let bounds = selected.map(|i| flyout.bounds(i)).unwrap_or_default();
+        let locked = is_locked(self.window);
+        let auto_brightness = ambient::has_sensor().then(settings::auto_brightness);
+        let dim_all = settings::brightness_offset_on();
+        let state = MenuState {
+            can_reset,
+            can_standby: self.can_standby,
+            can_transfer: flyout.can_transfer(),
+            locked,
+            paused: automation::is_paused(),
+            follows_primary,
+            auto_brightness,
+            can_match_builtin: builtin::has_display(),
+            can_locate: !bounds.is_empty(),
+            dim_all,
+        };
+        let command = show_context_menu(self.window, &state);
+        let tx = &self.tx;
+        match command {
+            Ok(Some(MenuCommand::ResetMonitor)) => {
+                if let Some(index) = selected {
+                    let _ = tx.send(BrightnessEvent::FactoryReset(index));
+                }
+            }
+            Ok(Some(MenuCommand::ResetAll)) => {
+                let _ = tx.send(BrightnessEvent::ResetAll);
+            }
+            Ok(Some(MenuCommand::MatchBuiltin)) => {
+                let _ = tx.send(BrightnessEvent::MatchBuiltin);
+            }
+            Ok(Some(MenuCommand::DimAll)) => {
+                settings::set_brightness_offset_on(!dim_all);
+                let _ = tx.send(BrightnessEvent::Offset(settings::brightness_offset()));
+            }
+            Ok(Some(MenuCommand::Lock)) => {
+                let locked = !locked;
+                set_locked(self.window, locked);
+                flyout.lock_controls(locked);
+                if let Ok(mut notification_icon) = self.notification_icon.lock() {
+                    let _ = notification_icon.set_locked(locked);
+                }
+            }
+            Ok(Some(MenuCommand::PauseAutomation)) => {
+                let paused = !automation::is_paused();
+                automation::set_paused(paused, tx);
+                if let Ok(mut notification_icon) = self.notification_icon.lock() {
+                    let _ = notification_icon.set_paused(paused);
+                }
+            }
+            Ok(Some(MenuCommand::FollowPrimary)) => {
+                if let (Some(index), Some(follows)) = (selected, follows_primary) {
+                    let _ = tx.send(BrightnessEvent::FollowPrimary(index, !follows));
+                }
+            }
+            Ok(Some(MenuCommand::AutoBrightness)) => {
+                let enabled = auto_brightness == Some(false);
+                settings::set_auto_brightness(enabled);
+                let _ = tx.send(BrightnessEvent::AutoBrightness(enabled));
+            }
+            Ok(Some(MenuCommand::Locate)) => {
+                if let Some(index) = selected {
+                    let _ = locate::flash(&bounds, index + 1);
+                }
+            }
+            Ok(Some(MenuCommand::Boost)) => {
+                let (percent, duration) = crate::MENU_BOOST;
+                let _ = tx.send(BrightnessEvent::Boost(None, percent, duration));
+            }
+            Ok(Some(MenuCommand::Standby)) => {
+                let _ = tx.send(BrightnessEvent::Standby(true));
+            }
+            Ok(Some(MenuCommand::Wake)) => {
+                let _ = tx.send(BrightnessEvent::Standby(false));
+            }
+            Ok(Some(MenuCommand::ExportSettings)) => flyout.export_settings(),
+            Ok(Some(MenuCommand::ImportSettings)) => flyout.import_settings(),
+            Ok(Some(MenuCommand::CopyDiagnostics)) => {
+                let _ = tx.send(BrightnessEvent::Diagnose);
+            }
+            Ok(Some(MenuCommand::Exit)) => unsafe {
+                SendMessageA(self.window, WM_CLOSE, WPARAM(0), LPARAM(0));
+            },
+            Ok(None) | Err(_) => (),
+        }
+    }
+
+    /// Replace the tooltip of the notification icon with `message`.
+    fn show_message(&self, message: &str) {
+        if let Ok(mut notification_icon) = self.notification_icon.lock() {
+            let _ = notification_icon.show_message(message);
+        }
+    }
+}
+
+/// Popup menu shown when the notification icon is right-clicked.
+pub struct ContextMenu(HMENU);
+
+impl Drop for ContextMenu {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyMenu(self.0);
+        }
+    }
+}
+
+impl ContextMenu {
+    pub fn new() -> Result<Self> {
+        let menu = unsafe { CreatePopupMenu()? };
+        Ok(ContextMenu(menu))
+    }
+
+    /// Add an item for `command`; disabled items are shown grayed out.
+    pub fn append(&self, command: MenuCommand, enabled: bool) -> Result<()> {
+        let flags = if enabled {
+            MF_STRING
+        } else {
+            MF_STRING | MF_GRAYED
+        };
+        self.append_raw(flags, command.id(), Some(command.label()))
+    }
+
+    /// Add an item for `command` with a check mark if `checked`.
+    pub fn append_checked(&self, command: MenuCommand, checked: bool) -> Result<()> {
+        let flags = if checked {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        self.append_raw(flags, command.id(), Some(command.label()))
+    }
+
+    pub fn append_separator(&self) -> Result<()> {
+        self.append_raw(MF_SEPARATOR, 0, None)
+    }
+
+    /// Show the menu at the cursor and block until it is dismissed. Returns the selected command.
+    pub fn show(&self, window: HWND) -> Option<MenuCommand> {
+        unsafe {
+            let mut point = POINT::default();
+            if !GetCursorPos(&mut point).as_bool() {
+                return None;
+            }
+            // Required so that the menu closes when clicking outside of it
+            SetForegroundWindow(window);
+            let id = TrackPopupMenu(
+                self.0,
+                TPM_RETURNCMD | TPM_RIGHTBUTTON,
+                point.x,
+                point.y,
+                0,
+                window,
+                std::ptr::null(),
+            );
+            // See "PRB: Menus for Notification Icons Do Not Work Correctly"
+            PostMessageA(window, WM_NULL, WPARAM(0), LPARAM(0));
+            MenuCommand::from_id(id.0 as usize)
+        }
+    }
+
+    fn append_raw(&self, flags: MENU_ITEM_FLAGS, id: usize, label: Option<&str>) -> Result<()> {
+        let label: Option<Vec<u16>> =
+            label.map(|label| label.encode_utf16().chain(std::iter::once(0)).collect());
+        let label = label
+            .as_ref()
+            .map_or(PCWSTR::default(), |l| PCWSTR(l.as_ptr()));
+        unsafe {
+            if AppendMenuW(self.0, flags, id, label).as_bool() {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        }
+    }
+}
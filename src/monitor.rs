@@ -1,133 +1,912 @@
-use std::{mem::MaybeUninit, thread, time::Duration};
+//! Brightness and VCP control of physical monitors through DDC/CI.
+
+#[cfg(any(test, feature = "fake-monitor"))]
+use std::collections::BTreeMap;
+use std::mem::MaybeUninit;
 
 use windows::{
-    core::Result,
+    core::{Interface, Result},
     Win32::{
         Devices::Display::{
-            DestroyPhysicalMonitor, GetMonitorBrightness, GetNumberOfPhysicalMonitorsFromHMONITOR,
-            GetPhysicalMonitorsFromHMONITOR, SetMonitorBrightness, PHYSICAL_MONITOR,
+            CapabilitiesRequestAndCapabilitiesReply, DestroyPhysicalMonitor,
+            GetCapabilitiesStringLength, GetMonitorBrightness,
+            GetNumberOfPhysicalMonitorsFromHMONITOR, GetPhysicalMonitorsFromHMONITOR,
+            GetVCPFeatureAndVCPFeatureReply, SetMonitorBrightness, SetVCPFeature, PHYSICAL_MONITOR,
+        },
+        Foundation::{BOOL, E_NOTIMPL, HANDLE, LPARAM, RECT},
+        Graphics::{
+            Dxgi::{CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput6},
+            Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO},
         },
-        Foundation::{BOOL, LPARAM, RECT},
-        Graphics::Gdi::{EnumDisplayMonitors, HDC, HMONITOR},
+        UI::WindowsAndMessaging::MONITORINFOF_PRIMARY,
     },
 };
 
+use crate::{
+    backoff::Backoff,
+    calibration::Calibration,
+    capabilities::Capabilities,
+    hdr::{DisplayTarget, SDR_WHITE_LEVEL_RANGE},
+    technology::{DisplayTechnology, VCP_DISPLAY_TECHNOLOGY},
+};
+
+/// VCP code for restoring the monitor's factory defaults. Writing any non-zero value triggers it.
+pub const VCP_RESTORE_FACTORY_DEFAULTS: u8 = 0x04;
+
+/// VCP code for the power mode of the monitor: 1 is on, 4 is standby, 5 is off.
+pub const VCP_POWER_MODE: u8 = 0xD6;
+
+/// VCP code for the volume of the monitor's built-in speakers.
+pub const VCP_SPEAKER_VOLUME: u8 = 0x62;
+
+/// VCP code for the brightness, for the pictures that `GetMonitorBrightness` does not cover.
+const VCP_BRIGHTNESS: u8 = 0x10;
+
+/// VCP code that selects which picture the following VCP codes apply to, for monitors that show
+/// several in picture-by-picture or picture-in-picture mode: 0 for the whole display, and the
+/// pictures besides the main one from 1.
+pub const VCP_WINDOW_SELECT: u8 = 0xA5;
+
+/// A physical monitor that supports DDC/CI. The underlying handle is released when dropped.
 pub struct Monitor {
-    physical_monitor: PHYSICAL_MONITOR,
+    connection: Connection,
     device_name: String,
     min_brightness: u32,
     current_brightness: u32,
     max_brightness: u32,
+    capabilities: Option<Capabilities>,
+    display_index: usize,
+    brightness_read_failed: bool,
+    /// Current and maximum speaker volume, if the monitor has speakers that can be controlled.
+    volume: Option<(u32, u32)>,
+    /// Window number, current and maximum brightness of each picture besides the main one, see
+    /// `get_pictures`.
+    pictures: Vec<(u8, u32, u32)>,
+    /// Luminance in nits at the lowest and highest brightness, if the display reports it.
+    luminance_range: Option<(f32, f32)>,
+    /// Percentage of the brightness range that the highest brightness maps to.
+    brightness_cap: u32,
+    /// Values written to the monitor in place of the capped brightness, see `set_calibration`.
+    calibration: Option<Calibration>,
+    /// Percentage of the brightness range taken off before capping, from -100 to 0, see
+    /// `set_brightness_offset`.
+    brightness_offset: i32,
+    /// Whether the monitor shows the primary display.
+    primary: bool,
+    /// Bounds of the display in virtual screen coordinates, if Windows reported them.
+    position: Option<RECT>,
+    /// The monitor as known to the display configuration API, for its HDR settings.
+    display_target: Option<DisplayTarget>,
+    /// Whether the brightness is the SDR white level in HDR mode instead of the one through DDC/CI,
+    /// see `use_sdr_white_level`.
+    sdr_white_level: bool,
+    /// The panel technology, as reported when the monitor was enumerated.
+    technology: DisplayTechnology,
 }
 
 impl Drop for Monitor {
     fn drop(&mut self) {
-        unsafe {
-            DestroyPhysicalMonitor(self.physical_monitor.hPhysicalMonitor);
+        match &self.connection {
+            Connection::Real(physical_monitor) => unsafe {
+                DestroyPhysicalMonitor(physical_monitor.hPhysicalMonitor);
+            },
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(_) => (),
         }
     }
 }
 
-impl Monitor {
-    pub fn try_set_brightness(&mut self, brightness: u32) -> Result<()> {
-        unsafe {
-            let result = SetMonitorBrightness(
-                self.physical_monitor.hPhysicalMonitor,
-                brightness.clamp(self.min_brightness, self.max_brightness),
-            );
-            if result != 0 {
-                // TODO: Maybe store brightness in Windows registry to allow persistence
-                self.current_brightness = brightness;
+/// How a `Monitor` is talked to.
+enum Connection {
+    /// Through DDC/CI, with the handle of the physical monitor.
+    Real(PHYSICAL_MONITOR),
+    /// Not at all; the calls are answered from memory, see `Monitor::fake`.
+    #[cfg(any(test, feature = "fake-monitor"))]
+    Fake(FakeDdc),
+}
+
+/// What a fake monitor answers to DDC/CI calls.
+#[cfg(any(test, feature = "fake-monitor"))]
+struct FakeDdc {
+    /// The minimum, current, and maximum brightness, as written to the monitor.
+    brightness: (u32, u32, u32),
+    /// Current and maximum value of each VCP code that can be read and written.
+    vcp_codes: BTreeMap<u8, (u32, u32)>,
+    capabilities: String,
+}
+
+impl Connection {
+    /// The handle of the physical monitor, or a null handle for a fake one, which makes any
+    /// DDC/CI function fail.
+    fn handle(&self) -> HANDLE {
+        match self {
+            Connection::Real(physical_monitor) => physical_monitor.hPhysicalMonitor,
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(_) => HANDLE::default(),
+        }
+    }
+
+    /// `SetMonitorBrightness`.
+    fn set_brightness(&mut self, brightness: u32) -> Result<()> {
+        match self {
+            Connection::Real(physical_monitor) => unsafe {
+                if SetMonitorBrightness(physical_monitor.hPhysicalMonitor, brightness) != 0 {
+                    Ok(())
+                } else {
+                    Err(windows::core::Error::from_win32())
+                }
+            },
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(fake) => {
+                let (min, _, max) = fake.brightness;
+                fake.brightness.1 = clamp_brightness(brightness, min, max);
                 Ok(())
-            } else {
-                Err(windows::core::Error::from_win32())
             }
         }
     }
 
+    /// `GetMonitorBrightness`, see `read_brightness`.
+    fn brightness(&self) -> Result<(u32, u32, u32)> {
+        match self {
+            Connection::Real(physical_monitor) => {
+                read_brightness(physical_monitor.hPhysicalMonitor)
+            }
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(fake) => Ok(fake.brightness),
+        }
+    }
+
+    /// `SetVCPFeature`.
+    fn set_vcp_feature(&mut self, code: u8, value: u32) -> Result<()> {
+        match self {
+            Connection::Real(physical_monitor) => unsafe {
+                if SetVCPFeature(physical_monitor.hPhysicalMonitor, code, value) != 0 {
+                    Ok(())
+                } else {
+                    Err(windows::core::Error::from_win32())
+                }
+            },
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(fake) => {
+                let (current, max) = (fake.vcp_codes.get_mut(&code))
+                    .ok_or_else(|| windows::core::Error::from(E_NOTIMPL))?;
+                *current = value.min(*max);
+                Ok(())
+            }
+        }
+    }
+
+    /// `GetVCPFeatureAndVCPFeatureReply`, as the current and maximum value.
+    fn vcp_feature(&self, code: u8) -> Result<(u32, u32)> {
+        match self {
+            Connection::Real(physical_monitor) => {
+                let mut current_value = 0;
+                let mut maximum_value = 0;
+                unsafe {
+                    let result = GetVCPFeatureAndVCPFeatureReply(
+                        physical_monitor.hPhysicalMonitor,
+                        code,
+                        std::ptr::null_mut(),
+                        &mut current_value,
+                        &mut maximum_value,
+                    );
+                    if result != 0 {
+                        Ok((current_value, maximum_value))
+                    } else {
+                        Err(windows::core::Error::from_win32())
+                    }
+                }
+            }
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(fake) => (fake.vcp_codes.get(&code).copied())
+                .ok_or_else(|| windows::core::Error::from(E_NOTIMPL)),
+        }
+    }
+
+    /// The raw capabilities string, see `read_capabilities`.
+    fn capabilities_string(&self) -> Result<String> {
+        match self {
+            Connection::Real(physical_monitor) => {
+                read_capabilities(physical_monitor.hPhysicalMonitor)
+            }
+            #[cfg(any(test, feature = "fake-monitor"))]
+            Connection::Fake(fake) => Ok(fake.capabilities.clone()),
+        }
+    }
+}
+
+impl Monitor {
+    /// A monitor that is not connected to anything, for testing code that takes monitors without
+    /// hardware. It answers like a monitor named `name` with the minimum, current, and maximum
+    /// `brightness` and the capabilities string `capabilities` would, e.g. `(vcp(10 62 D6))`: the
+    /// VCP codes listed there can be read and written, starting at 0 of a maximum of 100, and the
+    /// others fail with `E_NOTIMPL`. It shows the primary display if `primary`.
+    ///
+    /// Only available in the crate's own tests or with the `fake-monitor` feature.
+    #[cfg(any(test, feature = "fake-monitor"))]
+    pub fn fake(
+        name: &str,
+        brightness: (u32, u32, u32),
+        capabilities: &str,
+        primary: bool,
+    ) -> Monitor {
+        let parsed = Capabilities::parse(capabilities);
+        let vcp_codes = parsed.vcp_codes().map(|code| (code, (0, 100))).collect();
+        let (min_brightness, current_brightness, max_brightness) = brightness;
+        let mut monitor = Monitor {
+            connection: Connection::Fake(FakeDdc {
+                brightness,
+                vcp_codes,
+                capabilities: capabilities.to_owned(),
+            }),
+            device_name: name.to_owned(),
+            min_brightness,
+            current_brightness,
+            max_brightness,
+            capabilities: Some(parsed),
+            display_index: 0,
+            brightness_read_failed: false,
+            volume: None,
+            pictures: Vec::new(),
+            luminance_range: None,
+            brightness_cap: 100,
+            calibration: None,
+            brightness_offset: 0,
+            primary,
+            position: None,
+            display_target: None,
+            sdr_white_level: false,
+            technology: DisplayTechnology::Unknown,
+        };
+        read_features(&mut monitor);
+        monitor
+    }
+
+    /// Set the brightness, clamped to the range reported by the monitor and lowered according to
+    /// `set_brightness_cap` or mapped through `set_calibration`, with a single attempt.
+    pub fn try_set_brightness(&mut self, brightness: u32) -> Result<()> {
+        let brightness = clamp_brightness(brightness, self.min_brightness, self.max_brightness);
+        if let Some(target) = self.sdr_target() {
+            let range = (self.min_brightness, self.max_brightness);
+            let nits = brightness_to_nits(self.capped(brightness), SDR_WHITE_LEVEL_RANGE, range);
+            target.set_sdr_white_level(nits)?;
+            self.current_brightness = brightness;
+            return Ok(());
+        }
+        let capped = self.capped(brightness);
+        self.connection.set_brightness(capped)?;
+        // TODO: Maybe store brightness in Windows registry to allow persistence
+        self.current_brightness = brightness;
+        Ok(())
+    }
+
+    /// Set the brightness, clamped to the range reported by the monitor. Failed attempts are
+    /// retried with the default `Backoff` which can block the calling thread for about a second.
     pub fn set_brightness(&mut self, brightness: u32) -> Result<()> {
-        // 10ms, 20ms, 40ms, 80ms, etc.
-        let expo_backoff: [_; 8] = std::array::from_fn(|i| Duration::from_millis(10 * (1 << i)));
+        self.set_brightness_with_backoff(brightness, &Backoff::default())
+    }
 
+    /// Like `set_brightness` but retries according to `backoff`, e.g. with a shorter budget.
+    pub fn set_brightness_with_backoff(
+        &mut self,
+        brightness: u32,
+        backoff: &Backoff,
+    ) -> Result<()> {
         // Setting the brightness sometimes fail (i.e., when it's done repeatedly without
-        // sleeping). This loop retries it, waiting for increasingly long periods after
-        // each failure.
-        let mut result = Ok(());
-        for duration in expo_backoff {
-            result = self.try_set_brightness(brightness);
-            if result.is_ok() {
-                return Ok(());
+        // sleeping)
+        backoff.retry(|| self.try_set_brightness(brightness))
+    }
+
+    /// Change the brightness by `delta`, e.g. `-5` to make it 5 steps darker. Like `set_brightness`
+    /// the result is clamped to the range reported by the monitor.
+    pub fn step_brightness(&mut self, delta: i32) -> Result<()> {
+        self.set_brightness(step_brightness(self.current_brightness, delta))
+    }
+
+    /// Set the VCP `code` to `value`, e.g. `SetVCPFeature` on the physical monitor.
+    pub fn set_vcp_feature(&mut self, code: u8, value: u32) -> Result<()> {
+        self.connection.set_vcp_feature(code, value)
+    }
+
+    /// Lend the handle of the physical monitor to `f`, e.g. to call DDC/CI functions of
+    /// `Win32::Devices::Display` that are not wrapped here.
+    ///
+    /// The handle is only valid until the monitor is dropped, which destroys it with
+    /// `DestroyPhysicalMonitor`, so `f` must neither keep a copy of it nor destroy it itself.
+    /// Borrowing the monitor mutably keeps its other methods from talking to it at the same time.
+    /// Changes made through the handle are not reflected in the cached values, e.g.
+    /// `get_brightness` after a write of the brightness, until `refresh_brightness`. Monitors made
+    /// with `fake` lend a null handle.
+    pub fn with_handle<T>(&mut self, f: impl FnOnce(HANDLE) -> T) -> T {
+        f(self.connection.handle())
+    }
+
+    /// Read the current and maximum values of the VCP `code`.
+    pub fn get_vcp_feature(&self, code: u8) -> Result<(u32, u32)> {
+        self.connection.vcp_feature(code)
+    }
+
+    /// Set the volume of the monitor's speakers, clamped to the maximum it reported.
+    pub fn set_volume(&mut self, volume: u32) -> Result<()> {
+        let max_volume = match self.volume {
+            Some((_, max_volume)) => max_volume,
+            // No speakers, or they did not answer when the monitor was enumerated
+            None => return Err(windows::core::Error::from(E_NOTIMPL)),
+        };
+        let volume = volume.min(max_volume);
+        self.set_vcp_feature(VCP_SPEAKER_VOLUME, volume)?;
+        self.volume = Some((volume, max_volume));
+        Ok(())
+    }
+
+    /// Set the brightness of the picture shown as window number `window` of `get_pictures`, clamped
+    /// to its maximum. The whole display is selected again afterwards, also if writing failed.
+    pub fn set_picture_brightness(&mut self, window: u8, brightness: u32) -> Result<()> {
+        let picture = (self.pictures.iter())
+            .position(|&(number, _, _)| number == window)
+            // Only the pictures that answered when the monitor was enumerated
+            .ok_or_else(|| windows::core::Error::from(E_NOTIMPL))?;
+        let brightness = brightness.min(self.pictures[picture].2);
+        let result = self
+            .set_vcp_feature(VCP_WINDOW_SELECT, window as u32)
+            .and_then(|()| self.set_vcp_feature(VCP_BRIGHTNESS, brightness));
+        let _ = self.set_vcp_feature(VCP_WINDOW_SELECT, 0);
+        result?;
+        self.pictures[picture].1 = brightness;
+        Ok(())
+    }
+
+    /// Set the brightness at which the monitor is closest to `nits`, assuming that its luminance
+    /// follows the brightness linearly. Fails with `E_NOTIMPL` unless `get_luminance_range` is
+    /// known.
+    pub fn set_luminance_nits(&mut self, nits: f32) -> Result<()> {
+        let luminance_range = self
+            .luminance_range
+            .ok_or_else(|| windows::core::Error::from(E_NOTIMPL))?;
+        let luminance_range = self.capped_luminance_range(luminance_range);
+        let brightness_range = (self.min_brightness, self.max_brightness);
+        self.set_brightness(nits_to_brightness(nits, luminance_range, brightness_range))
+    }
+
+    /// Limit the brightness to `cap` percent of the range reported by the monitor, from 1 to 100.
+    /// The range seen through the other methods stays the same and is mapped onto the lower
+    /// `cap` percent, e.g. `get_max_brightness` is then written as `cap` percent of it. The
+    /// monitor itself is not written to.
+    pub fn set_brightness_cap(&mut self, cap: u32) {
+        let hardware = self.capped(self.current_brightness);
+        self.brightness_cap = cap.clamp(1, 100);
+        self.current_brightness = self.uncapped(hardware);
+    }
+
+    /// Percentage of the brightness range that `get_max_brightness` maps to, 100 unless changed
+    /// with `set_brightness_cap`.
+    pub fn get_brightness_cap(&self) -> u32 {
+        self.brightness_cap
+    }
+
+    /// Write the values of `calibration` to the monitor instead of the brightness, for monitors
+    /// whose response is far from linear. The percentages of the table are of the range reported
+    /// by the monitor, and the values are clamped to it. `None` goes back to the linear mapping of
+    /// `set_brightness_cap`, which has no effect while a calibration is set. The monitor itself is
+    /// not written to.
+    pub fn set_calibration(&mut self, calibration: Option<Calibration>) {
+        let hardware = self.capped(self.current_brightness);
+        self.calibration = calibration;
+        self.current_brightness = self.uncapped(hardware);
+    }
+
+    /// Lower everything written to the monitor by `offset` percent of its range, from -100 to 0,
+    /// clamped to its minimum. Applied before `set_brightness_cap` or `set_calibration`, and like
+    /// those the range seen through the other methods stays the same. The monitor itself is not
+    /// written to; the brightness has to be set again for the offset to show.
+    pub fn set_brightness_offset(&mut self, offset: i32) {
+        let hardware = self.capped(self.current_brightness);
+        self.brightness_offset = offset.clamp(-100, 0);
+        self.current_brightness = self.uncapped(hardware);
+    }
+
+    /// The calibration in effect: the one of `set_calibration`, or else the linear mapping up to
+    /// `get_brightness_cap`. Does not include `set_brightness_offset`.
+    pub fn get_calibration(&self) -> Calibration {
+        let range = (self.min_brightness, self.max_brightness);
+        match &self.calibration {
+            Some(calibration) => calibration.clone(),
+            None => Calibration::linear(
+                self.min_brightness,
+                cap_brightness(self.max_brightness, range, self.brightness_cap),
+            ),
+        }
+    }
+
+    /// `brightness` as written to the monitor.
+    fn capped(&self, brightness: u32) -> u32 {
+        let range = (self.min_brightness, self.max_brightness);
+        let brightness = offset_brightness(brightness, range, self.brightness_offset);
+        match &self.calibration {
+            Some(calibration) => {
+                let value = calibration.apply(brightness, range);
+                clamp_brightness(value, self.min_brightness, self.max_brightness)
             }
-            thread::sleep(duration);
+            None => cap_brightness(brightness, range, self.brightness_cap),
+        }
+    }
+
+    /// Brightness read from the monitor as seen through `get_brightness`.
+    fn uncapped(&self, brightness: u32) -> u32 {
+        let range = (self.min_brightness, self.max_brightness);
+        let brightness = match &self.calibration {
+            Some(calibration) => calibration.invert(brightness, range),
+            None => uncap_brightness(brightness, range, self.brightness_cap),
+        };
+        offset_brightness(brightness, range, -self.brightness_offset)
+    }
+
+    /// `brightness` read from the monitor as seen through `get_brightness`, or the current
+    /// brightness if that is what it was written as. `capped` is not invertible where the offset
+    /// clamps to the minimum, e.g. 10% with an offset of -20 is written as 0% and would otherwise
+    /// be read back as 20%.
+    fn read_back(&self, brightness: u32) -> u32 {
+        if self.capped(self.current_brightness) == brightness {
+            self.current_brightness
+        } else {
+            self.uncapped(brightness)
+        }
+    }
+
+    /// The luminance range shrunk along with the brightness range by the cap.
+    fn capped_luminance_range(&self, (min_nits, max_nits): (f32, f32)) -> (f32, f32) {
+        let cap = self.brightness_cap as f32 / 100.0;
+        (min_nits, min_nits + (max_nits - min_nits) * cap)
+    }
+
+    /// Restores the monitor's factory defaults then re-reads the brightness it was reset to.
+    pub fn reset_to_factory(&mut self) -> Result<()> {
+        self.set_vcp_feature(VCP_RESTORE_FACTORY_DEFAULTS, 1)?;
+        // The monitor is usually busy for a while after the reset so the read needs retrying
+        Backoff::default().retry(|| self.refresh_brightness())
+    }
+
+    /// Puts the monitor into standby, or turns it back on. Unlike a display turned off by Windows,
+    /// the monitor stays off until this is called again or its power button is pressed.
+    pub fn set_standby(&mut self, standby: bool) -> Result<()> {
+        const ON: u32 = 1;
+        const STANDBY: u32 = 4;
+        self.set_vcp_feature(VCP_POWER_MODE, if standby { STANDBY } else { ON })
+    }
+
+    /// The brightness that the monitor is at right now as read through DDC/CI, without the
+    /// mapping of `set_calibration` or `set_brightness_cap`, e.g. after it was set with the
+    /// monitor's own buttons. The cached values are left alone.
+    pub fn read_raw_brightness(&self) -> Result<u32> {
+        let (_, current_brightness, _) = self.connection.brightness()?;
+        Ok(current_brightness)
+    }
+
+    /// Re-reads the brightness from the monitor and updates the cached values.
+    pub fn refresh_brightness(&mut self) -> Result<()> {
+        if let Some(target) = self.sdr_target() {
+            let nits = target.sdr_white_level()?;
+            let range = (self.min_brightness, self.max_brightness);
+            self.current_brightness =
+                self.read_back(nits_to_brightness(nits, SDR_WHITE_LEVEL_RANGE, range));
+            return Ok(());
         }
-        // Return last result of `try_set_brightness`
-        result
+        let (min_brightness, current_brightness, max_brightness) = self.connection.brightness()?;
+        self.min_brightness = min_brightness;
+        self.max_brightness = max_brightness;
+        self.current_brightness = self.read_back(current_brightness);
+        self.brightness_read_failed = false;
+        Ok(())
+    }
+
+    /// Whether the display of the monitor is in HDR mode right now. `false` if that is not known,
+    /// e.g. with several physical monitors on one display.
+    pub fn is_hdr_enabled(&self) -> bool {
+        self.display_target
+            .is_some_and(|target| target.is_hdr_enabled())
+    }
+
+    /// Control the brightness of SDR content that Windows uses in HDR mode, like the slider of the
+    /// display settings, instead of the monitor's brightness through DDC/CI. The brightness then
+    /// goes from 0 to 100 for 80 to 480 nits, which is also its luminance range. Fails with
+    /// `E_NOTIMPL` unless `is_hdr_enabled`. Stays in effect if HDR is turned off afterwards, when
+    /// the writes go nowhere visible until it is turned on again.
+    pub fn use_sdr_white_level(&mut self) -> Result<()> {
+        let target = (self.display_target)
+            .filter(|target| target.is_hdr_enabled())
+            .ok_or_else(|| windows::core::Error::from(E_NOTIMPL))?;
+        let nits = target.sdr_white_level()?;
+        self.sdr_white_level = true;
+        self.min_brightness = 0;
+        self.max_brightness = 100;
+        self.luminance_range = Some(SDR_WHITE_LEVEL_RANGE);
+        self.brightness_read_failed = false;
+        self.current_brightness =
+            self.uncapped(nits_to_brightness(nits, SDR_WHITE_LEVEL_RANGE, (0, 100)));
+        Ok(())
+    }
+
+    /// Whether `use_sdr_white_level` switched the brightness to the SDR white level.
+    pub fn controls_sdr_white_level(&self) -> bool {
+        self.sdr_white_level
+    }
+
+    fn sdr_target(&self) -> Option<DisplayTarget> {
+        self.display_target.filter(|_| self.sdr_white_level)
+    }
+
+    /// Whether the brightness could not be read when the monitor was enumerated and has not been
+    /// since. The cached values are then only a guess: `FALLBACK_BRIGHTNESS` within 0 to 100.
+    pub fn brightness_read_failed(&self) -> bool {
+        self.brightness_read_failed
     }
 
+    /// The last brightness that was read from or successfully written to the monitor.
     pub fn get_brightness(&self) -> u32 {
         self.current_brightness
     }
 
+    /// The lowest brightness value accepted by the monitor.
+    pub fn get_min_brightness(&self) -> u32 {
+        self.min_brightness
+    }
+
+    /// The highest brightness value accepted by the monitor.
+    pub fn get_max_brightness(&self) -> u32 {
+        self.max_brightness
+    }
+
+    /// The last speaker volume that was read from or successfully written to the monitor, together
+    /// with its maximum. `None` unless `supports_volume`.
+    pub fn get_volume(&self) -> Option<(u32, u32)> {
+        self.volume
+    }
+
+    /// Luminance in nits at the lowest and highest brightness, as `(min, max)`. Taken from the
+    /// display's EDID, so it is only known for displays with a single physical monitor that report
+    /// it, usually HDR ones. The maximum is lowered along with the brightness by
+    /// `set_brightness_cap`.
+    pub fn get_luminance_range(&self) -> Option<(f32, f32)> {
+        (self.luminance_range).map(|range| self.capped_luminance_range(range))
+    }
+
+    /// Description of the monitor as reported by Windows.
     pub fn get_name(&self) -> &str {
         &self.device_name
     }
 
-    pub fn get_monitors() -> Result<Vec<Monitor>> {
-        let mut monitors = Vec::new();
-        let monitor_handles = get_monitor_handles()?;
-        for &monitor_handle in &monitor_handles {
-            for physical_monitor in get_physical_monitors(monitor_handle)? {
-                let mut min_brightness: MaybeUninit<u32> = MaybeUninit::uninit();
-                let mut current_brightness: MaybeUninit<u32> = MaybeUninit::uninit();
-                let mut max_brightness: MaybeUninit<u32> = MaybeUninit::uninit();
+    /// Whether the monitor shows the primary display, the one with the taskbar's notification area
+    /// and the origin of the virtual screen.
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
 
-                let device_name = {
-                    // Copy the `[u16; 128]` to a stack variable to avoid dealing with a reference to
-                    // a packed struct member and be forced to use unaligned pointer reads
-                    let desc = physical_monitor.szPhysicalMonitorDescription;
-                    string_from_raw_utf16(&desc)
-                };
+    /// Bounds of the display that the monitor shows, in virtual screen coordinates, e.g. for
+    /// placing a window on it. `None` if they were not reported when enumerating the displays.
+    pub fn bounds(&self) -> Option<RECT> {
+        self.position
+    }
 
-                unsafe {
-                    let result = GetMonitorBrightness(
-                        physical_monitor.hPhysicalMonitor,
-                        min_brightness.as_mut_ptr(),
-                        current_brightness.as_mut_ptr(),
-                        max_brightness.as_mut_ptr(),
-                    );
+    /// Index of the display (`HMONITOR`) that the monitor belongs to. Monitors with the same index
+    /// are showing the same content, e.g. when duplicating displays.
+    pub fn display_index(&self) -> usize {
+        self.display_index
+    }
 
-                    let monitor = Monitor {
-                        physical_monitor,
-                        device_name: device_name.clone(),
-                        min_brightness: min_brightness.assume_init(),
-                        current_brightness: current_brightness.assume_init(),
-                        max_brightness: max_brightness.assume_init(),
-                    };
+    /// The parsed capabilities string of the monitor. `None` if the monitor failed to report it.
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
 
-                    if result != 0 {
-                        monitors.push(monitor);
+    /// The capabilities string as the monitor reports it now, unparsed, e.g. for diagnostics.
+    pub fn read_capabilities_string(&self) -> Result<String> {
+        self.connection.capabilities_string()
+    }
+
+    /// The technology of the monitor's panel, from `VCP_DISPLAY_TECHNOLOGY` if the monitor supports
+    /// it or else the type in its capabilities string. `DisplayTechnology::Unknown` if it reports
+    /// neither.
+    pub fn technology(&self) -> DisplayTechnology {
+        self.technology
+    }
+
+    /// Whether the monitor advertises support for restoring its factory defaults.
+    pub fn supports_factory_reset(&self) -> bool {
+        self.capabilities()
+            .is_some_and(|c| c.supports(VCP_RESTORE_FACTORY_DEFAULTS))
+    }
+
+    /// The pictures besides the main one, whose brightness is not the one of `get_brightness`, as
+    /// their window number and the last current and maximum brightness read from or written to
+    /// them. Empty unless the monitor lists the windows of `VCP_WINDOW_SELECT` and showed several
+    /// pictures when it was enumerated.
+    pub fn get_pictures(&self) -> &[(u8, u32, u32)] {
+        &self.pictures
+    }
+
+    /// Whether the monitor advertises speakers whose volume could be read when it was enumerated.
+    pub fn supports_volume(&self) -> bool {
+        self.volume.is_some()
+    }
+
+    /// Whether the monitor advertises support for switching its power mode with `set_standby`.
+    pub fn supports_standby(&self) -> bool {
+        self.capabilities()
+            .is_some_and(|c| c.supports(VCP_POWER_MODE))
+    }
+}
+
+/// Brightness assumed for a monitor whose brightness could not be read, in the middle of the
+/// assumed 0 to 100 range.
+pub const FALLBACK_BRIGHTNESS: u32 = 50;
+
+/// Enumerate the physical monitors of all connected displays. Reading the brightness is retried
+/// like writes are. Monitors whose brightness still cannot be read are kept with
+/// `FALLBACK_BRIGHTNESS` if they answered the capabilities request, and skipped otherwise.
+/// Displays whose physical monitors cannot be listed even after retrying are skipped too, so the
+/// result may be partial, e.g. while the display mode is changing.
+pub fn get_monitors() -> Result<Vec<Monitor>> {
+    let mut monitors = Vec::new();
+    let displays = get_monitor_handles()?;
+    for (display_index, display) in displays.iter().enumerate() {
+        let monitor_handle = display.handle;
+        // The number of physical monitors is read again on each attempt since it may have changed
+        let physical_monitors =
+            match Backoff::default().retry(|| get_physical_monitors(monitor_handle)) {
+                Ok(physical_monitors) => physical_monitors,
+                Err(_) => continue,
+            };
+        let num_physical_monitors = physical_monitors.len();
+        let luminance_range = read_luminance_range(monitor_handle);
+        // Like the luminance, the HDR settings cannot be told apart with several physical monitors
+        let display_target =
+            DisplayTarget::find(monitor_handle).filter(|_| num_physical_monitors == 1);
+        for physical_monitor in physical_monitors {
+            let handle = physical_monitor.hPhysicalMonitor;
+            let device_name = {
+                // Copy the `[u16; 128]` to a stack variable to avoid dealing with a reference to
+                // a packed struct member and be forced to use unaligned pointer reads
+                let desc = physical_monitor.szPhysicalMonitorDescription;
+                string_from_raw_utf16(&desc)
+            };
+            let capabilities = read_capabilities(handle)
+                .ok()
+                .map(|raw| Capabilities::parse(&raw));
+
+            // The brightness values are only taken from a successful read, never from the out
+            // parameters of a failed `GetMonitorBrightness` call, which may be uninitialized
+            let brightness = Backoff::default().retry(|| read_brightness(handle));
+            let (min_brightness, current_brightness, max_brightness) = match brightness {
+                Ok(brightness) => brightness,
+                // Some monitors can be written to but not read from
+                Err(_) if capabilities.is_some() => (0, FALLBACK_BRIGHTNESS, 100),
+                Err(_) => {
+                    unsafe {
+                        DestroyPhysicalMonitor(handle);
                     }
-                };
+                    continue;
+                }
+            };
+
+            let mut monitor = Monitor {
+                connection: Connection::Real(physical_monitor),
+                device_name,
+                min_brightness,
+                current_brightness,
+                max_brightness,
+                capabilities,
+                display_index,
+                brightness_read_failed: brightness.is_err(),
+                volume: None,
+                pictures: Vec::new(),
+                // With several physical monitors there is no telling which one it belongs to
+                luminance_range: luminance_range.filter(|_| num_physical_monitors == 1),
+                brightness_cap: 100,
+                calibration: None,
+                brightness_offset: 0,
+                primary: display.primary,
+                position: display.bounds,
+                display_target,
+                sdr_white_level: false,
+                technology: DisplayTechnology::Unknown,
+            };
+            read_features(&mut monitor);
+            monitors.push(monitor);
+        }
+    }
+    Ok(monitors)
+}
+
+/// Read the speaker volume, panel technology, and pictures of a newly enumerated `monitor`, for the
+/// features its capabilities string lists.
+fn read_features(monitor: &mut Monitor) {
+    if (monitor.capabilities()).is_some_and(|c| c.supports(VCP_SPEAKER_VOLUME)) {
+        // Not retried; the monitor is still usable without its speakers
+        monitor.volume = monitor.get_vcp_feature(VCP_SPEAKER_VOLUME).ok();
+    }
+    monitor.technology = read_technology(monitor);
+    monitor.pictures = read_pictures(monitor);
+}
+
+/// The panel technology of `monitor`, see `Monitor::technology`. Not retried, like the volume.
+fn read_technology(monitor: &Monitor) -> DisplayTechnology {
+    let capabilities = match monitor.capabilities() {
+        Some(capabilities) => capabilities,
+        None => return DisplayTechnology::Unknown,
+    };
+    let from_vcp = (capabilities.supports(VCP_DISPLAY_TECHNOLOGY))
+        .then(|| monitor.get_vcp_feature(VCP_DISPLAY_TECHNOLOGY).ok())
+        .flatten()
+        .map(|(value, _)| DisplayTechnology::from_vcp_value(value))
+        .filter(|&technology| technology != DisplayTechnology::Unknown);
+    from_vcp.unwrap_or_else(|| {
+        (capabilities.display_type()).map_or(DisplayTechnology::Unknown, |tag| {
+            DisplayTechnology::from_type_tag(tag)
+        })
+    })
+}
+
+/// The pictures of `monitor` besides the main one, see `Monitor::get_pictures`. Monitors that show
+/// a single picture refuse to select the others. Not retried, like the volume.
+fn read_pictures(monitor: &mut Monitor) -> Vec<(u8, u32, u32)> {
+    let windows: Vec<u8> = match monitor.capabilities() {
+        Some(capabilities) => (capabilities.values(VCP_WINDOW_SELECT).iter())
+            .copied()
+            .filter(|&window| window != 0)
+            .collect(),
+        None => return Vec::new(),
+    };
+    if windows.is_empty() {
+        return Vec::new();
+    }
+    let pictures = (windows.into_iter())
+        .filter_map(|window| {
+            monitor
+                .set_vcp_feature(VCP_WINDOW_SELECT, window as u32)
+                .ok()?;
+            let (current, max) = monitor.get_vcp_feature(VCP_BRIGHTNESS).ok()?;
+            Some((window, current, max))
+        })
+        .collect();
+    let _ = monitor.set_vcp_feature(VCP_WINDOW_SELECT, 0);
+    pictures
+}
+
+/// Luminance range of the display `monitor_handle` in nits, as `(min, max)`. DXGI takes it from
+/// the EDID. `None` if the display is not found or reports no usable range.
+fn read_luminance_range(monitor_handle: HMONITOR) -> Option<(f32, f32)> {
+    let factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1().ok()? };
+    let adapters = (0..).map_while(|i| unsafe { factory.EnumAdapters1(i).ok() });
+    for adapter in adapters {
+        let outputs = (0..).map_while(|i| unsafe { adapter.EnumOutputs(i).ok() });
+        for output in outputs {
+            // `IDXGIOutput6` needs Windows 10 1803
+            let desc = match output.cast::<IDXGIOutput6>() {
+                Ok(output) => unsafe { output.GetDesc1() },
+                Err(_) => return None,
+            };
+            if let Ok(desc) = desc {
+                if desc.Monitor == monitor_handle {
+                    return luminance_range(desc.MinLuminance, desc.MaxLuminance);
+                }
             }
         }
-        Ok(monitors)
     }
+    None
+}
+
+/// `(min, max)` if they make a usable luminance range. Displays without the information in their
+/// EDID report zeroes.
+fn luminance_range(min: f32, max: f32) -> Option<(f32, f32)> {
+    (min >= 0.0 && max > min).then_some((min, max))
+}
+
+/// The brightness within `brightness_range` at which a monitor whose luminance spans
+/// `luminance_range` over that range is closest to `nits`, assuming the luminance follows the
+/// brightness linearly. Both ranges are `(min, max)`; `nits` outside of the luminance range are
+/// clamped to it.
+pub fn nits_to_brightness(
+    nits: f32,
+    luminance_range: (f32, f32),
+    brightness_range: (u32, u32),
+) -> u32 {
+    let (min_nits, max_nits) = luminance_range;
+    let (min, max) = brightness_range;
+    let fraction = ((nits - min_nits) / (max_nits - min_nits)).clamp(0.0, 1.0);
+    min + (fraction * max.saturating_sub(min) as f32).round() as u32
 }
 
-/// Get handles to all connected monitors. The returned handles does not need to be manually freed.
-fn get_monitor_handles() -> Result<Vec<HMONITOR>> {
+/// The inverse of `nits_to_brightness`: the luminance in nits at `brightness`.
+pub fn brightness_to_nits(
+    brightness: u32,
+    luminance_range: (f32, f32),
+    brightness_range: (u32, u32),
+) -> f32 {
+    let (min_nits, max_nits) = luminance_range;
+    let (min, max) = brightness_range;
+    if max <= min {
+        return max_nits;
+    }
+    let fraction = (brightness.clamp(min, max) - min) as f32 / (max - min) as f32;
+    min_nits + fraction * (max_nits - min_nits)
+}
+
+/// Read the minimum, current, and maximum brightness of a physical monitor. `Monitor`s are only
+/// built from the values of a successful read; see `get_monitors`.
+fn read_brightness(handle: HANDLE) -> Result<(u32, u32, u32)> {
+    // Initialized, unlike with `MaybeUninit`, since `GetMonitorBrightness` is not guaranteed to
+    // write anything when it fails
+    let mut min_brightness = 0;
+    let mut current_brightness = 0;
+    let mut max_brightness = 0;
+    unsafe {
+        let result = GetMonitorBrightness(
+            handle,
+            &mut min_brightness,
+            &mut current_brightness,
+            &mut max_brightness,
+        );
+        if result != 0 {
+            Ok((min_brightness, current_brightness, max_brightness))
+        } else {
+            Err(windows::core::Error::from_win32())
+        }
+    }
+}
+
+/// Request the raw capabilities string of a physical monitor. This is slow; it can take more than
+/// a second on some monitors.
+fn read_capabilities(handle: HANDLE) -> Result<String> {
+    unsafe {
+        let mut len = 0;
+        if GetCapabilitiesStringLength(handle, &mut len) == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        let mut buf = vec![0u8; len as usize];
+        if CapabilitiesRequestAndCapabilitiesReply(handle, &mut buf) == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        // The string is ASCII and null-terminated
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+}
+
+/// A display as enumerated by `get_monitor_handles`.
+struct Display {
+    handle: HMONITOR,
+    /// Whether it is the primary display.
+    primary: bool,
+    /// Its rectangle in virtual screen coordinates.
+    bounds: Option<RECT>,
+}
+
+/// Get handles to all connected monitors, with whether they are the primary display and their
+/// bounds. The returned handles does not need to be manually freed.
+fn get_monitor_handles() -> Result<Vec<Display>> {
     unsafe extern "system" fn callback(
         monitor_handle: HMONITOR,
         _: HDC,
-        _: *mut RECT,
+        rect: *mut RECT,
         param: LPARAM,
     ) -> BOOL {
-        let monitors: &mut Vec<HMONITOR> = &mut *(param.0 as *mut Vec<HMONITOR>);
-        monitors.push(monitor_handle);
+        let monitors: &mut Vec<Display> = &mut *(param.0 as *mut Vec<Display>);
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        // Treated as not primary if the display went away in the meantime
+        let primary = GetMonitorInfoW(monitor_handle, &mut info).as_bool()
+            && info.dwFlags & MONITORINFOF_PRIMARY != 0;
+        monitors.push(Display {
+            handle: monitor_handle,
+            primary,
+            bounds: rect.as_ref().copied(),
+        });
         BOOL(1)
     }
 
-    let mut monitors: Vec<HMONITOR> = Vec::new();
+    let mut monitors: Vec<Display> = Vec::new();
     let result = unsafe {
         EnumDisplayMonitors(
             HDC::default(),
@@ -152,6 +931,8 @@ fn get_num_physical_monitors(monitor_handle: HMONITOR) -> Result<u32> {
             monitor_handle,
             num_physical_monitors.as_mut_ptr(),
         );
+        // `assume_init` is only sound after the call reported success; a failed call may leave the
+        // value uninitialized
         if result != 0 {
             Ok(num_physical_monitors.assume_init())
         } else {
@@ -176,30 +957,236 @@ fn get_physical_monitors(monitor_handle: HMONITOR) -> Result<Vec<PHYSICAL_MONITO
     }
 }
 
-/// Create a `String` from a null-terminated UTF-16 string where the location of the null is not
-/// known.
+/// Create a `String` from a UTF-16 string that ends at the first null, or at the end of `array` if
+/// it fills all of it.
 fn string_from_raw_utf16(array: &[u16; 128]) -> String {
-    let mut zero_loc = 0;
-    for (i, &v) in array.iter().enumerate() {
-        zero_loc = i;
-        if v == 0 {
-            break;
-        }
+    let len = array.iter().position(|&c| c == 0).unwrap_or(array.len());
+    String::from_utf16_lossy(&array[..len])
+}
+
+/// `brightness` clamped to `min..=max`, the range reported by a monitor. Unlike `u32::clamp` it
+/// does not panic on a bogus range where `min > max`; `max` wins then.
+fn clamp_brightness(brightness: u32, min: u32, max: u32) -> u32 {
+    brightness.max(min).min(max)
+}
+
+/// `brightness` within `min..=max` mapped onto the lower `cap` percent of that range, i.e. the
+/// brightness written to a monitor whose brightness is capped. Rounded to the nearest value.
+fn cap_brightness(brightness: u32, (min, max): (u32, u32), cap: u32) -> u32 {
+    let range = max.saturating_sub(min) as u64;
+    if range == 0 {
+        return brightness;
     }
-    String::from_utf16_lossy(&array[..zero_loc])
+    let capped_range = (range * cap as u64 + 50) / 100;
+    let offset = brightness.saturating_sub(min) as u64;
+    min + ((offset * capped_range + range / 2) / range) as u32
+}
+
+/// Inverse of `cap_brightness`: the brightness within `min..=max` for a `brightness` read from the
+/// monitor. Values above the cap are clamped to `max`.
+fn uncap_brightness(brightness: u32, (min, max): (u32, u32), cap: u32) -> u32 {
+    let range = max.saturating_sub(min) as u64;
+    let capped_range = (range * cap as u64 + 50) / 100;
+    if capped_range == 0 {
+        // Everything above `min` is above the cap
+        return if brightness > min { max } else { min };
+    }
+    let offset = brightness.saturating_sub(min) as u64;
+    let uncapped = (offset * range + capped_range / 2) / capped_range;
+    min + uncapped.min(range) as u32
+}
+
+/// `brightness` moved by `offset` percent of `min..=max`, down for a negative one, and clamped to
+/// that range. Rounded to the nearest value.
+fn offset_brightness(brightness: u32, (min, max): (u32, u32), offset: i32) -> u32 {
+    let range = max.saturating_sub(min) as u64;
+    let delta = ((range * offset.unsigned_abs() as u64 + 50) / 100) as u32;
+    let brightness = if offset < 0 {
+        brightness.saturating_sub(delta)
+    } else {
+        brightness.saturating_add(delta)
+    };
+    clamp_brightness(brightness, min, max)
+}
+
+/// `brightness` changed by `delta`, saturating at the bounds of `u32`. The result is clamped later
+/// by `clamp_brightness`.
+fn step_brightness(brightness: u32, delta: i32) -> u32 {
+    brightness.saturating_add_signed(delta)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn raw_utf16(s: &str) -> [u16; 128] {
+        let mut array = [0; 128];
+        for (c, unit) in array.iter_mut().zip(s.encode_utf16()) {
+            *c = unit;
+        }
+        array
+    }
+
+    #[test]
+    fn description_strings() {
+        assert_eq!(
+            string_from_raw_utf16(&raw_utf16("Generic PnP Monitor")),
+            "Generic PnP Monitor"
+        );
+        assert_eq!(string_from_raw_utf16(&[0; 128]), "");
+
+        // No null at all; the last character is kept
+        let full = "M".repeat(127) + "X";
+        assert_eq!(string_from_raw_utf16(&raw_utf16(&full)), full);
+    }
+
+    #[test]
+    fn clamp_to_range() {
+        assert_eq!(clamp_brightness(50, 0, 100), 50);
+        assert_eq!(clamp_brightness(0, 10, 100), 10);
+        assert_eq!(clamp_brightness(150, 0, 100), 100);
+        assert_eq!(clamp_brightness(500, 0, 1000), 500);
+        assert_eq!(clamp_brightness(u32::MAX, 0, 1000), 1000);
+    }
+
+    #[test]
+    fn clamp_to_bogus_range() {
+        assert_eq!(clamp_brightness(50, 80, 20), 20);
+        assert_eq!(clamp_brightness(0, 0, 0), 0);
+    }
+
+    #[test]
+    fn cap_mapping() {
+        assert_eq!(cap_brightness(0, (0, 100), 70), 0);
+        assert_eq!(cap_brightness(50, (0, 100), 70), 35);
+        assert_eq!(cap_brightness(100, (0, 100), 70), 70);
+        assert_eq!(cap_brightness(100, (0, 100), 100), 100);
+        // The floor reported by the monitor stays where it is
+        assert_eq!(cap_brightness(20, (20, 120), 50), 20);
+        assert_eq!(cap_brightness(70, (20, 120), 50), 45);
+        assert_eq!(cap_brightness(120, (20, 120), 50), 70);
+        assert_eq!(cap_brightness(5, (5, 5), 50), 5);
+
+        assert_eq!(uncap_brightness(35, (0, 100), 70), 50);
+        assert_eq!(uncap_brightness(70, (0, 100), 70), 100);
+        assert_eq!(uncap_brightness(90, (0, 100), 70), 100);
+        assert_eq!(uncap_brightness(45, (20, 120), 50), 70);
+        assert_eq!(uncap_brightness(10, (20, 120), 50), 20);
+        assert_eq!(uncap_brightness(0, (0, 10), 1), 0);
+        assert_eq!(uncap_brightness(3, (0, 10), 1), 10);
+
+        // Every value the monitor can be set to reads back as itself
+        for cap in 1..=100 {
+            for (min, max) in [(0, 100), (20, 120), (0, 1000), (10, 37)] {
+                let capped_max = cap_brightness(max, (min, max), cap);
+                for hardware in min..=capped_max {
+                    let brightness = uncap_brightness(hardware, (min, max), cap);
+                    assert!((min..=max).contains(&brightness));
+                    assert_eq!(cap_brightness(brightness, (min, max), cap), hardware);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offset_mapping() {
+        assert_eq!(offset_brightness(50, (0, 100), 0), 50);
+        assert_eq!(offset_brightness(50, (0, 100), -20), 30);
+        assert_eq!(offset_brightness(10, (0, 100), -20), 0);
+        assert_eq!(offset_brightness(100, (20, 120), -100), 20);
+        assert_eq!(offset_brightness(500, (0, 1000), -15), 350);
+        // The inverse for reading back, clamped to the maximum
+        assert_eq!(offset_brightness(30, (0, 100), 20), 50);
+        assert_eq!(offset_brightness(90, (0, 100), 20), 100);
+    }
+
+    #[test]
+    fn nits_mapping() {
+        let luminance = (0.5, 600.5);
+        assert_eq!(nits_to_brightness(0.5, luminance, (0, 100)), 0);
+        assert_eq!(nits_to_brightness(300.5, luminance, (0, 100)), 50);
+        assert_eq!(nits_to_brightness(600.5, luminance, (0, 100)), 100);
+        assert_eq!(nits_to_brightness(150.5, luminance, (20, 1000)), 265);
+        // Clamped to the luminance range
+        assert_eq!(nits_to_brightness(0.0, luminance, (10, 100)), 10);
+        assert_eq!(nits_to_brightness(1000.0, luminance, (10, 100)), 100);
+
+        assert_eq!(brightness_to_nits(50, luminance, (0, 100)), 300.5);
+        assert_eq!(brightness_to_nits(150, luminance, (0, 100)), 600.5);
+        assert_eq!(brightness_to_nits(5, luminance, (5, 5)), 600.5);
+        for brightness in 0..=100 {
+            let nits = brightness_to_nits(brightness, luminance, (0, 100));
+            assert_eq!(nits_to_brightness(nits, luminance, (0, 100)), brightness);
+        }
+    }
+
+    #[test]
+    fn usable_luminance_range() {
+        assert_eq!(luminance_range(0.05, 1000.0), Some((0.05, 1000.0)));
+        assert_eq!(luminance_range(0.0, 0.0), None);
+        assert_eq!(luminance_range(-1.0, 400.0), None);
+    }
+
+    #[test]
+    fn step() {
+        assert_eq!(step_brightness(50, 5), 55);
+        assert_eq!(step_brightness(50, -5), 45);
+        assert_eq!(step_brightness(3, -5), 0);
+        assert_eq!(step_brightness(u32::MAX, 1), u32::MAX);
+        assert_eq!(clamp_brightness(step_brightness(98, 5), 0, 100), 100);
+    }
+
+    #[test]
+    fn fake_monitor() {
+        let mut monitor = Monitor::fake("Fake", (10, 50, 110), "(type(lcd)vcp(10 62))", true);
+        assert_eq!(monitor.get_name(), "Fake");
+        assert!(monitor.is_primary());
+        assert_eq!(monitor.get_brightness(), 50);
+        assert_eq!(monitor.get_volume(), Some((0, 100)));
+        assert_eq!(monitor.technology(), DisplayTechnology::Lcd);
+        assert!(!monitor.supports_standby());
+
+        monitor.set_brightness(200).unwrap();
+        assert_eq!(monitor.get_brightness(), 110);
+        // Written through the cap, and read back without it
+        monitor.set_brightness_cap(50);
+        monitor.set_brightness(110).unwrap();
+        assert_eq!(monitor.read_raw_brightness().unwrap(), 60);
+        monitor.refresh_brightness().unwrap();
+        assert_eq!(monitor.get_brightness(), 110);
+
+        monitor.set_volume(30).unwrap();
+        assert_eq!(
+            monitor.get_vcp_feature(VCP_SPEAKER_VOLUME).unwrap(),
+            (30, 100)
+        );
+        assert!(monitor.set_standby(true).is_err());
+        assert!(monitor.with_handle(|handle| handle.is_invalid()));
+    }
+
+    #[test]
+    fn brightness_below_offset() {
+        let mut monitor = Monitor::fake("Fake", (0, 50, 100), "(vcp(10))", false);
+        monitor.set_brightness_offset(-20);
+        monitor.set_brightness(10).unwrap();
+        assert_eq!(monitor.read_raw_brightness().unwrap(), 0);
+        // Not taken for a change to 20 made on the monitor
+        monitor.refresh_brightness().unwrap();
+        assert_eq!(monitor.get_brightness(), 10);
+
+        monitor.set_brightness(50).unwrap();
+        assert_eq!(monitor.read_raw_brightness().unwrap(), 30);
+        monitor.refresh_brightness().unwrap();
+        assert_eq!(monitor.get_brightness(), 50);
+    }
+
     #[test]
     fn set_brightness() {
         use std::{thread, time};
 
         let duration = time::Duration::from_secs(5);
 
-        let mut monitors = Monitor::get_monitors().unwrap();
+        let mut monitors = get_monitors().unwrap();
         let mut brightnesses = Vec::new();
         for monitor in &mut monitors {
             brightnesses.push(monitor.get_brightness());
@@ -0,0 +1,466 @@
+//! Named pipe server that lets other processes query and set the brightness. Clients write
+//! newline-terminated commands and get back one line per command. Brightness is in percent of each
+//! monitor's maximum, like in the flyout, except in `snapshot`:
+//!
+//! - `list`: `<index> <percent> <name>` for each monitor, followed by an empty line
+//! - `snapshot`: every physical monitor as a JSON array of `MonitorSnapshot`s on a single line
+//! - `snapshot <index>`: the `MonitorSnapshot` of the monitor, of the first physical one if it
+//!   duplicates others
+//! - `get <index>`: the brightness of the monitor
+//! - `set <index> <percent>`: sets the brightness of the monitor and replies with `ok`
+//! - `adjust <index> <delta>`: changes the brightness of the monitor by `delta` percent, e.g. `+10`
+//!   or `-5`, within its range and replies with `ok`. `all` instead of an index changes every
+//!   monitor.
+//! - `boost <index> <percent> <seconds>`: sets the brightness of the monitor, or of `all`, for
+//!   the number of seconds, then restores the brightness from before, and replies with `ok`
+//! - `exit`: replies with `ok` and closes the app like **Exit** in its menu
+//!
+//! Invalid commands are replied to with `error: <reason>`.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED, HANDLE, HWND,
+            LPARAM, WPARAM,
+        },
+        Storage::FileSystem::{
+            CreateFileA, FlushFileBuffers, ReadFile, WriteFile, FILE_ATTRIBUTE_NORMAL,
+            FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_NONE, OPEN_EXISTING,
+            PIPE_ACCESS_DUPLEX,
+        },
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeA, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+            PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+        },
+        UI::WindowsAndMessaging::{PostMessageA, WM_CLOSE},
+    },
+};
+
+use crate::{
+    group::{self, MonitorGroup},
+    priority::ChangeSource,
+    BrightnessEvent, SharedMonitors,
+};
+
+const PIPE_NAME: &[u8] = b"\\\\.\\pipe\\MonitorBrightnessController\0";
+/// `PIPE_NAME` for opening the pipe as a file, without the terminating NUL.
+const PIPE_PATH: &str = "\\\\.\\pipe\\MonitorBrightnessController";
+const BUFFER_SIZE: u32 = 512;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    List,
+    /// Every physical monitor, or the first one of the monitor at the index.
+    Snapshot(Option<usize>),
+    Get(usize),
+    /// Set the brightness of the monitor to the percentage.
+    Set(usize, u32),
+    /// Change the brightness of the monitor, or of all of them if `None`, by the percentage.
+    Adjust(Option<usize>, i32),
+    /// Set the brightness of the monitor, or of all of them if `None`, for the number of seconds.
+    Boost(Option<usize>, u32, u64),
+    /// Close the app.
+    Exit,
+}
+
+fn parse_command(line: &str) -> Result<Command, &'static str> {
+    let mut args = line.split_whitespace();
+    let command = args.next().ok_or("empty command")?;
+    let mut index = || {
+        args.next()
+            .ok_or("missing monitor index")?
+            .parse::<usize>()
+            .map_err(|_| "invalid monitor index")
+    };
+    let command = match command {
+        "list" => Command::List,
+        "snapshot" => match args.next() {
+            Some(i) => Command::Snapshot(Some(i.parse().map_err(|_| "invalid monitor index")?)),
+            None => Command::Snapshot(None),
+        },
+        "get" => Command::Get(index()?),
+        "set" => {
+            let i = index()?;
+            let brightness = args
+                .next()
+                .ok_or("missing brightness")?
+                .parse::<u32>()
+                .map_err(|_| "invalid brightness")?;
+            Command::Set(i, brightness.min(100))
+        }
+        "adjust" => {
+            let target = target(args.next())?;
+            let delta = args
+                .next()
+                .ok_or("missing brightness change")?
+                .parse::<i32>()
+                .map_err(|_| "invalid brightness change")?;
+            Command::Adjust(target, delta)
+        }
+        "boost" => {
+            let target = target(args.next())?;
+            let brightness = args
+                .next()
+                .ok_or("missing brightness")?
+                .parse::<u32>()
+                .map_err(|_| "invalid brightness")?;
+            let seconds = args
+                .next()
+                .ok_or("missing duration")?
+                .parse::<u64>()
+                .map_err(|_| "invalid duration")?;
+            Command::Boost(target, brightness.min(100), seconds)
+        }
+        "exit" => Command::Exit,
+        _ => return Err("unknown command"),
+    };
+    match args.next() {
+        Some(_) => Err("too many arguments"),
+        None => Ok(command),
+    }
+}
+
+/// A monitor index, or `None` for `all`.
+fn target(arg: Option<&str>) -> Result<Option<usize>, &'static str> {
+    match arg.ok_or("missing monitor index")? {
+        "all" => Ok(None),
+        i => Ok(Some(i.parse().map_err(|_| "invalid monitor index")?)),
+    }
+}
+
+/// Serves clients one at a time on a separate thread until dropped.
+pub struct PipeServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PipeServer {
+    /// Start the server. Monitor indices refer to `groups`. `set` commands are sent through
+    /// `sender` as automatic changes, which the controller passes on to the UI once applied.
+    /// `exit` closes `window`.
+    pub fn new(
+        sender: Sender<BrightnessEvent>,
+        monitors: SharedMonitors,
+        groups: Arc<Vec<MonitorGroup>>,
+        window: HWND,
+    ) -> PipeServer {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || server_loop(&stop, &sender, &monitors, &groups, window))
+        };
+        PipeServer {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PipeServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Connect to the pipe ourselves to wake up the server thread from `ConnectNamedPipe`. If
+        // that fails, a client is still connected and the thread is left to die with the process
+        // instead of blocking the exit.
+        let woken = unsafe {
+            CreateFileA(
+                PCSTR(PIPE_NAME.as_ptr()),
+                FILE_GENERIC_READ | FILE_GENERIC_WRITE,
+                FILE_SHARE_NONE,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                HANDLE::default(),
+            )
+            .map(|handle| CloseHandle(handle))
+            .is_ok()
+        };
+        if let Some(thread) = self.thread.take() {
+            if woken {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+fn server_loop(
+    stop: &AtomicBool,
+    sender: &Sender<BrightnessEvent>,
+    monitors: &SharedMonitors,
+    groups: &[MonitorGroup],
+    window: HWND,
+) {
+    while !stop.load(Ordering::SeqCst) {
+        let handle = unsafe {
+            CreateNamedPipeA(
+                PCSTR(PIPE_NAME.as_ptr()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null(),
+            )
+        };
+        let connection = match handle {
+            Ok(handle) => PipeConnection(handle),
+            Err(_) => return,
+        };
+        let connected = unsafe {
+            // A client that connects between the two calls is reported as an error
+            ConnectNamedPipe(connection.0, std::ptr::null_mut()).as_bool()
+                || GetLastError() == ERROR_PIPE_CONNECTED
+        };
+        if connected && !stop.load(Ordering::SeqCst) {
+            let _ = serve_client(&connection, sender, monitors, groups, window);
+        }
+    }
+}
+
+fn serve_client(
+    connection: &PipeConnection,
+    sender: &Sender<BrightnessEvent>,
+    monitors: &SharedMonitors,
+    groups: &[MonitorGroup],
+    window: HWND,
+) -> io::Result<()> {
+    let mut writer = connection;
+    for line in BufReader::new(connection).lines() {
+        let reply = match parse_command(&line?) {
+            Ok(command) => run_command(command, sender, monitors, groups, window),
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+fn run_command(
+    command: Command,
+    sender: &Sender<BrightnessEvent>,
+    monitors: &SharedMonitors,
+    groups: &[MonitorGroup],
+    window: HWND,
+) -> String {
+    match command {
+        Command::List => {
+            let monitors = monitors.lock().unwrap();
+            let mut reply = String::new();
+            for (i, group) in groups.iter().enumerate() {
+                let (percent, name) = (group.percent(&monitors), group.name(&monitors));
+                reply += &format!("{} {} {}\n", i, percent, name);
+            }
+            reply
+        }
+        // The last known state, to not hold up the controller with DDC/CI reads
+        Command::Snapshot(None) => {
+            match serde_json::to_string(&crate::monitor_snapshots(monitors, false)) {
+                Ok(json) => json,
+                Err(e) => format!("error: {}", e),
+            }
+        }
+        Command::Snapshot(Some(i)) => match groups.get(i) {
+            Some(group) => {
+                let snapshot = monitors.lock().unwrap()[group.members()[0]].snapshot();
+                match serde_json::to_string(&snapshot) {
+                    Ok(json) => json,
+                    Err(e) => format!("error: {}", e),
+                }
+            }
+            None => "error: no such monitor".to_owned(),
+        },
+        Command::Get(i) => match groups.get(i) {
+            Some(group) => group.percent(&monitors.lock().unwrap()).to_string(),
+            None => "error: no such monitor".to_owned(),
+        },
+        Command::Set(i, percent) => {
+            let max_brightness = match groups.get(i) {
+                Some(group) => group.brightness_range(&monitors.lock().unwrap()).1,
+                None => return "error: no such monitor".to_owned(),
+            };
+            let brightness = group::from_percent(percent, max_brightness);
+            let change = BrightnessEvent::Change(i, brightness, ChangeSource::Automatic);
+            if sender.send(change).is_err() {
+                return "error: controller stopped".to_owned();
+            }
+            "ok".to_owned()
+        }
+        Command::Adjust(target, delta) => {
+            let indices = match target {
+                Some(i) if i >= groups.len() => return "error: no such monitor".to_owned(),
+                Some(i) => i..i + 1,
+                None => 0..groups.len(),
+            };
+            for i in indices {
+                if sender.send(BrightnessEvent::Adjust(i, delta)).is_err() {
+                    return "error: controller stopped".to_owned();
+                }
+            }
+            "ok".to_owned()
+        }
+        Command::Boost(target, brightness, seconds) => {
+            if target.is_some_and(|i| i >= groups.len()) {
+                return "error: no such monitor".to_owned();
+            }
+            let boost = BrightnessEvent::Boost(target, brightness, Duration::from_secs(seconds));
+            if sender.send(boost).is_err() {
+                return "error: controller stopped".to_owned();
+            }
+            "ok".to_owned()
+        }
+        Command::Exit => {
+            // Posted so that the reply goes out before the window is gone
+            unsafe {
+                PostMessageA(window, WM_CLOSE, WPARAM(0), LPARAM(0));
+            }
+            "ok".to_owned()
+        }
+    }
+}
+
+/// Send `command` to the running instance as a client of the pipe and return its reply, e.g. for
+/// the command line.
+pub fn send_command(command: &str) -> io::Result<String> {
+    let mut pipe = OpenOptions::new().read(true).write(true).open(PIPE_PATH)?;
+    writeln!(pipe, "{}", command)?;
+    let mut reply = String::new();
+    BufReader::new(pipe).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_owned())
+}
+
+/// Like `send_command` for `list`, whose reply is a line for each monitor followed by an empty
+/// line. Returns the lines without the empty one.
+pub fn send_list_command() -> io::Result<Vec<String>> {
+    let mut pipe = OpenOptions::new().read(true).write(true).open(PIPE_PATH)?;
+    writeln!(pipe, "list")?;
+    let lines = BufReader::new(pipe).lines();
+    lines
+        .map(|line| line.map(|line| line.trim_end().to_owned()))
+        .take_while(|line| line.as_ref().map_or(true, |line| !line.is_empty()))
+        .collect()
+}
+
+/// Server end of a connected pipe instance. Disconnects the client and closes the instance when
+/// dropped.
+struct PipeConnection(HANDLE);
+
+impl Drop for PipeConnection {
+    fn drop(&mut self) {
+        unsafe {
+            FlushFileBuffers(self.0);
+            DisconnectNamedPipe(self.0);
+            CloseHandle(self.0);
+        }
+    }
+}
+
+impl Read for &PipeConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        unsafe {
+            let ok = ReadFile(
+                self.0,
+                buf.as_mut_ptr().cast(),
+                buf.len() as u32,
+                &mut read,
+                std::ptr::null_mut(),
+            );
+            if !ok.as_bool() {
+                // The client closing its end is the usual way a session ends
+                if GetLastError() == ERROR_BROKEN_PIPE {
+                    return Ok(0);
+                }
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(read as usize)
+    }
+}
+
+impl Write for &PipeConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        unsafe {
+            let ok = WriteFile(
+                self.0,
+                buf.as_ptr().cast(),
+                buf.len() as u32,
+                &mut written,
+                std::ptr::null_mut(),
+            );
+            if !ok.as_bool() {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse_command("list"), Ok(Command::List));
+        assert_eq!(parse_command("snapshot"), Ok(Command::Snapshot(None)));
+        assert_eq!(parse_command("snapshot 1"), Ok(Command::Snapshot(Some(1))));
+        assert_eq!(parse_command(" get 1 "), Ok(Command::Get(1)));
+        assert_eq!(parse_command("set 0 50"), Ok(Command::Set(0, 50)));
+        assert_eq!(parse_command("set 0 150"), Ok(Command::Set(0, 100)));
+        assert_eq!(
+            parse_command("adjust 1 +10"),
+            Ok(Command::Adjust(Some(1), 10))
+        );
+        assert_eq!(
+            parse_command("adjust all -5"),
+            Ok(Command::Adjust(None, -5))
+        );
+        assert_eq!(
+            parse_command("boost all 100 600"),
+            Ok(Command::Boost(None, 100, 600))
+        );
+        assert_eq!(
+            parse_command("boost 1 150 60"),
+            Ok(Command::Boost(Some(1), 100, 60))
+        );
+        assert_eq!(parse_command("exit"), Ok(Command::Exit));
+    }
+
+    #[test]
+    fn parse_invalid_commands() {
+        assert!(parse_command("").is_err());
+        assert!(parse_command("get").is_err());
+        assert!(parse_command("get x").is_err());
+        assert!(parse_command("snapshot x").is_err());
+        assert!(parse_command("snapshot 0 1").is_err());
+        assert!(parse_command("set 0").is_err());
+        assert!(parse_command("set 0 -1").is_err());
+        assert!(parse_command("list 0").is_err());
+        assert!(parse_command("brighten").is_err());
+        assert!(parse_command("adjust 0").is_err());
+        assert!(parse_command("adjust any 5").is_err());
+        assert!(parse_command("adjust 0 5%").is_err());
+        assert!(parse_command("boost all 100").is_err());
+        assert!(parse_command("boost all 100 -1").is_err());
+        assert!(parse_command("exit now").is_err());
+    }
+}
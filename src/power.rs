@@ -1,35 +1,47 @@
-use windows::{
-    core::Result,
-    Win32::{
-        Foundation::{HANDLE, HWND},
-        System::{
-            Power::{
-                RegisterPowerSettingNotification, UnregisterPowerSettingNotification, HPOWERNOTIFY,
-            },
-            SystemServices::GUID_CONSOLE_DISPLAY_STATE,
+use windows::Win32::{
+    Foundation::{HANDLE, HWND},
+    System::{
+        Power::{
+            RegisterPowerSettingNotification, UnregisterPowerSettingNotification, HPOWERNOTIFY,
         },
+        SystemServices::GUID_CONSOLE_DISPLAY_STATE,
     },
 };
 
+/// Registration for power event notifications. Null if registering failed, in which case there is
+/// nothing to unregister.
 pub struct PowerNotifyHandle(HPOWERNOTIFY);
 
 impl Drop for PowerNotifyHandle {
     fn drop(&mut self) {
+        if self.0.is_invalid() {
+            return;
+        }
         unsafe {
             if !UnregisterPowerSettingNotification(self.0).as_bool() {
-                panic!("{:?}", windows::core::Error::from_win32());
+                let error = windows::core::Error::from_win32();
+                crate::report_drop_error("unregister the power notifications", &error);
             }
         }
     }
 }
 
 impl PowerNotifyHandle {
-    /// Registers the given window for power event notifications.
-    pub fn new(hwnd: HWND) -> Result<PowerNotifyHandle> {
-        unsafe {
-            let handle =
-                RegisterPowerSettingNotification(HANDLE(hwnd.0), &GUID_CONSOLE_DISPLAY_STATE, 0)?;
-            Ok(PowerNotifyHandle(handle))
+    /// Registers the given window for power event notifications. Registering can fail in restricted
+    /// sessions; the app then runs without resetting the brightness when the display wakes up.
+    pub fn new(hwnd: HWND) -> PowerNotifyHandle {
+        let handle = unsafe {
+            RegisterPowerSettingNotification(HANDLE(hwnd.0), &GUID_CONSOLE_DISPLAY_STATE, 0)
+        };
+        match handle {
+            Ok(handle) => PowerNotifyHandle(handle),
+            Err(e) => {
+                crate::debug_log(&format!(
+                    "Not resetting the brightness on display wake, registering failed: {}",
+                    e
+                ));
+                PowerNotifyHandle(HPOWERNOTIFY(0))
+            }
         }
     }
 }
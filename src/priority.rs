@@ -0,0 +1,175 @@
+//! Precedence between brightness changes from different sources. A change made by the user always
+//! wins, and automatic changes stay away from a monitor for a while after the user set it.
+
+use std::time::{Duration, Instant};
+
+use crate::strings::strings;
+
+/// What changed the brightness, for `BrightnessEvent::Change` and to explain changes to the user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChangeSource {
+    /// The user, through the flyout.
+    Manual,
+    /// Another process, through the named pipe or the command line. It asked for the change
+    /// explicitly, so the change is applied like the user's, see `ChangeSource::explicit`.
+    Automatic,
+    /// The user, through the hotkey, the brightness keys, or resetting every monitor from the
+    /// context menu. Like `Manual`, but the flyout has to catch up.
+    Hotkey,
+    /// Idle dimming, and restoring the brightness on the next input.
+    Idle,
+    /// Auto brightness, following the ambient light sensor.
+    Ambient,
+    /// A boost, and restoring the brightness when it ends.
+    Boost,
+    /// The primary display, taken on by the monitors that follow it.
+    FollowPrimary,
+    /// The monitor itself, e.g. its own buttons or a factory reset.
+    Monitor,
+    /// The built-in display, followed by every monitor if set in the settings.
+    Builtin,
+}
+
+impl ChangeSource {
+    const ALL: [ChangeSource; 9] = [
+        ChangeSource::Manual,
+        ChangeSource::Automatic,
+        ChangeSource::Hotkey,
+        ChangeSource::Idle,
+        ChangeSource::Ambient,
+        ChangeSource::Boost,
+        ChangeSource::FollowPrimary,
+        ChangeSource::Monitor,
+        ChangeSource::Builtin,
+    ];
+
+    /// Number for passing the source in a window message.
+    pub fn id(self) -> usize {
+        self as usize
+    }
+
+    /// The source with the number from `id`, `Manual` for unknown ones.
+    pub fn from_id(id: usize) -> ChangeSource {
+        ChangeSource::ALL
+            .into_iter()
+            .find(|source| source.id() == id)
+            .unwrap_or(ChangeSource::Manual)
+    }
+
+    /// Whether the user made the change, which the grace period of `ChangePriority` protects.
+    pub fn by_user(self) -> bool {
+        matches!(self, ChangeSource::Manual | ChangeSource::Hotkey)
+    }
+
+    /// Whether someone asked for this very change: the user, or another process through the named
+    /// pipe or the command line, e.g. with keys mapped to `--adjust`. Such changes are always
+    /// applied and start the grace period of `ChangePriority`.
+    pub fn explicit(self) -> bool {
+        self.by_user() || self == ChangeSource::Automatic
+    }
+
+    /// Appended to the brightness in the tooltip and balloons to say what changed it. Empty for
+    /// changes through the flyout, which the user just made.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            ChangeSource::Manual => "",
+            ChangeSource::Automatic => strings().source_automatic,
+            ChangeSource::Hotkey => strings().source_hotkey,
+            ChangeSource::Idle => strings().source_idle,
+            ChangeSource::Ambient => strings().source_ambient,
+            ChangeSource::Boost => strings().source_boost,
+            ChangeSource::FollowPrimary => strings().source_follow_primary,
+            ChangeSource::Monitor => strings().source_monitor,
+            ChangeSource::Builtin => strings().source_builtin,
+        }
+    }
+}
+
+/// When a monitor was last changed by the user, to keep automatic changes from undoing it.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ChangePriority {
+    /// Time of the last manual change, if any.
+    manual_at: Option<Instant>,
+}
+
+impl ChangePriority {
+    /// How long automatic changes are ignored after a manual one.
+    pub const GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+    /// Whether a change from `source` made at `now` should be applied. Explicit changes always are
+    /// and start the grace period.
+    pub fn accept(&mut self, source: ChangeSource, now: Instant) -> bool {
+        if source.explicit() {
+            self.manual_at = Some(now);
+            return true;
+        }
+        match self.manual_at {
+            Some(manual_at) => now.saturating_duration_since(manual_at) >= Self::GRACE_PERIOD,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn automatic_changes_without_manual_ones() {
+        let start = Instant::now();
+        let mut priority = ChangePriority::default();
+        assert!(priority.accept(ChangeSource::Ambient, start));
+        assert!(priority.accept(ChangeSource::Ambient, start + Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn manual_beats_automatic() {
+        let start = Instant::now();
+        let mut priority = ChangePriority::default();
+        // A manual change supersedes an automatic one in progress
+        assert!(priority.accept(ChangeSource::Ambient, start));
+        assert!(priority.accept(ChangeSource::Manual, start + Duration::from_millis(10)));
+
+        // Automatic ones are then ignored until the grace period has passed
+        let manual = start + Duration::from_millis(10);
+        assert!(!priority.accept(ChangeSource::Ambient, manual));
+        assert!(!priority.accept(
+            ChangeSource::Ambient,
+            manual + ChangePriority::GRACE_PERIOD / 2
+        ));
+        assert!(priority.accept(ChangeSource::Ambient, manual + ChangePriority::GRACE_PERIOD));
+
+        // Manual changes are never ignored and restart the grace period
+        let later = manual + ChangePriority::GRACE_PERIOD;
+        assert!(priority.accept(ChangeSource::Manual, later));
+        assert!(!priority.accept(ChangeSource::Ambient, later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn hotkey_counts_as_manual() {
+        let start = Instant::now();
+        let mut priority = ChangePriority::default();
+        assert!(priority.accept(ChangeSource::Hotkey, start));
+        assert!(!priority.accept(ChangeSource::Ambient, start + Duration::from_secs(1)));
+        assert!(!priority.accept(ChangeSource::Idle, start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn pipe_commands_count_as_explicit() {
+        let start = Instant::now();
+        let mut priority = ChangePriority::default();
+        // Not ignored after a change in the flyout, and keeps automatic changes away in turn
+        assert!(priority.accept(ChangeSource::Manual, start));
+        assert!(priority.accept(ChangeSource::Automatic, start + Duration::from_secs(1)));
+        let later = start + ChangePriority::GRACE_PERIOD;
+        assert!(!priority.accept(ChangeSource::Ambient, later));
+    }
+
+    #[test]
+    fn source_round_trip() {
+        for source in ChangeSource::ALL {
+            assert_eq!(ChangeSource::from_id(source.id()), source);
+        }
+        assert_eq!(ChangeSource::from_id(100), ChangeSource::Manual);
+    }
+}
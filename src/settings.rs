@@ -0,0 +1,915 @@
+//! Per-user settings stored in the registry, under
+//! `HKEY_CURRENT_USER\Software\MonitorBrightnessController`. There is no UI for them yet; they are
+//! changed with e.g. `reg add`. Missing or invalid values fall back to the defaults. The app itself
+//! only writes which one-time hints were shown, which monitor was selected last, which monitors
+//! follow the primary display, whether auto brightness is on, and the calibration points captured
+//! from the command line.
+
+use std::{sync::OnceLock, time::Duration};
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{
+            RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_DWORD, REG_SZ, RRF_RT_REG_DWORD,
+            RRF_RT_REG_SZ,
+        },
+    },
+};
+
+use monitor_brightness_controller::Calibration;
+
+use crate::{ambient::AmbientCurve, group::PERCENT_MAX};
+
+const KEY: &str = "Software\\MonitorBrightnessController";
+
+/// Size of the flyout. The expanded size suits large high resolution displays.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlyoutSize {
+    Compact,
+    Expanded,
+}
+
+impl FlyoutSize {
+    /// Name of the `DWORD` value: 0 for compact, the default, and 1 for expanded.
+    const VALUE: &'static str = "FlyoutSize";
+
+    /// Factor applied to the sizes of the compact flyout.
+    pub fn scale(self) -> f64 {
+        match self {
+            FlyoutSize::Compact => 1.0,
+            FlyoutSize::Expanded => 1.5,
+        }
+    }
+
+    fn from_setting(value: Option<u32>) -> FlyoutSize {
+        match value {
+            Some(1) => FlyoutSize::Expanded,
+            _ => FlyoutSize::Compact,
+        }
+    }
+}
+
+/// Arrangement of the controls of the XAML flyout.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlyoutLayout {
+    /// A single slider for the monitor chosen in a selector, with presets and the volume.
+    Selector,
+    /// A slider for every monitor, stacked vertically.
+    AllMonitors,
+}
+
+impl FlyoutLayout {
+    /// Name of the `DWORD` value: 0 for the selector, the default, and 1 for all monitors.
+    const VALUE: &'static str = "FlyoutLayout";
+
+    fn from_setting(value: Option<u32>) -> FlyoutLayout {
+        match value {
+            Some(1) => FlyoutLayout::AllMonitors,
+            _ => FlyoutLayout::Selector,
+        }
+    }
+}
+
+/// Brightness in percent that `BrightnessEvent::ResetAll` sets every monitor to. Read each time so
+/// that changes apply without restarting.
+pub fn default_brightness() -> u32 {
+    default_brightness_from_setting(read_dword(KEY, DEFAULT_BRIGHTNESS_VALUE))
+}
+
+/// Name of the `DWORD` value of `default_brightness`.
+const DEFAULT_BRIGHTNESS_VALUE: &str = "DefaultBrightness";
+
+fn default_brightness_from_setting(value: Option<u32>) -> u32 {
+    value.map_or(80, |percent| percent.min(PERCENT_MAX))
+}
+
+/// Dimming of every monitor while the user is idle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IdleDim {
+    /// How long there has to be no input before dimming.
+    pub after: Duration,
+    /// Brightness in percent that the monitors are dimmed to. Darker monitors are left alone.
+    pub percent: u32,
+}
+
+/// Name of the `DWORD` value with the minutes of `IdleDim::after`; 0, the default, turns dimming
+/// off.
+const IDLE_DIM_MINUTES_VALUE: &str = "IdleDimMinutes";
+/// Name of the `DWORD` value of `IdleDim::percent`.
+const IDLE_DIM_BRIGHTNESS_VALUE: &str = "IdleDimBrightness";
+
+/// Whether and how to dim the monitors while the user is idle. Only read at startup.
+pub fn idle_dim() -> Option<IdleDim> {
+    idle_dim_from_settings(
+        read_dword(KEY, IDLE_DIM_MINUTES_VALUE),
+        read_dword(KEY, IDLE_DIM_BRIGHTNESS_VALUE),
+    )
+}
+
+fn idle_dim_from_settings(minutes: Option<u32>, percent: Option<u32>) -> Option<IdleDim> {
+    match minutes {
+        Some(minutes) if minutes > 0 => Some(IdleDim {
+            after: Duration::from_secs(minutes as u64 * 60),
+            percent: percent.map_or(20, |percent| percent.min(PERCENT_MAX)),
+        }),
+        _ => None,
+    }
+}
+
+/// What happens to the brightness of the monitors when the app exits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitBrightness {
+    /// Keep whatever was set last.
+    Leave,
+    /// Set every monitor to the percentage.
+    Set(u32),
+    /// Go back to the brightness each monitor had when the app started.
+    Restore,
+}
+
+impl ExitBrightness {
+    /// Name of the string value: `restore`, a percentage, or anything else to leave the brightness
+    /// as it is.
+    const VALUE: &'static str = "ExitBrightness";
+
+    fn from_setting(value: Option<&str>) -> ExitBrightness {
+        match value.map(str::trim) {
+            Some(value) if value.eq_ignore_ascii_case("restore") => ExitBrightness::Restore,
+            Some(value) => match value.trim_end_matches('%').parse::<u32>() {
+                Ok(percent) => ExitBrightness::Set(percent.min(PERCENT_MAX)),
+                Err(_) => ExitBrightness::Leave,
+            },
+            None => ExitBrightness::Leave,
+        }
+    }
+}
+
+/// What to do with the brightness on exit. Read when exiting so that changes apply without
+/// restarting.
+pub fn exit_brightness() -> ExitBrightness {
+    ExitBrightness::from_setting(read_string(KEY, ExitBrightness::VALUE).as_deref())
+}
+
+/// What happens when a write to a monitor still fails after its retries. The slider moves back in
+/// any case, and monitors that never answered get the DDC/CI hint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteFailures {
+    /// Nothing else.
+    Silent,
+    /// A notification names the monitor, once per monitor until the app restarts.
+    NotifyOnce,
+    /// The monitor is left alone after failing several times in a row, without probing it again,
+    /// until the displays change or turn back on.
+    Disable,
+}
+
+impl WriteFailures {
+    /// Name of the string value: `silent`, `notify`, or `disable`.
+    const VALUE: &'static str = "WriteFailures";
+
+    fn from_setting(value: Option<&str>) -> WriteFailures {
+        match value.map(str::trim) {
+            Some(value) if value.eq_ignore_ascii_case("silent") => WriteFailures::Silent,
+            Some(value) if value.eq_ignore_ascii_case("disable") => WriteFailures::Disable,
+            _ => WriteFailures::NotifyOnce,
+        }
+    }
+}
+
+/// What happens when a write to a monitor fails. Read at startup.
+pub fn write_failures() -> WriteFailures {
+    WriteFailures::from_setting(read_string(KEY, WriteFailures::VALUE).as_deref())
+}
+
+/// Subkey of `KEY` with the brightness caps: a `DWORD` value per monitor, named after its
+/// description as shown in the flyout.
+const BRIGHTNESS_CAPS_KEY: &str = "BrightnessCaps";
+
+/// Highest brightness, in percent of its range, that the monitor named `device_name` is set to. The
+/// slider's maximum maps to it. Monitors with the same description share their cap.
+pub fn brightness_cap(device_name: &str) -> u32 {
+    let key = format!("{KEY}\\{BRIGHTNESS_CAPS_KEY}");
+    brightness_cap_from_setting(read_dword(&key, device_name))
+}
+
+fn brightness_cap_from_setting(value: Option<u32>) -> u32 {
+    // A cap of 0 would leave the slider without any effect
+    value.map_or(PERCENT_MAX, |percent| percent.clamp(1, PERCENT_MAX))
+}
+
+/// Subkey of `KEY` with the brightness floors: a `DWORD` value per monitor description with the
+/// percentage of its range that the slider's minimum maps to.
+const BRIGHTNESS_FLOORS_KEY: &str = "BrightnessFloors";
+
+/// Default floor of OLED monitors, which can flicker or crush dark shades at their lowest
+/// brightness.
+const OLED_FLOOR: u32 = 10;
+
+/// Lowest brightness, in percent of the range up to the cap, that the monitor named `device_name`
+/// is set to. Defaults to `OLED_FLOOR` for `oled` monitors and 0 for the others. Ignored for
+/// monitors with a calibration table, which sets its own lowest value.
+pub fn brightness_floor(device_name: &str, oled: bool) -> u32 {
+    let key = format!("{KEY}\\{BRIGHTNESS_FLOORS_KEY}");
+    brightness_floor_from_setting(read_dword(&key, device_name), oled)
+}
+
+fn brightness_floor_from_setting(value: Option<u32>, oled: bool) -> u32 {
+    // A floor of 100 would leave the slider without any effect
+    let default = if oled { OLED_FLOOR } else { 0 };
+    value.map_or(default, |percent| percent.min(PERCENT_MAX - 1))
+}
+
+/// Name of the string value with the offset of **Dim all monitors**, in percent of each monitor's
+/// range from `-100` to `0`, e.g. `-20`.
+const BRIGHTNESS_OFFSET_VALUE: &str = "BrightnessOffset";
+
+/// Name of the DWORD value that is `1` while the offset is applied, as toggled in the context menu.
+const BRIGHTNESS_OFFSET_ON_VALUE: &str = "BrightnessOffsetOn";
+
+/// Offset of **Dim all monitors** unless set otherwise.
+const DEFAULT_BRIGHTNESS_OFFSET: i32 = -20;
+
+/// Offset applied to every monitor on top of its own brightness, in percent of its range: the one
+/// from the settings while **Dim all monitors** is on, otherwise 0.
+pub fn brightness_offset() -> i32 {
+    if brightness_offset_on() {
+        brightness_offset_from_setting(read_string(KEY, BRIGHTNESS_OFFSET_VALUE).as_deref())
+    } else {
+        0
+    }
+}
+
+fn brightness_offset_from_setting(value: Option<&str>) -> i32 {
+    value
+        .and_then(|value| value.trim().parse::<i32>().ok())
+        .map_or(DEFAULT_BRIGHTNESS_OFFSET, |offset| offset.clamp(-100, 0))
+}
+
+/// Whether **Dim all monitors** is on.
+pub fn brightness_offset_on() -> bool {
+    read_dword(KEY, BRIGHTNESS_OFFSET_ON_VALUE) == Some(1)
+}
+
+/// Remember whether **Dim all monitors** is on.
+pub fn set_brightness_offset_on(enabled: bool) {
+    write_dword(KEY, BRIGHTNESS_OFFSET_ON_VALUE, enabled as u32);
+}
+
+/// Subkey of `KEY` with the calibration tables: a string value per monitor description with the
+/// points of its table, e.g. `0:5, 50:20, 100:80` for 5 at 0 percent, 20 at 50, and 80 at 100.
+const CALIBRATIONS_KEY: &str = "Calibrations";
+
+/// The calibration table of the monitors named `device_name`, if they have a valid one. Tables
+/// that are not monotonic are ignored like missing ones.
+pub fn calibration(device_name: &str) -> Option<Calibration> {
+    let key = format!("{KEY}\\{CALIBRATIONS_KEY}");
+    calibration_from_setting(read_string(&key, device_name).as_deref())
+}
+
+/// Store `calibration` as the table of the monitors named `device_name`, for the next startup.
+pub fn set_calibration(device_name: &str, calibration: &Calibration) {
+    let key = format!("{KEY}\\{CALIBRATIONS_KEY}");
+    write_string(&key, device_name, &calibration_to_setting(calibration));
+}
+
+fn calibration_from_setting(value: Option<&str>) -> Option<Calibration> {
+    Calibration::new(points_from_setting(value?)?)
+}
+
+/// Points separated by commas, each two numbers separated by a colon, e.g. `0:5, 50:20`.
+fn points_from_setting(value: &str) -> Option<Vec<(u32, u32)>> {
+    let parse_point = |point: &str| {
+        let (x, y) = point.split_once(':')?;
+        Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+    };
+    value.split(',').map(parse_point).collect()
+}
+
+fn calibration_to_setting(calibration: &Calibration) -> String {
+    let points: Vec<String> = (calibration.points().iter())
+        .map(|(percent, value)| format!("{percent}:{value}"))
+        .collect();
+    points.join(", ")
+}
+
+/// Name of the `DWORD` value with the least number of milliseconds between two brightness writes to
+/// the same monitor.
+const WRITE_INTERVAL_VALUE: &str = "WriteInterval";
+/// Subkey of `KEY` with a `DWORD` value per monitor description that overrides
+/// `WRITE_INTERVAL_VALUE` for the monitors with that description.
+const WRITE_INTERVALS_KEY: &str = "WriteIntervals";
+
+/// Least time between two brightness writes to the monitors named `device_name`. Some monitors
+/// ignore or garble writes that follow each other more closely.
+pub fn write_interval(device_name: &str) -> Duration {
+    let key = format!("{KEY}\\{WRITE_INTERVALS_KEY}");
+    let millis = read_dword(&key, device_name).or_else(|| read_dword(KEY, WRITE_INTERVAL_VALUE));
+    write_interval_from_setting(millis)
+}
+
+fn write_interval_from_setting(millis: Option<u32>) -> Duration {
+    // Longer waits would make the monitor lag far behind the slider
+    Duration::from_millis(millis.map_or(50, |millis| millis.min(1000)) as u64)
+}
+
+/// Subkey of `KEY` with the order of the selector: a `DWORD` value per monitor, named after its
+/// description, with its position.
+const MONITOR_ORDER_KEY: &str = "MonitorOrder";
+
+/// Position of the monitor named `device_name` in the selector, if one was set. Monitors without a
+/// position come after those with one.
+pub fn monitor_position(device_name: &str) -> Option<u32> {
+    let key = format!("{KEY}\\{MONITOR_ORDER_KEY}");
+    read_dword(&key, device_name)
+}
+
+/// Subkey of `KEY` with a `DWORD` value per monitor description, 1 for monitors that are left out
+/// of the flyout.
+const HIDDEN_MONITORS_KEY: &str = "HiddenMonitors";
+
+/// Whether the monitors named `device_name` are left alone, e.g. capture cards or virtual displays
+/// that report DDC/CI support.
+pub fn is_monitor_hidden(device_name: &str) -> bool {
+    let key = format!("{KEY}\\{HIDDEN_MONITORS_KEY}");
+    read_dword(&key, device_name) == Some(1)
+}
+
+/// Name of the `DWORD` value that is 1 once the welcome notification was shown.
+const WELCOME_SHOWN_VALUE: &str = "WelcomeShown";
+
+/// Whether the welcome notification was shown, i.e. whether the app ran before for this user.
+pub fn welcome_shown() -> bool {
+    read_dword(KEY, WELCOME_SHOWN_VALUE) == Some(1)
+}
+
+/// Remember that the welcome notification was shown, so that it is not shown again.
+pub fn set_welcome_shown() {
+    write_dword(KEY, WELCOME_SHOWN_VALUE, 1);
+}
+
+/// Subkey of `KEY` with a `DWORD` value per monitor description for which the hint to turn on
+/// DDC/CI was shown.
+const DDC_CI_HINTS_KEY: &str = "DdcCiHints";
+
+/// Whether the hint to turn on DDC/CI was shown for the monitor named `device_name`, in this or an
+/// earlier session.
+pub fn ddc_ci_hint_shown(device_name: &str) -> bool {
+    let key = format!("{KEY}\\{DDC_CI_HINTS_KEY}");
+    read_dword(&key, device_name).is_some_and(|shown| shown != 0)
+}
+
+/// Remember that the hint to turn on DDC/CI was shown for the monitor named `device_name`, so that
+/// it is not shown again.
+pub fn set_ddc_ci_hint_shown(device_name: &str) {
+    let key = format!("{KEY}\\{DDC_CI_HINTS_KEY}");
+    write_dword(&key, device_name, 1);
+}
+
+/// Subkey of `KEY` with a `DWORD` value per monitor description for which the hint about the floor
+/// of OLED monitors was shown.
+const OLED_HINTS_KEY: &str = "OledHints";
+
+/// Whether the hint about the brightness floor of OLED monitors was shown for the monitor named
+/// `device_name`, in this or an earlier session.
+pub fn oled_hint_shown(device_name: &str) -> bool {
+    let key = format!("{KEY}\\{OLED_HINTS_KEY}");
+    read_dword(&key, device_name).is_some_and(|shown| shown != 0)
+}
+
+/// Remember that the hint about the brightness floor was shown for the OLED monitor named
+/// `device_name`, so that it is not shown again.
+pub fn set_oled_hint_shown(device_name: &str) {
+    let key = format!("{KEY}\\{OLED_HINTS_KEY}");
+    write_dword(&key, device_name, 1);
+}
+
+/// Subkey of `KEY` with a `DWORD` value per monitor description, 1 for monitors whose brightness
+/// follows the one of the primary display.
+const FOLLOW_PRIMARY_KEY: &str = "FollowPrimary";
+
+/// Whether the monitors named `device_name` take on the brightness of the primary display.
+pub fn follows_primary(device_name: &str) -> bool {
+    let key = format!("{KEY}\\{FOLLOW_PRIMARY_KEY}");
+    read_dword(&key, device_name) == Some(1)
+}
+
+/// Remember whether the monitors named `device_name` follow the primary display.
+pub fn set_follows_primary(device_name: &str, follow: bool) {
+    let key = format!("{KEY}\\{FOLLOW_PRIMARY_KEY}");
+    write_dword(&key, device_name, follow as u32);
+}
+
+/// Name of the `DWORD` value that is 1 while the brightness follows the ambient light sensor.
+const AUTO_BRIGHTNESS_VALUE: &str = "AutoBrightness";
+
+/// Whether the brightness follows the ambient light sensor, as toggled in the context menu.
+pub fn auto_brightness() -> bool {
+    read_dword(KEY, AUTO_BRIGHTNESS_VALUE) == Some(1)
+}
+
+/// Remember whether the brightness follows the ambient light sensor.
+pub fn set_auto_brightness(enabled: bool) {
+    write_dword(KEY, AUTO_BRIGHTNESS_VALUE, enabled as u32);
+}
+
+/// Name of the DWORD value that makes the monitors follow the brightness of the built-in display,
+/// e.g. a laptop's panel, whenever it changes. Read at startup.
+const FOLLOW_BUILTIN_DISPLAY_VALUE: &str = "FollowBuiltinDisplay";
+
+/// Whether the monitors follow the built-in display as its brightness changes.
+pub fn follow_builtin_display() -> bool {
+    read_dword(KEY, FOLLOW_BUILTIN_DISPLAY_VALUE) == Some(1)
+}
+
+/// Name of the string value with the points of the `AmbientCurve`, each the illuminance in lux and
+/// the brightness in percent, e.g. `0:20, 50:40, 300:70, 1000:100`.
+const AMBIENT_CURVE_VALUE: &str = "AmbientCurve";
+
+/// The curve that auto brightness follows. Read at startup.
+pub fn ambient_curve() -> AmbientCurve {
+    ambient_curve_from_setting(read_string(KEY, AMBIENT_CURVE_VALUE).as_deref())
+}
+
+fn ambient_curve_from_setting(value: Option<&str>) -> AmbientCurve {
+    (value.and_then(points_from_setting))
+        .and_then(AmbientCurve::new)
+        .unwrap_or_default()
+}
+
+/// What the brightness keys of keyboards change.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BrightnessKeys {
+    /// Change of the brightness in percent per press.
+    pub step: u32,
+    /// Whether only the primary display is changed instead of every monitor.
+    pub primary_only: bool,
+}
+
+/// Name of the `DWORD` value of `BrightnessKeys::step`; 0 leaves the keys to Windows.
+const BRIGHTNESS_KEY_STEP_VALUE: &str = "BrightnessKeyStep";
+/// Name of the `DWORD` value that is 1 for `BrightnessKeys::primary_only`.
+const BRIGHTNESS_KEYS_PRIMARY_VALUE: &str = "BrightnessKeysPrimaryOnly";
+
+/// Whether and how the brightness keys change the monitors. Only read at startup.
+pub fn brightness_keys() -> Option<BrightnessKeys> {
+    brightness_keys_from_settings(
+        read_dword(KEY, BRIGHTNESS_KEY_STEP_VALUE),
+        read_dword(KEY, BRIGHTNESS_KEYS_PRIMARY_VALUE),
+    )
+}
+
+fn brightness_keys_from_settings(
+    step: Option<u32>,
+    primary_only: Option<u32>,
+) -> Option<BrightnessKeys> {
+    match step.unwrap_or(10) {
+        0 => None,
+        step => Some(BrightnessKeys {
+            step: step.min(PERCENT_MAX),
+            primary_only: primary_only == Some(1),
+        }),
+    }
+}
+
+/// Name of the string value with the name of the monitor that was selected last.
+const SELECTED_MONITOR_VALUE: &str = "SelectedMonitor";
+
+/// Name of the monitor that was selected in the flyout last, in this or an earlier session.
+pub fn selected_monitor() -> Option<String> {
+    read_string(KEY, SELECTED_MONITOR_VALUE)
+}
+
+/// Remember the monitor named `device_name` as the selected one, to select it again at the next
+/// startup.
+pub fn set_selected_monitor(device_name: &str) {
+    write_string(KEY, SELECTED_MONITOR_VALUE, device_name);
+}
+
+/// The flyout size, read once so that the native window and its contents always agree.
+pub fn flyout_size() -> FlyoutSize {
+    static SIZE: OnceLock<FlyoutSize> = OnceLock::new();
+    *SIZE.get_or_init(|| FlyoutSize::from_setting(read_dword(KEY, FlyoutSize::VALUE)))
+}
+
+/// Name of the DWORD value that, if `1`, shows tick marks on the brightness sliders and snaps them
+/// to every 10 percent while dragging.
+const SNAP_SLIDER_VALUE: &str = "SnapSlider";
+
+/// Whether the brightness sliders snap to their tick marks. Read once like the other flyout
+/// settings.
+pub fn snap_slider() -> bool {
+    static SNAP: OnceLock<bool> = OnceLock::new();
+    *SNAP.get_or_init(|| read_dword(KEY, SNAP_SLIDER_VALUE) == Some(1))
+}
+
+/// Name of the string value of `flyout_offset`: the horizontal and vertical offset separated by a
+/// comma, e.g. `-8,-8`.
+const FLYOUT_OFFSET_VALUE: &str = "FlyoutOffset";
+
+/// Offset in pixels from the flyout's position next to the taskbar, positive to the right and
+/// down. Read once like the other flyout settings.
+pub fn flyout_offset() -> (i32, i32) {
+    static OFFSET: OnceLock<(i32, i32)> = OnceLock::new();
+    *OFFSET.get_or_init(|| {
+        flyout_offset_from_setting(read_string(KEY, FLYOUT_OFFSET_VALUE).as_deref())
+    })
+}
+
+fn flyout_offset_from_setting(value: Option<&str>) -> (i32, i32) {
+    let parse = |value: &str| {
+        let (x, y) = value.split_once(',')?;
+        Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+    };
+    value.and_then(parse).unwrap_or((0, 0))
+}
+
+/// Name of the `DWORD` value of `flyout_at_cursor`: 1 to open at the cursor, 0 or no value for the
+/// default.
+const FLYOUT_AT_CURSOR_VALUE: &str = "FlyoutAtCursor";
+
+/// Whether the hotkey opens the flyout centered on the cursor instead of next to the notification
+/// area. Clicking the notification icon always opens it there. Read each time the hotkey is
+/// pressed.
+pub fn flyout_at_cursor() -> bool {
+    read_dword(KEY, FLYOUT_AT_CURSOR_VALUE) == Some(1)
+}
+
+/// Background tint of the flyout in place of the one of the Windows theme.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FlyoutTint {
+    /// Red, green, and blue.
+    pub rgb: [u8; 3],
+    /// From 0, see-through, to 1, solid.
+    pub opacity: f64,
+}
+
+/// Name of the string value with the color of `FlyoutTint`, e.g. `#1E3A5F`.
+const TINT_COLOR_VALUE: &str = "TintColor";
+/// Name of the string value with the opacity of `FlyoutTint`, e.g. `0.5`.
+const TINT_OPACITY_VALUE: &str = "TintOpacity";
+
+/// The tint chosen for the flyout, if any. Read once like the other flyout settings.
+pub fn flyout_tint() -> Option<FlyoutTint> {
+    static TINT: OnceLock<Option<FlyoutTint>> = OnceLock::new();
+    *TINT.get_or_init(|| {
+        flyout_tint_from_settings(
+            read_string(KEY, TINT_COLOR_VALUE).as_deref(),
+            read_string(KEY, TINT_OPACITY_VALUE).as_deref(),
+        )
+    })
+}
+
+/// Without a valid color the theme's tint is kept. The opacity defaults to that of the dark tint.
+fn flyout_tint_from_settings(color: Option<&str>, opacity: Option<&str>) -> Option<FlyoutTint> {
+    let hex = color?.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let opacity = opacity
+        .and_then(|opacity| opacity.trim().parse::<f64>().ok())
+        .filter(|opacity| (0.0..=1.0).contains(opacity))
+        .unwrap_or(0.2);
+    Some(FlyoutTint {
+        rgb: [channel(0)?, channel(2)?, channel(4)?],
+        opacity,
+    })
+}
+
+/// Name of the `DWORD` value of `invert_scroll`: 1 to invert, 0 or no value for the default.
+const INVERT_SCROLL_VALUE: &str = "InvertScroll";
+
+/// Whether scrolling up darkens instead of brightens. Read once, when first scrolling.
+pub fn invert_scroll() -> bool {
+    static INVERT: OnceLock<bool> = OnceLock::new();
+    *INVERT.get_or_init(|| read_dword(KEY, INVERT_SCROLL_VALUE) == Some(1))
+}
+
+/// Name of the `DWORD` value of `editable_brightness`: 0 for a read-only number, 1 or no value for
+/// the default.
+const EDITABLE_BRIGHTNESS_VALUE: &str = "EditableBrightness";
+
+/// Whether the brightness number next to the slider can be typed into. Read once, when building
+/// the controls.
+pub fn editable_brightness() -> bool {
+    static EDITABLE: OnceLock<bool> = OnceLock::new();
+    *EDITABLE.get_or_init(|| read_dword(KEY, EDITABLE_BRIGHTNESS_VALUE) != Some(0))
+}
+
+/// The flyout layout, read once since the controls are only built at startup.
+pub fn flyout_layout() -> FlyoutLayout {
+    static LAYOUT: OnceLock<FlyoutLayout> = OnceLock::new();
+    *LAYOUT.get_or_init(|| FlyoutLayout::from_setting(read_dword(KEY, FlyoutLayout::VALUE)))
+}
+
+fn read_dword(key: &str, name: &str) -> Option<u32> {
+    let subkey = to_wide(key);
+    let value = to_wide(name);
+
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut data as *mut u32 as *mut _,
+            &mut size,
+        )
+    };
+    (result == ERROR_SUCCESS).then_some(data)
+}
+
+/// Write a `DWORD` value, creating `key` if needed. Failures are ignored like missing values when
+/// reading.
+fn write_dword(key: &str, name: &str, data: u32) {
+    let subkey = to_wide(key);
+    let value = to_wide(name);
+    unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            REG_DWORD.0,
+            &data as *const u32 as *const _,
+            std::mem::size_of::<u32>() as u32,
+        );
+    }
+}
+
+fn read_string(key: &str, name: &str) -> Option<String> {
+    let subkey = to_wide(key);
+    let value = to_wide(name);
+
+    // The first call gets the size in bytes, including the terminating NUL
+    let read = |data: *mut u16, size: &mut u32| unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            data as *mut _,
+            size,
+        )
+    };
+    let mut size = 0u32;
+    if read(std::ptr::null_mut(), &mut size) != ERROR_SUCCESS {
+        return None;
+    }
+    let mut data = vec![0u16; size as usize / 2];
+    if read(data.as_mut_ptr(), &mut size) != ERROR_SUCCESS {
+        return None;
+    }
+    let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    String::from_utf16(&data[..len]).ok()
+}
+
+/// Write a string value to `key`. Failures are ignored like missing values when reading.
+fn write_string(key: &str, name: &str, data: &str) {
+    let subkey = to_wide(key);
+    let value = to_wide(name);
+    let data = to_wide(data);
+    unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            REG_SZ.0,
+            data.as_ptr() as *const _,
+            (data.len() * std::mem::size_of::<u16>()) as u32,
+        );
+    }
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flyout_size_from_setting() {
+        assert_eq!(FlyoutSize::from_setting(None), FlyoutSize::Compact);
+        assert_eq!(FlyoutSize::from_setting(Some(0)), FlyoutSize::Compact);
+        assert_eq!(FlyoutSize::from_setting(Some(1)), FlyoutSize::Expanded);
+        assert_eq!(FlyoutSize::from_setting(Some(7)), FlyoutSize::Compact);
+    }
+
+    #[test]
+    fn flyout_layout_from_setting() {
+        assert_eq!(FlyoutLayout::from_setting(None), FlyoutLayout::Selector);
+        assert_eq!(FlyoutLayout::from_setting(Some(0)), FlyoutLayout::Selector);
+        assert_eq!(
+            FlyoutLayout::from_setting(Some(1)),
+            FlyoutLayout::AllMonitors
+        );
+        assert_eq!(FlyoutLayout::from_setting(Some(2)), FlyoutLayout::Selector);
+    }
+
+    #[test]
+    fn exit_brightness_from_setting() {
+        assert_eq!(ExitBrightness::from_setting(None), ExitBrightness::Leave);
+        assert_eq!(
+            ExitBrightness::from_setting(Some("")),
+            ExitBrightness::Leave
+        );
+        assert_eq!(
+            ExitBrightness::from_setting(Some("Restore")),
+            ExitBrightness::Restore
+        );
+        assert_eq!(
+            ExitBrightness::from_setting(Some("100")),
+            ExitBrightness::Set(100)
+        );
+        assert_eq!(
+            ExitBrightness::from_setting(Some(" 40% ")),
+            ExitBrightness::Set(40)
+        );
+        assert_eq!(
+            ExitBrightness::from_setting(Some("250")),
+            ExitBrightness::Set(PERCENT_MAX)
+        );
+        assert_eq!(
+            ExitBrightness::from_setting(Some("leave")),
+            ExitBrightness::Leave
+        );
+    }
+
+    #[test]
+    fn write_failures_from_setting() {
+        assert_eq!(WriteFailures::from_setting(None), WriteFailures::NotifyOnce);
+        assert_eq!(
+            WriteFailures::from_setting(Some(" Silent")),
+            WriteFailures::Silent
+        );
+        assert_eq!(
+            WriteFailures::from_setting(Some("disable")),
+            WriteFailures::Disable
+        );
+        assert_eq!(
+            WriteFailures::from_setting(Some("notify")),
+            WriteFailures::NotifyOnce
+        );
+    }
+
+    #[test]
+    fn flyout_offset_is_two_numbers() {
+        assert_eq!(flyout_offset_from_setting(None), (0, 0));
+        assert_eq!(flyout_offset_from_setting(Some("-8,-8")), (-8, -8));
+        assert_eq!(flyout_offset_from_setting(Some(" 12 , 0 ")), (12, 0));
+        assert_eq!(flyout_offset_from_setting(Some("12")), (0, 0));
+        assert_eq!(flyout_offset_from_setting(Some("a,3")), (0, 0));
+    }
+
+    #[test]
+    fn flyout_tint_needs_a_color() {
+        let tint = |rgb, opacity| Some(FlyoutTint { rgb, opacity });
+        assert_eq!(flyout_tint_from_settings(None, Some("0.5")), None);
+        assert_eq!(
+            flyout_tint_from_settings(Some("#1E3A5F"), Some("0.5")),
+            tint([0x1e, 0x3a, 0x5f], 0.5)
+        );
+        assert_eq!(
+            flyout_tint_from_settings(Some("ffffff"), None),
+            tint([0xff, 0xff, 0xff], 0.2)
+        );
+        assert_eq!(
+            flyout_tint_from_settings(Some("#000000"), Some("2")),
+            tint([0, 0, 0], 0.2)
+        );
+        assert_eq!(flyout_tint_from_settings(Some("#12345"), None), None);
+        assert_eq!(flyout_tint_from_settings(Some("#12345G"), None), None);
+        assert_eq!(flyout_tint_from_settings(Some("#ää123"), None), None);
+    }
+
+    #[test]
+    fn brightness_cap_is_a_percentage() {
+        assert_eq!(brightness_cap_from_setting(None), PERCENT_MAX);
+        assert_eq!(brightness_cap_from_setting(Some(0)), 1);
+        assert_eq!(brightness_cap_from_setting(Some(70)), 70);
+        assert_eq!(brightness_cap_from_setting(Some(250)), PERCENT_MAX);
+    }
+
+    #[test]
+    fn brightness_floor_defaults_by_technology() {
+        assert_eq!(brightness_floor_from_setting(None, false), 0);
+        assert_eq!(brightness_floor_from_setting(None, true), OLED_FLOOR);
+        // Set explicitly, also to turn it off for OLED monitors
+        assert_eq!(brightness_floor_from_setting(Some(0), true), 0);
+        assert_eq!(brightness_floor_from_setting(Some(5), false), 5);
+        assert_eq!(brightness_floor_from_setting(Some(100), false), 99);
+    }
+
+    #[test]
+    fn brightness_offset_from_string() {
+        assert_eq!(
+            brightness_offset_from_setting(None),
+            DEFAULT_BRIGHTNESS_OFFSET
+        );
+        assert_eq!(brightness_offset_from_setting(Some(" -35 ")), -35);
+        assert_eq!(brightness_offset_from_setting(Some("0")), 0);
+        // Offsets only dim
+        assert_eq!(brightness_offset_from_setting(Some("20")), 0);
+        assert_eq!(brightness_offset_from_setting(Some("-150")), -100);
+        assert_eq!(
+            brightness_offset_from_setting(Some("dim")),
+            DEFAULT_BRIGHTNESS_OFFSET
+        );
+    }
+
+    #[test]
+    fn calibration_is_a_list_of_points() {
+        let calibration = calibration_from_setting(Some("0:5, 50:20,100 : 80")).unwrap();
+        assert_eq!(calibration.points(), [(0, 5), (50, 20), (100, 80)]);
+        assert_eq!(calibration_to_setting(&calibration), "0:5, 50:20, 100:80");
+        assert_eq!(calibration_from_setting(None), None);
+        assert_eq!(calibration_from_setting(Some("")), None);
+        assert_eq!(calibration_from_setting(Some("0:5, 50")), None);
+        // Not monotonic
+        assert_eq!(calibration_from_setting(Some("0:5, 50:40, 100:30")), None);
+    }
+
+    #[test]
+    fn ambient_curve_falls_back_to_the_default() {
+        let curve = ambient_curve_from_setting(Some("0:10, 500:100"));
+        assert_eq!(curve.percent_at(250.0), 55);
+        assert_eq!(ambient_curve_from_setting(None), AmbientCurve::default());
+        assert_eq!(
+            ambient_curve_from_setting(Some("0:10, dark")),
+            AmbientCurve::default()
+        );
+        assert_eq!(
+            ambient_curve_from_setting(Some("10:10, 10:20")),
+            AmbientCurve::default()
+        );
+    }
+
+    #[test]
+    fn write_interval_defaults_to_50_ms() {
+        assert_eq!(write_interval_from_setting(None), Duration::from_millis(50));
+        assert_eq!(write_interval_from_setting(Some(0)), Duration::ZERO);
+        assert_eq!(
+            write_interval_from_setting(Some(120)),
+            Duration::from_millis(120)
+        );
+        assert_eq!(
+            write_interval_from_setting(Some(60000)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn idle_dim_from_settings_needs_minutes() {
+        assert_eq!(idle_dim_from_settings(None, Some(10)), None);
+        assert_eq!(idle_dim_from_settings(Some(0), Some(10)), None);
+        assert_eq!(
+            idle_dim_from_settings(Some(5), None),
+            Some(IdleDim {
+                after: Duration::from_secs(300),
+                percent: 20
+            })
+        );
+        assert_eq!(
+            idle_dim_from_settings(Some(1), Some(250)),
+            Some(IdleDim {
+                after: Duration::from_secs(60),
+                percent: PERCENT_MAX
+            })
+        );
+    }
+
+    #[test]
+    fn default_brightness_is_a_percentage() {
+        assert_eq!(default_brightness_from_setting(None), 80);
+        assert_eq!(default_brightness_from_setting(Some(0)), 0);
+        assert_eq!(default_brightness_from_setting(Some(35)), 35);
+        assert_eq!(default_brightness_from_setting(Some(250)), PERCENT_MAX);
+    }
+
+    #[test]
+    fn brightness_keys_from_setting() {
+        let keys = |step, primary_only| Some(BrightnessKeys { step, primary_only });
+        assert_eq!(brightness_keys_from_settings(None, None), keys(10, false));
+        assert_eq!(brightness_keys_from_settings(Some(0), Some(1)), None);
+        assert_eq!(
+            brightness_keys_from_settings(Some(5), Some(1)),
+            keys(5, true)
+        );
+        assert_eq!(
+            brightness_keys_from_settings(Some(5), Some(2)),
+            keys(5, false)
+        );
+        assert_eq!(
+            brightness_keys_from_settings(Some(400), None),
+            keys(PERCENT_MAX, false)
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! The state of a monitor as plain data, for everything that reports or saves it, e.g. the named
+//! pipe or exported settings.
+
+use serde::Serialize;
+
+use crate::{DisplayTechnology, Monitor};
+
+/// The state of a monitor as last read, taken with `Monitor::snapshot`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct MonitorSnapshot {
+    /// Description of the monitor, as returned by `Monitor::get_name`.
+    pub name: String,
+    /// Whether the monitor shows the primary display.
+    pub primary: bool,
+    /// Lowest brightness the monitor accepts.
+    pub min_brightness: u32,
+    /// Brightness last read from or written to the monitor.
+    pub brightness: u32,
+    /// Highest brightness the monitor accepts.
+    pub max_brightness: u32,
+    /// VCP codes that the capabilities string lists, in ascending order. Empty if the monitor did
+    /// not report its capabilities.
+    pub vcp_codes: Vec<u8>,
+    /// Technology of the monitor's panel, see `Monitor::technology`.
+    pub technology: DisplayTechnology,
+}
+
+impl Monitor {
+    /// The state of the monitor as last read. Does not talk to the monitor; call
+    /// `refresh_brightness` first for the current brightness.
+    pub fn snapshot(&self) -> MonitorSnapshot {
+        MonitorSnapshot {
+            name: self.get_name().to_owned(),
+            primary: self.is_primary(),
+            min_brightness: self.get_min_brightness(),
+            brightness: self.get_brightness(),
+            max_brightness: self.get_max_brightness(),
+            vcp_codes: (self.capabilities())
+                .map(|capabilities| capabilities.vcp_codes().collect())
+                .unwrap_or_default(),
+            technology: self.technology(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_snapshot() {
+        let snapshot = MonitorSnapshot {
+            name: "Generic PnP Monitor".to_owned(),
+            primary: true,
+            min_brightness: 0,
+            brightness: 40,
+            max_brightness: 100,
+            vcp_codes: vec![0x10, 0x12],
+            technology: DisplayTechnology::Oled,
+        };
+        assert_eq!(
+            serde_json::to_string(&snapshot).unwrap(),
+            r#"{"name":"Generic PnP Monitor","primary":true,"min_brightness":0,"brightness":40,"max_brightness":100,"vcp_codes":[16,18],"technology":"oled"}"#
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! User-facing text in the user's UI language. Adding a language only needs a new entry in
+//! `LANGUAGES`; English is used for everything else.
+
+use std::sync::OnceLock;
+
+use windows::Win32::{
+    Globalization::GetUserDefaultUILanguage,
+    System::SystemServices::{
+        LANG_ARABIC, LANG_ENGLISH, LANG_FRENCH, LANG_GERMAN, LANG_HEBREW, LANG_SPANISH,
+    },
+};
+
+/// Text of a single language. `{}` in a template is replaced with the argument passed to
+/// `Strings::format`.
+pub struct Strings {
+    /// Whether the text is written right to left, which also mirrors the flyout's layout.
+    pub right_to_left: bool,
+    pub select_monitor: &'static str,
+    /// Tooltip of the notification icon; takes the brightness.
+    pub brightness_tooltip: &'static str,
+    /// Appended to the tooltip of the notification icon while the brightness is locked.
+    pub locked_suffix: &'static str,
+    /// Appended to the tooltip of the notification icon while the automatic changes are paused.
+    pub paused_suffix: &'static str,
+    /// Appended to the brightness in the tooltip and balloons by `ChangeSource::suffix`.
+    pub source_automatic: &'static str,
+    pub source_hotkey: &'static str,
+    pub source_idle: &'static str,
+    pub source_ambient: &'static str,
+    pub source_boost: &'static str,
+    pub source_follow_primary: &'static str,
+    pub source_monitor: &'static str,
+    pub source_builtin: &'static str,
+    /// Appended to the name of the primary display in the selector.
+    pub primary_suffix: &'static str,
+    /// Appended to the name of monitors whose slider sets the brightness of SDR content in HDR
+    /// mode instead of the monitor's own brightness.
+    pub sdr_suffix: &'static str,
+    /// Takes the monitor name.
+    pub reset_failed: &'static str,
+    /// Shown when a monitor that answered before refuses a brightness; takes the monitor name.
+    pub brightness_rejected: &'static str,
+    pub standby_failed: &'static str,
+    pub wake_failed: &'static str,
+    /// Shown once per monitor whose writes all fail; takes the monitor name.
+    pub ddc_ci_disabled: &'static str,
+    /// Notification the first time an OLED monitor gets its brightness floor, with its name.
+    pub oled_floor: &'static str,
+    /// Tooltip of the button that keeps the flyout open.
+    pub pin_tooltip: &'static str,
+    pub menu_reset_monitor: &'static str,
+    pub menu_reset_all: &'static str,
+    pub menu_lock: &'static str,
+    pub menu_pause_automation: &'static str,
+    pub menu_follow_primary: &'static str,
+    pub menu_auto_brightness: &'static str,
+    pub menu_locate: &'static str,
+    pub menu_match_builtin: &'static str,
+    /// Names the duration of `MENU_BOOST`.
+    pub menu_boost: &'static str,
+    pub menu_dim_all: &'static str,
+    pub menu_standby: &'static str,
+    pub menu_wake: &'static str,
+    pub menu_export_settings: &'static str,
+    pub menu_import_settings: &'static str,
+    pub menu_copy_diagnostics: &'static str,
+    pub menu_exit: &'static str,
+    /// Takes the number of monitors.
+    pub settings_imported: &'static str,
+    /// Takes the error.
+    pub import_failed: &'static str,
+    /// Takes the error.
+    pub export_failed: &'static str,
+    pub diagnostics_copied: &'static str,
+    pub diagnostics_failed: &'static str,
+    /// Shown once at startup when the classic flyout is used instead of the XAML one.
+    pub xaml_unavailable: &'static str,
+    /// Tooltip of the label of a picture slider, with the number of the picture. Shown for monitors
+    /// in picture-by-picture or picture-in-picture mode.
+    pub picture_brightness: &'static str,
+    /// Tooltip of the notification icon while the monitors are read at startup.
+    pub loading_monitors: &'static str,
+    /// Shown on the first start. Clicking it opens the flyout.
+    pub welcome: &'static str,
+}
+
+impl Strings {
+    /// Fill in the `{}` of `template`.
+    pub fn format(template: &str, arg: impl std::fmt::Display) -> String {
+        template.replacen("{}", &arg.to_string(), 1)
+    }
+}
+
+const ENGLISH: Strings = Strings {
+    right_to_left: false,
+    select_monitor: "Select monitor",
+    brightness_tooltip: "Brightness: {}",
+    locked_suffix: " [locked]",
+    paused_suffix: " [automation paused]",
+    source_automatic: " (other app)",
+    source_hotkey: " (shortcut)",
+    source_idle: " (idle)",
+    source_ambient: " (auto brightness)",
+    source_boost: " (boost)",
+    source_follow_primary: " (primary display)",
+    source_monitor: " (monitor)",
+    source_builtin: " (built-in display)",
+    primary_suffix: " (Primary)",
+    sdr_suffix: " (SDR content)",
+    reset_failed: "Could not reset {}",
+    brightness_rejected: "{} did not accept the brightness",
+    standby_failed: "Could not turn off all monitors",
+    wake_failed: "Could not turn on all monitors",
+    ddc_ci_disabled: "{} does not respond. Turn on DDC/CI in the monitor's on-screen menu",
+    oled_floor: "{} is an OLED monitor. Its slider starts above the lowest brightness, where OLED panels can flicker",
+    pin_tooltip: "Keep open",
+    menu_reset_monitor: "Reset monitor",
+    menu_reset_all: "Set all to default brightness",
+    menu_lock: "Lock brightness",
+    menu_pause_automation: "Pause automation",
+    menu_follow_primary: "Follow primary display",
+    menu_auto_brightness: "Auto brightness",
+    menu_locate: "Locate monitor",
+    menu_match_builtin: "Match built-in display",
+    menu_boost: "Boost for 10 minutes",
+    menu_dim_all: "Dim all monitors",
+    menu_standby: "Turn off monitors",
+    menu_wake: "Turn on monitors",
+    menu_export_settings: "Export settings…",
+    menu_import_settings: "Import settings…",
+    menu_copy_diagnostics: "Copy diagnostics",
+    menu_exit: "Exit",
+    diagnostics_copied: "Copied the diagnostics to the clipboard",
+    diagnostics_failed: "Could not copy the diagnostics to the clipboard",
+    settings_imported: "Applied the settings of {} monitors",
+    import_failed: "Could not import the settings: {}",
+    export_failed: "Could not export the settings: {}",
+    xaml_unavailable: "XAML islands are not available; using a basic flyout instead",
+    picture_brightness: "Brightness of picture {}",
+    loading_monitors: "Reading the monitors…",
+    welcome: "Click the brightness icon in the notification area to adjust your monitors, or \
+              right-click it for more. Click here to try it now.",
+};
+
+/// Keyed by primary language ID.
+const LANGUAGES: &[(u32, Strings)] = &[
+    (LANG_ENGLISH, ENGLISH),
+    (
+        LANG_GERMAN,
+        Strings {
+            right_to_left: false,
+            select_monitor: "Monitor auswählen",
+            brightness_tooltip: "Helligkeit: {}",
+            locked_suffix: " [gesperrt]",
+            paused_suffix: " [Automatik pausiert]",
+            source_automatic: " (andere App)",
+            source_hotkey: " (Tastenkürzel)",
+            source_idle: " (Leerlauf)",
+            source_ambient: " (automatische Helligkeit)",
+            source_boost: " (Boost)",
+            source_follow_primary: " (Hauptbildschirm)",
+            source_monitor: " (Bildschirm)",
+            source_builtin: " (integrierter Bildschirm)",
+            primary_suffix: " (Hauptbildschirm)",
+            sdr_suffix: " (SDR-Inhalte)",
+            reset_failed: "{} konnte nicht zurückgesetzt werden",
+            brightness_rejected: "{} hat die Helligkeit nicht übernommen",
+            standby_failed: "Nicht alle Monitore konnten ausgeschaltet werden",
+            wake_failed: "Nicht alle Monitore konnten eingeschaltet werden",
+            ddc_ci_disabled:
+                "{} antwortet nicht. Aktivieren Sie DDC/CI im Bildschirmmenü des Monitors",
+            oled_floor: "{} ist ein OLED-Monitor. Sein Schieberegler beginnt über der niedrigsten Helligkeit, bei der OLED-Bildschirme flimmern können",
+            pin_tooltip: "Geöffnet lassen",
+            menu_reset_monitor: "Monitor zurücksetzen",
+            menu_reset_all: "Alle auf Standardhelligkeit",
+            menu_lock: "Helligkeit sperren",
+            menu_pause_automation: "Automatik pausieren",
+            menu_follow_primary: "Primärem Bildschirm folgen",
+            menu_auto_brightness: "Automatische Helligkeit",
+            menu_locate: "Bildschirm finden",
+            menu_match_builtin: "An integrierten Bildschirm anpassen",
+            menu_boost: "10 Minuten lang aufhellen",
+            menu_dim_all: "Alle Bildschirme abdunkeln",
+            menu_standby: "Monitore ausschalten",
+            menu_wake: "Monitore einschalten",
+            menu_export_settings: "Einstellungen exportieren…",
+            menu_import_settings: "Einstellungen importieren…",
+            menu_copy_diagnostics: "Diagnose kopieren",
+            menu_exit: "Beenden",
+            diagnostics_copied: "Die Diagnose wurde in die Zwischenablage kopiert",
+            diagnostics_failed: "Die Diagnose konnte nicht in die Zwischenablage kopiert werden",
+            settings_imported: "Einstellungen von {} Monitoren übernommen",
+            import_failed: "Einstellungen konnten nicht importiert werden: {}",
+            export_failed: "Einstellungen konnten nicht exportiert werden: {}",
+            xaml_unavailable:
+                "XAML Islands sind nicht verfügbar; stattdessen wird ein einfaches Flyout verwendet",
+            picture_brightness: "Helligkeit von Bild {}",
+            loading_monitors: "Monitore werden gelesen…",
+            welcome: "Klicken Sie auf das Helligkeitssymbol im Infobereich, um Ihre Monitore \
+                      einzustellen, oder mit der rechten Maustaste für mehr. Hier klicken, um es \
+                      jetzt auszuprobieren.",
+        },
+    ),
+    (
+        LANG_SPANISH,
+        Strings {
+            right_to_left: false,
+            select_monitor: "Seleccionar monitor",
+            brightness_tooltip: "Brillo: {}",
+            locked_suffix: " [bloqueado]",
+            paused_suffix: " [automatización en pausa]",
+            source_automatic: " (otra aplicación)",
+            source_hotkey: " (atajo)",
+            source_idle: " (inactividad)",
+            source_ambient: " (brillo automático)",
+            source_boost: " (impulso)",
+            source_follow_primary: " (pantalla principal)",
+            source_monitor: " (pantalla)",
+            source_builtin: " (pantalla integrada)",
+            primary_suffix: " (Principal)",
+            sdr_suffix: " (contenido SDR)",
+            reset_failed: "No se pudo restablecer {}",
+            brightness_rejected: "{} no aceptó el brillo",
+            standby_failed: "No se pudieron apagar todos los monitores",
+            wake_failed: "No se pudieron encender todos los monitores",
+            ddc_ci_disabled: "{} no responde. Active DDC/CI en el menú en pantalla del monitor",
+            oled_floor: "{} es un monitor OLED. Su control deslizante empieza por encima del brillo más bajo, en el que las pantallas OLED pueden parpadear",
+            pin_tooltip: "Mantener abierto",
+            menu_reset_monitor: "Restablecer monitor",
+            menu_reset_all: "Brillo predeterminado en todos",
+            menu_lock: "Bloquear brillo",
+            menu_pause_automation: "Pausar automatización",
+            menu_follow_primary: "Seguir a la pantalla principal",
+            menu_auto_brightness: "Brillo automático",
+            menu_locate: "Localizar pantalla",
+            menu_match_builtin: "Igualar a la pantalla integrada",
+            menu_boost: "Aumentar brillo 10 minutos",
+            menu_dim_all: "Atenuar todas las pantallas",
+            menu_standby: "Apagar monitores",
+            menu_wake: "Encender monitores",
+            menu_export_settings: "Exportar configuración…",
+            menu_import_settings: "Importar configuración…",
+            menu_copy_diagnostics: "Copiar diagnóstico",
+            menu_exit: "Salir",
+            diagnostics_copied: "Se copió el diagnóstico al portapapeles",
+            diagnostics_failed: "No se pudo copiar el diagnóstico al portapapeles",
+            settings_imported: "Se aplicó la configuración de {} monitores",
+            import_failed: "No se pudo importar la configuración: {}",
+            export_failed: "No se pudo exportar la configuración: {}",
+            xaml_unavailable: "XAML Islands no está disponible; se usa un panel básico en su lugar",
+            picture_brightness: "Brillo de la imagen {}",
+            loading_monitors: "Leyendo los monitores…",
+            welcome: "Haga clic en el icono de brillo del área de notificación para ajustar sus \
+                      monitores, o con el botón derecho para más opciones. Haga clic aquí para \
+                      probarlo ahora.",
+        },
+    ),
+    (
+        LANG_FRENCH,
+        Strings {
+            right_to_left: false,
+            select_monitor: "Sélectionner un écran",
+            brightness_tooltip: "Luminosité : {}",
+            locked_suffix: " [verrouillé]",
+            paused_suffix: " [automatisation en pause]",
+            source_automatic: " (autre application)",
+            source_hotkey: " (raccourci)",
+            source_idle: " (inactivité)",
+            source_ambient: " (luminosité automatique)",
+            source_boost: " (boost)",
+            source_follow_primary: " (écran principal)",
+            source_monitor: " (écran)",
+            source_builtin: " (écran intégré)",
+            primary_suffix: " (Principal)",
+            sdr_suffix: " (contenu SDR)",
+            reset_failed: "Impossible de réinitialiser {}",
+            brightness_rejected: "{} n'a pas accepté la luminosité",
+            standby_failed: "Impossible d'éteindre tous les écrans",
+            wake_failed: "Impossible d'allumer tous les écrans",
+            ddc_ci_disabled: "{} ne répond pas. Activez DDC/CI dans le menu à l'écran du moniteur",
+            oled_floor: "{} est un écran OLED. Son curseur commence au-dessus de la luminosité la plus basse, où les dalles OLED peuvent scintiller",
+            pin_tooltip: "Garder ouvert",
+            menu_reset_monitor: "Réinitialiser l'écran",
+            menu_reset_all: "Luminosité par défaut partout",
+            menu_lock: "Verrouiller la luminosité",
+            menu_pause_automation: "Suspendre l'automatisation",
+            menu_follow_primary: "Suivre l'écran principal",
+            menu_auto_brightness: "Luminosité automatique",
+            menu_locate: "Localiser l'écran",
+            menu_match_builtin: "Aligner sur l'écran intégré",
+            menu_boost: "Éclaircir pendant 10 minutes",
+            menu_dim_all: "Assombrir tous les écrans",
+            menu_standby: "Éteindre les écrans",
+            menu_wake: "Allumer les écrans",
+            menu_export_settings: "Exporter les paramètres…",
+            menu_import_settings: "Importer les paramètres…",
+            menu_copy_diagnostics: "Copier le diagnostic",
+            menu_exit: "Quitter",
+            diagnostics_copied: "Diagnostic copié dans le presse-papiers",
+            diagnostics_failed: "Impossible de copier le diagnostic dans le presse-papiers",
+            settings_imported: "Paramètres de {} écrans appliqués",
+            import_failed: "Impossible d'importer les paramètres : {}",
+            export_failed: "Impossible d'exporter les paramètres : {}",
+            xaml_unavailable:
+                "Les îlots XAML ne sont pas disponibles ; un panneau simple est utilisé à la place",
+            picture_brightness: "Luminosité de l'image {}",
+            loading_monitors: "Lecture des écrans…",
+            welcome:
+                "Cliquez sur l'icône de luminosité de la zone de notification pour régler vos \
+                      écrans, ou cliquez droit pour plus d'options. Cliquez ici pour l'essayer.",
+        },
+    ),
+    (
+        LANG_ARABIC,
+        Strings {
+            right_to_left: true,
+            select_monitor: "اختر الشاشة",
+            brightness_tooltip: "السطوع: {}",
+            locked_suffix: " [مقفل]",
+            paused_suffix: " [الأتمتة متوقفة مؤقتًا]",
+            source_automatic: " (تطبيق آخر)",
+            source_hotkey: " (اختصار)",
+            source_idle: " (خمول)",
+            source_ambient: " (السطوع التلقائي)",
+            source_boost: " (تعزيز)",
+            source_follow_primary: " (الشاشة الرئيسية)",
+            source_monitor: " (الشاشة)",
+            source_builtin: " (الشاشة المدمجة)",
+            primary_suffix: " (الرئيسية)",
+            sdr_suffix: " (محتوى SDR)",
+            reset_failed: "تعذرت إعادة تعيين {}",
+            brightness_rejected: "لم يقبل {} السطوع",
+            standby_failed: "تعذر إيقاف تشغيل كل الشاشات",
+            wake_failed: "تعذر تشغيل كل الشاشات",
+            ddc_ci_disabled: "لا تستجيب {}. قم بتشغيل DDC/CI من قائمة الشاشة",
+            oled_floor: "{} شاشة OLED. يبدأ شريط التمرير فوق أدنى سطوع، حيث قد تومض شاشات OLED",
+            pin_tooltip: "إبقاء مفتوحًا",
+            menu_reset_monitor: "إعادة تعيين الشاشة",
+            menu_reset_all: "السطوع الافتراضي لكل الشاشات",
+            menu_lock: "قفل السطوع",
+            menu_pause_automation: "إيقاف الأتمتة مؤقتًا",
+            menu_follow_primary: "اتباع الشاشة الرئيسية",
+            menu_auto_brightness: "السطوع التلقائي",
+            menu_locate: "تحديد موقع الشاشة",
+            menu_match_builtin: "مطابقة الشاشة المدمجة",
+            menu_boost: "زيادة السطوع لمدة 10 دقائق",
+            menu_dim_all: "تعتيم كل الشاشات",
+            menu_standby: "إيقاف تشغيل الشاشات",
+            menu_wake: "تشغيل الشاشات",
+            menu_export_settings: "تصدير الإعدادات…",
+            menu_import_settings: "استيراد الإعدادات…",
+            menu_copy_diagnostics: "نسخ التشخيص",
+            menu_exit: "خروج",
+            diagnostics_copied: "تم نسخ التشخيص إلى الحافظة",
+            diagnostics_failed: "تعذر نسخ التشخيص إلى الحافظة",
+            settings_imported: "تم تطبيق إعدادات {} شاشات",
+            import_failed: "تعذر استيراد الإعدادات: {}",
+            export_failed: "تعذر تصدير الإعدادات: {}",
+            xaml_unavailable: "جزر XAML غير متاحة؛ يتم استخدام واجهة مبسطة بدلاً من ذلك",
+            picture_brightness: "سطوع الصورة {}",
+            loading_monitors: "جارٍ قراءة الشاشات…",
+            welcome: "انقر على أيقونة السطوع في منطقة الإعلامات لضبط شاشاتك، أو انقر بزر الماوس \
+                      الأيمن لمزيد من الخيارات. انقر هنا لتجربتها الآن.",
+        },
+    ),
+    (
+        LANG_HEBREW,
+        Strings {
+            right_to_left: true,
+            select_monitor: "בחר צג",
+            brightness_tooltip: "בהירות: {}",
+            locked_suffix: " [נעול]",
+            paused_suffix: " [האוטומציה מושהית]",
+            source_automatic: " (אפליקציה אחרת)",
+            source_hotkey: " (קיצור מקשים)",
+            source_idle: " (חוסר פעילות)",
+            source_ambient: " (בהירות אוטומטית)",
+            source_boost: " (הגברה)",
+            source_follow_primary: " (מסך ראשי)",
+            source_monitor: " (מסך)",
+            source_builtin: " (מסך מובנה)",
+            primary_suffix: " (ראשי)",
+            sdr_suffix: " (תוכן SDR)",
+            reset_failed: "לא ניתן לאפס את {}",
+            brightness_rejected: "{} לא קיבל את הבהירות",
+            standby_failed: "לא ניתן לכבות את כל הצגים",
+            wake_failed: "לא ניתן להפעיל את כל הצגים",
+            ddc_ci_disabled: "{} אינו מגיב. הפעל את DDC/CI בתפריט המסך של הצג",
+            oled_floor: "{} הוא צג OLED. המחוון שלו מתחיל מעל הבהירות הנמוכה ביותר, שבה מסכי OLED עלולים להבהב",
+            pin_tooltip: "השאר פתוח",
+            menu_reset_monitor: "אפס צג",
+            menu_reset_all: "בהירות ברירת מחדל לכל הצגים",
+            menu_lock: "נעילת בהירות",
+            menu_pause_automation: "השהה אוטומציה",
+            menu_follow_primary: "עקוב אחר המסך הראשי",
+            menu_auto_brightness: "בהירות אוטומטית",
+            menu_locate: "אתר את המסך",
+            menu_match_builtin: "התאם למסך המובנה",
+            menu_boost: "הגבר בהירות ל-10 דקות",
+            menu_dim_all: "עמעם את כל המסכים",
+            menu_standby: "כבה צגים",
+            menu_wake: "הפעל צגים",
+            menu_export_settings: "ייצוא הגדרות…",
+            menu_import_settings: "ייבוא הגדרות…",
+            menu_copy_diagnostics: "העתק אבחון",
+            menu_exit: "יציאה",
+            diagnostics_copied: "האבחון הועתק ללוח",
+            diagnostics_failed: "לא ניתן להעתיק את האבחון ללוח",
+            settings_imported: "הוחלו ההגדרות של {} צגים",
+            import_failed: "לא ניתן לייבא את ההגדרות: {}",
+            export_failed: "לא ניתן לייצא את ההגדרות: {}",
+            xaml_unavailable: "איי XAML אינם זמינים; נעשה שימוש בחלונית בסיסית במקום",
+            picture_brightness: "בהירות תמונה {}",
+            loading_monitors: "קורא את הצגים…",
+            welcome: "לחץ על סמל הבהירות באזור ההודעות כדי לכוונן את הצגים, או לחץ עליו לחיצה \
+                      ימנית לאפשרויות נוספות. לחץ כאן כדי לנסות עכשיו.",
+        },
+    ),
+];
+
+/// Text for the user's UI language, looked up once.
+pub fn strings() -> &'static Strings {
+    static STRINGS: OnceLock<&'static Strings> = OnceLock::new();
+    STRINGS.get_or_init(|| {
+        let language_id = unsafe { GetUserDefaultUILanguage() };
+        strings_for(language_id)
+    })
+}
+
+fn strings_for(language_id: u16) -> &'static Strings {
+    // The low 10 bits are the primary language, the rest the sublanguage (e.g. US or UK English)
+    let primary = language_id as u32 & 0x3ff;
+    LANGUAGES
+        .iter()
+        .find(|(id, _)| *id == primary)
+        .map_or(&ENGLISH, |(_, strings)| strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_by_primary_language() {
+        // German (Austria) and an unlisted language
+        assert_eq!(strings_for(0x0c07).menu_exit, "Beenden");
+        assert_eq!(strings_for(0x0411).menu_exit, "Exit");
+        assert!(strings_for(0x040d).right_to_left);
+
+        for (_, strings) in LANGUAGES {
+            assert!(strings.brightness_tooltip.contains("{}"));
+            let templates = [
+                strings.reset_failed,
+                strings.brightness_rejected,
+                strings.ddc_ci_disabled,
+                strings.oled_floor,
+                strings.picture_brightness,
+                strings.settings_imported,
+                strings.import_failed,
+                strings.export_failed,
+            ];
+            for template in templates {
+                assert!(template.contains("{}"), "{}", template);
+            }
+        }
+        assert_eq!(
+            Strings::format(ENGLISH.brightness_tooltip, 42),
+            "Brightness: 42"
+        );
+    }
+}
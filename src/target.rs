@@ -0,0 +1,129 @@
+//! What the controller thread needs from a monitor, so that `brightness_controller_loop` can be
+//! driven by fakes in tests instead of monitors with real handles. `Monitor` implements it by
+//! talking DDC/CI.
+
+use windows::{core::Result, Win32::Foundation::E_NOTIMPL};
+
+use monitor_brightness_controller::Monitor;
+
+use crate::diagnostics::MonitorDiagnosis;
+
+/// A monitor whose brightness the controller sets. The methods mirror those of `Monitor`; the
+/// features that not every monitor has default to being unsupported, failing with `E_NOTIMPL`.
+pub trait BrightnessTarget {
+    fn get_name(&self) -> &str;
+    fn is_primary(&self) -> bool;
+    /// The brightness last read or written.
+    fn get_brightness(&self) -> u32;
+    fn get_min_brightness(&self) -> u32;
+    fn get_max_brightness(&self) -> u32;
+    /// Write the brightness, retrying a monitor that is busy.
+    fn set_brightness(&mut self, brightness: u32) -> Result<()>;
+    /// Write the brightness with a single attempt, e.g. to probe a monitor that kept failing.
+    fn try_set_brightness(&mut self, brightness: u32) -> Result<()>;
+    /// Re-read the brightness, e.g. after it was changed with the monitor's own buttons.
+    fn refresh_brightness(&mut self) -> Result<()>;
+    /// Test the monitor for the report of `BrightnessEvent::Diagnose`.
+    fn diagnose(&mut self) -> MonitorDiagnosis;
+
+    /// Whether the brightness is that of SDR content, which Windows controls itself.
+    fn controls_sdr_white_level(&self) -> bool {
+        false
+    }
+
+    fn set_brightness_offset(&mut self, _offset: i32) {}
+
+    fn supports_standby(&self) -> bool {
+        false
+    }
+
+    fn set_standby(&mut self, _standby: bool) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn supports_volume(&self) -> bool {
+        false
+    }
+
+    fn set_volume(&mut self, _volume: u32) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn set_picture_brightness(&mut self, _window: u8, _brightness: u32) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn reset_to_factory(&mut self) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+impl BrightnessTarget for Monitor {
+    fn get_name(&self) -> &str {
+        Monitor::get_name(self)
+    }
+
+    fn is_primary(&self) -> bool {
+        Monitor::is_primary(self)
+    }
+
+    fn get_brightness(&self) -> u32 {
+        Monitor::get_brightness(self)
+    }
+
+    fn get_min_brightness(&self) -> u32 {
+        Monitor::get_min_brightness(self)
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        Monitor::get_max_brightness(self)
+    }
+
+    fn set_brightness(&mut self, brightness: u32) -> Result<()> {
+        Monitor::set_brightness(self, brightness)
+    }
+
+    fn try_set_brightness(&mut self, brightness: u32) -> Result<()> {
+        Monitor::try_set_brightness(self, brightness)
+    }
+
+    fn refresh_brightness(&mut self) -> Result<()> {
+        Monitor::refresh_brightness(self)
+    }
+
+    fn diagnose(&mut self) -> MonitorDiagnosis {
+        MonitorDiagnosis::run(self)
+    }
+
+    fn controls_sdr_white_level(&self) -> bool {
+        Monitor::controls_sdr_white_level(self)
+    }
+
+    fn set_brightness_offset(&mut self, offset: i32) {
+        Monitor::set_brightness_offset(self, offset)
+    }
+
+    fn supports_standby(&self) -> bool {
+        Monitor::supports_standby(self)
+    }
+
+    fn set_standby(&mut self, standby: bool) -> Result<()> {
+        Monitor::set_standby(self, standby)
+    }
+
+    fn supports_volume(&self) -> bool {
+        Monitor::supports_volume(self)
+    }
+
+    fn set_volume(&mut self, volume: u32) -> Result<()> {
+        Monitor::set_volume(self, volume)
+    }
+
+    fn set_picture_brightness(&mut self, window: u8, brightness: u32) -> Result<()> {
+        Monitor::set_picture_brightness(self, window, brightness)
+    }
+
+    fn reset_to_factory(&mut self) -> Result<()> {
+        Monitor::reset_to_factory(self)
+    }
+}
@@ -0,0 +1,109 @@
+//! The kind of panel of a monitor, as far as it tells. OLED panels behave differently at low
+//! brightness, e.g. crushing dark shades or flickering, so they get a different floor.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// VCP code for the display technology type, see `DisplayTechnology::from_vcp_value`.
+pub const VCP_DISPLAY_TECHNOLOGY: u8 = 0xB6;
+
+/// The technology of a monitor's panel.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayTechnology {
+    /// A liquid crystal display, whatever its backlight.
+    Lcd,
+    /// An organic light-emitting diode panel, without a backlight.
+    Oled,
+    /// Anything else that the monitor names, e.g. a CRT or a plasma display.
+    Other,
+    /// The monitor does not tell, or not in a known way.
+    Unknown,
+}
+
+impl DisplayTechnology {
+    /// The technology from the value of `VCP_DISPLAY_TECHNOLOGY`. MCCS defines the low byte: 3 is
+    /// an active matrix LCD, 6 is OLED, and the other values from 1 to 9 are older technologies.
+    pub fn from_vcp_value(value: u32) -> DisplayTechnology {
+        match value & 0xFF {
+            3 => DisplayTechnology::Lcd,
+            6 => DisplayTechnology::Oled,
+            1..=9 => DisplayTechnology::Other,
+            _ => DisplayTechnology::Unknown,
+        }
+    }
+
+    /// The technology from the `type(...)` of a capabilities string, e.g. `lcd`. Many OLED
+    /// monitors claim to be LCDs there, so `from_vcp_value` is preferred when available.
+    pub fn from_type_tag(tag: &str) -> DisplayTechnology {
+        match tag.trim().to_ascii_lowercase().as_str() {
+            "lcd" | "led" => DisplayTechnology::Lcd,
+            "oled" | "amoled" => DisplayTechnology::Oled,
+            "crt" | "plasma" => DisplayTechnology::Other,
+            _ => DisplayTechnology::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for DisplayTechnology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DisplayTechnology::Lcd => "LCD",
+            DisplayTechnology::Oled => "OLED",
+            DisplayTechnology::Other => "other",
+            DisplayTechnology::Unknown => "unknown",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn technology_from_vcp() {
+        assert_eq!(DisplayTechnology::from_vcp_value(3), DisplayTechnology::Lcd);
+        assert_eq!(
+            DisplayTechnology::from_vcp_value(6),
+            DisplayTechnology::Oled
+        );
+        // Only the low byte is the type
+        assert_eq!(
+            DisplayTechnology::from_vcp_value(0x0106),
+            DisplayTechnology::Oled
+        );
+        assert_eq!(
+            DisplayTechnology::from_vcp_value(1),
+            DisplayTechnology::Other
+        );
+        assert_eq!(
+            DisplayTechnology::from_vcp_value(0),
+            DisplayTechnology::Unknown
+        );
+        assert_eq!(
+            DisplayTechnology::from_vcp_value(42),
+            DisplayTechnology::Unknown
+        );
+    }
+
+    #[test]
+    fn technology_from_type_tag() {
+        assert_eq!(
+            DisplayTechnology::from_type_tag("lcd"),
+            DisplayTechnology::Lcd
+        );
+        assert_eq!(
+            DisplayTechnology::from_type_tag(" OLED "),
+            DisplayTechnology::Oled
+        );
+        assert_eq!(
+            DisplayTechnology::from_type_tag("crt"),
+            DisplayTechnology::Other
+        );
+        assert_eq!(
+            DisplayTechnology::from_type_tag("monitor"),
+            DisplayTechnology::Unknown
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! The light or dark theme that Windows uses for apps.
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Name of the setting in the `lParam` of the `WM_SETTINGCHANGE` that is broadcast when the
+    /// theme changes.
+    pub const SETTING_CHANGED: &'static [u8] = b"ImmersiveColorSet";
+
+    /// Read the current apps theme from the registry. Falls back to dark, the flyout's original
+    /// look, on versions of Windows without the setting.
+    pub fn current() -> Theme {
+        Theme::read("AppsUseLightTheme")
+    }
+
+    /// Read the theme of the taskbar, which can differ from the apps theme, e.g. with a dark
+    /// taskbar and light apps. Falls back to dark like `Theme::current`.
+    pub fn taskbar() -> Theme {
+        Theme::read("SystemUsesLightTheme")
+    }
+
+    fn read(value: &str) -> Theme {
+        let subkey = to_wide("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize");
+        let value = to_wide(value);
+
+        let mut data = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let result = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(subkey.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                RRF_RT_REG_DWORD,
+                std::ptr::null_mut(),
+                &mut data as *mut u32 as *mut _,
+                &mut size,
+            )
+        };
+        if result == ERROR_SUCCESS && data != 0 {
+            Theme::Light
+        } else {
+            Theme::Dark
+        }
+    }
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
@@ -0,0 +1,63 @@
+//! Spacing of successive brightness writes to the same monitor. The controller waits out the rest
+//! of the interval before writing, so the latest value is always written, only a little later.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriteThrottle {
+    /// Least time from the end of one write to the start of the next.
+    interval: Duration,
+    /// When the last write ended, if any.
+    last_write: Option<Instant>,
+}
+
+impl WriteThrottle {
+    pub fn new(interval: Duration) -> WriteThrottle {
+        WriteThrottle {
+            interval,
+            last_write: None,
+        }
+    }
+
+    /// How long to wait at `now` before the next write.
+    pub fn delay(&self, now: Instant) -> Duration {
+        self.last_write.map_or(Duration::ZERO, |last_write| {
+            (last_write + self.interval).saturating_duration_since(now)
+        })
+    }
+
+    /// Record that a write ended at `now`, whether it succeeded or not.
+    pub fn record(&mut self, now: Instant) {
+        self.last_write = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_out_the_interval() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(50);
+        let mut throttle = WriteThrottle::new(interval);
+        assert_eq!(throttle.delay(start), Duration::ZERO);
+
+        throttle.record(start);
+        assert_eq!(throttle.delay(start), interval);
+        assert_eq!(
+            throttle.delay(start + Duration::from_millis(20)),
+            Duration::from_millis(30)
+        );
+        assert_eq!(throttle.delay(start + interval), Duration::ZERO);
+        assert_eq!(throttle.delay(start + 2 * interval), Duration::ZERO);
+    }
+
+    #[test]
+    fn zero_interval_never_waits() {
+        let start = Instant::now();
+        let mut throttle = WriteThrottle::new(Duration::ZERO);
+        throttle.record(start);
+        assert_eq!(throttle.delay(start), Duration::ZERO);
+    }
+}
@@ -1,61 +1,475 @@
-use std::{marker::PhantomData, ops::Deref, sync::mpsc::Sender};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_char, CStr},
+    ops::Deref,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    sync::mpsc::{self, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
 
 use windows::{
-    core::{Result, PCSTR},
+    core::{Result, PCSTR, PCWSTR},
     Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
-        System::{LibraryLoader::GetModuleHandleA, Power::POWERBROADCAST_SETTING},
+        Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, SIZE, WPARAM},
+        Graphics::Gdi::{
+            CreateFontW, DeleteObject, GetDC, GetMonitorInfoW, GetSysColorBrush,
+            GetTextExtentPoint32W, MonitorFromPoint, MonitorFromRect, ReleaseDC, SelectObject,
+            CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, FF_DONTCARE, FW_NORMAL,
+            HBRUSH, HFONT, MONITORINFO, MONITOR_DEFAULTTONEAREST, OUT_DEFAULT_PRECIS,
+        },
+        System::{
+            LibraryLoader::GetModuleHandleA,
+            Power::POWERBROADCAST_SETTING,
+            SystemInformation::GetTickCount,
+            Threading::{GetCurrentProcessId, GetCurrentThreadId},
+        },
         UI::{
+            Input::KeyboardAndMouse::{
+                GetLastInputInfo, RegisterHotKey, SetFocus, HOT_KEY_MODIFIERS, LASTINPUTINFO,
+                VIRTUAL_KEY,
+            },
             Shell::{
                 SHAppBarMessage, ABE_BOTTOM, ABE_LEFT, ABE_RIGHT, ABE_TOP, ABM_GETTASKBARPOS,
-                APPBARDATA, NIN_SELECT,
+                APPBARDATA, NIN_BALLOONUSERCLICK, NIN_SELECT,
             },
             WindowsAndMessaging::{
-                CreateWindowExA, DefWindowProcA, GetWindowLongPtrA, GetWindowRect, KillTimer,
-                LoadCursorW, PostQuitMessage, RegisterClassExA, SendMessageA, SetForegroundWindow,
-                SetTimer, SetWindowLongPtrA, SetWindowPos, ShowWindow, CS_DROPSHADOW,
-                GWLP_USERDATA, HWND_TOPMOST, IDC_ARROW, PBT_POWERSETTINGCHANGE, SWP_SHOWWINDOW,
-                SW_HIDE, WM_ACTIVATEAPP, WM_CLOSE, WM_CONTEXTMENU, WM_DESTROY, WM_POWERBROADCAST,
-                WM_TIMER, WNDCLASSEXA, WS_EX_NOREDIRECTIONBITMAP, WS_EX_TOOLWINDOW, WS_POPUP,
+                CreateWindowExA, DefWindowProcA, DispatchMessageA, GetCursorPos,
+                GetForegroundWindow, GetMessageA, GetWindow, GetWindowLongPtrA, GetWindowRect,
+                GetWindowThreadProcessId, IsWindowVisible, KillTimer, LoadCursorW, PeekMessageA,
+                PostMessageA, PostQuitMessage, RegisterClassExA, SendMessageA, SetForegroundWindow,
+                SetLayeredWindowAttributes, SetTimer, SetWindowLongPtrA, SetWindowPos, ShowWindow,
+                TranslateMessage, CBN_SELCHANGE, COLOR_WINDOW, CS_DROPSHADOW, GWLP_USERDATA,
+                GW_CHILD, HWND_MESSAGE, HWND_TOPMOST, IDC_ARROW, LWA_ALPHA, MSG,
+                PBT_POWERSETTINGCHANGE, PM_REMOVE, SWP_SHOWWINDOW, SW_HIDE, WINDOW_EX_STYLE,
+                WM_ACTIVATEAPP, WM_APP, WM_COMMAND, WM_CONTEXTMENU, WM_DESTROY, WM_DISPLAYCHANGE,
+                WM_ENDSESSION, WM_HOTKEY, WM_HSCROLL, WM_INPUT, WM_NULL, WM_POWERBROADCAST,
+                WM_QUERYENDSESSION, WM_SETFOCUS, WM_SETTINGCHANGE, WM_TIMER, WNDCLASSEXA,
+                WS_EX_LAYERED, WS_EX_NOREDIRECTIONBITMAP, WS_EX_TOOLWINDOW, WS_POPUP,
             },
         },
     },
 };
 
-use crate::{BrightnessEvent, NotificationIcon};
+use crate::{
+    automation, diagnostics, keys,
+    priority::ChangeSource,
+    settings::{self, IdleDim},
+    theme::Theme,
+    BrightnessEvent, NotificationIcon,
+};
+
+thread_local! {
+    /// The cursor position that the flyout was last opened at, or `None` if it was opened next to
+    /// the notification area. Kept while the flyout is resized, e.g. when the selector opens.
+    static CURSOR_ANCHOR: Cell<Option<POINT>> = const { Cell::new(None) };
+    /// Width of the compact flyout, see `Window::width`.
+    static FLYOUT_WIDTH: Cell<i32> = const { Cell::new(Window::WIDTH) };
+}
 
 /// Calculate the position where the window would be shown. This should be near where the controls
-/// for sound, Wi-Fi, etc.
+/// for sound, Wi-Fi, etc., moved by `settings::flyout_offset`, or centered on the cursor if the
+/// flyout was opened there. Every `SetWindowPos` of the window goes through here.
 pub fn window_position(width: i32, height: i32) -> (i32, i32) {
+    if let Some(cursor) = CURSOR_ANCHOR.get() {
+        let monitor = unsafe { MonitorFromPoint(cursor, MONITOR_DEFAULTTONEAREST) };
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+            return cursor_position(cursor, info.rcWork, (width, height));
+        }
+    }
     let mut pabd = APPBARDATA {
         cbSize: std::mem::size_of::<APPBARDATA>() as u32,
         ..Default::default()
     };
     let ret = unsafe { SHAppBarMessage(ABM_GETTASKBARPOS, &mut pabd) };
     if ret != 0 {
-        match pabd.uEdge {
-            ABE_BOTTOM => return (pabd.rc.right - width, pabd.rc.top - height),
-            ABE_LEFT => return (pabd.rc.right, pabd.rc.bottom - height),
-            ABE_RIGHT => return (pabd.rc.left - width, pabd.rc.bottom - height),
-            ABE_TOP => return (pabd.rc.right - width, pabd.rc.bottom),
-            _ => (), // Unknown value; fallthrough the panic
+        // Coordinates are virtual screen ones, so the taskbar can be on any monitor
+        let monitor = unsafe { MonitorFromRect(&pabd.rc, MONITOR_DEFAULTTONEAREST) };
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+            if let Some(position) =
+                flyout_position(pabd.uEdge, pabd.rc, info.rcMonitor, width, height)
+            {
+                // `SPI_GETWORKAREA` only knows the primary monitor's work area
+                let offset = settings::flyout_offset();
+                return offset_position(position, offset, info.rcWork, (width, height));
+            }
         }
     }
     panic!("Could not get taskbar position")
 }
 
+/// Position of a `width` by `height` flyout flush against a taskbar on `edge` of `monitor`, at the
+/// end with the notification area. A vertical taskbar has it at the bottom like the volume flyout.
+/// The flyout is kept on `monitor` even if the taskbar rectangle reaches past it, e.g. when it
+/// spans the full height of the screen. Returns `None` for an unknown `edge`.
+fn flyout_position(
+    edge: u32,
+    taskbar: RECT,
+    monitor: RECT,
+    width: i32,
+    height: i32,
+) -> Option<(i32, i32)> {
+    let (x, y) = match edge {
+        ABE_BOTTOM => (monitor.right - width, taskbar.top - height),
+        ABE_LEFT => (taskbar.right, monitor.bottom - height),
+        ABE_RIGHT => (taskbar.left - width, monitor.bottom - height),
+        ABE_TOP => (monitor.right - width, taskbar.bottom),
+        _ => return None,
+    };
+    // `max` first so that the top left corner stays visible if the flyout is larger than the
+    // monitor
+    let x = x.min(monitor.right - width).max(monitor.left);
+    let y = y.min(monitor.bottom - height).max(monitor.top);
+    Some((x, y))
+}
+
+/// `position` of a flyout of `size` moved by `offset`, but not out of `work_area` beyond where it
+/// already was. The taskbar is outside the work area, so the flyout cannot be moved over it.
+fn offset_position(
+    (x, y): (i32, i32),
+    (x_offset, y_offset): (i32, i32),
+    work_area: RECT,
+    (width, height): (i32, i32),
+) -> (i32, i32) {
+    let clamp = |position: i32, offset: i32, min: i32, max: i32| {
+        (position.saturating_add(offset)).clamp(min.min(position), max.max(position))
+    };
+    (
+        clamp(x, x_offset, work_area.left, work_area.right - width),
+        clamp(y, y_offset, work_area.top, work_area.bottom - height),
+    )
+}
+
+/// Position of a flyout of `size` centered on `cursor`, but within `work_area`. The top left corner
+/// stays visible if the flyout is larger than the work area.
+fn cursor_position(cursor: POINT, work_area: RECT, (width, height): (i32, i32)) -> (i32, i32) {
+    let x = (cursor.x - width / 2).min(work_area.right - width);
+    let y = (cursor.y - height / 2).min(work_area.bottom - height);
+    (x.max(work_area.left), y.max(work_area.top))
+}
+
+/// `content_width` within `Window::WIDTH` and `Window::MAX_WIDTH`, see `Window::fit_width`.
+fn fitting_width(content_width: i32) -> i32 {
+    content_width.clamp(Window::WIDTH, Window::MAX_WIDTH)
+}
+
+/// Font of the text of the flyout, the default one of XAML.
+const UI_FONT: &str = "Segoe UI";
+
+/// Width in pixels of `text` in `UI_FONT` at `font_size` pixels, e.g. to fit the flyout to a
+/// monitor name. 0 if it cannot be measured.
+pub fn text_width(text: &str, font_size: f64) -> i32 {
+    let face: Vec<u16> = UI_FONT.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        // A negative height is that of the characters, like the font size of XAML
+        let font = CreateFontW(
+            -(font_size.round() as i32),
+            0,
+            0,
+            0,
+            FW_NORMAL as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET,
+            OUT_DEFAULT_PRECIS,
+            CLIP_DEFAULT_PRECIS,
+            CLEARTYPE_QUALITY,
+            FF_DONTCARE,
+            PCWSTR(face.as_ptr()),
+        );
+        let width = measure_text(text, Some(font));
+        DeleteObject(font);
+        width
+    }
+}
+
+/// Width in pixels of `text` in the system font, which the classic controls show it in since they
+/// are not given another one. 0 if it cannot be measured.
+pub fn system_text_width(text: &str) -> i32 {
+    measure_text(text, None)
+}
+
+/// Width of `text` in `font`, or in the default font of a device context without one.
+fn measure_text(text: &str, font: Option<HFONT>) -> i32 {
+    let text: Vec<u16> = text.encode_utf16().collect();
+    let mut size = SIZE::default();
+    unsafe {
+        let dc = GetDC(HWND(0));
+        let previous = font.map(|font| SelectObject(dc, font));
+        let measured = GetTextExtentPoint32W(dc, &text, &mut size).as_bool();
+        if let Some(previous) = previous {
+            SelectObject(dc, previous);
+        }
+        ReleaseDC(HWND(0), dc);
+        if measured {
+            size.cx
+        } else {
+            0
+        }
+    }
+}
+
+/// Scale a size in pixels of the compact flyout to the configured flyout size.
+pub fn scaled(size: i32) -> i32 {
+    (size as f64 * settings::flyout_size().scale()).round() as i32
+}
+
+/// The inverse of `scaled`, e.g. for the size of something that does not grow with the flyout.
+pub fn unscaled(size: i32) -> i32 {
+    (size as f64 / settings::flyout_size().scale()).round() as i32
+}
+
+/// Events raised by `window_procedure` that need more than the native window to handle, e.g. the
+/// XAML controls.
+pub enum WindowEvent {
+    /// The notification icon was right-clicked.
+    ContextMenu,
+    /// The controller thread changed the brightness of a monitor on its own, for the reason given.
+    BrightnessChanged(usize, u32, ChangeSource),
+    /// The controller thread failed to restore the factory defaults of a monitor.
+    FactoryResetFailed(usize),
+    /// A monitor of the selector entry at the index refused the brightness it was set to. The
+    /// flyout was already moved back by a `BrightnessChanged`.
+    BrightnessRejected(usize),
+    /// The controller thread failed to put some monitors into standby, or to wake them up if
+    /// `false`.
+    StandbyFailed(bool),
+    /// The writes to the monitor at the index, not a selector entry, never succeeded, most likely
+    /// because DDC/CI is turned off in its on-screen menu.
+    DdcCiDisabled(usize),
+    /// The report requested with `BrightnessEvent::Diagnose` was copied to the clipboard, or
+    /// copying it failed if `false`.
+    DiagnosticsCopied(bool),
+    /// Whether the monitors of the selector entry at the index can be controlled changed.
+    UsableChanged(usize, bool),
+    /// The interval set with `Window::start_brightness_polling` has passed.
+    PollBrightness,
+    /// The apps theme was switched between light and dark.
+    ThemeChanged,
+    /// The XAML content is fading in or out and should be drawn at the opacity, from 0 to 1.
+    OpacityChanged(f32),
+    /// The trackbar of the classic flyout was moved.
+    TrackbarMoved,
+    /// Another monitor was chosen in the selector of the classic flyout.
+    SelectionChanged,
+}
+
+/// What the native window is used for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WindowKind {
+    /// The flyout, hosting the XAML controls.
+    Xaml,
+    /// The flyout with classic Win32 controls, for when XAML is unavailable.
+    Classic,
+    /// A message-only window without a flyout, for `--daemon`. It still gets the power broadcasts
+    /// and timers, but no broadcasts like `WM_SETTINGCHANGE` or `WM_ENDSESSION`.
+    MessageOnly,
+}
+
+type EventHandler = Rc<dyn Fn(WindowEvent)>;
+
+/// Data reachable from `window_procedure` through `GWLP_USERDATA`.
+struct WindowData<'a> {
+    sender: &'a Sender<BrightnessEvent>,
+    event_handler: RefCell<Option<EventHandler>>,
+    /// Whether the flyout stays open when it loses the focus.
+    pinned: Cell<bool>,
+    /// Whether brightness changes from the flyout, the hotkey, and the brightness keys are ignored.
+    locked: Cell<bool>,
+    end_session_handler: RefCell<Option<Box<dyn FnOnce()>>>,
+    /// Set by `Window::start_idle_dimming`.
+    idle_dim: Cell<Option<IdleDim>>,
+    /// While `Window::while_loading` runs, whether the flyout was asked to open. There is nothing
+    /// to show until then.
+    loading: Cell<Option<bool>>,
+    /// Whether the window is layered, see `set_opacity`.
+    layered: bool,
+    /// When the current fade started and whether it is fading in.
+    fade: Cell<Option<(Instant, bool)>>,
+}
+
+/// Get the `WindowData` stored by `Window::new`. Returns `None` once the `Window` is dropped.
+unsafe fn window_data<'a>(hwnd: HWND) -> Option<&'a WindowData<'a>> {
+    let ptr = GetWindowLongPtrA(hwnd, GWLP_USERDATA) as *const WindowData;
+    ptr.as_ref()
+}
+
+/// Keep the flyout in `hwnd` open when it loses the focus, or let it hide again if `false`.
+/// Clicking the notification icon still hides a pinned flyout.
+pub fn set_pinned(hwnd: HWND, pinned: bool) {
+    if let Some(data) = unsafe { window_data(hwnd) } {
+        data.pinned.set(pinned);
+    }
+}
+
+fn is_pinned(hwnd: HWND) -> bool {
+    unsafe { window_data(hwnd) }.is_some_and(|data| data.pinned.get())
+}
+
+/// Ignore brightness changes from the flyout in `hwnd`, the hotkey, and the brightness keys, or
+/// accept them again if `false`. The flyouts check `is_locked` before sending their changes.
+pub fn set_locked(hwnd: HWND, locked: bool) {
+    if let Some(data) = unsafe { window_data(hwnd) } {
+        data.locked.set(locked);
+    }
+}
+
+/// Whether the brightness was locked with `set_locked`.
+pub fn is_locked(hwnd: HWND) -> bool {
+    unsafe { window_data(hwnd) }.is_some_and(|data| data.locked.get())
+}
+
+/// Pass `event` to the handler set with `Window::set_event_handler`.
+unsafe fn raise_event(hwnd: HWND, event: WindowEvent) {
+    if let Some(data) = window_data(hwnd) {
+        // Clone the handler out of the `RefCell` since it can reenter `window_procedure`, e.g.
+        // through the modal loop of a context menu
+        let handler = data.event_handler.borrow().clone();
+        if let Some(handler) = handler {
+            handler(event);
+        }
+    }
+}
+
+/// Set the opacity of the flyout from 0 to 1, of the whole window if it is layered. The XAML flyout
+/// is not, since a layered window can keep the XAML island of a `WS_EX_NOREDIRECTIONBITMAP` window
+/// from being drawn; its content is faded through `WindowEvent::OpacityChanged` instead.
+unsafe fn set_opacity(hwnd: HWND, opacity: f32) {
+    if window_data(hwnd).is_some_and(|data| data.layered) {
+        SetLayeredWindowAttributes(hwnd, 0, (opacity * 255.0) as u8, LWA_ALPHA);
+    } else {
+        raise_event(hwnd, WindowEvent::OpacityChanged(opacity));
+    }
+}
+
+/// Time since the last keyboard or mouse input of the session, or `None` if it cannot be read.
+unsafe fn idle_time() -> Option<Duration> {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if GetLastInputInfo(&mut info).as_bool() {
+        Some(ticks_since(GetTickCount(), info.dwTime))
+    } else {
+        None
+    }
+}
+
+/// Time from `earlier` to `now`, both from `GetTickCount` which wraps around every 49.7 days.
+fn ticks_since(now: u32, earlier: u32) -> Duration {
+    Duration::from_millis(now.wrapping_sub(earlier) as u64)
+}
+
+/// Whether a deactivation is only moving the focus to another window of this process, e.g. a popup
+/// of the XAML island. `thread_id` is the owner of the window being activated.
+unsafe fn activation_stays_in_process(thread_id: u32) -> bool {
+    if thread_id == GetCurrentThreadId() {
+        return true;
+    }
+    let foreground = GetForegroundWindow();
+    if foreground.0 == 0 {
+        return false;
+    }
+    let mut process_id = 0;
+    GetWindowThreadProcessId(foreground, &mut process_id);
+    process_id == GetCurrentProcessId()
+}
+
 /// Wrapper class for a `HWND`. Indirectly owns a reference to a `Sender<BrightnessEvent>`
 pub struct Window<'a> {
     inner: HWND,
-    sender: PhantomData<&'a Sender<BrightnessEvent>>,
+    data: Box<WindowData<'a>>,
+}
+
+impl<'a> Drop for Window<'a> {
+    fn drop(&mut self) {
+        // Prevent `window_procedure` from accessing the freed `WindowData`
+        unsafe {
+            SetWindowLongPtrA(self.inner, GWLP_USERDATA, 0);
+        }
+    }
 }
 
 impl<'a> Window<'a> {
+    /// Size of the compact flyout. Use `scaled` for the size of the actual window. The width is the
+    /// least one; long monitor names widen the flyout, see `width`.
     pub const WIDTH: i32 = 360;
-    pub const HEIGHT: i32 = 100;
+    pub const HEIGHT: i32 = 136;
+    /// Widest the compact flyout gets for long monitor names. Longer ones are cut off.
+    pub const MAX_WIDTH: i32 = 560;
+
+    /// Width of the compact flyout, `WIDTH` unless widened with `fit_width`. Use `scaled` for the
+    /// width of the actual window.
+    pub fn width() -> i32 {
+        FLYOUT_WIDTH.get()
+    }
+
+    /// Widen the compact flyout so that it is `content_width` wide, within `WIDTH` and
+    /// `MAX_WIDTH`. Applies to the sizes set afterwards, which re-anchor the window through
+    /// `window_position`.
+    pub fn fit_width(content_width: i32) {
+        FLYOUT_WIDTH.set(fitting_width(content_width));
+    }
 
-    /// Create a native window that acts as a container for XAML.
-    pub fn new(sender: &'a Sender<BrightnessEvent>) -> Result<Self> {
+    /// Posted when the brightness changed without going through the slider, with the monitor
+    /// index as `WPARAM` and its new brightness, at most 16 bits like any VCP value, as the low
+    /// word of `LPARAM`. The high word is the `ChangeSource::id` of the change.
+    pub const BRIGHTNESS_CHANGED: u32 = WM_APP + 2;
+    /// Posted by the controller thread with the monitor index as `WPARAM`.
+    pub const FACTORY_RESET_FAILED: u32 = WM_APP + 3;
+    /// Posted by the controller thread with whether the monitors were put into standby as
+    /// `WPARAM`.
+    pub const STANDBY_FAILED: u32 = WM_APP + 4;
+    /// Posted by the controller thread with a selector entry index as `WPARAM` and whether any of
+    /// its monitors answer as `LPARAM`, when that changes and after the entry was selected.
+    pub const USABLE_CHANGED: u32 = WM_APP + 5;
+
+    /// Posted by another instance of the app to have the flyout shown, see `instance`.
+    pub const SHOW_FLYOUT: u32 = WM_APP + 6;
+    /// Posted by the controller thread with the index of a monitor, not a selector entry, as
+    /// `WPARAM` when none of its writes succeeded, once per session.
+    pub const DDC_CI_DISABLED: u32 = WM_APP + 7;
+    /// Posted by the controller thread with a `Box<String>` of the report of `diagnostics` as
+    /// `LPARAM`, which the window takes ownership of. Reports still queued when the window is
+    /// destroyed are freed on `WM_DESTROY`.
+    pub const DIAGNOSTICS: u32 = WM_APP + 8;
+    /// Sent by `hide_flyout`.
+    const HIDE_FLYOUT: u32 = WM_APP + 9;
+    /// Posted by the controller thread with a selector entry index as `WPARAM` when a monitor of
+    /// it refused a brightness, see `BrightnessChange::rejected`.
+    pub const BRIGHTNESS_REJECTED: u32 = WM_APP + 10;
+
+    /// Class of the native window, used to find it from another instance.
+    pub const CLASS_NAME: PCSTR = PCSTR(b"MonitorBrightnessController\0".as_ptr() as *mut u8);
+
+    /// How long the flyout takes to fade in or out. Shorter than the time the flyout cannot be
+    /// reopened after losing focus, so that it is always hidden before showing it again.
+    pub const FADE_DURATION: Duration = Duration::from_millis(120);
+
+    const TIMER_BRIGHTNESS_POLL: usize = 4;
+    const TIMER_IDLE_CHECK: usize = 6;
+    /// How often the time since the last input is checked while idle dimming is on. Also how long
+    /// the monitors may stay dimmed after the user is back.
+    const IDLE_CHECK_INTERVAL: u32 = 1000;
+    /// ID of the hotkey registered by `register_reset_all_hotkey`.
+    const HOTKEY_RESET_ALL: i32 = 1;
+    /// ID of the hotkey registered by `register_show_flyout_hotkey`.
+    const HOTKEY_SHOW_FLYOUT: i32 = 2;
+
+    /// Create a native window that acts as a container for XAML or for classic Win32 controls,
+    /// depending on `kind`, or that only receives messages. GDI cannot draw to a window that hosts
+    /// XAML.
+    pub fn new(sender: &'a Sender<BrightnessEvent>, kind: WindowKind) -> Result<Self> {
         /// Handles the window events. A function inside a function does not allow the inner
         /// function to access the outer functions variables; this is only placed here to emphasize
         /// that this should only be used inside `Window::new`.
@@ -67,14 +481,78 @@ impl<'a> Window<'a> {
         ) -> LRESULT {
             const TIMER_LOST_FOCUS: usize = 2;
             const TIMER_BRIGHTNESS_RESET: usize = 3;
+            const TIMER_FADE: usize = 5;
 
             static mut LOST_FOCUS: bool = false;
             static mut MONITOR_TURNED_OFF: bool = false;
+            /// Whether `BrightnessEvent::IdleDim` dimmed the monitors.
+            static mut IDLE_DIMMED: bool = false;
+
+            /// Fade the window in, or out and then hide it. Only the opacity is animated, from a
+            /// timer, so nothing else waits for the fade to finish.
+            unsafe fn start_fade(hwnd: HWND, fade_in: bool) {
+                const FRAME_INTERVAL: u32 = 15;
+
+                let data = match window_data(hwnd) {
+                    Some(data) => data,
+                    // Nothing to keep the fade in once the `Window` is dropped
+                    None => {
+                        if !fade_in {
+                            ShowWindow(hwnd, SW_HIDE);
+                        }
+                        return;
+                    }
+                };
+                if fade_in {
+                    set_opacity(hwnd, 0.0);
+                }
+                data.fade.set(Some((Instant::now(), fade_in)));
+                SetTimer(hwnd, TIMER_FADE, FRAME_INTERVAL, None);
+            }
+
+            /// Fade the flyout in next to the notification area, or centered on the cursor if
+            /// `at_cursor`, and give it the focus.
+            unsafe fn show_flyout(hwnd: HWND, at_cursor: bool) {
+                if let Some(data) = window_data(hwnd).filter(|data| data.loading.get().is_some()) {
+                    data.loading.set(Some(true));
+                    return;
+                }
+                let mut cursor = POINT::default();
+                let at_cursor = at_cursor && GetCursorPos(&mut cursor).as_bool();
+                CURSOR_ANCHOR.set(at_cursor.then_some(cursor));
+                // Recalculate the position in case the taskbar position was changed
+                let mut rect = std::mem::MaybeUninit::uninit();
+                let res = GetWindowRect(hwnd, rect.as_mut_ptr());
+                if res.as_bool() {
+                    let rect = rect.assume_init();
+                    let width = scaled(Window::width());
+                    let height = rect.bottom - rect.top;
+                    let (x, y) = window_position(width, height);
+                    start_fade(hwnd, true);
+                    SetWindowPos(hwnd, HWND_TOPMOST, x, y, width, height, SWP_SHOWWINDOW);
+                    SetForegroundWindow(hwnd);
+                }
+            }
 
             match umsg {
+                WM_SETFOCUS => {
+                    // The window itself has nothing to focus. Handing the focus on to the XAML
+                    // island, or the first classic control, lets the keyboard reach the controls,
+                    // e.g. the arrow keys to the slider.
+                    let child = GetWindow(hwnd, GW_CHILD);
+                    if child.0 != 0 {
+                        SetFocus(child);
+                    }
+                    LRESULT(0)
+                }
                 WM_ACTIVATEAPP => {
-                    if wparam.0 == 0 {
-                        ShowWindow(hwnd, SW_HIDE);
+                    // Only hide when the focus really left the app. Otherwise the flyout, and the
+                    // monitor selection if it is open, stay as they are.
+                    if wparam.0 == 0
+                        && !activation_stays_in_process(lparam.0 as u32)
+                        && !is_pinned(hwnd)
+                    {
+                        start_fade(hwnd, false);
                         SetTimer(hwnd, TIMER_LOST_FOCUS, 200, None);
                         LOST_FOCUS = true;
                     }
@@ -90,56 +568,218 @@ impl<'a> Window<'a> {
                         TIMER_BRIGHTNESS_RESET => {
                             KillTimer(hwnd, TIMER_BRIGHTNESS_RESET);
 
-                            // SAFETY: `Window` is just a `HWND` with a lifetime
-                            let window: Window = std::mem::transmute(hwnd);
-                            let _ = window.send(BrightnessEvent::Reset);
+                            if let Some(data) = window_data(hwnd) {
+                                let _ = data.sender.send(BrightnessEvent::Reset);
+                            }
+                        }
+                        Window::TIMER_BRIGHTNESS_POLL => {
+                            raise_event(hwnd, WindowEvent::PollBrightness);
+                        }
+                        Window::TIMER_IDLE_CHECK => {
+                            let data = window_data(hwnd);
+                            let idle_dim = data.and_then(|data| data.idle_dim.get());
+                            if let (Some(data), Some(idle_dim), Some(idle)) =
+                                (data, idle_dim, idle_time())
+                            {
+                                // Displays already turned off or dimmed by Windows are left alone
+                                let dim = idle >= idle_dim.after
+                                    && !MONITOR_TURNED_OFF
+                                    && !automation::is_paused();
+                                if dim != IDLE_DIMMED && (dim || idle < idle_dim.after) {
+                                    IDLE_DIMMED = dim;
+                                    let percent = dim.then_some(idle_dim.percent);
+                                    let _ = data.sender.send(BrightnessEvent::IdleDim(percent));
+                                }
+                            }
+                        }
+                        TIMER_FADE => {
+                            let fade = window_data(hwnd).map(|data| (data, data.fade.get()));
+                            if let Some((data, Some((start, fade_in)))) = fade {
+                                let progress = start.elapsed().as_secs_f32()
+                                    / Window::FADE_DURATION.as_secs_f32();
+                                let progress = progress.min(1.0);
+                                let opacity = if fade_in { progress } else { 1.0 - progress };
+                                set_opacity(hwnd, opacity);
+                                if progress >= 1.0 {
+                                    KillTimer(hwnd, TIMER_FADE);
+                                    data.fade.set(None);
+                                    if !fade_in {
+                                        ShowWindow(hwnd, SW_HIDE);
+                                    }
+                                }
+                            } else {
+                                KillTimer(hwnd, TIMER_FADE);
+                            }
                         }
                         _ => (),
                     }
                     LRESULT(0)
                 }
+                WM_SETTINGCHANGE => {
+                    if lparam.0 != 0 {
+                        let setting = CStr::from_ptr(lparam.0 as *const c_char);
+                        if setting.to_bytes() == Theme::SETTING_CHANGED {
+                            raise_event(hwnd, WindowEvent::ThemeChanged);
+                        }
+                    }
+                    LRESULT(0)
+                }
+                WM_HSCROLL => {
+                    raise_event(hwnd, WindowEvent::TrackbarMoved);
+                    LRESULT(0)
+                }
+                WM_COMMAND => {
+                    let notification = (wparam.0 >> 16) as u32 & 0xffff;
+                    if notification == CBN_SELCHANGE {
+                        raise_event(hwnd, WindowEvent::SelectionChanged);
+                    }
+                    LRESULT(0)
+                }
                 WM_DESTROY => {
+                    // Reports that are still queued would be dropped with the window without being
+                    // freed. Once the window is gone, posting fails and the controller thread frees
+                    // the report itself.
+                    let mut msg = MSG::default();
+                    while PeekMessageA(
+                        &mut msg,
+                        hwnd,
+                        Window::DIAGNOSTICS,
+                        Window::DIAGNOSTICS,
+                        PM_REMOVE,
+                    )
+                    .as_bool()
+                    {
+                        drop(Box::from_raw(msg.lParam.0 as *mut String));
+                    }
                     PostQuitMessage(0);
                     LRESULT(0)
                 }
+                // Never holds up logging off or shutting down
+                WM_QUERYENDSESSION => LRESULT(1),
+                WM_ENDSESSION => {
+                    // The process can be terminated as soon as this returns, without unwinding
+                    // `main`, so its cleanup is done here. Nothing to do if the session goes on.
+                    if wparam.0 != 0 {
+                        let handler = window_data(hwnd)
+                            .and_then(|data| data.end_session_handler.borrow_mut().take());
+                        if let Some(handler) = handler {
+                            handler();
+                        }
+                    }
+                    LRESULT(0)
+                }
                 NotificationIcon::MESSAGE => {
                     let loword = lparam.0 as u32 & 0xffff;
                     match loword {
                         // left clicked
                         NIN_SELECT => {
-                            if !LOST_FOCUS {
-                                // Recalculate the position in case the taskbar position was
-                                // changed
-                                let mut rect = std::mem::MaybeUninit::uninit();
-                                let res = GetWindowRect(hwnd, rect.as_mut_ptr());
-                                if res.as_bool() {
-                                    let rect = rect.assume_init();
-                                    let width = Window::WIDTH;
-                                    let height = rect.bottom - rect.top;
-                                    let (x, y) = window_position(width, height);
-                                    SetWindowPos(
-                                        hwnd,
-                                        HWND_TOPMOST,
-                                        x,
-                                        y,
-                                        width,
-                                        height,
-                                        SWP_SHOWWINDOW,
-                                    );
-                                    SetForegroundWindow(hwnd);
-                                }
+                            if is_pinned(hwnd) && IsWindowVisible(hwnd).as_bool() {
+                                // Focus is not lost while pinned, so this is how it is closed
+                                start_fade(hwnd, false);
+                            } else if !LOST_FOCUS {
+                                show_flyout(hwnd, false);
                             }
+                            LRESULT(0)
                         }
                         // right clicked
                         WM_CONTEXTMENU => {
-                            // TODO: create context menu
-                            SendMessageA(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+                            raise_event(hwnd, WindowEvent::ContextMenu);
+                            LRESULT(0)
                         }
-                        _ => (),
+                        // The notifications are all about the monitors, e.g. the welcome one
+                        NIN_BALLOONUSERCLICK => {
+                            show_flyout(hwnd, false);
+                            LRESULT(0)
+                        }
+                        // Everything else gets the default processing, e.g. `NIN_POPUPOPEN` and
+                        // `NIN_POPUPCLOSE` of the tooltip or the other balloon notifications
+                        _ => DefWindowProcA(hwnd, umsg, wparam, lparam),
+                    }
+                }
+                Window::SHOW_FLYOUT => {
+                    if IsWindowVisible(hwnd).as_bool() {
+                        SetForegroundWindow(hwnd);
+                    } else {
+                        show_flyout(hwnd, false);
                     }
                     LRESULT(0)
                 }
+                Window::HIDE_FLYOUT => {
+                    // Even while pinned, like clicking the notification icon
+                    if IsWindowVisible(hwnd).as_bool() {
+                        start_fade(hwnd, false);
+                    }
+                    LRESULT(0)
+                }
+                Window::BRIGHTNESS_CHANGED => {
+                    let brightness = lparam.0 as u32 & 0xFFFF;
+                    let source = ChangeSource::from_id((lparam.0 as u32 >> 16) as usize);
+                    let event = WindowEvent::BrightnessChanged(wparam.0, brightness, source);
+                    raise_event(hwnd, event);
+                    LRESULT(0)
+                }
+                Window::FACTORY_RESET_FAILED => {
+                    raise_event(hwnd, WindowEvent::FactoryResetFailed(wparam.0));
+                    LRESULT(0)
+                }
+                Window::BRIGHTNESS_REJECTED => {
+                    raise_event(hwnd, WindowEvent::BrightnessRejected(wparam.0));
+                    LRESULT(0)
+                }
+                Window::STANDBY_FAILED => {
+                    raise_event(hwnd, WindowEvent::StandbyFailed(wparam.0 != 0));
+                    LRESULT(0)
+                }
+                Window::USABLE_CHANGED => {
+                    raise_event(hwnd, WindowEvent::UsableChanged(wparam.0, lparam.0 != 0));
+                    LRESULT(0)
+                }
+                Window::DDC_CI_DISABLED => {
+                    raise_event(hwnd, WindowEvent::DdcCiDisabled(wparam.0));
+                    LRESULT(0)
+                }
+                Window::DIAGNOSTICS => {
+                    let report = Box::from_raw(lparam.0 as *mut String);
+                    let copied = diagnostics::copy_to_clipboard(hwnd, &report).is_ok();
+                    raise_event(hwnd, WindowEvent::DiagnosticsCopied(copied));
+                    LRESULT(0)
+                }
+                WM_INPUT => {
+                    if let Some(up) = keys::pressed(lparam) {
+                        if let Some(data) = window_data(hwnd).filter(|data| !data.locked.get()) {
+                            let _ = data.sender.send(BrightnessEvent::BrightnessKey(up));
+                        }
+                    }
+                    // Frees the input
+                    DefWindowProcA(hwnd, umsg, wparam, lparam)
+                }
+                WM_HOTKEY => {
+                    if wparam.0 == Window::HOTKEY_RESET_ALL as usize {
+                        if let Some(data) = window_data(hwnd).filter(|data| !data.locked.get()) {
+                            let _ = data.sender.send(BrightnessEvent::ResetAll);
+                        }
+                    } else if wparam.0 == Window::HOTKEY_SHOW_FLYOUT as usize {
+                        if IsWindowVisible(hwnd).as_bool() {
+                            start_fade(hwnd, false);
+                        } else {
+                            show_flyout(hwnd, settings::flyout_at_cursor());
+                        }
+                    }
+                    LRESULT(0)
+                }
+                WM_DISPLAYCHANGE => {
+                    // E.g. a monitor was plugged back in. Like when the displays turn back on, the
+                    // brightness is re-applied once they had time to settle, which also probes the
+                    // monitors that were left alone for failing.
+                    SetTimer(hwnd, TIMER_BRIGHTNESS_RESET, 5000, None);
+                    LRESULT(0)
+                }
                 WM_POWERBROADCAST => {
+                    // `GUID_CONSOLE_DISPLAY_STATE` is the state of the console as a whole, not of
+                    // each display. A monitor that goes to sleep and wakes up on its own, e.g.
+                    // through its power button, raises nothing here; `BrightnessEvent::Reset`
+                    // re-applies every monitor and keeps retrying those that are slow to wake.
+                    // Writes are held back from when the displays turn off until that `Reset`.
                     if wparam.0 as u32 == PBT_POWERSETTINGCHANGE {
                         const OFF: u8 = 0;
                         const ON: u8 = 1;
@@ -148,7 +788,13 @@ impl<'a> Window<'a> {
                         let setting = &*(lparam.0 as *const POWERBROADCAST_SETTING);
                         let monitor_state = setting.Data[0];
                         match monitor_state {
-                            OFF | DIMMED => {
+                            OFF => {
+                                MONITOR_TURNED_OFF = true;
+                                if let Some(data) = window_data(hwnd) {
+                                    let _ = data.sender.send(BrightnessEvent::DisplaysOff);
+                                }
+                            }
+                            DIMMED => {
                                 MONITOR_TURNED_OFF = true;
                             }
                             ON => {
@@ -166,20 +812,35 @@ impl<'a> Window<'a> {
             }
         }
 
-        const MAIN_WINDOW_CLASS: PCSTR =
-            PCSTR(b"MonitorBrightnessController\0".as_ptr() as *mut u8);
-
         let instance = unsafe { GetModuleHandleA(PCSTR::default())? };
         let cursor = unsafe { LoadCursorW(None, IDC_ARROW)? };
 
+        // Layered for fading in and out, except with XAML, see `set_opacity`
+        let (ex_style, background) = match kind {
+            WindowKind::Xaml => {
+                let ex_style = WS_EX_NOREDIRECTIONBITMAP | WS_EX_TOOLWINDOW;
+                (ex_style, HBRUSH::default())
+            }
+            WindowKind::Classic => {
+                let brush = unsafe { GetSysColorBrush(COLOR_WINDOW.0 as i32) };
+                (WS_EX_TOOLWINDOW | WS_EX_LAYERED, brush)
+            }
+            WindowKind::MessageOnly => (WINDOW_EX_STYLE::default(), HBRUSH::default()),
+        };
+        let parent = match kind {
+            WindowKind::MessageOnly => HWND_MESSAGE,
+            _ => HWND::default(),
+        };
+
         let wcex = WNDCLASSEXA {
             cbSize: std::mem::size_of::<WNDCLASSEXA>() as u32,
             style: CS_DROPSHADOW,
             lpfnWndProc: Some(window_procedure),
             hInstance: instance,
             hCursor: cursor,
+            hbrBackground: background,
             lpszMenuName: PCSTR::default(),
-            lpszClassName: MAIN_WINDOW_CLASS,
+            lpszClassName: Window::CLASS_NAME,
             ..Default::default()
         };
         unsafe {
@@ -188,39 +849,51 @@ impl<'a> Window<'a> {
             }
         }
 
-        let (x, y) = window_position(Self::WIDTH, Self::HEIGHT);
+        let (width, height) = (scaled(Self::width()), scaled(Self::HEIGHT));
+        let (x, y) = window_position(width, height);
 
         let hwnd = unsafe {
             CreateWindowExA(
-                WS_EX_NOREDIRECTIONBITMAP | WS_EX_TOOLWINDOW,
-                MAIN_WINDOW_CLASS,
+                ex_style,
+                Window::CLASS_NAME,
                 None,
                 WS_POPUP,
                 x,
                 y,
-                Self::WIDTH,
-                Self::HEIGHT,
-                None,
+                width,
+                height,
+                parent,
                 None,
                 instance,
                 std::ptr::null(),
             )
         };
         if hwnd.0 != 0 {
-            // SAFETY: This stores a `&Sender<BrightnessEvent>` to the `HWND` which can later be
-            // referenced through `GetWindowLongPtrA`. The `PhantomData` member of `Window` ensures
-            // that it does not exceed the lifetime of the `&Sender<BrightnessEvent>` argument.
+            // Layered windows stay invisible until their opacity is set
+            let layered = kind == WindowKind::Classic;
+            if layered {
+                unsafe {
+                    SetLayeredWindowAttributes(hwnd, 0, 255, LWA_ALPHA);
+                }
+            }
+            let data = Box::new(WindowData {
+                sender,
+                event_handler: RefCell::new(None),
+                pinned: Cell::new(false),
+                locked: Cell::new(false),
+                end_session_handler: RefCell::new(None),
+                idle_dim: Cell::new(None),
+                loading: Cell::new(None),
+                layered,
+                fade: Cell::new(None),
+            });
+            // SAFETY: This stores a `&WindowData` to the `HWND` which can later be referenced
+            // through `GetWindowLongPtrA`. The pointer is cleared when `Window` is dropped and
+            // `WindowData` cannot outlive the `&Sender<BrightnessEvent>` argument.
             unsafe {
-                SetWindowLongPtrA(
-                    hwnd,
-                    GWLP_USERDATA,
-                    sender as *const Sender<BrightnessEvent> as isize,
-                );
-            }
-            Ok(Window {
-                inner: hwnd,
-                sender: PhantomData,
-            })
+                SetWindowLongPtrA(hwnd, GWLP_USERDATA, &*data as *const WindowData as isize);
+            }
+            Ok(Window { inner: hwnd, data })
         } else {
             Err(windows::core::Error::from_win32())
         }
@@ -230,6 +903,157 @@ impl<'a> Window<'a> {
     pub fn as_handle(&self) -> HWND {
         self.inner
     }
+
+    /// Fade the flyout out and hide it, the same as when it loses the focus.
+    pub fn hide_flyout(&self) {
+        unsafe {
+            SendMessageA(self.inner, Window::HIDE_FLYOUT, WPARAM(0), LPARAM(0));
+        }
+    }
+
+    /// Raise `WindowEvent::PollBrightness` every `interval` until the window is destroyed.
+    pub fn start_brightness_polling(&self, interval: Duration) -> Result<()> {
+        let interval = interval.as_millis().try_into().unwrap_or(u32::MAX);
+        unsafe {
+            if SetTimer(self.inner, Window::TIMER_BRIGHTNESS_POLL, interval, None) != 0 {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        }
+    }
+
+    /// Dim the monitors with `BrightnessEvent::IdleDim` once there was no input for
+    /// `idle_dim.after`, and restore them on the next input.
+    pub fn start_idle_dimming(&self, idle_dim: IdleDim) -> Result<()> {
+        self.data.idle_dim.set(Some(idle_dim));
+        unsafe {
+            if SetTimer(
+                self.inner,
+                Window::TIMER_IDLE_CHECK,
+                Window::IDLE_CHECK_INTERVAL,
+                None,
+            ) != 0
+            {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        }
+    }
+
+    /// Send `BrightnessEvent::ResetAll` whenever `modifiers` + `key` is pressed, in any app. The
+    /// hotkey is unregistered when the window is destroyed.
+    pub fn register_reset_all_hotkey(
+        &self,
+        modifiers: HOT_KEY_MODIFIERS,
+        key: VIRTUAL_KEY,
+    ) -> Result<()> {
+        unsafe {
+            if RegisterHotKey(
+                self.inner,
+                Window::HOTKEY_RESET_ALL,
+                modifiers,
+                key.0 as u32,
+            )
+            .as_bool()
+            {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        }
+    }
+
+    /// Show the flyout whenever `modifiers` + `key` is pressed, in any app, or hide it if it is
+    /// shown. It opens at the cursor if `settings::flyout_at_cursor`. The hotkey is unregistered
+    /// when the window is destroyed.
+    pub fn register_show_flyout_hotkey(
+        &self,
+        modifiers: HOT_KEY_MODIFIERS,
+        key: VIRTUAL_KEY,
+    ) -> Result<()> {
+        unsafe {
+            if RegisterHotKey(
+                self.inner,
+                Window::HOTKEY_SHOW_FLYOUT,
+                modifiers,
+                key.0 as u32,
+            )
+            .as_bool()
+            {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        }
+    }
+
+    /// Send `BrightnessEvent::BrightnessKey` whenever a brightness key of a keyboard is pressed, in
+    /// any app.
+    pub fn register_brightness_keys(&self) -> Result<()> {
+        keys::register(self.inner)
+    }
+
+    /// Run `f` on another thread while handling the messages of this thread, so that the
+    /// notification icon responds before slow work like reading the monitors is done. Opening the
+    /// flyout meanwhile is remembered until `finish_loading`. Panics of `f` are passed on.
+    pub fn while_loading<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> T {
+        self.data.loading.set(Some(false));
+        let (sender, receiver) = mpsc::channel();
+        let hwnd = self.inner;
+        thread::spawn(move || {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+            // Wakes up `GetMessageA` below
+            unsafe {
+                PostMessageA(hwnd, WM_NULL, WPARAM(0), LPARAM(0));
+            }
+        });
+        let mut msg = MSG::default();
+        let result = loop {
+            match receiver.try_recv() {
+                Ok(result) => break result,
+                Err(TryRecvError::Disconnected) => unreachable!("the result is always sent"),
+                Err(TryRecvError::Empty) => {}
+            }
+            unsafe {
+                if !GetMessageA(&mut msg, HWND::default(), 0, 0).as_bool() {
+                    // Left for the message loop of `main`
+                    PostQuitMessage(msg.wParam.0 as i32);
+                    break receiver.recv().unwrap();
+                }
+                TranslateMessage(&msg);
+                DispatchMessageA(&msg);
+            }
+        };
+        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// End the loading state of `while_loading` once the flyout has something to show, and open it
+    /// if that was asked for in the meantime.
+    pub fn finish_loading(&self) {
+        if self.data.loading.take() == Some(true) {
+            unsafe {
+                PostMessageA(self.inner, Window::SHOW_FLYOUT, WPARAM(0), LPARAM(0));
+            }
+        }
+    }
+
+    /// Set what is run when the session ends, e.g. on logoff or shutdown, in place of the cleanup
+    /// that `main` and the destructors would do. It is run at most once.
+    pub fn set_end_session_handler(&self, handler: impl FnOnce() + 'static) {
+        *self.data.end_session_handler.borrow_mut() = Some(Box::new(handler));
+    }
+
+    /// Set the handler for the `WindowEvent`s, replacing the previous one.
+    pub fn set_event_handler(&self, handler: impl Fn(WindowEvent) + 'static) {
+        *self.data.event_handler.borrow_mut() = Some(Rc::new(handler));
+    }
+
+    /// Drop the handler set with `set_event_handler` along with everything it captured.
+    pub fn remove_event_handler(&self) {
+        self.data.event_handler.borrow_mut().take();
+    }
 }
 
 impl<'a> Deref for Window<'a> {
@@ -237,12 +1061,134 @@ impl<'a> Deref for Window<'a> {
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        // SAFETY: Gets the `*const Sender<BrightnessEvent>` that was stored in `Window::new`.
-        // The `Sender` should be valid throughout the lifetime of this `Window` because we
-        // required `PhantomData<&'a Sender<BrightnessEvent>>` in its type signature.
-        unsafe {
-            let ptr = GetWindowLongPtrA(self.inner, GWLP_USERDATA);
-            &*(ptr as *const Sender<BrightnessEvent>)
-        }
+        self.data.sender
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_within_limits() {
+        assert_eq!(fitting_width(200), Window::WIDTH);
+        assert_eq!(fitting_width(Window::WIDTH + 40), Window::WIDTH + 40);
+        assert_eq!(fitting_width(2000), Window::MAX_WIDTH);
+    }
+
+    #[test]
+    fn ticks_wrap_around() {
+        assert_eq!(ticks_since(5000, 2000), Duration::from_secs(3));
+        assert_eq!(ticks_since(1000, u32::MAX - 999), Duration::from_secs(2));
+        assert_eq!(ticks_since(7, 7), Duration::ZERO);
+    }
+
+    const MONITOR: RECT = RECT {
+        left: 1920,
+        top: 0,
+        right: 3840,
+        bottom: 1080,
+    };
+
+    #[test]
+    fn flush_against_taskbar() {
+        let bottom = RECT {
+            top: 1032,
+            ..MONITOR
+        };
+        let top = RECT {
+            bottom: 48,
+            ..MONITOR
+        };
+        let left = RECT {
+            right: 1982,
+            ..MONITOR
+        };
+        let right = RECT {
+            left: 3778,
+            ..MONITOR
+        };
+        assert_eq!(
+            flyout_position(ABE_BOTTOM, bottom, MONITOR, 360, 136),
+            Some((3480, 896))
+        );
+        assert_eq!(
+            flyout_position(ABE_TOP, top, MONITOR, 360, 136),
+            Some((3480, 48))
+        );
+        assert_eq!(
+            flyout_position(ABE_LEFT, left, MONITOR, 360, 136),
+            Some((1982, 944))
+        );
+        assert_eq!(
+            flyout_position(ABE_RIGHT, right, MONITOR, 360, 136),
+            Some((3418, 944))
+        );
+    }
+
+    #[test]
+    fn stays_on_monitor() {
+        // Reaches past the monitor, e.g. while auto-hidden or on a taller neighbouring display
+        let left = RECT {
+            left: 1918,
+            top: -200,
+            right: 1922,
+            bottom: 1400,
+        };
+        assert_eq!(
+            flyout_position(ABE_LEFT, left, MONITOR, 360, 136),
+            Some((1922, 944))
+        );
+        assert_eq!(
+            flyout_position(ABE_LEFT, left, MONITOR, 360, 2000),
+            Some((1922, 0))
+        );
+        assert_eq!(flyout_position(7, left, MONITOR, 360, 136), None);
+    }
+
+    #[test]
+    fn offset_within_work_area() {
+        let work_area = RECT {
+            bottom: 1032,
+            ..MONITOR
+        };
+        let size = (360, 136);
+        let flush = (3480, 896);
+        assert_eq!(offset_position(flush, (0, 0), work_area, size), flush);
+        assert_eq!(
+            offset_position(flush, (-12, -8), work_area, size),
+            (3468, 888)
+        );
+        // Neither over the taskbar nor off the monitor
+        assert_eq!(offset_position(flush, (50, 50), work_area, size), flush);
+        assert_eq!(
+            offset_position(flush, (-5000, -5000), work_area, size),
+            (1920, 0)
+        );
+        // A flyout that is already partly outside is not moved further out
+        let tall = (1920, 0);
+        assert_eq!(
+            offset_position(tall, (-10, -10), work_area, (360, 2000)),
+            tall
+        );
+    }
+
+    #[test]
+    fn centered_on_cursor() {
+        let work_area = RECT {
+            bottom: 1032,
+            ..MONITOR
+        };
+        let size = (360, 136);
+        let at = |x, y| cursor_position(POINT { x, y }, work_area, size);
+        assert_eq!(at(2880, 500), (2700, 432));
+        // Kept within the work area near its edges
+        assert_eq!(at(1930, 10), (1920, 0));
+        assert_eq!(at(3835, 1075), (3480, 896));
+        // The top left corner of a flyout taller than the work area
+        assert_eq!(
+            cursor_position(POINT { x: 2880, y: 500 }, work_area, (360, 2000)),
+            (2700, 0)
+        );
     }
 }
@@ -9,16 +9,34 @@ use windows::{
     },
 };
 
-pub fn create_image() -> Result<Image> {
-    const IMAGE_WIDTH: i32 = 24;
-    const IMAGE_HEIGHT: i32 = 24;
+use crate::theme::Theme;
 
+/// The brightness icon, drawn in white for the dark theme and in black for the light one.
+pub fn create_image(theme: Theme) -> Result<Image> {
+    let image = Image::new()?;
+    set_image_theme(&image, theme)?;
+    image.SetStretch(Stretch::None)?;
+    Ok(image)
+}
+
+/// Size of `ICON` in pixels.
+pub const IMAGE_WIDTH: i32 = 24;
+pub const IMAGE_HEIGHT: i32 = 24;
+
+/// Redraw an image made by `create_image` for `theme`.
+pub fn set_image_theme(image: &Image, theme: Theme) -> Result<()> {
     let buffer = Buffer::Create(ICON.len() as u32)?;
     let writeable: IBufferByteAccess = buffer.cast()?;
     unsafe {
         let ptr = writeable.Buffer()?;
         let slice = &mut *(ptr as *mut [u8; ICON.len()]);
         slice.copy_from_slice(&ICON);
+        if theme == Theme::Light {
+            // Premultiplied white to black: keep only the alpha of each BGRA pixel
+            for pixel in slice.chunks_exact_mut(4) {
+                pixel[..3].fill(0);
+            }
+        }
         buffer.SetLength(ICON.len() as u32)?;
     }
 
@@ -32,14 +50,11 @@ pub fn create_image() -> Result<Image> {
 
     let image_source = SoftwareBitmapSource::new()?;
     let _ignored_async = image_source.SetBitmapAsync(bitmap)?;
-
-    let image = Image::new()?;
-    image.SetSource(image_source)?;
-    image.SetStretch(Stretch::None)?;
-    Ok(image)
+    image.SetSource(image_source)
 }
 
-const ICON: [u8; 2304] = [
+/// The brightness icon as white, premultiplied BGRA pixels, row by row from the top.
+pub const ICON: [u8; 2304] = [
     0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
     0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
     0x0, 0x0, 0x40, 0x40, 0x40, 0x40, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x40, 0x40,
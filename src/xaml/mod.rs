@@ -1,47 +1,132 @@
-mod image;
+pub mod image;
 
-use std::{io::Write, marker::PhantomData, sync::mpsc::Sender};
+use std::{
+    cell::RefCell,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+};
 
 use windows::{
     core::{Interface, Result, HSTRING},
+    Foundation::{PropertyValue, TypedEventHandler},
+    System::VirtualKey,
     Win32::{
-        Foundation::{BOOL, HWND},
-        System::WinRT::Xaml::{IDesktopWindowXamlSourceNative, IDesktopWindowXamlSourceNative2},
-        UI::WindowsAndMessaging::{
-            SetWindowPos, MSG, SWP_DEFERERASE, SWP_NOREDRAW, SWP_NOSENDCHANGING, SWP_NOZORDER,
-            SWP_SHOWWINDOW,
+        Foundation::{BOOL, HWND, RECT},
+        System::WinRT::Xaml::IDesktopWindowXamlSourceNative2,
+        UI::{
+            Input::KeyboardAndMouse::{GetKeyState, VK_CONTROL, VK_ESCAPE, VK_SHIFT, VK_TAB},
+            WindowsAndMessaging::{
+                IsWindowVisible, SetWindowPos, MSG, SWP_DEFERERASE, SWP_NOREDRAW,
+                SWP_NOSENDCHANGING, SWP_NOZORDER, SWP_SHOWWINDOW, WHEEL_DELTA, WM_KEYDOWN,
+            },
         },
     },
     UI::{
+        Color,
         Text::FontWeights,
         Xaml::{
             Controls::{
-                Button, ContentControl, Control, ItemsControl, ListBox, Orientation, Panel,
-                Primitives::{ButtonBase, RangeBase, RangeBaseValueChangedEventHandler, Selector},
-                SelectionMode, Slider, StackPanel, TextBlock,
+                Border, Button, ContentControl, Control, Image, ItemsControl, ListBox, Orientation,
+                Panel,
+                Primitives::{
+                    ButtonBase, RangeBase, RangeBaseValueChangedEventHandler, RepeatButton,
+                    Selector, SliderSnapsTo, TickPlacement, ToggleButton,
+                },
+                SelectionChangedEventHandler, SelectionMode, Slider, StackPanel, TextBlock,
+                TextBox, ToolTipService,
+            },
+            ElementTheme, FlowDirection, FrameworkElement, HorizontalAlignment,
+            Hosting::{
+                DesktopWindowXamlSource, DesktopWindowXamlSourceTakeFocusRequestedEventArgs,
+                WindowsXamlManager,
             },
-            FrameworkElement, HorizontalAlignment,
-            Hosting::{DesktopWindowXamlSource, WindowsXamlManager},
-            Media::{AcrylicBackgroundSource, AcrylicBrush},
-            RoutedEventHandler, TextAlignment, Thickness, UIElement, VerticalAlignment, Visibility,
+            Input::{KeyEventHandler, PointerEventHandler},
+            Media::{AcrylicBackgroundSource, AcrylicBrush, FontFamily, ScaleTransform},
+            RoutedEventHandler, TextAlignment, TextTrimming, TextWrapping, Thickness, UIElement,
+            VerticalAlignment, Visibility,
         },
     },
 };
 
-use crate::{window::window_position, BrightnessEvent, Monitor, NotificationIcon, Window};
+use monitor_brightness_controller::brightness_to_nits;
+
+use crate::{
+    config::{Config, ConfigError},
+    dialog,
+    group::{self, from_percent, to_percent, PERCENT_MAX},
+    menu::{FlyoutMenu, MenuHandler},
+    priority::ChangeSource,
+    settings::{self, FlyoutLayout},
+    strings::{strings, Strings},
+    theme::Theme,
+    window::{is_locked, scaled, set_pinned, text_width, window_position, WindowEvent},
+    BrightnessEvent, Monitor, MonitorGroup, NotificationIcon, SharedMonitors, Window, APP_NAME,
+};
 
 pub struct XamlControls<'a> {
     manager: WindowsXamlManager,
     source: IDesktopWindowXamlSourceNative2,
-    parent: PhantomData<&'a HWND>,
+    parent: &'a Window<'a>,
+    /// The monitor selector, which `FlyoutLayout::AllMonitors` does not have.
+    selector: Option<MonitorSelector>,
+}
+
+/// The selector of `FlyoutLayout::Selector`: the button showing the chosen entry and the list
+/// below it.
+struct MonitorSelector {
+    button: Button,
+    list_box: ListBox,
+    /// Height of the controls below the selector, to collapse the window to.
+    controls_height: i32,
+}
+
+impl MonitorSelector {
+    /// Select the next entry, or the previous one if `backwards`, wrapping around at either end.
+    /// The `SelectionChanged` handlers move the slider without writing the brightness.
+    fn cycle(&self, parent: HWND, backwards: bool) -> Result<()> {
+        let selector = Selector::from(&self.list_box);
+        let count = ItemsControl::from(&self.list_box).Items()?.Size()? as i32;
+        selector.SetSelectedIndex(cycled_index(selector.SelectedIndex()?, count, backwards))?;
+        // Also shows the name of the new entry on the button
+        hide_selection(&self.button, parent, &self.list_box, self.controls_height)
+    }
+
+    /// Close the list of entries if it is open, leaving the selection as it is.
+    fn collapse(&self, parent: HWND) -> Result<()> {
+        if UIElement::from(&self.list_box).Visibility()? == Visibility::Visible {
+            hide_selection(&self.button, parent, &self.list_box, self.controls_height)?;
+        }
+        Ok(())
+    }
+}
+
+/// Index of the entry after `current` out of `count`, or before it if `backwards`, wrapping around.
+/// Starts at either end if nothing is selected.
+fn cycled_index(current: i32, count: i32, backwards: bool) -> i32 {
+    match (current, backwards) {
+        _ if count <= 0 => current,
+        (i, false) if i < 0 => 0,
+        (i, true) if i < 0 => count - 1,
+        (i, backwards) => (i + if backwards { -1 } else { 1 }).rem_euclid(count),
+    }
 }
 
 impl<'a> Drop for XamlControls<'a> {
     fn drop(&mut self) {
+        // The handler holds references to XAML controls which must be released before closing
+        self.parent.remove_event_handler();
         if let Ok(source) = self.source.cast::<DesktopWindowXamlSource>() {
-            source.Close().unwrap();
+            if let Err(e) = source.Close() {
+                crate::report_drop_error("close the XAML source", &e);
+            }
+        }
+        if let Err(e) = self.manager.Close() {
+            crate::report_drop_error("close the XAML manager", &e);
         }
-        self.manager.Close().unwrap();
     }
 }
 
@@ -54,34 +139,164 @@ impl<'a> XamlControls<'a> {
         Bottom: 6.0,
     };
     const SELECTOR_HEIGHT: i32 = 45;
+    /// Room left around monitor names in the selector for the padding of the selector button and
+    /// the list items.
+    const NAME_MARGIN: i32 = 2 * 12;
     const CONTROLS_HEIGHT: i32 = Window::HEIGHT - XamlControls::SELECTOR_HEIGHT;
+    const PRESETS_HEIGHT: i32 = 36;
+    const SLIDER_ROW_HEIGHT: i32 = XamlControls::CONTROLS_HEIGHT - XamlControls::PRESETS_HEIGHT;
     const SLIDER_WIDTH: i32 = 232;
     const SLIDER_HEIGHT: i32 = 28;
     const BRIGHTNESS_TEXT_FONT_SIZE: f64 = 23.5;
+    /// Font size of the luminance below the brightness, for displays that report it.
+    const NITS_FONT_SIZE: f64 = 11.0;
+    /// Brightness of the buttons below the slider, in percent.
+    const BRIGHTNESS_PRESETS: [u32; 3] = [25, 50, 100];
+    /// Background tints of the flyout for each theme, similar to the flyouts of Windows.
+    const DARK_TINT: Color = Color {
+        A: 50,
+        R: 0,
+        G: 0,
+        B: 0,
+    };
+    const LIGHT_TINT: Color = Color {
+        A: 120,
+        R: 243,
+        G: 243,
+        B: 243,
+    };
+    /// Brightness change per click of the buttons next to the slider, in percent.
+    const BRIGHTNESS_STEP: f64 = 5.0;
+    /// Width of each of the buttons next to the slider. The slider is narrower by both.
+    const STEP_BUTTON_WIDTH: i32 = 28;
+    /// Font of the icons drawn as text.
+    const SYMBOL_FONT: &'static str = "Segoe MDL2 Assets";
+    /// Pushpin of `SYMBOL_FONT`, for the button that keeps the flyout open.
+    const PIN_GLYPH: &'static str = "\u{E718}";
+    /// Speaker of `SYMBOL_FONT`, next to the volume slider.
+    const VOLUME_GLYPH: &'static str = "\u{E767}";
+    /// Height of the row with the volume slider, which is only there if any monitor has speakers
+    /// that can be controlled.
+    const VOLUME_ROW_HEIGHT: i32 = 36;
+    /// Height of each row with the slider of a picture besides the main one, as many as the most
+    /// pictures that any monitor shows.
+    const PICTURE_ROW_HEIGHT: i32 = 36;
+    /// Brightness change per mouse wheel notch over the slider row, in percent.
+    const WHEEL_STEP: f64 = 2.0;
+    /// Distance between the tick marks that the slider snaps to with `settings::snap_slider`, in
+    /// percent.
+    const SNAP_STEP: f64 = 10.0;
+    /// Height of the row of each monitor with `FlyoutLayout::AllMonitors`: its name above the
+    /// slider.
+    const MONITOR_ROW_HEIGHT: i32 = 80;
+
+    /// Height of everything below the selector.
+    fn controls_height(has_volume: bool, picture_rows: usize) -> i32 {
+        let pictures_height = XamlControls::PICTURE_ROW_HEIGHT * picture_rows as i32;
+        if has_volume {
+            XamlControls::CONTROLS_HEIGHT + XamlControls::VOLUME_ROW_HEIGHT + pictures_height
+        } else {
+            XamlControls::CONTROLS_HEIGHT + pictures_height
+        }
+    }
 
+    /// Build the flyout in `parent`, which must have been created to host XAML. `manager` is the
+    /// result of `WindowsXamlManager::InitializeForCurrentThread`, which fails where XAML islands
+    /// are not available. If a step fails, what was set up so far is torn down again, including
+    /// `manager`, and `parent` is left without an event handler.
     pub fn new(
-        parent: &'a Window,
-        monitors: &[Monitor],
+        parent: &'a Window<'a>,
+        manager: WindowsXamlManager,
+        monitors: SharedMonitors,
+        groups: Arc<Vec<MonitorGroup>>,
         tx: Sender<BrightnessEvent>,
-        notification_icon: NotificationIcon,
+        notification_icon: Arc<Mutex<NotificationIcon>>,
     ) -> Result<Self> {
-        let manager = WindowsXamlManager::InitializeForCurrentThread()?;
-        let xaml_source = DesktopWindowXamlSource::new()?;
-        let interop: IDesktopWindowXamlSourceNative = xaml_source.cast()?;
+        let source = DesktopWindowXamlSource::new().and_then(|source| source.cast());
+        let source: IDesktopWindowXamlSourceNative2 = match source {
+            Ok(source) => source,
+            Err(e) => {
+                if let Err(e) = manager.Close() {
+                    crate::debug_log(&format!("Failed to close the XAML manager: {}", e));
+                }
+                return Err(e);
+            }
+        };
+        // From here on, returning early drops `flyout`, which closes the source and the manager
+        // and removes the event handler like a finished flyout would
+        let mut flyout = XamlControls {
+            manager,
+            source,
+            parent,
+            selector: None,
+        };
+        let xaml_source: DesktopWindowXamlSource = flyout.source.cast()?;
         let window = unsafe {
-            interop.AttachToWindow(parent.as_handle())?;
-            interop.WindowHandle()?
+            flyout.source.AttachToWindow(parent.as_handle())?;
+            flyout.source.WindowHandle()?
         };
 
-        let controls = XamlControls::create_controls(
-            window,
-            parent.as_handle(),
-            monitors,
+        // The flyout is widened for the longest monitor name before anything is laid out
+        let name_width = {
+            let monitors = monitors.lock().unwrap();
+            (groups.iter())
+                .map(|group| {
+                    text_width(
+                        &group.display_name(&monitors),
+                        XamlControls::TEXTBLOCK_FONT_SIZE,
+                    )
+                })
+                .max()
+                .unwrap_or(0)
+        };
+        let padding = XamlControls::TEXTBLOCK_PADDING.Left + XamlControls::TEXTBLOCK_PADDING.Right;
+        Window::fit_width(name_width + padding.ceil() as i32 + XamlControls::NAME_MARGIN);
+
+        let context = ControlsContext {
+            monitors: monitors.clone(),
+            groups,
             tx,
             notification_icon,
-        )?;
-        xaml_source.SetContent(&controls)?;
-        let source: IDesktopWindowXamlSourceNative2 = xaml_source.cast()?;
+        };
+        let (controls, height, selector) = match settings::flyout_layout() {
+            FlyoutLayout::Selector => {
+                let (has_volume, picture_rows) = {
+                    let monitors = monitors.lock().unwrap();
+                    let picture_rows = (monitors.iter())
+                        .map(|monitor| monitor.get_pictures().len())
+                        .max()
+                        .unwrap_or(0);
+                    (monitors.iter().any(Monitor::supports_volume), picture_rows)
+                };
+                let (controls, selector) = XamlControls::create_controls(
+                    window,
+                    parent,
+                    context,
+                    has_volume,
+                    picture_rows,
+                )?;
+                let height = XamlControls::SELECTOR_HEIGHT
+                    + XamlControls::controls_height(has_volume, picture_rows);
+                (controls, height, Some(selector))
+            }
+            FlyoutLayout::AllMonitors => {
+                let (controls, height) =
+                    XamlControls::create_all_monitors_controls(parent, context)?;
+                (controls, height, None)
+            }
+        };
+        xaml_source.SetContent(scale_content(controls)?)?;
+        // Tabbing past the last control asks the host to take the focus. The flyout has nothing
+        // else to focus, so it goes around to the first control, or the last one with Shift.
+        xaml_source.TakeFocusRequested(TypedEventHandler::<
+            DesktopWindowXamlSource,
+            DesktopWindowXamlSourceTakeFocusRequestedEventArgs,
+        >::new(|sender, args| {
+            if let (Some(sender), Some(args)) = (sender, args) {
+                sender.NavigateFocus(args.Request()?)?;
+            }
+            Ok(())
+        }))?;
 
         // Sets the XAML window's position on its parent
         unsafe {
@@ -90,23 +305,43 @@ impl<'a> XamlControls<'a> {
                 HWND(0),
                 0,
                 0,
-                Window::WIDTH,
-                Window::HEIGHT,
+                scaled(Window::width()),
+                scaled(height),
                 SWP_SHOWWINDOW,
             );
         }
+        // The native window was created without the volume row, or for a single monitor
+        resize_window(parent.as_handle(), height);
 
-        Ok(XamlControls {
-            manager,
-            source,
-            parent: PhantomData,
-        })
+        flyout.selector = selector;
+        Ok(flyout)
     }
 
     /// Intercept Windows message events. Used in a `GetMessage` loop.
+    /// <kbd>Ctrl</kbd>+<kbd>Tab</kbd> selects the next monitor and
+    /// <kbd>Ctrl</kbd>+<kbd>Shift</kbd>+<kbd>Tab</kbd> the previous one; <kbd>Tab</kbd> alone still
+    /// moves the focus. <kbd>Esc</kbd> closes the flyout. Everything else goes to the XAML island
+    /// first, before `TranslateMessage` turns keys into characters. The island only gets keys while
+    /// it has the Win32 focus, which the window hands it on `WM_SETFOCUS`.
     pub fn filter_message(&self, message: *const MSG) -> bool {
         let mut processed = BOOL(0);
         unsafe {
+            let msg = &*message;
+            if msg.message == WM_KEYDOWN && msg.wParam.0 == VK_ESCAPE.0 as usize {
+                // Hidden collapsed, so that it opens at its usual size next time
+                if let Some(selector) = &self.selector {
+                    let _ = selector.collapse(self.parent.as_handle());
+                }
+                self.parent.hide_flyout();
+                return true;
+            }
+            if let (Some(selector), WM_KEYDOWN) = (&self.selector, msg.message) {
+                if msg.wParam.0 == VK_TAB.0 as usize && GetKeyState(VK_CONTROL.0 as i32) < 0 {
+                    let backwards = GetKeyState(VK_SHIFT.0 as i32) < 0;
+                    let _ = selector.cycle(self.parent.as_handle(), backwards);
+                    return true;
+                }
+            }
             if self
                 .source
                 .PreTranslateMessage(message, &mut processed)
@@ -121,64 +356,256 @@ impl<'a> XamlControls<'a> {
     /// Builds the XAML controls.
     fn create_controls(
         window: HWND,
-        parent: HWND,
-        monitors: &[Monitor],
-        tx: Sender<BrightnessEvent>,
-        notification_icon: NotificationIcon,
-    ) -> Result<StackPanel> {
+        parent: &Window,
+        context: ControlsContext,
+        has_volume: bool,
+        picture_rows: usize,
+    ) -> Result<(StackPanel, MonitorSelector)> {
+        let ControlsContext {
+            monitors: shared_monitors,
+            groups,
+            tx,
+            notification_icon,
+        } = context;
         let brush = AcrylicBrush::new()?;
         brush.SetBackgroundSource(AcrylicBackgroundSource::HostBackdrop)?;
-        brush.SetTintColor(windows::UI::ColorHelper::FromArgb(50, 0, 0, 0)?)?;
 
         let xaml_container = StackPanel::new()?;
         Panel::from(&xaml_container).SetBackground(brush.clone())?;
+        if strings().right_to_left {
+            // Inherited by all the controls, so the slider also fills from the right
+            FrameworkElement::from(&xaml_container).SetFlowDirection(FlowDirection::RightToLeft)?;
+        }
 
-        let selected_monitor = monitors.first().unwrap();
-        let init_brightness = selected_monitor.get_brightness();
-
-        let button = create_selector(&brush, selected_monitor.get_name())?;
-        let list_box = create_selector_choices(monitors)?;
-        let slider_container = create_slider_control(
+        let (button, list_box, entries, can_standby) = {
+            let monitors = shared_monitors.lock().unwrap();
+            let selected_group = &groups[group::initial_index(&groups, &monitors)];
+            let button = create_selector(&brush, &selected_group.display_name(&monitors))?;
+            let list_box = create_selector_choices(&monitors, &groups)?;
+            let entries: Vec<SelectorEntry> = groups
+                .iter()
+                .map(|group| SelectorEntry::new(group, &monitors))
+                .collect();
+            let can_standby = monitors.iter().any(Monitor::supports_standby);
+            (button, list_box, entries, can_standby)
+        };
+        let image = image::create_image(Theme::current())?;
+        let (slider_container, slider) = create_slider_control(
             &brush,
+            &image,
             list_box.clone(),
-            init_brightness,
-            tx,
-            notification_icon,
+            &shared_monitors,
+            groups.clone(),
+            tx.clone(),
+            parent.as_handle(),
         )?;
 
-        set_button_click_event(window, parent, &button, list_box.clone())?;
+        let presets = create_presets(&brush, &slider, parent.as_handle())?;
+        let pin = create_pin_button(&brush, parent.as_handle())?;
+        // An empty element of the same width on the other side keeps the presets centered
+        let spacer = Border::new()?;
+        FrameworkElement::from(&spacer).SetWidth(XamlControls::STEP_BUTTON_WIDTH as f64)?;
+        Panel::from(&presets).Children()?.InsertAt(0, spacer)?;
+        Panel::from(&presets).Children()?.Append(pin)?;
+
+        let volume = has_volume
+            .then(|| {
+                create_volume_control(&brush, &list_box, &shared_monitors, groups.clone(), &tx)
+            })
+            .transpose()?;
+        let pictures = (0..picture_rows)
+            .map(|row| {
+                create_picture_control(
+                    &brush,
+                    &list_box,
+                    &shared_monitors,
+                    groups.clone(),
+                    &tx,
+                    parent.as_handle(),
+                    row,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let controls_height = XamlControls::controls_height(has_volume, picture_rows);
+
+        set_button_click_event(
+            window,
+            parent.as_handle(),
+            &button,
+            list_box.clone(),
+            controls_height,
+        )?;
+        // The selector is a larger target than the slider; scrolling does not open the list
+        set_wheel_event(
+            &UIElement::from(&button),
+            slider.clone(),
+            parent.as_handle(),
+        )?;
+        let controls = FlyoutControls {
+            root: xaml_container.clone(),
+            brush,
+            images: vec![image],
+            sliders: Sliders::Selected(list_box.clone(), slider),
+        };
+        apply_theme(&controls, Theme::current())?;
+        set_window_event_handler(
+            parent,
+            entries,
+            can_standby,
+            controls,
+            tx,
+            notification_icon,
+            shared_monitors,
+        );
 
         // Set the XAML size to its expanded size. Minimizes flickering when resizing the parent
         // window
         let items = ItemsControl::from(&list_box).Items()?;
         let num_items = items.Size()? as i32;
-        let height =
-            XamlControls::CONTROLS_HEIGHT + XamlControls::SELECTOR_HEIGHT * (1 + num_items);
+        let height = scaled(controls_height + XamlControls::SELECTOR_HEIGHT * (1 + num_items));
         unsafe {
-            SetWindowPos(window, HWND(0), 0, 0, Window::WIDTH, height, SWP_SHOWWINDOW);
+            SetWindowPos(
+                window,
+                HWND(0),
+                0,
+                0,
+                scaled(Window::width()),
+                height,
+                SWP_SHOWWINDOW,
+            );
         }
 
+        let selector = MonitorSelector {
+            button: button.clone(),
+            list_box: list_box.clone(),
+            controls_height,
+        };
         Panel::from(&xaml_container).Children()?.Append(button)?;
         Panel::from(&xaml_container).Children()?.Append(list_box)?;
         Panel::from(&xaml_container)
             .Children()?
             .Append(slider_container)?;
+        Panel::from(&xaml_container).Children()?.Append(presets)?;
+        if let Some(volume) = volume {
+            Panel::from(&xaml_container).Children()?.Append(volume)?;
+        }
+        for picture in pictures {
+            Panel::from(&xaml_container).Children()?.Append(picture)?;
+        }
+        UIElement::from(&xaml_container).UpdateLayout()?;
+
+        Ok((xaml_container, selector))
+    }
+
+    /// Build the controls of `FlyoutLayout::AllMonitors`: a row with a slider for every selector
+    /// entry instead of the selector. Returns them together with their height.
+    fn create_all_monitors_controls(
+        parent: &Window,
+        context: ControlsContext,
+    ) -> Result<(StackPanel, i32)> {
+        let ControlsContext {
+            monitors: shared_monitors,
+            groups,
+            tx,
+            notification_icon,
+        } = context;
+        let brush = AcrylicBrush::new()?;
+        brush.SetBackgroundSource(AcrylicBackgroundSource::HostBackdrop)?;
+
+        let xaml_container = StackPanel::new()?;
+        Panel::from(&xaml_container).SetBackground(brush.clone())?;
+        if strings().right_to_left {
+            FrameworkElement::from(&xaml_container).SetFlowDirection(FlowDirection::RightToLeft)?;
+        }
+
+        let (entries, can_standby, shown) = {
+            let monitors = shared_monitors.lock().unwrap();
+            let entries: Vec<SelectorEntry> = groups
+                .iter()
+                .map(|group| SelectorEntry::new(group, &monitors))
+                .collect();
+            let can_standby = monitors.iter().any(Monitor::supports_standby);
+            let shown: Vec<(String, (u32, u32), u32)> = (groups.iter())
+                .map(|group| {
+                    (
+                        group.display_name(&monitors),
+                        group.brightness_range(&monitors),
+                        group.brightness(&monitors),
+                    )
+                })
+                .collect();
+            (entries, can_standby, shown)
+        };
+
+        let mut images = Vec::new();
+        let mut sliders = Vec::new();
+        for (index, (name, range, brightness)) in shown.into_iter().enumerate() {
+            let (row, image, slider) = create_monitor_row(
+                &brush,
+                &name,
+                (range, brightness),
+                index,
+                tx.clone(),
+                parent.as_handle(),
+            )?;
+            Panel::from(&xaml_container).Children()?.Append(row)?;
+            images.push(image);
+            sliders.push(slider);
+        }
+
+        let controls = FlyoutControls {
+            root: xaml_container.clone(),
+            brush,
+            images,
+            sliders: Sliders::All(sliders),
+        };
+        apply_theme(&controls, Theme::current())?;
+        let height = XamlControls::MONITOR_ROW_HEIGHT * entries.len() as i32;
+        set_window_event_handler(
+            parent,
+            entries,
+            can_standby,
+            controls,
+            tx,
+            notification_icon,
+            shared_monitors,
+        );
         UIElement::from(&xaml_container).UpdateLayout()?;
 
-        Ok(xaml_container)
+        Ok((xaml_container, height))
     }
 }
 
+/// The monitors that the controls of either layout show and where they send their changes to.
+struct ControlsContext {
+    monitors: SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    tx: Sender<BrightnessEvent>,
+    notification_icon: Arc<Mutex<NotificationIcon>>,
+}
+
+/// Wraps the controls, which are laid out for the compact flyout, so that they are drawn at the
+/// configured flyout size. The wrapper keeps the left-to-right direction so that the scaling
+/// stays anchored to the top left corner of the window even for right-to-left languages.
+fn scale_content(controls: StackPanel) -> Result<Border> {
+    let scale = settings::flyout_size().scale();
+    let transform = ScaleTransform::new()?;
+    transform.SetScaleX(scale)?;
+    transform.SetScaleY(scale)?;
+
+    let content = Border::new()?;
+    content.SetChild(controls)?;
+    UIElement::from(&content).SetRenderTransform(transform)?;
+    Ok(content)
+}
+
 /// Monitor selector at the top of the window.
 fn create_selector(brush: &AcrylicBrush, init_text: &str) -> Result<Button> {
     let button = Button::new()?;
-    FrameworkElement::from(&button).SetWidth(Window::WIDTH as f64)?;
+    FrameworkElement::from(&button).SetWidth(Window::width() as f64)?;
     FrameworkElement::from(&button).SetHeight(XamlControls::SELECTOR_HEIGHT as f64)?;
 
-    let text_block = TextBlock::new()?;
-    text_block.SetPadding(XamlControls::TEXTBLOCK_PADDING)?;
-    text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
-    text_block.SetText(HSTRING::from(init_text))?;
+    let text_block = create_name_text_block(&HSTRING::from(init_text))?;
 
     ContentControl::from(&button).SetContent(text_block)?;
     Control::from(&button).SetBackground(brush)?;
@@ -187,22 +614,42 @@ fn create_selector(brush: &AcrylicBrush, init_text: &str) -> Result<Button> {
     Ok(button)
 }
 
-/// Selection of monitors; initially hidden.
-fn create_selector_choices(monitors: &[Monitor]) -> Result<ListBox> {
+/// Text of the selector or one of its entries. Names too long for the flyout, which some monitors
+/// report, are cut off with an ellipsis.
+fn create_name_text_block(text: &HSTRING) -> Result<TextBlock> {
+    let text_block = TextBlock::new()?;
+    text_block.SetPadding(XamlControls::TEXTBLOCK_PADDING)?;
+    text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    text_block.SetTextWrapping(TextWrapping::NoWrap)?;
+    text_block.SetTextTrimming(TextTrimming::CharacterEllipsis)?;
+    // The width up to which monitor names are shown
+    let max_width = Window::width() - XamlControls::NAME_MARGIN;
+    FrameworkElement::from(&text_block).SetMaxWidth(max_width as f64)?;
+    set_name_text(&text_block, text)?;
+    Ok(text_block)
+}
+
+/// Change the text of a `TextBlock` from `create_name_text_block`. The full text is also shown as
+/// a tooltip in case it is cut off.
+fn set_name_text(text_block: &TextBlock, text: &HSTRING) -> Result<()> {
+    text_block.SetText(text)?;
+    ToolTipService::SetToolTip(text_block, PropertyValue::CreateString(text)?)
+}
+
+/// Selection of monitors, one entry per group; initially hidden.
+fn create_selector_choices(monitors: &[Monitor], groups: &[MonitorGroup]) -> Result<ListBox> {
     let list_box = ListBox::new()?;
     // Because there is only one slider control
     list_box.SetSelectionMode(SelectionMode::Single)?;
 
     let items = ItemsControl::from(&list_box).Items()?;
-    for monitor in monitors {
-        let text_block = TextBlock::new()?;
-        text_block.SetPadding(XamlControls::TEXTBLOCK_PADDING)?;
-        text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
-        text_block.SetText(HSTRING::from(monitor.get_name()))?;
+    for group in groups {
+        let text_block = create_name_text_block(&HSTRING::from(group.display_name(monitors)))?;
         items.Append(text_block)?;
     }
-    if !monitors.is_empty() {
-        Selector::from(&list_box).SetSelectedIndex(0)?;
+    if !groups.is_empty() {
+        let initial = group::initial_index(groups, monitors);
+        Selector::from(&list_box).SetSelectedIndex(initial as i32)?;
     }
     let num_items = items.Size()? as i32;
     let height = XamlControls::SELECTOR_HEIGHT * num_items;
@@ -213,68 +660,1074 @@ fn create_selector_choices(monitors: &[Monitor]) -> Result<ListBox> {
 }
 
 /// Consists of a brightness icon, a slider, and a text for the currently selected monitor's
-/// brightness.
+/// brightness. The slider follows the brightness and range of `groups[i]` while the `i`th selector
+/// entry is selected. Returns the container together with the `Slider` in it.
 fn create_slider_control(
     brush: &AcrylicBrush,
+    image: &Image,
     list_box: ListBox,
-    init_brightness: u32,
+    monitors: &SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
     tx: Sender<BrightnessEvent>,
-    mut notification_icon: NotificationIcon,
-) -> Result<StackPanel> {
+    parent: HWND,
+) -> Result<(StackPanel, Slider)> {
     let slider_container = StackPanel::new()?;
     Panel::from(&slider_container).SetBackground(brush)?;
     slider_container.SetOrientation(Orientation::Horizontal)?;
-    FrameworkElement::from(&slider_container).SetHeight(XamlControls::CONTROLS_HEIGHT as f64)?;
+    FrameworkElement::from(&slider_container).SetHeight(XamlControls::SLIDER_ROW_HEIGHT as f64)?;
 
-    let width = (Window::WIDTH - XamlControls::SLIDER_WIDTH) as f64 / 2.0;
+    let selected = Selector::from(&list_box).SelectedIndex()? as usize;
+    let (init_range, init_brightness) = {
+        let monitors = monitors.lock().unwrap();
+        (
+            groups[selected].brightness_range(&monitors),
+            groups[selected].brightness(&monitors),
+        )
+    };
 
-    let image = image::create_image()?;
-    FrameworkElement::from(&image).SetWidth(width)?;
-    FrameworkElement::from(&image).SetMargin(Thickness {
+    let width = (Window::width() - XamlControls::SLIDER_WIDTH) as f64 / 2.0;
+
+    FrameworkElement::from(image).SetWidth(width)?;
+    FrameworkElement::from(image).SetMargin(Thickness {
         Left: 0.0,
         Top: 4.0,
         Right: 0.0,
         Bottom: 0.0,
     })?;
 
-    let brightness_number = TextBlock::new()?;
-    FrameworkElement::from(&brightness_number).SetWidth(width)?;
-    brightness_number.SetTextAlignment(TextAlignment::Center)?;
-    FrameworkElement::from(&brightness_number).SetVerticalAlignment(VerticalAlignment::Center)?;
-    brightness_number.SetFontSize(XamlControls::BRIGHTNESS_TEXT_FONT_SIZE)?;
-    brightness_number.SetText(num_to_hstring(init_brightness))?;
+    let brightness_number = BrightnessNumber::new(width, XamlControls::BRIGHTNESS_TEXT_FONT_SIZE)?;
+    brightness_number.set_percent(to_percent(init_brightness, init_range.1))?;
+
+    // Luminance readout below the percentage, for displays that report their luminance range
+    let luminance_ranges: Vec<Option<(f32, f32)>> = {
+        let monitors = monitors.lock().unwrap();
+        (groups.iter())
+            .map(|group| group.luminance_range(&monitors))
+            .collect()
+    };
+    let nits_text = TextBlock::new()?;
+    nits_text.SetTextAlignment(TextAlignment::Center)?;
+    nits_text.SetFontSize(XamlControls::NITS_FONT_SIZE)?;
+    let readout = StackPanel::new()?;
+    FrameworkElement::from(&readout).SetVerticalAlignment(VerticalAlignment::Center)?;
+    Panel::from(&readout)
+        .Children()?
+        .Append(brightness_number.element())?;
+    Panel::from(&readout)
+        .Children()?
+        .Append(nits_text.clone())?;
+
+    let decrease = create_step_button(brush, "\u{2212}")?;
+    let increase = create_step_button(brush, "+")?;
 
     let slider = Slider::new()?;
-    FrameworkElement::from(&slider).SetWidth(XamlControls::SLIDER_WIDTH as f64)?;
+    let slider_width = XamlControls::SLIDER_WIDTH - 2 * XamlControls::STEP_BUTTON_WIDTH;
+    FrameworkElement::from(&slider).SetWidth(slider_width as f64)?;
     FrameworkElement::from(&slider).SetHeight(XamlControls::SLIDER_HEIGHT as f64)?;
-    RangeBase::from(&slider).SetMaximum(100.0)?;
-    RangeBase::from(&slider).SetMinimum(0.0)?;
+    set_slider_range(&slider, init_range)?;
     RangeBase::from(&slider).SetValue(init_brightness as f64)?;
+    set_step_buttons_enabled(&decrease, &increase, &slider)?;
+    show_nits(&nits_text, &slider, luminance_ranges[selected])?;
 
     let brightness_number_clone = brightness_number.clone();
+    let (nits_text_clone, luminance_ranges_clone) = (nits_text.clone(), luminance_ranges.clone());
+    let (decrease_clone, increase_clone) = (decrease.clone(), increase.clone());
+    let list_box_clone = list_box.clone();
+    // Set while the slider follows a newly selected entry, which must not change its brightness
+    let switching = Arc::new(AtomicBool::new(false));
+    let switching_clone = switching.clone();
 
     // `Slider::ValueChanged` callback is run on the main/UI thread. This should return immediately
     // to prevent GUI lagging hence the use of a separate thread to update the monitor brightness
+    // and the tooltip
+    let tx_clone = tx.clone();
     RangeBase::from(&slider).ValueChanged(RangeBaseValueChangedEventHandler::new(
-        move |_caller, args| {
-            if let Some(args) = args {
-                let index = Selector::from(&list_box).SelectedIndex()? as usize;
+        move |caller, args| {
+            if let (Some(caller), Some(args)) = (caller, args) {
+                let index = Selector::from(&list_box_clone).SelectedIndex()? as usize;
                 let brightness = args.NewValue()? as u32;
-                let _ = tx.send(BrightnessEvent::Change(index, brightness));
+                if !switching_clone.load(Ordering::SeqCst) && !is_locked(parent) {
+                    let _ = tx.send(BrightnessEvent::Change(
+                        index,
+                        brightness,
+                        ChangeSource::Manual,
+                    ));
+                }
+
+                let slider: Slider = caller.cast()?;
+                // The slider is in the monitor's own units, which may be finer than percents
+                let max = RangeBase::from(&slider).Maximum()? as u32;
+                brightness_number_clone.set_percent(to_percent(brightness, max))?;
+                let luminance_range = luminance_ranges_clone.get(index).copied().flatten();
+                show_nits(&nits_text_clone, &slider, luminance_range)?;
+                set_step_buttons_enabled(&decrease_clone, &increase_clone, &slider)?;
+            }
+            Ok(())
+        },
+    ))?;
 
-                brightness_number_clone.SetText(num_to_hstring(brightness))?;
-                notification_icon.modify_tooltip(brightness)?;
+    let step_buttons = (decrease.clone(), increase.clone());
+    set_selection_changed_event(
+        &list_box,
+        &slider,
+        step_buttons,
+        monitors.clone(),
+        groups,
+        switching,
+        tx_clone,
+    )?;
+    // Registered after the handler that moves the slider to the newly selected entry
+    let slider_clone = slider.clone();
+    Selector::from(&list_box).SelectionChanged(SelectionChangedEventHandler::new(
+        move |sender, _args| {
+            if let Some(sender) = sender {
+                let selector: Selector = sender.cast()?;
+                let luminance_range = usize::try_from(selector.SelectedIndex()?)
+                    .ok()
+                    .and_then(|index| luminance_ranges.get(index).copied().flatten());
+                show_nits(&nits_text, &slider_clone, luminance_range)?;
             }
             Ok(())
         },
     ))?;
+    set_wheel_event(&UIElement::from(&slider_container), slider.clone(), parent)?;
+    brightness_number.set_enter_event(slider.clone(), parent)?;
+    let step = XamlControls::BRIGHTNESS_STEP;
+    set_step_button_click_event(&decrease, slider.clone(), -step, parent)?;
+    set_step_button_click_event(&increase, slider.clone(), step, parent)?;
 
     Panel::from(&slider_container).Children()?.Append(image)?;
-    Panel::from(&slider_container).Children()?.Append(slider)?;
     Panel::from(&slider_container)
         .Children()?
-        .Append(brightness_number)?;
-    Ok(slider_container)
+        .Append(decrease)?;
+    Panel::from(&slider_container)
+        .Children()?
+        .Append(slider.clone())?;
+    Panel::from(&slider_container)
+        .Children()?
+        .Append(increase)?;
+    Panel::from(&slider_container).Children()?.Append(readout)?;
+    Ok((slider_container, slider))
+}
+
+/// Row of `FlyoutLayout::AllMonitors` for the selector entry at `index`: its `name` above the
+/// brightness icon, a slider, and the brightness in percent. The slider starts at `brightness`
+/// within `range`.
+fn create_monitor_row(
+    brush: &AcrylicBrush,
+    name: &str,
+    (range, brightness): ((u32, u32), u32),
+    index: usize,
+    tx: Sender<BrightnessEvent>,
+    parent: HWND,
+) -> Result<(StackPanel, Image, Slider)> {
+    let row = StackPanel::new()?;
+    Panel::from(&row).SetBackground(brush)?;
+    FrameworkElement::from(&row).SetHeight(XamlControls::MONITOR_ROW_HEIGHT as f64)?;
+    let name = create_name_text_block(&HSTRING::from(name))?;
+
+    let slider_container = StackPanel::new()?;
+    Panel::from(&slider_container).SetBackground(brush)?;
+    slider_container.SetOrientation(Orientation::Horizontal)?;
+
+    // The same widths as the single slider of `FlyoutLayout::Selector`
+    let width = (Window::width() - XamlControls::SLIDER_WIDTH) as f64 / 2.0;
+    let image = image::create_image(Theme::current())?;
+    FrameworkElement::from(&image).SetWidth(width)?;
+
+    let slider = Slider::new()?;
+    FrameworkElement::from(&slider).SetWidth(XamlControls::SLIDER_WIDTH as f64)?;
+    FrameworkElement::from(&slider).SetHeight(XamlControls::SLIDER_HEIGHT as f64)?;
+    set_slider_range(&slider, range)?;
+    RangeBase::from(&slider).SetValue(brightness as f64)?;
+
+    let brightness_number = BrightnessNumber::new(width, XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    brightness_number.set_percent(to_percent(brightness, range.1))?;
+
+    let brightness_number_clone = brightness_number.clone();
+    RangeBase::from(&slider).ValueChanged(RangeBaseValueChangedEventHandler::new(
+        move |caller, args| {
+            if let (Some(caller), Some(args)) = (caller, args) {
+                let brightness = args.NewValue()? as u32;
+                if !is_locked(parent) {
+                    let _ = tx.send(BrightnessEvent::Change(
+                        index,
+                        brightness,
+                        ChangeSource::Manual,
+                    ));
+                }
+                let slider: Slider = caller.cast()?;
+                let max = RangeBase::from(&slider).Maximum()? as u32;
+                brightness_number_clone.set_percent(to_percent(brightness, max))?;
+            }
+            Ok(())
+        },
+    ))?;
+    set_wheel_event(&UIElement::from(&slider_container), slider.clone(), parent)?;
+    brightness_number.set_enter_event(slider.clone(), parent)?;
+
+    Panel::from(&slider_container)
+        .Children()?
+        .Append(image.clone())?;
+    Panel::from(&slider_container)
+        .Children()?
+        .Append(slider.clone())?;
+    Panel::from(&slider_container)
+        .Children()?
+        .Append(brightness_number.element())?;
+    Panel::from(&row).Children()?.Append(name)?;
+    Panel::from(&row).Children()?.Append(slider_container)?;
+    Ok((row, image, slider))
+}
+
+/// The brightness in percent next to a slider. Typing a percentage into it and pressing
+/// <kbd>Enter</kbd> moves the slider there, unless turned off with `settings::editable_brightness`.
+#[derive(Clone)]
+enum BrightnessNumber {
+    ReadOnly(TextBlock),
+    Editable(TextBox),
+}
+
+impl BrightnessNumber {
+    fn new(width: f64, font_size: f64) -> Result<BrightnessNumber> {
+        if settings::editable_brightness() {
+            let text_box = TextBox::new()?;
+            FrameworkElement::from(&text_box).SetWidth(width)?;
+            FrameworkElement::from(&text_box).SetVerticalAlignment(VerticalAlignment::Center)?;
+            text_box.SetTextAlignment(TextAlignment::Center)?;
+            Control::from(&text_box).SetFontSize(font_size)?;
+            // Room for "100%"
+            text_box.SetMaxLength(4)?;
+            Ok(BrightnessNumber::Editable(text_box))
+        } else {
+            let text_block = TextBlock::new()?;
+            FrameworkElement::from(&text_block).SetWidth(width)?;
+            FrameworkElement::from(&text_block).SetVerticalAlignment(VerticalAlignment::Center)?;
+            text_block.SetTextAlignment(TextAlignment::Center)?;
+            text_block.SetFontSize(font_size)?;
+            Ok(BrightnessNumber::ReadOnly(text_block))
+        }
+    }
+
+    fn element(&self) -> UIElement {
+        match self {
+            BrightnessNumber::ReadOnly(text_block) => UIElement::from(text_block),
+            BrightnessNumber::Editable(text_box) => UIElement::from(text_box),
+        }
+    }
+
+    fn set_percent(&self, percent: u32) -> Result<()> {
+        match self {
+            BrightnessNumber::ReadOnly(text_block) => text_block.SetText(num_to_hstring(percent)),
+            BrightnessNumber::Editable(text_box) => text_box.SetText(num_to_hstring(percent)),
+        }
+    }
+
+    /// Move `slider` to the percentage typed in when <kbd>Enter</kbd> is pressed. The slider sends
+    /// the change like when dragged. Invalid input is replaced by the slider's brightness.
+    fn set_enter_event(&self, slider: Slider, parent: HWND) -> Result<()> {
+        let text_box = match self {
+            BrightnessNumber::ReadOnly(_) => return Ok(()),
+            BrightnessNumber::Editable(text_box) => text_box.clone(),
+        };
+        let text_box_clone = text_box.clone();
+        UIElement::from(&text_box).KeyDown(KeyEventHandler::new(move |_sender, args| {
+            if let Some(args) = args {
+                if args.Key()? != VirtualKey::Enter {
+                    return Ok(());
+                }
+                args.SetHandled(true)?;
+                let slider = RangeBase::from(&slider);
+                if let (Some(percent), false) = (
+                    parse_percent(&text_box_clone.Text()?.to_string_lossy()),
+                    is_locked(parent),
+                ) {
+                    let value = percent_to_range(&slider, percent as f64)?;
+                    slider.SetValue(clamp_to_range(&slider, value)?)?;
+                }
+                let percent = to_percent(slider.Value()? as u32, slider.Maximum()? as u32);
+                text_box_clone.SetText(num_to_hstring(percent))?;
+            }
+            Ok(())
+        }))?;
+        Ok(())
+    }
+}
+
+/// The percentage typed into a `BrightnessNumber`, e.g. `42` or `42%`, limited to 100.
+fn parse_percent(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text.strip_suffix('%').unwrap_or(text).trim_end();
+    digits
+        .parse::<u32>()
+        .ok()
+        .map(|percent| percent.min(PERCENT_MAX))
+}
+
+/// Show the luminance at the slider's brightness in `text`, or hide it if `luminance_range` is not
+/// known.
+fn show_nits(text: &TextBlock, slider: &Slider, luminance_range: Option<(f32, f32)>) -> Result<()> {
+    let luminance_range = match luminance_range {
+        Some(luminance_range) => luminance_range,
+        None => return UIElement::from(text).SetVisibility(Visibility::Collapsed),
+    };
+    let slider = RangeBase::from(slider);
+    let brightness_range = (slider.Minimum()? as u32, slider.Maximum()? as u32);
+    let nits = brightness_to_nits(slider.Value()? as u32, luminance_range, brightness_range);
+    // The unit of nits; needs no translation
+    text.SetText(HSTRING::from(format!("{:.0} cd/m\u{B2}", nits)))?;
+    UIElement::from(text).SetVisibility(Visibility::Visible)
+}
+
+/// Button next to the slider that repeats its `Click` while held down.
+fn create_step_button(brush: &AcrylicBrush, label: &str) -> Result<RepeatButton> {
+    let text_block = TextBlock::new()?;
+    text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    text_block.SetText(HSTRING::from(label))?;
+
+    let button = RepeatButton::new()?;
+    FrameworkElement::from(&button).SetWidth(XamlControls::STEP_BUTTON_WIDTH as f64)?;
+    FrameworkElement::from(&button).SetVerticalAlignment(VerticalAlignment::Center)?;
+    ContentControl::from(&button).SetContent(text_block)?;
+    Control::from(&button).SetBackground(brush)?;
+    Control::from(&button).SetBorderThickness(Thickness::default())?; // Disable border
+    Control::from(&button).SetPadding(Thickness::default())?;
+    Ok(button)
+}
+
+/// Move the slider by `step` percent, unless the brightness is locked. Goes through `ValueChanged`
+/// like dragging the slider.
+fn set_step_button_click_event(
+    button: &RepeatButton,
+    slider: Slider,
+    step: f64,
+    parent: HWND,
+) -> Result<()> {
+    ButtonBase::from(button)
+        .Click(RoutedEventHandler::new(move |_button, _args| {
+            if is_locked(parent) {
+                return Ok(());
+            }
+            let slider = RangeBase::from(&slider);
+            let step = percent_to_range(&slider, step)?;
+            slider.SetValue(clamp_to_range(&slider, slider.Value()? + step)?)
+        }))
+        .and(Ok(()))
+}
+
+/// Disable the button that cannot move the slider any further.
+fn set_step_buttons_enabled(
+    decrease: &RepeatButton,
+    increase: &RepeatButton,
+    slider: &Slider,
+) -> Result<()> {
+    let slider = RangeBase::from(slider);
+    let value = slider.Value()?;
+    Control::from(decrease).SetIsEnabled(value > slider.Minimum()?)?;
+    Control::from(increase).SetIsEnabled(value < slider.Maximum()?)
+}
+
+/// Limit the slider to the brightness range of a selector entry, so that its position always
+/// matches what the monitors can show. The values are used as is, so monitors with a maximum
+/// above 100 get a correspondingly finer slider. With `settings::snap_slider`, the tick marks are
+/// spaced to match the range.
+fn set_slider_range(slider: &Slider, (min, max): (u32, u32)) -> Result<()> {
+    let range = RangeBase::from(slider);
+    // Lower the minimum first; XAML raises the maximum whenever it is set below the minimum
+    range.SetMinimum(0.0)?;
+    range.SetMaximum(max as f64)?;
+    range.SetMinimum(min as f64)?;
+    if settings::snap_slider() {
+        set_slider_ticks(slider)?;
+    }
+    Ok(())
+}
+
+/// Show tick marks every `XamlControls::SNAP_STEP` percent and snap the thumb to them while it is
+/// dragged, clicked, or moved with the keyboard. Values set in code, e.g. by the step buttons or
+/// changes from elsewhere, are kept as they are, so `ValueChanged` only sees snapped values from
+/// the user's own dragging. XAML counts the ticks from the minimum.
+fn set_slider_ticks(slider: &Slider) -> Result<()> {
+    let step = percent_to_range(&RangeBase::from(slider), XamlControls::SNAP_STEP)?;
+    slider.SetTickFrequency(step)?;
+    slider.SetTickPlacement(TickPlacement::Outside)?;
+    slider.SetSnapsTo(SliderSnapsTo::Ticks)
+}
+
+/// `percent` of the maximum of `slider`, for moving it by similar amounts regardless of the
+/// monitor's resolution. See `to_percent`.
+fn percent_to_range(slider: &RangeBase, percent: f64) -> Result<f64> {
+    let max = slider.Maximum()?;
+    if max <= PERCENT_MAX as f64 {
+        Ok(percent)
+    } else {
+        Ok(percent * max / 100.0)
+    }
+}
+
+/// `value` limited to the current range of `slider`.
+fn clamp_to_range(slider: &RangeBase, value: f64) -> Result<f64> {
+    Ok(value.clamp(slider.Minimum()?, slider.Maximum()?))
+}
+
+/// Switch the slider to the range and brightness of the newly selected entry. `ValueChanged`
+/// updates the text but sends no `BrightnessEvent::Change` while `switching` is set. The tooltip
+/// follows through `BrightnessEvent::Select`.
+fn set_selection_changed_event(
+    list_box: &ListBox,
+    slider: &Slider,
+    (decrease, increase): (RepeatButton, RepeatButton),
+    monitors: SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    switching: Arc<AtomicBool>,
+    tx: Sender<BrightnessEvent>,
+) -> Result<()> {
+    let slider = slider.clone();
+    Selector::from(list_box)
+        .SelectionChanged(SelectionChangedEventHandler::new(move |sender, _args| {
+            if let Some(sender) = sender {
+                let selector: Selector = sender.cast()?;
+                let index = match usize::try_from(selector.SelectedIndex()?) {
+                    Ok(index) if index < groups.len() => index,
+                    _ => return Ok(()),
+                };
+                let (range, brightness) = {
+                    let monitors = monitors.lock().unwrap();
+                    (
+                        groups[index].brightness_range(&monitors),
+                        groups[index].brightness(&monitors),
+                    )
+                };
+                let _ = tx.send(BrightnessEvent::Select(index));
+
+                switching.store(true, Ordering::SeqCst);
+                let result = set_slider_range(&slider, range)
+                    .and_then(|_| RangeBase::from(&slider).SetValue(brightness as f64));
+                switching.store(false, Ordering::SeqCst);
+                result?;
+                set_step_buttons_enabled(&decrease, &increase, &slider)?;
+            }
+            Ok(())
+        }))
+        .and(Ok(()))
+}
+
+/// Scrolling over `element`, the slider row or the selector, moves the slider by
+/// `XamlControls::WHEEL_STEP` percent per notch, up for brighter unless `settings::invert_scroll`.
+/// The parent window does not handle `WM_MOUSEWHEEL` and `filter_message` lets it through to the
+/// XAML island, so the event arrives here like any other pointer input. Ignored while the
+/// brightness is locked.
+fn set_wheel_event(element: &UIElement, slider: Slider, parent: HWND) -> Result<()> {
+    // High resolution wheels report fractions of a notch; keep them until they add up to a step
+    let mut pending = 0.0;
+    element
+        .PointerWheelChanged(PointerEventHandler::new(move |sender, args| {
+            if let (Some(sender), Some(args)) = (sender, args) {
+                let sender: UIElement = sender.cast()?;
+                let delta = args
+                    .GetCurrentPoint(&sender)?
+                    .Properties()?
+                    .MouseWheelDelta()?;
+                let slider = RangeBase::from(&slider);
+                let notch_step = percent_to_range(&slider, XamlControls::WHEEL_STEP)?;
+                pending += wheel_notches(delta, settings::invert_scroll()) * notch_step;
+                let step = pending.trunc();
+                pending -= step;
+                if step != 0.0 && !is_locked(parent) {
+                    // Goes through `ValueChanged` like dragging the slider
+                    slider.SetValue(clamp_to_range(&slider, slider.Value()? + step)?)?;
+                }
+                args.SetHandled(true)?;
+            }
+            Ok(())
+        }))
+        .and(Ok(()))
+}
+
+/// Notches the wheel turned by `delta`, positive for brighter.
+fn wheel_notches(delta: i32, inverted: bool) -> f64 {
+    let notches = delta as f64 / WHEEL_DELTA as f64;
+    if inverted {
+        -notches
+    } else {
+        notches
+    }
+}
+
+/// Row of buttons for quickly setting the brightness to one of `XamlControls::BRIGHTNESS_PRESETS`.
+fn create_presets(brush: &AcrylicBrush, slider: &Slider, parent: HWND) -> Result<StackPanel> {
+    let presets_container = StackPanel::new()?;
+    Panel::from(&presets_container).SetBackground(brush)?;
+    presets_container.SetOrientation(Orientation::Horizontal)?;
+    FrameworkElement::from(&presets_container).SetHeight(XamlControls::PRESETS_HEIGHT as f64)?;
+    FrameworkElement::from(&presets_container)
+        .SetHorizontalAlignment(HorizontalAlignment::Center)?;
+
+    let num_presets = XamlControls::BRIGHTNESS_PRESETS.len() as f64;
+    let width = XamlControls::SLIDER_WIDTH as f64 / num_presets;
+    for preset in XamlControls::BRIGHTNESS_PRESETS {
+        let text_block = TextBlock::new()?;
+        text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+        text_block.SetText(HSTRING::from(format!("{}%", preset)))?;
+
+        let button = Button::new()?;
+        FrameworkElement::from(&button).SetWidth(width)?;
+        ContentControl::from(&button).SetContent(text_block)?;
+        Control::from(&button).SetBackground(brush)?;
+        Control::from(&button).SetBorderThickness(Thickness::default())?; // Disable border
+
+        // Only moves the thumb; `ValueChanged` sends the `BrightnessEvent` and updates the text and
+        // tooltip like when dragging. Nothing happens if the slider is already at the preset.
+        let slider = slider.clone();
+        ButtonBase::from(&button).Click(RoutedEventHandler::new(move |_button, _args| {
+            if is_locked(parent) {
+                return Ok(());
+            }
+            let slider = RangeBase::from(&slider);
+            let brightness = from_percent(preset, slider.Maximum()? as u32);
+            slider.SetValue(clamp_to_range(&slider, brightness as f64)?)
+        }))?;
+
+        Panel::from(&presets_container).Children()?.Append(button)?;
+    }
+    Ok(presets_container)
+}
+
+/// `XamlControls::SYMBOL_FONT`.
+fn symbol_font() -> Result<FontFamily> {
+    FontFamily::CreateInstanceWithName(HSTRING::from(XamlControls::SYMBOL_FONT))
+}
+
+/// Slider for the speaker volume of the selected entry, below the presets. It is disabled while an
+/// entry without speakers is selected.
+fn create_volume_control(
+    brush: &AcrylicBrush,
+    list_box: &ListBox,
+    monitors: &SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    tx: &Sender<BrightnessEvent>,
+) -> Result<StackPanel> {
+    let volume_container = StackPanel::new()?;
+    Panel::from(&volume_container).SetBackground(brush)?;
+    volume_container.SetOrientation(Orientation::Horizontal)?;
+    FrameworkElement::from(&volume_container).SetHeight(XamlControls::VOLUME_ROW_HEIGHT as f64)?;
+
+    // As wide as the brightness icon so both sliders line up
+    let width = (Window::width() - XamlControls::SLIDER_WIDTH) as f64 / 2.0;
+    let icon = TextBlock::new()?;
+    FrameworkElement::from(&icon).SetWidth(width)?;
+    FrameworkElement::from(&icon).SetVerticalAlignment(VerticalAlignment::Center)?;
+    icon.SetTextAlignment(TextAlignment::Center)?;
+    icon.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    icon.SetFontFamily(symbol_font()?)?;
+    icon.SetText(HSTRING::from(XamlControls::VOLUME_GLYPH))?;
+
+    let slider = Slider::new()?;
+    FrameworkElement::from(&slider).SetWidth(XamlControls::SLIDER_WIDTH as f64)?;
+    FrameworkElement::from(&slider).SetHeight(XamlControls::SLIDER_HEIGHT as f64)?;
+    FrameworkElement::from(&slider).SetVerticalAlignment(VerticalAlignment::Center)?;
+    let selected = Selector::from(list_box).SelectedIndex()? as usize;
+    let init_volume =
+        (groups.get(selected)).and_then(|group| group.volume(&monitors.lock().unwrap()));
+    show_volume(&slider, init_volume)?;
+
+    // Set while the slider follows a newly selected entry, which must not change its volume
+    let switching = Arc::new(AtomicBool::new(false));
+
+    let (list_box_clone, switching_clone, tx) = (list_box.clone(), switching.clone(), tx.clone());
+    let num_groups = groups.len();
+    RangeBase::from(&slider).ValueChanged(RangeBaseValueChangedEventHandler::new(
+        move |_caller, args| {
+            if let Some(args) = args {
+                // Nothing is selected at -1
+                let selected = Selector::from(&list_box_clone).SelectedIndex()?;
+                let index = match usize::try_from(selected) {
+                    Ok(index) if index < num_groups => index,
+                    _ => return Ok(()),
+                };
+                if !switching_clone.load(Ordering::SeqCst) {
+                    let volume = args.NewValue()? as u32;
+                    let _ = tx.send(BrightnessEvent::VolumeChange(index, volume));
+                }
+            }
+            Ok(())
+        },
+    ))?;
+
+    let (slider_clone, monitors) = (slider.clone(), monitors.clone());
+    Selector::from(list_box).SelectionChanged(SelectionChangedEventHandler::new(
+        move |sender, _args| {
+            if let Some(sender) = sender {
+                let selector: Selector = sender.cast()?;
+                if let Some(group) = usize::try_from(selector.SelectedIndex()?)
+                    .ok()
+                    .and_then(|index| groups.get(index))
+                {
+                    let volume = group.volume(&monitors.lock().unwrap());
+                    switching.store(true, Ordering::SeqCst);
+                    let result = show_volume(&slider_clone, volume);
+                    switching.store(false, Ordering::SeqCst);
+                    result?;
+                }
+            }
+            Ok(())
+        },
+    ))?;
+
+    Panel::from(&volume_container).Children()?.Append(icon)?;
+    Panel::from(&volume_container).Children()?.Append(slider)?;
+    Ok(volume_container)
+}
+
+/// Move the volume slider to `volume`, as `(current, max)`, or disable it if there is none.
+fn show_volume(slider: &Slider, volume: Option<(u32, u32)>) -> Result<()> {
+    let (current, max) = volume.unwrap_or_default();
+    let range = RangeBase::from(slider);
+    range.SetMaximum(max as f64)?;
+    range.SetValue(current as f64)?;
+    Control::from(slider).SetIsEnabled(volume.is_some())
+}
+
+/// Slider for the brightness of the picture after the main one at `row`, e.g. the second picture
+/// of picture-by-picture mode for row 0, below the volume. It is labeled with the number of the
+/// picture, counting the main one as 1, and disabled while an entry that does not show that
+/// picture is selected.
+fn create_picture_control(
+    brush: &AcrylicBrush,
+    list_box: &ListBox,
+    monitors: &SharedMonitors,
+    groups: Arc<Vec<MonitorGroup>>,
+    tx: &Sender<BrightnessEvent>,
+    parent: HWND,
+    row: usize,
+) -> Result<StackPanel> {
+    let picture_container = StackPanel::new()?;
+    Panel::from(&picture_container).SetBackground(brush)?;
+    picture_container.SetOrientation(Orientation::Horizontal)?;
+    FrameworkElement::from(&picture_container)
+        .SetHeight(XamlControls::PICTURE_ROW_HEIGHT as f64)?;
+
+    // Lines up with the volume slider
+    let width = (Window::width() - XamlControls::SLIDER_WIDTH) as f64 / 2.0;
+    let number = row + 2;
+    let label = TextBlock::new()?;
+    FrameworkElement::from(&label).SetWidth(width)?;
+    FrameworkElement::from(&label).SetVerticalAlignment(VerticalAlignment::Center)?;
+    label.SetTextAlignment(TextAlignment::Center)?;
+    label.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    label.SetText(HSTRING::from(number.to_string()))?;
+    let tooltip = Strings::format(strings().picture_brightness, number);
+    ToolTipService::SetToolTip(&label, PropertyValue::CreateString(HSTRING::from(tooltip))?)?;
+
+    let slider = Slider::new()?;
+    FrameworkElement::from(&slider).SetWidth(XamlControls::SLIDER_WIDTH as f64)?;
+    FrameworkElement::from(&slider).SetHeight(XamlControls::SLIDER_HEIGHT as f64)?;
+    FrameworkElement::from(&slider).SetVerticalAlignment(VerticalAlignment::Center)?;
+    // Window number of the picture that the slider shows, 0 while there is none
+    let window = Arc::new(AtomicU8::new(0));
+    let selected = Selector::from(list_box).SelectedIndex()? as usize;
+    let init_picture = (groups.get(selected))
+        .and_then(|group| group.pictures(&monitors.lock().unwrap()).get(row).copied());
+    show_picture(&slider, &window, init_picture)?;
+
+    // Set while the slider follows a newly selected entry, which must not change its brightness
+    let switching = Arc::new(AtomicBool::new(false));
+
+    let (list_box_clone, switching_clone, tx) = (list_box.clone(), switching.clone(), tx.clone());
+    let window_clone = window.clone();
+    let num_groups = groups.len();
+    RangeBase::from(&slider).ValueChanged(RangeBaseValueChangedEventHandler::new(
+        move |_caller, args| {
+            if let Some(args) = args {
+                // Nothing is selected at -1
+                let selected = Selector::from(&list_box_clone).SelectedIndex()?;
+                let index = match usize::try_from(selected) {
+                    Ok(index) if index < num_groups => index,
+                    _ => return Ok(()),
+                };
+                let window = window_clone.load(Ordering::SeqCst);
+                if !switching_clone.load(Ordering::SeqCst) && window != 0 && !is_locked(parent) {
+                    let brightness = args.NewValue()? as u32;
+                    let event = BrightnessEvent::PictureBrightnessChange(index, window, brightness);
+                    let _ = tx.send(event);
+                }
+            }
+            Ok(())
+        },
+    ))?;
+
+    let (slider_clone, monitors) = (slider.clone(), monitors.clone());
+    Selector::from(list_box).SelectionChanged(SelectionChangedEventHandler::new(
+        move |sender, _args| {
+            if let Some(sender) = sender {
+                let selector: Selector = sender.cast()?;
+                if let Some(group) = usize::try_from(selector.SelectedIndex()?)
+                    .ok()
+                    .and_then(|index| groups.get(index))
+                {
+                    let picture = group.pictures(&monitors.lock().unwrap()).get(row).copied();
+                    switching.store(true, Ordering::SeqCst);
+                    let result = show_picture(&slider_clone, &window, picture);
+                    switching.store(false, Ordering::SeqCst);
+                    result?;
+                }
+            }
+            Ok(())
+        },
+    ))?;
+
+    Panel::from(&picture_container).Children()?.Append(label)?;
+    Panel::from(&picture_container).Children()?.Append(slider)?;
+    Ok(picture_container)
+}
+
+/// Move the slider of a picture to `picture`, as `(window, current, max)`, and remember its window
+/// number in `window`, or disable the slider if there is none.
+fn show_picture(slider: &Slider, window: &AtomicU8, picture: Option<(u8, u32, u32)>) -> Result<()> {
+    let (number, current, max) = picture.unwrap_or_default();
+    window.store(number, Ordering::SeqCst);
+    let range = RangeBase::from(slider);
+    range.SetMaximum(max as f64)?;
+    range.SetValue(current as f64)?;
+    Control::from(slider).SetIsEnabled(picture.is_some())
+}
+
+/// Toggles whether the flyout stays open when it loses the focus, e.g. to adjust several monitors
+/// while looking at them.
+fn create_pin_button(brush: &AcrylicBrush, parent: HWND) -> Result<ToggleButton> {
+    let text_block = TextBlock::new()?;
+    text_block.SetFontSize(XamlControls::TEXTBLOCK_FONT_SIZE)?;
+    text_block.SetFontFamily(symbol_font()?)?;
+    text_block.SetText(HSTRING::from(XamlControls::PIN_GLYPH))?;
+
+    let button = ToggleButton::new()?;
+    FrameworkElement::from(&button).SetWidth(XamlControls::STEP_BUTTON_WIDTH as f64)?;
+    FrameworkElement::from(&button).SetVerticalAlignment(VerticalAlignment::Center)?;
+    ContentControl::from(&button).SetContent(text_block)?;
+    Control::from(&button).SetBackground(brush)?;
+    Control::from(&button).SetBorderThickness(Thickness::default())?; // Disable border
+    Control::from(&button).SetPadding(Thickness::default())?;
+    ToolTipService::SetToolTip(
+        &button,
+        PropertyValue::CreateString(HSTRING::from(strings().pin_tooltip))?,
+    )?;
+
+    ButtonBase::from(&button).Click(RoutedEventHandler::new(move |button, _args| {
+        if let Some(button) = button {
+            let button: ToggleButton = button.cast()?;
+            set_pinned(parent, button.IsChecked()?.Value()?);
+        }
+        Ok(())
+    }))?;
+    Ok(button)
+}
+
+/// What the window event handler needs to know about a selector entry.
+struct SelectorEntry {
+    name: String,
+    member_names: Vec<String>,
+    can_reset: bool,
+    max_brightness: u32,
+    primary: bool,
+    /// Bounds of the members that are known, for `locate::flash`.
+    bounds: Vec<RECT>,
+}
+
+impl SelectorEntry {
+    fn new(group: &MonitorGroup, monitors: &[Monitor]) -> SelectorEntry {
+        SelectorEntry {
+            name: group.name(monitors),
+            member_names: (group.members().iter())
+                .map(|&member| monitors[member].get_name().to_owned())
+                .collect(),
+            can_reset: group.supports_factory_reset(monitors),
+            max_brightness: group.brightness_range(monitors).1,
+            primary: group.is_primary(monitors),
+            bounds: (group.members().iter())
+                .filter_map(|&member| monitors[member].bounds())
+                .collect(),
+        }
+    }
+
+    /// Whether the monitors follow the primary display, `None` for the primary one itself.
+    fn follows_primary(&self) -> Option<bool> {
+        let follows = || (self.member_names.iter()).all(|name| settings::follows_primary(name));
+        (!self.primary).then(follows)
+    }
+}
+
+/// Controls that are updated after they were created.
+struct FlyoutControls {
+    root: StackPanel,
+    /// Background of all the controls.
+    brush: AcrylicBrush,
+    /// The brightness icons, one per slider.
+    images: Vec<Image>,
+    sliders: Sliders,
+}
+
+/// The brightness sliders of the flyout, depending on its `FlyoutLayout`.
+enum Sliders {
+    /// A single slider for the entry chosen in the selector.
+    Selected(ListBox, Slider),
+    /// A slider for every selector entry, by index.
+    All(Vec<Slider>),
+}
+
+impl Sliders {
+    /// Index of the entry chosen in the selector. Always `None` without a selector.
+    fn selected(&self) -> Option<usize> {
+        match self {
+            Sliders::Selected(list_box, _) => Selector::from(list_box)
+                .SelectedIndex()
+                .ok()
+                .and_then(|i| usize::try_from(i).ok()),
+            Sliders::All(_) => None,
+        }
+    }
+
+    /// The slider currently showing the selector entry at `index`, if any.
+    fn get(&self, index: usize) -> Option<&Slider> {
+        match self {
+            Sliders::Selected(_, slider) => (self.selected() == Some(index)).then_some(slider),
+            Sliders::All(sliders) => sliders.get(index),
+        }
+    }
+}
+
+/// Switch the tint of the background, the text, and the icon to `theme`. A tint from the settings
+/// replaces the one of the theme.
+fn apply_theme(controls: &FlyoutControls, theme: Theme) -> Result<()> {
+    let (tint, element_theme) = match theme {
+        Theme::Light => (XamlControls::LIGHT_TINT, ElementTheme::Light),
+        Theme::Dark => (XamlControls::DARK_TINT, ElementTheme::Dark),
+    };
+    let tint = settings::flyout_tint().map_or(tint, |tint| {
+        let [r, g, b] = tint.rgb;
+        Color {
+            A: (tint.opacity * 255.0).round() as u8,
+            R: r,
+            G: g,
+            B: b,
+        }
+    });
+    controls.brush.SetTintColor(tint)?;
+    // The text and the controls follow the requested theme of their parent
+    FrameworkElement::from(&controls.root).SetRequestedTheme(element_theme)?;
+    for image in &controls.images {
+        image::set_image_theme(image, theme)?;
+    }
+    Ok(())
+}
+
+/// What `MenuHandler` needs to know about the XAML flyout.
+struct XamlMenu {
+    window: HWND,
+    entries: Vec<SelectorEntry>,
+    controls: FlyoutControls,
+    /// Whether the monitors of each entry answer, as last raised by `UsableChanged`.
+    usable: RefCell<Vec<bool>>,
+    tx: Sender<BrightnessEvent>,
+    notification_icon: Arc<Mutex<NotificationIcon>>,
+    monitors: SharedMonitors,
+}
+
+impl FlyoutMenu for XamlMenu {
+    fn selected(&self) -> Option<usize> {
+        self.controls.sliders.selected()
+    }
+
+    fn name(&self, index: usize) -> Option<String> {
+        self.entries.get(index).map(|entry| entry.name.clone())
+    }
+
+    fn can_reset(&self, index: usize) -> bool {
+        self.entries.get(index).is_some_and(|entry| entry.can_reset)
+    }
+
+    fn follows_primary(&self, index: usize) -> Option<bool> {
+        self.entries.get(index)?.follows_primary()
+    }
+
+    fn bounds(&self, index: usize) -> Vec<RECT> {
+        self.entries
+            .get(index)
+            .map_or_else(Vec::new, |entry| entry.bounds.clone())
+    }
+
+    fn lock_controls(&self, locked: bool) {
+        for (index, &usable) in self.usable.borrow().iter().enumerate() {
+            if let Some(slider) = self.controls.sliders.get(index) {
+                let _ = Control::from(slider).SetIsEnabled(usable && !locked);
+            }
+        }
+    }
+
+    fn can_transfer(&self) -> bool {
+        true
+    }
+
+    fn export_settings(&self) {
+        if let Err(e) = export_settings(self.window, &self.monitors) {
+            let message = Strings::format(strings().export_failed, e);
+            show_balloon(&self.notification_icon, &message);
+        }
+    }
+
+    fn import_settings(&self) {
+        let message = match import_settings(self.window) {
+            Ok(Some(config)) => {
+                let (changes, applied) = saved_brightness(&config, &self.entries);
+                for (index, brightness) in changes {
+                    if let Some(slider) = self.controls.sliders.get(index) {
+                        // Also sends the change through `ValueChanged`
+                        let _ = RangeBase::from(slider).SetValue(brightness as f64);
+                    } else {
+                        let _ = self.tx.send(BrightnessEvent::Change(
+                            index,
+                            brightness,
+                            ChangeSource::Manual,
+                        ));
+                    }
+                }
+                Strings::format(strings().settings_imported, applied)
+            }
+            Ok(None) => return,
+            Err(e) => Strings::format(strings().import_failed, e),
+        };
+        show_balloon(&self.notification_icon, &message);
+    }
+}
+
+/// Handles the events from the native window that need the XAML controls.
+fn set_window_event_handler(
+    parent: &Window,
+    entries: Vec<SelectorEntry>,
+    can_standby: bool,
+    controls: FlyoutControls,
+    tx: Sender<BrightnessEvent>,
+    notification_icon: Arc<Mutex<NotificationIcon>>,
+    monitors: SharedMonitors,
+) {
+    let hwnd = parent.as_handle();
+    let handler = MenuHandler::new(
+        hwnd,
+        can_standby,
+        tx.clone(),
+        notification_icon.clone(),
+        monitors.clone(),
+    );
+    let flyout = XamlMenu {
+        window: hwnd,
+        usable: RefCell::new(vec![true; entries.len()]),
+        entries,
+        controls,
+        tx,
+        notification_icon,
+        monitors,
+    };
+
+    // Errors are ignored since there is nowhere to propagate them to; at worst the UI is stale
+    parent.set_event_handler(move |event| {
+        if handler.handle_event(&event, &flyout) {
+            return;
+        }
+        let XamlMenu {
+            entries,
+            controls,
+            usable,
+            tx,
+            notification_icon,
+            ..
+        } = &flyout;
+
+        match event {
+            WindowEvent::BrightnessChanged(index, brightness, source) => {
+                if let Some(slider) = controls.sliders.get(index) {
+                    // Also updates the text and the tooltip through `ValueChanged`
+                    let _ = RangeBase::from(slider).SetValue(brightness as f64);
+                }
+                // Without the flyout there is no other feedback for the change
+                let hidden = unsafe { !IsWindowVisible(hwnd).as_bool() };
+                if crate::NOTIFY_BRIGHTNESS_CHANGES && hidden {
+                    if let (Some(entry), Ok(mut notification_icon)) =
+                        (entries.get(index), notification_icon.lock())
+                    {
+                        let percent = to_percent(brightness, entry.max_brightness);
+                        let _ = notification_icon.notify_brightness(&entry.name, percent, source);
+                    }
+                }
+            }
+            WindowEvent::UsableChanged(index, is_usable) => {
+                if let Some(usable) = usable.borrow_mut().get_mut(index) {
+                    *usable = is_usable;
+                }
+                if let Some(slider) = controls.sliders.get(index) {
+                    let enabled = is_usable && !is_locked(hwnd);
+                    let _ = Control::from(slider).SetIsEnabled(enabled);
+                }
+            }
+            WindowEvent::PollBrightness => {
+                // Only the monitors with a slider, to keep the DDC/CI traffic low
+                for index in (0..entries.len()).filter(|&i| controls.sliders.get(i).is_some()) {
+                    let _ = tx.send(BrightnessEvent::Refresh(index));
+                }
+            }
+            WindowEvent::OpacityChanged(opacity) => {
+                let _ = UIElement::from(&controls.root).SetOpacity(opacity as f64);
+            }
+            WindowEvent::ThemeChanged => {
+                let _ = apply_theme(controls, Theme::current());
+                if let Ok(mut notification_icon) = notification_icon.lock() {
+                    let _ = notification_icon.set_theme(Theme::taskbar());
+                }
+            }
+            // Handled by `MenuHandler`, or only raised by the classic flyout
+            _ => (),
+        }
+    });
+}
+
+/// Ask for a file and save the brightness of every monitor to it, by name.
+fn export_settings(hwnd: HWND, monitors: &SharedMonitors) -> std::result::Result<(), ConfigError> {
+    let path = match dialog::save_json_file(hwnd, "brightness.json") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let snapshots = crate::monitor_snapshots(monitors, false);
+    let config =
+        Config::new((snapshots.into_iter()).map(|snapshot| (snapshot.name, snapshot.brightness)));
+    config.export(&path)
+}
+
+/// Ask for a file exported by `export_settings` and read it. `None` if the dialog was cancelled.
+fn import_settings(hwnd: HWND) -> std::result::Result<Option<Config>, ConfigError> {
+    match dialog::open_json_file(hwnd) {
+        Some(path) => Config::import(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The brightness to set for each selector entry with a monitor known to `config`, and how many
+/// monitors were known.
+fn saved_brightness(config: &Config, entries: &[SelectorEntry]) -> (Vec<(usize, u32)>, usize) {
+    let mut changes = Vec::new();
+    let mut applied = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let mut saved = (entry.member_names.iter()).filter_map(|name| config.brightness(name));
+        // A selector entry has a single brightness; the first known member decides it
+        if let Some(brightness) = saved.next() {
+            changes.push((index, brightness));
+            applied += 1 + saved.count();
+        }
+    }
+    (changes, applied)
+}
+
+fn show_balloon(notification_icon: &Mutex<NotificationIcon>, message: &str) {
+    if let Ok(mut notification_icon) = notification_icon.lock() {
+        let _ = notification_icon.show_balloon(APP_NAME, message);
+    }
 }
 
 /// Handles revealing/hiding the selection of monitors.
@@ -283,6 +1736,7 @@ fn set_button_click_event(
     parent: HWND,
     button: &Button,
     list_box: ListBox,
+    controls_height: i32,
 ) -> Result<()> {
     ButtonBase::from(button)
         .Click(RoutedEventHandler::new(move |button, _args| {
@@ -292,16 +1746,18 @@ fn set_button_click_event(
                     let num_items = items.Size()? as i32;
                     // Increate native window height to accomodate the revealed `ListBox`
                     unsafe {
-                        let height = XamlControls::CONTROLS_HEIGHT
-                            + XamlControls::SELECTOR_HEIGHT * (1 + num_items);
-                        let (x, y) = window_position(Window::WIDTH, height);
-                        SetWindowPos(window, HWND(0), 0, 0, Window::WIDTH, height, SWP_SHOWWINDOW);
+                        let width = scaled(Window::width());
+                        let height = scaled(
+                            controls_height + XamlControls::SELECTOR_HEIGHT * (1 + num_items),
+                        );
+                        let (x, y) = window_position(width, height);
+                        SetWindowPos(window, HWND(0), 0, 0, width, height, SWP_SHOWWINDOW);
                         SetWindowPos(
                             parent,
                             HWND(0),
                             x,
                             y,
-                            Window::WIDTH,
+                            width,
                             height,
                             SWP_NOZORDER | SWP_NOSENDCHANGING | SWP_NOREDRAW | SWP_DEFERERASE,
                         );
@@ -312,33 +1768,50 @@ fn set_button_click_event(
                     // Sets the selector text to "Select Monitor"
                     let button: Button = button.cast()?;
                     let text_block: TextBlock = ContentControl::from(&button).Content()?.cast()?;
-                    text_block.SetText(HSTRING::from("Select monitor"))?;
+                    set_name_text(&text_block, &HSTRING::from(strings().select_monitor))?;
                     text_block.SetFontWeight(FontWeights::Bold()?)?;
                 }
             } else if let Some(button) = button {
                 let button: Button = button.cast()?;
-                hide_selection(&button, parent, &list_box)?;
+                hide_selection(&button, parent, &list_box, controls_height)?;
             }
             Ok(())
         }))
         .and(Ok(()))
 }
 
-/// Hides the selection of monitors.
-pub fn hide_selection(button: &Button, parent: HWND, list_box: &ListBox) -> Result<()> {
-    // Return the native window to its default size
+/// Return the native window to its default size, with `controls_height` below the selector.
+fn collapse_window(parent: HWND, controls_height: i32) {
+    resize_window(parent, XamlControls::SELECTOR_HEIGHT + controls_height);
+}
+
+/// Resize the native window to `height` of the compact flyout, keeping it next to the
+/// notification area.
+fn resize_window(parent: HWND, height: i32) {
+    let width = scaled(Window::width());
+    let height = scaled(height);
+    let (x, y) = window_position(width, height);
     unsafe {
-        let (x, y) = window_position(Window::WIDTH, Window::HEIGHT);
         SetWindowPos(
             parent,
             HWND(0),
             x,
             y,
-            Window::WIDTH,
-            Window::HEIGHT,
+            width,
+            height,
             SWP_NOZORDER | SWP_NOSENDCHANGING | SWP_NOREDRAW | SWP_DEFERERASE,
         );
     }
+}
+
+/// Hides the selection of monitors.
+pub fn hide_selection(
+    button: &Button,
+    parent: HWND,
+    list_box: &ListBox,
+    controls_height: i32,
+) -> Result<()> {
+    collapse_window(parent, controls_height);
     // Re-hide the selection
     let _ = UIElement::from(list_box).SetVisibility(Visibility::Collapsed);
 
@@ -346,16 +1819,88 @@ pub fn hide_selection(button: &Button, parent: HWND, list_box: &ListBox) -> Resu
     let text_block: TextBlock = ContentControl::from(button).Content()?.cast()?;
     let selected_item = Selector::from(list_box).SelectedItem()?;
     let monitor_name: TextBlock = selected_item.cast()?;
-    text_block.SetText(monitor_name.Text()?)?;
+    set_name_text(&text_block, &monitor_name.Text()?)?;
     text_block.SetFontWeight(FontWeights::Normal()?)?;
 
     Ok(())
 }
 
-/// Helper function for creating a `HSTRING` from an integer.
+/// Helper function for creating a `HSTRING` from a brightness value. Values above 100 are clamped
+/// since the UI only ever shows a percentage.
 fn num_to_hstring(num: u32) -> HSTRING {
-    let mut buf: [u8; 11] = [0; 11];
-    write!(&mut buf[..], "{}", num).unwrap();
-    let s = std::str::from_utf8(&buf).unwrap();
+    const MAX_DISPLAYED: u32 = 100;
+
+    // Large enough for "100", the longest value after clamping
+    let mut buf = [0u8; 3];
+    let remaining = {
+        let mut cursor = &mut buf[..];
+        write!(cursor, "{}", num.min(MAX_DISPLAYED)).unwrap();
+        cursor.len()
+    };
+    // Only the written bytes; trailing zeros would otherwise end up as embedded NULs
+    let written = buf.len() - remaining;
+    let s = std::str::from_utf8(&buf[..written]).unwrap();
     HSTRING::from(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycling_wraps_around() {
+        assert_eq!(cycled_index(0, 3, false), 1);
+        assert_eq!(cycled_index(2, 3, false), 0);
+        assert_eq!(cycled_index(0, 3, true), 2);
+        assert_eq!(cycled_index(2, 3, true), 1);
+        assert_eq!(cycled_index(0, 1, false), 0);
+        assert_eq!(cycled_index(-1, 3, false), 0);
+        assert_eq!(cycled_index(-1, 3, true), 2);
+        assert_eq!(cycled_index(-1, 0, false), -1);
+    }
+
+    #[test]
+    fn wheel_direction() {
+        assert_eq!(wheel_notches(WHEEL_DELTA as i32, false), 1.0);
+        assert_eq!(wheel_notches(-(WHEEL_DELTA as i32) / 2, false), -0.5);
+        assert_eq!(wheel_notches(WHEEL_DELTA as i32, true), -1.0);
+        assert_eq!(wheel_notches(-(WHEEL_DELTA as i32) / 2, true), 0.5);
+    }
+
+    #[test]
+    fn typed_percentages() {
+        assert_eq!(parse_percent("42"), Some(42));
+        assert_eq!(parse_percent(" 42 % "), Some(42));
+        assert_eq!(parse_percent("0"), Some(0));
+        assert_eq!(parse_percent("150"), Some(100));
+        assert_eq!(parse_percent(""), None);
+        assert_eq!(parse_percent("-5"), None);
+        assert_eq!(parse_percent("4.5"), None);
+        assert_eq!(parse_percent("bright"), None);
+    }
+
+    #[test]
+    fn num_to_hstring_digits() {
+        let zero = num_to_hstring(0);
+        assert_eq!(zero, "0");
+        assert_eq!(zero.len(), 1);
+
+        let hundred = num_to_hstring(100);
+        assert_eq!(hundred, "100");
+        assert_eq!(hundred.len(), 3);
+
+        let two_digits = num_to_hstring(42);
+        assert_eq!(two_digits, "42");
+        assert_eq!(two_digits.len(), 2);
+    }
+
+    #[test]
+    fn num_to_hstring_clamps() {
+        let clamped = num_to_hstring(255);
+        assert_eq!(clamped, "100");
+        assert_eq!(clamped.len(), 3);
+        assert!(!clamped.as_wide().contains(&0));
+
+        assert_eq!(num_to_hstring(u32::MAX), "100");
+    }
+}